@@ -1,6 +1,6 @@
 import { useEffect, useRef, useState } from "react";
 import { AnimatePresence } from "motion/react";
-import { IO, TrackInfo, events } from "./lib/socket-io";
+import { IO, Palette, TrackInfo, events } from "./lib/socket-io";
 import { useInactivityTracker } from "./hooks/use-inactivity-tracker";
 import { PlayerControls } from "./components/player/player-controls";
 import { TrackMetaData } from "./components/player/track-display";
@@ -10,16 +10,30 @@ import { TrackThumbnail } from "./components/player/track-thumbnail";
 import { useHotKey } from "./hooks/use-hotkey";
 
 const INACTIVITY_TIMEOUT = 10 * 1000;
-const PRIMARY_HUE_KEY = "--primary-hue";
-
-function updatePrimaryColorHue(hue: number) {
-  document.documentElement.style.setProperty(PRIMARY_HUE_KEY, hue.toString());
+function updatePalette(palette: Palette | null) {
+  const style = document.documentElement.style;
+  if (!palette) {
+    style.removeProperty("--primary");
+    style.removeProperty("--primary-foreground");
+    return;
+  }
+  // The client always uses the dark theme
+  style.setProperty("--primary", palette.theme.dark.progress);
+  style.setProperty("--primary-foreground", palette.theme.dark.on_progress);
 }
 
-async function getServerUrl() {
-  const res = await fetch("/server-info");
-  const data = await res.json();
-  return data.socketio_url;
+type ServerInfo = {
+  socketio_url: string;
+  socketio_path: string;
+  base_path: string;
+};
+
+async function getServerInfo(): Promise<ServerInfo | null> {
+  // Relative, so it resolves under the base path when served behind a proxy.
+  // Without server info the client is served next to SocketIO, so connect to the same origin
+  const res = await fetch("server-info").catch(() => null);
+  if (!res?.ok) return null;
+  return res.json();
 }
 
 function App() {
@@ -33,12 +47,12 @@ function App() {
   });
 
   async function setupSocketIO() {
-    const url = await getServerUrl();
-    io.current = new IO(url);
+    const info = await getServerInfo();
+    io.current = new IO(info?.socketio_url, info?.socketio_path);
     const socket = io.current.socket;
 
-    socket.on(events.TRACK_INFO, (track: TrackInfo) => {
-      updatePrimaryColorHue(track.accent_color);
+    socket.on(events.TRACK_INFO, (track: TrackInfo | null) => {
+      updatePalette(track?.palette ?? null);
       setTrack(track);
     });
 
@@ -87,6 +101,7 @@ function App() {
                 key="progress-bar"
                 io={io.current}
                 active={active}
+                trackId={track.track_id}
                 duration={track.duration}
               />
               <PlayerControls key="controls" active={active} io={io.current} />
@@ -7,24 +7,37 @@ import { IO, TrackTimeline, events } from "@/lib/socket-io";
 type ProgressBarProps = {
   io: IO;
   active: boolean;
+  trackId: string;
   duration: number;
 };
 
-export function ProgressBar({ io, active, duration }: ProgressBarProps) {
+export function ProgressBar({
+  io,
+  active,
+  trackId,
+  duration,
+}: ProgressBarProps) {
   const [progress, setProgress] = useState(0);
+  const [timelineDuration, setTimelineDuration] = useState<number | null>(null);
   const [seeking, setSeeking] = useState(false);
+  const length = timelineDuration ?? duration;
+
+  // The track info's duration until the new track's timeline arrives
+  useEffect(() => setTimelineDuration(null), [trackId]);
 
   useEffect(() => {
-    const onTrackTimeline = (timeline: TrackTimeline) => {
-      if (seeking) return false;
-      setProgress(timeline.progress);
+    const onTrackTimeline = ({ timeline }: TrackTimeline) => {
+      // A late timeline of the previous track
+      if (seeking || timeline.track_id !== trackId) return false;
+      setProgress(timeline.position);
+      setTimelineDuration(timeline.duration || null);
     };
 
     io.socket.on(events.TRACK_TIMELINE, onTrackTimeline);
     return () => {
       io.socket.off(events.TRACK_TIMELINE, onTrackTimeline);
     };
-  }, [io, seeking]);
+  }, [io, seeking, trackId]);
 
   function handleSeek([newVal]: [number]) {
     setSeeking(false);
@@ -35,11 +48,12 @@ export function ProgressBar({ io, active, duration }: ProgressBarProps) {
     <motion.div layout="position">
       <Slider
         min={0}
-        max={duration}
+        max={length}
         value={[progress]}
         onValueChange={([newVal]) => {
           setSeeking(true);
           setProgress(newVal);
+          io.seekPreview(newVal);
         }}
         onValueCommit={handleSeek}
         trackCn={cn("bg-white/15", !active && "h-0.5!")}
@@ -52,7 +66,7 @@ export function ProgressBar({ io, active, duration }: ProgressBarProps) {
         )}
       >
         <span>{formatMilliseconds(progress)}</span>
-        <span>{formatMilliseconds(duration)}</span>
+        <span>{formatMilliseconds(length)}</span>
       </div>
     </motion.div>
   );
@@ -3,40 +3,276 @@ import { io, Socket } from "socket.io-client";
 export const events = {
   GET_MEDIA_DETAILS: "get_media_details",
   TOGGLE_PLAY_PAUSE: "toggle_play_pause",
+  PLAY: "play",
+  PAUSE: "pause",
+  STOP: "stop",
+  FAST_FORWARD: "fast_forward",
+  REWIND: "rewind",
   NEXT_TRACK: "next_track",
   PREVIOUS_TRACK: "previous_track",
   SEEK: "seek",
+  SEEK_PREVIEW: "seek_preview",
   TOGGLE_SHUFFLE: "toggle_shuffle",
   SET_REPEAT_MODE: "set_repeat_mode",
+  GET_LYRICS: "get_lyrics",
+  GET_QUEUE: "get_queue",
+  SPOTIFY_SAVE_TRACK: "spotify_save_track",
+  SPOTIFY_ADD_TO_PLAYLIST: "spotify_add_to_playlist",
+  SPOTIFY_ADD_TO_QUEUE: "spotify_add_to_queue",
+  SPOTIFY_GET_DEVICES: "spotify_get_devices",
+  SPOTIFY_TRANSFER_PLAYBACK: "spotify_transfer_playback",
+  SET_SLEEP_TIMER: "set_sleep_timer",
+  CANCEL_SLEEP_TIMER: "cancel_sleep_timer",
+  CANCEL_INACTIVITY_PAUSE: "cancel_inactivity_pause",
+  ADD_SCHEDULE: "add_schedule",
+  LIST_SCHEDULES: "list_schedules",
+  REMOVE_SCHEDULE: "remove_schedule",
+  ADMIN_KICK: "admin_kick",
+  FULL_STATE: "full_state",
+  GET_STATE_SNAPSHOT: "get_state_snapshot",
+  HELLO: "hello",
+  SET_VOLUME: "set_volume",
 
+  SESSION_STATE: "session_state",
   TRACK_INFO: "track_info",
   TRACK_CONTROLS: "track_controls",
   TRACK_TIMELINE: "track_timeline",
+  LYRICS: "lyrics",
+  QUEUE: "queue",
+  SPOTIFY_DEVICES: "spotify_devices",
+  SLEEP_TIMER_STATUS: "sleep_timer_status",
+  INACTIVITY_STATUS: "inactivity_status",
+  SCHEDULES: "schedules",
+  SERVER_PING: "server_ping",
+  STATE_SNAPSHOT: "state_snapshot",
+  PLUGIN_EVENT: "plugin_event",
+  TRACK_SKIPPED: "track_skipped",
+  SERVER_SHUTDOWN: "server_shutdown",
+  RESYNC: "resync",
 };
 
-export type TrackInfo = {
+export type SessionInfo = {
+  session_id: string;
+  source_app_id: string;
+  app_name: string;
+};
+
+export type SessionState = {
+  state: "active" | "none";
+  session: SessionInfo | null;
+};
+
+export type ThemeTokens = {
+  background: string;
+  foreground: string;
+  muted_foreground: string;
+  progress: string;
+  on_progress: string;
+};
+
+export type Palette = {
+  dominant: string;
+  vibrant: string;
+  muted: string;
+  text: string;
+  theme: {
+    dark: ThemeTokens;
+    light: ThemeTokens;
+  };
+};
+
+export type ThumbnailVariants = {
+  small: string;
+  medium: string;
+  full: string;
+};
+
+export type TrackMetadata = {
+  album: string | null;
+  year: number | null;
+  genres: string[];
+  artist_image: string | null;
+  sources: string[];
+};
+
+export type TrackInfo = SessionInfo & {
   title: string;
   artist: string;
   album: string | null;
+  album_artist: string | null;
+  subtitle: string | null;
+  track_number: number | null;
+  album_track_count: number | null;
+  genres: string[];
+  playback_type: "unknown" | "music" | "video" | "image";
+  track_id: string;
+  previous_track_id: string | null;
+  /** @deprecated Read at another time than the position, use the timeline's duration */
   duration: number;
   thumbnail: string;
-  accent_color: number; // only hue 0-360
+  thumbnails: ThumbnailVariants | null;
+  palette: Palette | null;
+  metadata: TrackMetadata | null;
+  is_advertisement: boolean;
+  stale: boolean;
+};
+
+export type LyricLine = {
+  time: number; // In milliseconds
+  text: string;
+};
+
+export type Lyrics = {
+  title: string;
+  artist: string;
+  source: string;
+  synced: boolean;
+  lines: LyricLine[];
+  plain: string;
+  lrc: string | null;
+};
+
+export type QueueItem = {
+  title: string;
+  artist: string;
+  album: string | null;
+  duration: number;
+  artwork: string | null;
+};
+
+export type Queue = {
+  source: string;
+  current: QueueItem | null;
+  upcoming: QueueItem[];
 };
 
-export type TrackControls = {
+export type SpotifyDevice = {
+  id: string;
+  name: string;
+  kind: string;
+  active: boolean;
+  volume: number | null;
+};
+
+export type FadeOptions = {
+  fade_ms: number;
+  target?: "system" | "app";
+};
+
+export type SleepTimerRequest = {
+  minutes?: number;
+  at?: number;
+  fade_seconds?: number;
+};
+
+export type SleepTimerStatus = {
+  active: boolean;
+  ends_at: number | null;
+  fade_seconds: number | null;
+};
+
+export type ScheduledAction =
+  | { type: "play" }
+  | { type: "pause" }
+  | { type: "set_volume"; level: number };
+
+export type ScheduleRequest = {
+  cron: string;
+  action: ScheduledAction;
+  label?: string;
+};
+
+export type Schedule = {
+  id: number;
+  cron: string;
+  action: ScheduledAction;
+  label: string | null;
+};
+
+export type TrackControls = SessionInfo & {
+  track_id: string; // Controls of another track than the current one are outdated
   shuffle_enabled: boolean;
   auto_repeat_mode_enabled: boolean;
   next_enabled: boolean;
   prev_enabled: boolean;
   play_pause_enabled: boolean;
+  play_enabled: boolean;
+  pause_enabled: boolean;
+  stop_enabled: boolean;
+  fast_forward_enabled: boolean;
+  rewind_enabled: boolean;
 
   shuffle: boolean;
   auto_repeat_mode: AutoRepeatMode;
   playing: boolean;
+  stale: boolean;
 };
 
-export type TrackTimeline = {
+export type SeekRange = {
+  start: number;
+  end: number;
+};
+
+export type Timeline = {
+  track_id: string; // Timelines of another track than the current one are outdated
+  position: number; // In milliseconds
+  duration: number; // In milliseconds, 0 when unknown
+  rate: number;
+  updated_at: number | null;
+  seekable: SeekRange[];
+};
+
+export type TrackTimeline = SessionInfo & {
+  /** @deprecated Use timeline.position */
   progress: number;
+  timeline: Timeline;
+};
+
+export type Capabilities = {
+  lyrics: boolean;
+  metadata: boolean;
+  spotify: boolean;
+  media_keys: boolean;
+};
+
+export type StateSnapshot = {
+  session: SessionState;
+  sessions: SessionInfo[];
+  track_info: TrackInfo | null;
+  track_controls: TrackControls | null;
+  track_timeline: TrackTimeline | null;
+  volume: number | null;
+  sleep_timer: SleepTimerStatus;
+  capabilities: Capabilities;
+};
+
+export const PROTOCOL_VERSION = 1;
+
+export type Feature =
+  | "state_snapshot"
+  | "lyrics"
+  | "queue"
+  | "spotify"
+  | "sleep_timer"
+  | "schedules";
+
+export type ServerHello = {
+  protocol_version: number;
+  features: Feature[];
+  capabilities: Capabilities;
+  device: string | null;
+};
+
+export type PluginEvent = {
+  plugin: string;
+  name: string;
+  data: unknown;
+};
+
+export type TrackSkipped = SessionInfo & {
+  title: string;
+  artist: string;
+  rule: number;
 };
 
 export type TrackProgress = {
@@ -50,38 +286,149 @@ export enum AutoRepeatMode {
   List = "list",
 }
 
+export type CommandAck = {
+  ok: boolean;
+  error: string | null;
+};
+
+export type ServerPing = {
+  timestamp: number;
+};
+
 export class IO {
   socket: Socket;
 
-  constructor(url?: string) {
-    this.socket = io(url);
+  constructor(url?: string, path?: string) {
+    this.socket = io(url, path ? { path } : undefined);
+    // Answer the heartbeat so the server can measure latency
+    this.socket.on(events.SERVER_PING, (ping: ServerPing, ack?: (ping: ServerPing) => void) => {
+      ack?.(ping);
+    });
+  }
+
+  togglePlayPause(fade?: FadeOptions): Promise<CommandAck> {
+    return fade
+      ? this.socket.emitWithAck(events.TOGGLE_PLAY_PAUSE, fade)
+      : this.socket.emitWithAck(events.TOGGLE_PLAY_PAUSE);
+  }
+
+  play(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.PLAY);
+  }
+
+  pause(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.PAUSE);
+  }
+
+  stop(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.STOP);
+  }
+
+  fastForward(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.FAST_FORWARD);
+  }
+
+  rewind(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.REWIND);
+  }
+
+  nextTrack(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.NEXT_TRACK);
+  }
+
+  previousTrack(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.PREVIOUS_TRACK);
+  }
+
+  toggleShuffle(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.TOGGLE_SHUFFLE);
+  }
+
+  setRepeatMode(mode: AutoRepeatMode): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.SET_REPEAT_MODE, mode);
+  }
+
+  seek(position: number): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.SEEK, { position });
+  }
+
+  seekPreview(position: number): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.SEEK_PREVIEW, { position });
+  }
+
+  getMediaDetails(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.GET_MEDIA_DETAILS);
+  }
+
+  getLyrics(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.GET_LYRICS);
+  }
+
+  getQueue(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.GET_QUEUE);
+  }
+
+  spotifySaveTrack(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.SPOTIFY_SAVE_TRACK);
+  }
+
+  spotifyAddToPlaylist(playlistId: string): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.SPOTIFY_ADD_TO_PLAYLIST, { playlist_id: playlistId });
+  }
+
+  spotifyAddToQueue(uri: string): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.SPOTIFY_ADD_TO_QUEUE, { uri });
+  }
+
+  spotifyGetDevices(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.SPOTIFY_GET_DEVICES);
+  }
+
+  spotifyTransferPlayback(deviceId: string, play = false): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.SPOTIFY_TRANSFER_PLAYBACK, { device_id: deviceId, play });
+  }
+
+  setSleepTimer(request: SleepTimerRequest): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.SET_SLEEP_TIMER, request);
+  }
+
+  cancelSleepTimer(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.CANCEL_SLEEP_TIMER);
+  }
+
+  addSchedule(request: ScheduleRequest): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.ADD_SCHEDULE, request);
   }
 
-  togglePlayPause() {
-    this.socket.emit(events.TOGGLE_PLAY_PAUSE);
+  listSchedules(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.LIST_SCHEDULES);
   }
 
-  nextTrack() {
-    this.socket.emit(events.NEXT_TRACK);
+  removeSchedule(id: number): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.REMOVE_SCHEDULE, { id });
   }
 
-  previousTrack() {
-    this.socket.emit(events.PREVIOUS_TRACK);
+  adminKick(id: string): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.ADMIN_KICK, { id });
   }
 
-  toggleShuffle() {
-    this.socket.emit(events.TOGGLE_SHUFFLE);
+  fullState(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.FULL_STATE);
   }
 
-  setRepeatMode(mode: AutoRepeatMode) {
-    this.socket.emit(events.SET_REPEAT_MODE, mode);
+  getStateSnapshot(): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.GET_STATE_SNAPSHOT);
   }
 
-  seek(position: number) {
-    this.socket.emit(events.SEEK, { position });
+  setVolume(level: number): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.SET_VOLUME, { level });
   }
 
-  getMediaDetails() {
-    this.socket.emit(events.GET_MEDIA_DETAILS);
+  hello(features: Feature[], deviceToken?: string): Promise<CommandAck> {
+    return this.socket.emitWithAck(events.HELLO, {
+      protocol_version: PROTOCOL_VERSION,
+      features,
+      ...(deviceToken ? { device_token: deviceToken } : {}),
+    });
   }
 }
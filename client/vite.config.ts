@@ -4,6 +4,8 @@ import tailwindcss from "@tailwindcss/vite";
 
 // https://vite.dev/config/
 export default defineConfig({
+  // Relative asset paths so the client works under any --base-path
+  base: "./",
   plugins: [react(), tailwindcss()],
   resolve: {
     alias: {
@@ -0,0 +1,37 @@
+use std::io::Cursor;
+
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use image::{ImageOutputFormat, Rgb, RgbImage};
+use media_controller_core::{palette, utils};
+
+/// Side length of the artwork apps usually hand out
+const ARTWORK_SIZE: u32 = 300;
+
+/// A JPEG with gradients in every channel, so the median cut has colors to split
+fn artwork() -> Vec<u8> {
+    let image = RgbImage::from_fn(ARTWORK_SIZE, ARTWORK_SIZE, |x, y| {
+        Rgb([
+            (x * 255 / ARTWORK_SIZE) as u8,
+            (y * 255 / ARTWORK_SIZE) as u8,
+            ((x + y) * 127 / ARTWORK_SIZE) as u8,
+        ])
+    });
+    let mut bytes = Cursor::new(Vec::new());
+    image
+        .write_to(&mut bytes, ImageOutputFormat::Jpeg(85))
+        .expect("encoding to memory can't fail");
+    bytes.into_inner()
+}
+
+fn thumbnail(c: &mut Criterion) {
+    let artwork = artwork();
+    c.bench_function("encode thumbnail as data URL", |b| {
+        b.iter(|| utils::encode_image_to_base64(black_box(&artwork)))
+    });
+    c.bench_function("extract palette", |b| {
+        b.iter(|| palette::extract_palette(black_box(&artwork)))
+    });
+}
+
+criterion_group!(benches, thumbnail);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+use crate::media_manager::TrackInfo;
+
+/// Titles players report for ad breaks, compared case-insensitively
+const AD_TITLES: [&str; 4] = ["advertisement", "spotify", "spotify free", "ad break"];
+/// Apps that play ads between tracks and report them as a track without an artist
+const AD_SUPPORTED_APPS: [&str; 2] = ["spotify", "music.youtube.com"];
+
+/// Guess whether a track is an ad break
+///
+/// Players don't flag ads, so this goes by well-known ad titles, a missing artist
+/// in apps with ad-supported tiers and ads with no known length.
+pub fn is_advertisement(track: &TrackInfo) -> bool {
+    let title = track.title.trim().to_lowercase();
+    if AD_TITLES.contains(&title.as_str()) || title.starts_with("advertisement") {
+        return true;
+    }
+
+    let app = track.session.source_app_id.to_lowercase();
+    let ad_supported = AD_SUPPORTED_APPS.iter().any(|name| app.contains(name));
+    ad_supported && track.artist.trim().is_empty() && (track.duration == 0 || !title.is_empty())
+}
@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use crate::controller::Command;
+use crate::error::{MediaError, MediaResult};
+use crate::media_manager::{SessionInfo, SessionState, TrackControls, TrackInfo, TrackTimeline};
+use crate::session_watcher::SessionEvent;
+
+/// A change a backend reports, the controller reads the new state on its thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendChange {
+    /// A property of the current session changed
+    Session(SessionEvent),
+    /// Another session became current, or there is none anymore
+    CurrentSession,
+}
+
+/// What is published about a session, read for clients pinned to its app
+#[derive(Debug, Clone)]
+pub struct SessionSnapshot {
+    pub track_info: TrackInfo,
+    pub track_controls: Option<TrackControls>,
+    pub track_timeline: Option<TrackTimeline>,
+}
+
+/// Called by a backend whenever something changed, from any thread
+pub type ChangeNotifier = Arc<dyn Fn(BackendChange) + Send + Sync>;
+
+/// A source of media sessions, driven by a [`MediaController`](crate::MediaController)
+/// on a thread of its own.
+///
+/// [`MediaManager`](crate::MediaManager) talks to the sessions of Windows apps,
+/// [`MockBackend`](crate::mock::MockBackend) plays a scripted library and
+/// [`DlnaBackend`](crate::dlna::DlnaBackend) controls a renderer on the network.
+pub trait MediaBackend: Send {
+    fn session_state(&self) -> SessionState;
+
+    /// Every session known to the backend, the current one is not necessarily first
+    fn sessions(&self) -> MediaResult<Vec<SessionInfo>>;
+
+    /// App User Model ID of the app owning the current session
+    fn current_app_id(&self) -> MediaResult<String>;
+
+    fn is_playing(&self) -> MediaResult<bool>;
+
+    fn track_info(&self) -> MediaResult<TrackInfo>;
+
+    /// The current track without its artwork, which takes longest to read, for
+    /// telling whether the track changed
+    fn track_info_without_artwork(&self) -> MediaResult<TrackInfo> {
+        self.track_info().map(TrackInfo::without_artwork)
+    }
+
+    fn track_controls(&self) -> MediaResult<TrackControls>;
+
+    fn track_timeline(&self) -> MediaResult<TrackTimeline>;
+
+    /// Raw artwork of the current track
+    fn artwork(&self) -> MediaResult<Vec<u8>>;
+
+    /// Run a command against the current session
+    ///
+    /// # Returns
+    /// * `MediaResult<bool>` - Whether the app accepted the command
+    fn execute(&self, command: &Command) -> MediaResult<bool>;
+
+    /// Read the session of an app, which need not be the current one
+    ///
+    /// Backends that don't tell sessions apart only know the current app.
+    ///
+    /// # Arguments
+    /// * `app_id` - Source app id, compared case-insensitively
+    /// * `artwork` - Whether the track comes with its artwork
+    fn session_snapshot(&self, app_id: &str, artwork: bool) -> MediaResult<SessionSnapshot> {
+        if !self.current_app_id()?.eq_ignore_ascii_case(app_id) {
+            return Err(MediaError::NoSession);
+        }
+        Ok(SessionSnapshot {
+            track_info: match artwork {
+                true => self.track_info()?,
+                false => self.track_info_without_artwork()?,
+            },
+            track_controls: self.track_controls().ok(),
+            track_timeline: self.track_timeline().ok(),
+        })
+    }
+
+    /// Run a command against the session of an app, which need not be the current one
+    ///
+    /// # Arguments
+    /// * `app_id` - Source app id, compared case-insensitively
+    /// * `command` - Command to run
+    ///
+    /// # Returns
+    /// * `MediaResult<bool>` - Whether the app accepted the command
+    fn execute_for(&self, app_id: &str, command: &Command) -> MediaResult<bool> {
+        if !self.current_app_id()?.eq_ignore_ascii_case(app_id) {
+            return Err(MediaError::NoSession);
+        }
+        self.execute(command)
+    }
+
+    /// Start reporting changes
+    ///
+    /// # Arguments
+    /// * `notify` - Called for every change until `unwatch`
+    fn watch(&mut self, notify: ChangeNotifier) -> MediaResult<()>;
+
+    /// Report the changes of the new current session, called after a
+    /// [`BackendChange::CurrentSession`]
+    fn follow_session(&mut self) {}
+
+    /// Stop reporting changes
+    fn unwatch(&mut self);
+
+    /// Change handlers currently registered
+    fn handler_count(&self) -> usize;
+}
@@ -0,0 +1,509 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, mpsc};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, oneshot};
+use tracing::Instrument;
+
+use crate::backend::{BackendChange, MediaBackend};
+use crate::error::{MediaError, MediaResult};
+use crate::media_manager::{AutoRepeatMode, TrackControls, TrackInfo, TrackTimeline};
+use crate::session_watcher::SessionEvent;
+
+const EVENT_CAPACITY: usize = 64;
+/// Changes waiting to be coalesced, some apps report their timeline many times a second
+const CHANGE_QUEUE_CAPACITY: usize = 16;
+/// Longest a track change with partial metadata is held back, waiting for the rest
+const SETTLE_WINDOW: Duration = Duration::from_millis(600);
+/// Interval between reads of a held back track, artwork is often readable only later
+const SETTLE_RETRY: Duration = Duration::from_millis(150);
+
+type Job = Box<dyn FnOnce(&dyn MediaBackend) + Send>;
+type Reply<T> = mpsc::SyncSender<T>;
+
+/// A playback command for the current session
+#[derive(Debug, Clone)]
+pub enum Command {
+    TogglePlayPause,
+    Play,
+    Pause,
+    Stop,
+    FastForward,
+    Rewind,
+    NextTrack,
+    PreviousTrack,
+    /// Jump to a position in milliseconds
+    Seek { ms: u64 },
+    SetRepeat { mode: AutoRepeatMode },
+    ToggleShuffle,
+}
+
+impl Command {
+    /// Name used in errors and logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::TogglePlayPause => "play/pause",
+            Command::Play => "play",
+            Command::Pause => "pause",
+            Command::Stop => "stop",
+            Command::FastForward => "fast forward",
+            Command::Rewind => "rewind",
+            Command::NextTrack => "next track",
+            Command::PreviousTrack => "previous track",
+            Command::Seek { .. } => "seek",
+            Command::SetRepeat { .. } => "set repeat mode",
+            Command::ToggleShuffle => "toggle shuffle",
+        }
+    }
+
+    fn run(self, backend: &dyn MediaBackend) -> MediaResult<()> {
+        if !backend.execute(&self)? {
+            return Err(MediaError::rejected(self.name()));
+        }
+        Ok(())
+    }
+
+    fn run_for(self, backend: &dyn MediaBackend, app_id: &str) -> MediaResult<()> {
+        if !backend.execute_for(app_id, &self)? {
+            return Err(MediaError::rejected(self.name()));
+        }
+        Ok(())
+    }
+}
+
+/// A change of the current session, read on the media thread right after it happened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ManagerEvent {
+    /// Boxed, a track with its artwork is far larger than the other changes
+    TrackChanged(Box<TrackInfo>),
+    ControlsChanged(TrackControls),
+    TimelineChanged(TrackTimeline),
+    /// Another session became current, or there is none anymore
+    SessionChanged,
+}
+
+enum Message {
+    Call(Job),
+    Command(Command, Reply<MediaResult<()>>),
+    /// Register the change handlers
+    Watch(Reply<MediaResult<()>>),
+    /// Remove every change handler
+    Unwatch(Reply<()>),
+    /// Swap in a new backend and register the handlers on it
+    Replace(Box<dyn MediaBackend>, Reply<MediaResult<()>>),
+    /// Coalesced changes of the backend, answered once they are read and broadcast
+    Changed(Vec<BackendChange>, Reply<()>),
+}
+
+/// Handle to the thread owning the [`MediaBackend`].
+///
+/// The thread receives commands and calls over a channel and runs them one after
+/// another, so a slow WinRT `.get()` never holds a lock an async handler waits on.
+/// It also drives the change handlers of the backend and broadcasts every change as
+/// a [`ManagerEvent`].
+#[derive(Clone)]
+pub struct MediaController {
+    messages: mpsc::Sender<Message>,
+    events: broadcast::Sender<ManagerEvent>,
+    handlers: Arc<AtomicUsize>,
+    thread: ThreadId,
+}
+
+impl MediaController {
+    /// Move the backend to a thread of its own, which runs for the rest of the process
+    pub fn spawn(backend: Box<dyn MediaBackend>) -> Self {
+        let (messages, receiver) = mpsc::channel();
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let handlers = Arc::new(AtomicUsize::new(0));
+
+        let (queue, changes) = mpsc::sync_channel(CHANGE_QUEUE_CAPACITY);
+        let dropped = Arc::new(AtomicU8::new(0));
+        let coalescer = Coalescer {
+            changes,
+            dropped: Arc::clone(&dropped),
+            messages: messages.clone(),
+        };
+        thread::spawn(move || coalescer.run());
+
+        let actor = Actor {
+            backend,
+            changes: ChangeQueue { queue, dropped },
+            events: events.clone(),
+            handlers: Arc::clone(&handlers),
+            settling: None,
+        };
+        let thread = thread::spawn(move || actor.run(receiver));
+
+        Self {
+            messages,
+            events,
+            handlers,
+            thread: thread.thread().id(),
+        }
+    }
+
+    /// Changes of the current session
+    pub fn events(&self) -> broadcast::Receiver<ManagerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Registered session handlers
+    pub fn handler_count(&self) -> usize {
+        self.handlers.load(Ordering::Relaxed)
+    }
+
+    /// Run a command against the current session
+    pub fn command(&self, command: Command) -> MediaResult<()> {
+        let _span = tracing::info_span!("media_call", call = ?command).entered();
+        self.request(|reply| Message::Command(command, reply))?
+    }
+
+    /// Run a command against the session of an app, which need not be the current one
+    ///
+    /// # Arguments
+    /// * `app_id` - Source app id, compared case-insensitively
+    /// * `command` - Command to run
+    pub fn command_for(&self, app_id: &str, command: Command) -> MediaResult<()> {
+        let _span = tracing::info_span!("media_call", call = ?command, app_id).entered();
+        let app_id = app_id.to_string();
+        self.request(|reply| {
+            Message::Call(Box::new(move |backend| {
+                reply.send(command.run_for(backend, &app_id)).ok();
+            }))
+        })?
+    }
+
+    /// Start broadcasting changes of the current session
+    pub fn watch(&self) -> MediaResult<()> {
+        self.request(Message::Watch)?
+    }
+
+    /// Stop broadcasting changes and remove every session handler
+    pub fn unwatch(&self) -> MediaResult<()> {
+        self.request(Message::Unwatch)
+    }
+
+    /// Replace a broken backend, keeping the changes broadcast
+    pub fn replace(&self, backend: Box<dyn MediaBackend>) -> MediaResult<()> {
+        self.request(|reply| Message::Replace(backend, reply))?
+    }
+
+    /// Read from the backend on the media thread and block until it returns
+    ///
+    /// # Arguments
+    /// * `call` - Gets the backend, must not call back into the handle
+    pub fn call<R, F>(&self, call: F) -> MediaResult<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&dyn MediaBackend) -> R + Send + 'static,
+    {
+        let _span = tracing::info_span!("media_call", call = call_name::<F>()).entered();
+        self.request(|reply| {
+            Message::Call(Box::new(move |backend| {
+                reply.send(call(backend)).ok();
+            }))
+        })
+    }
+
+    /// Read from the backend on the media thread, awaiting it without blocking the runtime
+    ///
+    /// # Arguments
+    /// * `call` - Gets the backend, must not call back into the handle
+    pub async fn call_async<R, F>(&self, call: F) -> MediaResult<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&dyn MediaBackend) -> R + Send + 'static,
+    {
+        let span = tracing::info_span!("media_call", call = call_name::<F>());
+        let (sender, receiver) = oneshot::channel();
+        self.send(Message::Call(Box::new(move |backend| {
+            sender.send(call(backend)).ok();
+        })))?;
+        receiver
+            .instrument(span)
+            .await
+            .map_err(MediaError::internal)
+    }
+
+    /// Send a message and block until the media thread answers it
+    fn request<R>(&self, message: impl FnOnce(Reply<R>) -> Message) -> MediaResult<R> {
+        if thread::current().id() == self.thread {
+            // Waiting for its own queue would never return
+            return Err(MediaError::internal(
+                "Media manager called from its own thread",
+            ));
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.send(message(sender))?;
+        receiver.recv().map_err(MediaError::internal)
+    }
+
+    fn send(&self, message: Message) -> MediaResult<()> {
+        self.messages
+            .send(message)
+            .map_err(|_| MediaError::internal("Media manager thread stopped"))
+    }
+}
+
+/// Names a call after the function that made it, closures are named after the
+/// function they are in, e.g. `media_controller::events::EventHub::current_state`
+fn call_name<F>() -> &'static str {
+    std::any::type_name::<F>().trim_end_matches("::{{closure}}")
+}
+
+/// Bounded queue between the change handlers of the backend and the [`Coalescer`]
+#[derive(Clone)]
+struct ChangeQueue {
+    queue: mpsc::SyncSender<BackendChange>,
+    /// Bits of the changes that didn't fit, see [`change_bit`]
+    dropped: Arc<AtomicU8>,
+}
+
+impl ChangeQueue {
+    /// Queue a change without blocking the WinRT thread of the handler
+    fn push(&self, change: BackendChange) {
+        if let Err(mpsc::TrySendError::Full(change)) = self.queue.try_send(change) {
+            // Read once the queue drains, however often it happened meanwhile
+            self.dropped.fetch_or(change_bit(change), Ordering::Relaxed);
+        }
+    }
+}
+
+fn change_bit(change: BackendChange) -> u8 {
+    match change {
+        BackendChange::Session(SessionEvent::MediaProperties) => 1,
+        BackendChange::Session(SessionEvent::PlaybackInfo) => 1 << 1,
+        BackendChange::Session(SessionEvent::TimelineProperties) => 1 << 2,
+        BackendChange::CurrentSession => 1 << 3,
+    }
+}
+
+/// Hands the queued changes to the media thread, each kind once per batch
+///
+/// A batch is only sent once the previous one was read, so changes arriving faster
+/// than the media thread reads them collapse into one read of the latest state
+/// instead of piling up in front of the commands of clients.
+struct Coalescer {
+    changes: mpsc::Receiver<BackendChange>,
+    dropped: Arc<AtomicU8>,
+    messages: mpsc::Sender<Message>,
+}
+
+impl Coalescer {
+    fn run(self) {
+        while let Ok(first) = self.changes.recv() {
+            let mut batch = vec![first];
+            let dropped = self.dropped.swap(0, Ordering::Relaxed);
+            let later = self.changes.try_iter().chain(
+                [
+                    BackendChange::CurrentSession,
+                    BackendChange::Session(SessionEvent::MediaProperties),
+                    BackendChange::Session(SessionEvent::PlaybackInfo),
+                    BackendChange::Session(SessionEvent::TimelineProperties),
+                ]
+                .into_iter()
+                .filter(|change| dropped & change_bit(*change) != 0),
+            );
+            for change in later {
+                // Reading the state again would find the same, newest state
+                if !batch.contains(&change) {
+                    batch.push(change);
+                }
+            }
+
+            let (reply, read) = mpsc::sync_channel(1);
+            if self.messages.send(Message::Changed(batch, reply)).is_err() {
+                return;
+            }
+            // Also returns when reading panicked and the reply was dropped
+            read.recv().ok();
+        }
+    }
+}
+
+/// State of the media thread
+struct Actor {
+    backend: Box<dyn MediaBackend>,
+    /// Handed to the backend's change notifier, which only queues the change
+    changes: ChangeQueue,
+    events: broadcast::Sender<ManagerEvent>,
+    handlers: Arc<AtomicUsize>,
+    /// A track change held back until its metadata is complete
+    settling: Option<Settling>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Settling {
+    /// The track is broadcast as it is from then on
+    deadline: Instant,
+    next_read: Instant,
+}
+
+impl Actor {
+    fn run(mut self, receiver: mpsc::Receiver<Message>) {
+        loop {
+            let due = self
+                .settling
+                .is_some_and(|settling| Instant::now() >= settling.next_read);
+            if due {
+                self.guarded(Actor::settle);
+            }
+
+            let message = match self.settling {
+                Some(settling) => {
+                    let timeout = settling.next_read.saturating_duration_since(Instant::now());
+                    match receiver.recv_timeout(timeout) {
+                        Ok(message) => message,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                None => match receiver.recv() {
+                    Ok(message) => message,
+                    Err(_) => return,
+                },
+            };
+            self.guarded(|actor| actor.handle(message));
+        }
+    }
+
+    /// A panicking call must not take every later call down with it
+    fn guarded(&mut self, work: impl FnOnce(&mut Self)) {
+        if panic::catch_unwind(AssertUnwindSafe(|| work(self))).is_err() {
+            tracing::error!("Media manager call panicked");
+        }
+    }
+
+    fn handle(&mut self, message: Message) {
+        match message {
+            Message::Call(job) => job(self.backend.as_ref()),
+            Message::Command(command, reply) => {
+                reply.send(command.run(self.backend.as_ref())).ok();
+            }
+            Message::Watch(reply) => {
+                reply.send(self.watch()).ok();
+            }
+            Message::Unwatch(reply) => {
+                self.unwatch();
+                reply.send(()).ok();
+            }
+            Message::Replace(backend, reply) => {
+                self.unwatch();
+                self.backend = backend;
+                reply.send(self.watch()).ok();
+            }
+            Message::Changed(changes, reply) => {
+                for change in changes {
+                    self.changed(change);
+                }
+                reply.send(()).ok();
+            }
+        }
+    }
+
+    fn changed(&mut self, change: BackendChange) {
+        match change {
+            BackendChange::Session(event) => self.broadcast_change(event),
+            BackendChange::CurrentSession => {
+                tracing::info!("Current media session changed");
+                // The new session's state is read as a whole
+                self.settling = None;
+                self.backend.follow_session();
+                self.count_handlers();
+                self.emit(ManagerEvent::SessionChanged);
+            }
+        }
+    }
+
+    fn watch(&mut self) -> MediaResult<()> {
+        let changes = self.changes.clone();
+        let result = self
+            .backend
+            .watch(Arc::new(move |change| changes.push(change)));
+        self.count_handlers();
+        result
+    }
+
+    fn unwatch(&mut self) {
+        self.settling = None;
+        self.backend.unwatch();
+        self.count_handlers();
+    }
+
+    fn count_handlers(&self) {
+        self.handlers
+            .store(self.backend.handler_count(), Ordering::Relaxed);
+    }
+
+    fn broadcast_change(&mut self, event: SessionEvent) {
+        let event = match event {
+            SessionEvent::MediaProperties => {
+                match self.backend.track_info() {
+                    Ok(track) => self.track_changed(track),
+                    Err(e) => tracing::error!("Failed to get track info: {}", e),
+                }
+                return;
+            }
+            SessionEvent::PlaybackInfo => self
+                .backend
+                .track_controls()
+                .map(ManagerEvent::ControlsChanged)
+                .map_err(|e| ("track controls", e)),
+            SessionEvent::TimelineProperties => self
+                .backend
+                .track_timeline()
+                .map(ManagerEvent::TimelineChanged)
+                .map_err(|e| ("track timeline", e)),
+        };
+        match event {
+            Ok(event) => self.emit(event),
+            Err((what, e)) => tracing::error!("Failed to get {}: {}", what, e),
+        }
+    }
+
+    /// Broadcast a track once it is complete, holding back partial metadata for
+    /// up to the settle window so clients don't flicker through it
+    fn track_changed(&mut self, track: TrackInfo) {
+        let now = Instant::now();
+        let expired = self
+            .settling
+            .is_some_and(|settling| now >= settling.deadline);
+        if track.is_complete() || expired {
+            self.settling = None;
+            self.emit(ManagerEvent::TrackChanged(Box::new(track)));
+            return;
+        }
+
+        // Later partial changes don't push the deadline back
+        let deadline = self
+            .settling
+            .map_or(now + SETTLE_WINDOW, |settling| settling.deadline);
+        self.settling = Some(Settling {
+            deadline,
+            next_read: now + SETTLE_RETRY,
+        });
+    }
+
+    /// Read a held back track again, its artwork may have become readable
+    fn settle(&mut self) {
+        match self.backend.track_info() {
+            Ok(track) => self.track_changed(track),
+            Err(e) => {
+                // The session went away, its partial track is of no use anymore
+                tracing::debug!("Failed to read held back track info: {}", e);
+                self.settling = None;
+            }
+        }
+    }
+
+    fn emit(&self, event: ManagerEvent) {
+        // Sending only fails when nobody is subscribed, which is fine
+        self.events.send(event).ok();
+    }
+}
@@ -0,0 +1,703 @@
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+use reqwest::blocking::Client;
+use roxmltree::{Document, Node};
+
+use crate::backend::{BackendChange, ChangeNotifier, MediaBackend};
+use crate::controller::Command;
+use crate::episode::Episode;
+use crate::error::{MediaError, MediaResult};
+use crate::media_manager::{
+    AutoRepeatMode, PlaybackType, SessionInfo, SessionState, SessionStatus, Timeline,
+    TrackControls, TrackInfo, TrackTimeline, track_id,
+};
+use crate::session_watcher::SessionEvent;
+use crate::utils;
+
+const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+const SSDP_ADDRESS: &str = "239.255.255.250:1900";
+/// How long renderers get to answer the search
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Renderers rarely support eventing reliably, so their state is polled
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Step of fast forward and rewind
+const SEEK_STEP_MS: u64 = 10_000;
+
+/// UPnP error codes of a SOAP fault
+const TRANSITION_NOT_AVAILABLE: u32 = 701;
+const INVALID_ACTION: u32 = 401;
+const OPTIONAL_ACTION_NOT_IMPLEMENTED: u32 = 602;
+
+/// A media renderer found on the network
+#[derive(Debug, Clone)]
+struct Renderer {
+    name: String,
+    /// Unique Device Name, e.g. `uuid:5f9ec1b3-...`, used as the app id
+    udn: String,
+    control_url: Url,
+}
+
+impl Renderer {
+    fn session_info(&self) -> SessionInfo {
+        SessionInfo {
+            session_id: utils::session_id_from_app_id(&self.udn),
+            source_app_id: self.udn.clone(),
+            app_name: self.name.clone(),
+        }
+    }
+}
+
+/// The AVTransport service of the controlled renderer
+struct Transport {
+    client: Client,
+    renderer: Renderer,
+}
+
+/// State of the renderer as far as events are concerned
+#[derive(Debug, Default, PartialEq)]
+struct Status {
+    transport_state: String,
+    play_mode: String,
+    track_uri: String,
+    track_metadata: String,
+    position_ms: u64,
+    /// `0` when the renderer doesn't know it
+    duration_ms: u64,
+}
+
+impl Status {
+    fn title(&self, metadata: &TrackMetadata) -> String {
+        // Without metadata the file name is the best guess at a title
+        metadata.title.clone().unwrap_or_else(|| {
+            let path = self.track_uri.split('?').next().unwrap_or_default();
+            path.rsplit('/').next().unwrap_or_default().to_string()
+        })
+    }
+
+    fn track_id(&self, source_app_id: &str, metadata: &TrackMetadata) -> String {
+        track_id(
+            source_app_id,
+            &self.title(metadata),
+            metadata.artist.as_deref().unwrap_or_default(),
+            self.duration_ms,
+        )
+    }
+}
+
+/// What the DIDL-Lite metadata of a track holds
+#[derive(Debug, Default)]
+struct TrackMetadata {
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    album_art: Option<String>,
+    track_number: Option<u32>,
+    /// The item's `upnp:class` is a video item
+    video: bool,
+    episode: Option<Episode>,
+}
+
+impl Transport {
+    /// Invoke an AVTransport action on instance 0
+    ///
+    /// # Arguments
+    /// * `action` - Action name, e.g. `Play`
+    /// * `arguments` - Arguments after the `InstanceID`, in the order the action declares them
+    ///
+    /// # Returns
+    /// * `MediaResult<HashMap<String, String>>` - The output arguments by name
+    fn call(
+        &self,
+        action: &str,
+        arguments: &[(&str, &str)],
+    ) -> MediaResult<HashMap<String, String>> {
+        let arguments: String = arguments
+            .iter()
+            .map(|(name, value)| format!("<{name}>{value}</{name}>"))
+            .collect();
+        let body = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:{action} xmlns:u="{AV_TRANSPORT}"><InstanceID>0</InstanceID>{arguments}</u:{action}></s:Body></s:Envelope>"#
+        );
+
+        let response = self
+            .client
+            .post(self.renderer.control_url.clone())
+            .header("Content-Type", r#"text/xml; charset="utf-8""#)
+            .header("SOAPAction", format!(r#""{AV_TRANSPORT}#{action}""#))
+            .body(body)
+            .send()
+            .map_err(MediaError::internal)?;
+        let status = response.status();
+        let text = response.text().map_err(MediaError::internal)?;
+        let document = Document::parse(&text).map_err(MediaError::internal)?;
+
+        if !status.is_success() {
+            let code = document
+                .descendants()
+                .find(|node| node.has_tag_name("errorCode"))
+                .and_then(|node| node.text())
+                .and_then(|code| code.trim().parse().ok());
+            return Err(match code {
+                Some(TRANSITION_NOT_AVAILABLE) => MediaError::rejected(action),
+                Some(INVALID_ACTION | OPTIONAL_ACTION_NOT_IMPLEMENTED) => {
+                    MediaError::unsupported(action)
+                }
+                _ => MediaError::internal(format!("{} failed with HTTP {}", action, status)),
+            });
+        }
+
+        let answer = format!("{}Response", action);
+        let outputs = document
+            .descendants()
+            .find(|node| node.has_tag_name(answer.as_str()))
+            .map(|node| {
+                node.children()
+                    .filter(Node::is_element)
+                    .map(|output| {
+                        let value = output.text().unwrap_or_default().to_string();
+                        (output.tag_name().name().to_string(), value)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(outputs)
+    }
+
+    fn status(&self) -> MediaResult<Status> {
+        let mut transport = self.call("GetTransportInfo", &[])?;
+        let mut position = self.call("GetPositionInfo", &[])?;
+        // Not every renderer implements play modes
+        let mut settings = self.call("GetTransportSettings", &[]).unwrap_or_default();
+        Ok(Status {
+            transport_state: output(&mut transport, "CurrentTransportState"),
+            play_mode: output(&mut settings, "PlayMode"),
+            track_uri: output(&mut position, "TrackURI"),
+            position_ms: parse_time(&output(&mut position, "RelTime")).unwrap_or_default(),
+            track_metadata: output(&mut position, "TrackMetaData"),
+            duration_ms: parse_time(&output(&mut position, "TrackDuration")).unwrap_or_default(),
+        })
+    }
+
+    fn seek(&self, ms: u64) -> MediaResult<()> {
+        self.call(
+            "Seek",
+            &[("Unit", "REL_TIME"), ("Target", &format_time(ms))],
+        )
+        .map(|_| ())
+    }
+
+    fn set_play_mode(&self, mode: &str) -> MediaResult<()> {
+        self.call("SetPlayMode", &[("NewPlayMode", mode)])
+            .map(|_| ())
+    }
+
+    fn artwork(&self, url: &str) -> MediaResult<Vec<u8>> {
+        // Album art URIs may be relative to the device
+        let url = self
+            .renderer
+            .control_url
+            .join(url)
+            .map_err(MediaError::internal)?;
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(MediaError::internal)?;
+        let bytes = response.bytes().map_err(MediaError::internal)?;
+        Ok(bytes.to_vec())
+    }
+}
+
+/// A backend controlling a UPnP/DLNA media renderer, e.g. a smart TV or a network
+/// speaker, through its AVTransport service.
+///
+/// Renderers are discovered over SSDP. Each one is a session, the chosen one is
+/// the current session. Its state is polled, since eventing is rarely reliable.
+pub struct DlnaBackend {
+    transport: Arc<Transport>,
+    /// Every renderer that answered the search
+    renderers: Vec<Renderer>,
+    /// Cleared to stop the polling thread of the current watch
+    running: Option<Arc<AtomicBool>>,
+}
+
+impl DlnaBackend {
+    /// Search the network for renderers and control one of them
+    ///
+    /// # Arguments
+    /// * `name` - Part of the friendly name of the renderer to control, the first
+    ///   one found when `None`
+    pub fn discover(name: Option<&str>) -> MediaResult<Self> {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(MediaError::internal)?;
+
+        let mut renderers = Vec::new();
+        for location in search()? {
+            match describe(&client, &location) {
+                Ok(Some(renderer)) => {
+                    tracing::info!("Found DLNA renderer {} at {}", renderer.name, location);
+                    renderers.push(renderer);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to describe device at {}: {}", location, e),
+            }
+        }
+
+        let wanted = name.map(str::to_lowercase);
+        let renderer = renderers
+            .iter()
+            .find(|renderer| match &wanted {
+                Some(wanted) => renderer.name.to_lowercase().contains(wanted),
+                None => true,
+            })
+            .cloned()
+            .ok_or(MediaError::NoSession)?;
+        tracing::info!("Controlling DLNA renderer {}", renderer.name);
+
+        Ok(Self {
+            transport: Arc::new(Transport { client, renderer }),
+            renderers,
+            running: None,
+        })
+    }
+
+    fn session_info(&self) -> SessionInfo {
+        self.transport.renderer.session_info()
+    }
+
+    /// The current track, its artwork is downloaded from the renderer
+    ///
+    /// # Arguments
+    /// * `artwork` - Whether to download the artwork
+    fn read_track_info(&self, artwork: bool) -> MediaResult<TrackInfo> {
+        let status = self.transport.status()?;
+        if status.track_uri.is_empty() && status.track_metadata.is_empty() {
+            return Err(MediaError::NoSession);
+        }
+        let metadata = parse_metadata(&status.track_metadata).unwrap_or_default();
+        let title = status.title(&metadata);
+        let artist = metadata.artist.clone().unwrap_or_default();
+
+        let session = self.session_info();
+        let mut info = TrackInfo {
+            track_id: status.track_id(&session.source_app_id, &metadata),
+            previous_track_id: None,
+            session,
+            title,
+            artist,
+            thumbnail: None,
+            thumbnails: None,
+            album: metadata.album,
+            album_artist: metadata.artist,
+            subtitle: None,
+            track_number: metadata.track_number,
+            album_track_count: None,
+            genres: Vec::new(),
+            playback_type: if metadata.video {
+                PlaybackType::Video
+            } else {
+                PlaybackType::Music
+            },
+            episode: metadata.episode,
+            duration: status.duration_ms,
+            palette: None,
+            metadata: None,
+            is_advertisement: false,
+            stale: false,
+        };
+        if let Some(url) = metadata.album_art.as_ref().filter(|_| artwork) {
+            match self.transport.artwork(url) {
+                Ok(bytes) => info.set_artwork(&bytes),
+                Err(e) => tracing::warn!("Failed to get artwork from {}: {}", url, e),
+            }
+        }
+        Ok(info)
+    }
+
+    fn metadata(&self) -> MediaResult<TrackMetadata> {
+        let status = self.transport.status()?;
+        Ok(parse_metadata(&status.track_metadata).unwrap_or_default())
+    }
+}
+
+impl Drop for DlnaBackend {
+    fn drop(&mut self) {
+        self.unwatch();
+    }
+}
+
+impl MediaBackend for DlnaBackend {
+    fn session_state(&self) -> SessionState {
+        SessionState {
+            state: SessionStatus::Active,
+            session: Some(self.session_info()),
+        }
+    }
+
+    fn sessions(&self) -> MediaResult<Vec<SessionInfo>> {
+        Ok(self.renderers.iter().map(Renderer::session_info).collect())
+    }
+
+    fn current_app_id(&self) -> MediaResult<String> {
+        Ok(self.transport.renderer.udn.clone())
+    }
+
+    fn is_playing(&self) -> MediaResult<bool> {
+        Ok(is_playing(&self.transport.status()?.transport_state))
+    }
+
+    fn track_info(&self) -> MediaResult<TrackInfo> {
+        self.read_track_info(true)
+    }
+
+    fn track_info_without_artwork(&self) -> MediaResult<TrackInfo> {
+        self.read_track_info(false)
+    }
+
+    fn track_controls(&self) -> MediaResult<TrackControls> {
+        let status = self.transport.status()?;
+        // Optional, every control is offered when the renderer doesn't list them
+        let actions = self
+            .transport
+            .call("GetCurrentTransportActions", &[])
+            .ok()
+            .and_then(|mut outputs| outputs.remove("Actions"));
+        let offers = |action: &str| match &actions {
+            Some(actions) => actions
+                .split(',')
+                .any(|offered| offered.trim().eq_ignore_ascii_case(action)),
+            None => true,
+        };
+        let has_play_modes = !status.play_mode.is_empty();
+        let metadata = parse_metadata(&status.track_metadata).unwrap_or_default();
+        let session = self.session_info();
+
+        Ok(TrackControls {
+            track_id: status.track_id(&session.source_app_id, &metadata),
+            session,
+            shuffle_enabled: has_play_modes,
+            auto_repeat_mode_enabled: has_play_modes,
+            next_enabled: offers("Next"),
+            prev_enabled: offers("Previous"),
+            play_pause_enabled: offers("Play") || offers("Pause"),
+            play_enabled: offers("Play"),
+            pause_enabled: offers("Pause"),
+            stop_enabled: offers("Stop"),
+            fast_forward_enabled: offers("Seek"),
+            rewind_enabled: offers("Seek"),
+            shuffle: matches!(status.play_mode.as_str(), "SHUFFLE" | "RANDOM"),
+            auto_repeat_mode: match status.play_mode.as_str() {
+                "REPEAT_ONE" => AutoRepeatMode::Track,
+                "REPEAT_ALL" => AutoRepeatMode::List,
+                _ => AutoRepeatMode::None,
+            },
+            playing: is_playing(&status.transport_state),
+            stale: false,
+        })
+    }
+
+    fn track_timeline(&self) -> MediaResult<TrackTimeline> {
+        let status = self.transport.status()?;
+        let metadata = parse_metadata(&status.track_metadata).unwrap_or_default();
+        let session = self.session_info();
+        let track_id = status.track_id(&session.source_app_id, &metadata);
+        let timeline = TrackTimeline {
+            session,
+            progress: status.position_ms,
+            start_time: 0,
+            end_time: status.duration_ms,
+            min_seek_time: 0,
+            max_seek_time: status.duration_ms,
+            last_updated: None,
+            playback_rate: 1.0,
+            // Streams of unknown length can't be seeked in
+            seek_enabled: status.duration_ms > 0,
+            timeline: Timeline::default(),
+        };
+        Ok(timeline.unify(track_id))
+    }
+
+    fn artwork(&self) -> MediaResult<Vec<u8>> {
+        let metadata = self.metadata()?;
+        let url = metadata
+            .album_art
+            .ok_or_else(|| MediaError::unsupported("artwork"))?;
+        self.transport.artwork(&url)
+    }
+
+    fn execute(&self, command: &Command) -> MediaResult<bool> {
+        let transport = &self.transport;
+        match command {
+            Command::TogglePlayPause if self.is_playing()? => {
+                transport.call("Pause", &[])?;
+            }
+            Command::TogglePlayPause | Command::Play => {
+                transport.call("Play", &[("Speed", "1")])?;
+            }
+            Command::Pause => {
+                transport.call("Pause", &[])?;
+            }
+            Command::Stop => {
+                transport.call("Stop", &[])?;
+            }
+            Command::FastForward => {
+                let position = transport.status()?.position_ms + SEEK_STEP_MS;
+                transport.seek(position)?;
+            }
+            Command::Rewind => {
+                let position = transport.status()?.position_ms;
+                transport.seek(position.saturating_sub(SEEK_STEP_MS))?;
+            }
+            Command::NextTrack => {
+                transport.call("Next", &[])?;
+            }
+            Command::PreviousTrack => {
+                transport.call("Previous", &[])?;
+            }
+            Command::Seek { ms } => transport.seek(*ms)?,
+            Command::SetRepeat { mode } => transport.set_play_mode(match mode {
+                AutoRepeatMode::None => "NORMAL",
+                AutoRepeatMode::Track => "REPEAT_ONE",
+                AutoRepeatMode::List => "REPEAT_ALL",
+            })?,
+            Command::ToggleShuffle => {
+                let shuffle = self.track_controls()?.shuffle;
+                transport.set_play_mode(if shuffle { "NORMAL" } else { "SHUFFLE" })?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn watch(&mut self, notify: ChangeNotifier) -> MediaResult<()> {
+        self.unwatch();
+        let running = Arc::new(AtomicBool::new(true));
+        let transport = Arc::clone(&self.transport);
+        let polling = Arc::clone(&running);
+        thread::Builder::new()
+            .name("dlna-backend".to_string())
+            .spawn(move || poll(&transport, &notify, &polling))
+            .map_err(MediaError::internal)?;
+        self.running = Some(running);
+        Ok(())
+    }
+
+    fn unwatch(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn handler_count(&self) -> usize {
+        usize::from(self.running.is_some())
+    }
+}
+
+/// Report what changed on the renderer until the watch that started the thread ends
+fn poll(transport: &Transport, notify: &ChangeNotifier, running: &AtomicBool) {
+    let mut last: Option<Status> = None;
+    let mut reachable = true;
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        if !running.load(Ordering::Relaxed) {
+            return;
+        }
+        let status = match transport.status() {
+            Ok(status) => status,
+            Err(e) => {
+                // Logged once per outage, a switched off TV is not worth a line a second
+                if reachable {
+                    tracing::warn!(
+                        "DLNA renderer {} is unreachable: {}",
+                        transport.renderer.name,
+                        e
+                    );
+                }
+                reachable = false;
+                continue;
+            }
+        };
+        reachable = true;
+
+        let changed = last.map(|last| changes(&last, &status)).unwrap_or_default();
+        last = Some(status);
+        for event in changed {
+            notify(BackendChange::Session(event));
+        }
+    }
+}
+
+/// # Returns
+/// * `Vec<SessionEvent>` - What differs between two polls
+fn changes(last: &Status, status: &Status) -> Vec<SessionEvent> {
+    // Renderers often learn the duration only after the track started
+    if last.track_uri != status.track_uri
+        || last.track_metadata != status.track_metadata
+        || last.duration_ms != status.duration_ms
+    {
+        return vec![
+            SessionEvent::MediaProperties,
+            SessionEvent::PlaybackInfo,
+            SessionEvent::TimelineProperties,
+        ];
+    }
+    let mut changed = Vec::new();
+    if last.transport_state != status.transport_state || last.play_mode != status.play_mode {
+        changed.push(SessionEvent::PlaybackInfo);
+    }
+    if last.position_ms != status.position_ms {
+        changed.push(SessionEvent::TimelineProperties);
+    }
+    changed
+}
+
+/// Send an SSDP search for AVTransport services
+///
+/// # Returns
+/// * `MediaResult<Vec<String>>` - URLs of the device descriptions that answered
+fn search() -> MediaResult<Vec<String>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(MediaError::internal)?;
+    socket
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .map_err(MediaError::internal)?;
+    let request = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {SSDP_ADDRESS}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {AV_TRANSPORT}\r\n\r\n"
+    );
+    socket
+        .send_to(request.as_bytes(), SSDP_ADDRESS)
+        .map_err(MediaError::internal)?;
+
+    let started = Instant::now();
+    let mut locations = Vec::new();
+    let mut buffer = [0; 2048];
+    while started.elapsed() < DISCOVERY_TIMEOUT {
+        // Timeouts only give the loop a chance to check the deadline
+        let Ok(size) = socket.recv(&mut buffer) else {
+            continue;
+        };
+        let response = String::from_utf8_lossy(&buffer[..size]);
+        let location = response.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("location")
+                .then(|| value.trim().to_string())
+        });
+        match location {
+            Some(location) if !locations.contains(&location) => locations.push(location),
+            _ => {}
+        }
+    }
+    Ok(locations)
+}
+
+/// Read a device description
+///
+/// # Returns
+/// * `MediaResult<Option<Renderer>>` - `None` when no device in it has an AVTransport service
+fn describe(client: &Client, location: &str) -> MediaResult<Option<Renderer>> {
+    let text = client
+        .get(location)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .map_err(MediaError::internal)?;
+    let document = Document::parse(&text).map_err(MediaError::internal)?;
+
+    let Some(service) = document.descendants().find(|node| {
+        node.has_tag_name("service")
+            && child_text(*node, "serviceType")
+                .is_some_and(|kind| kind.starts_with("urn:schemas-upnp-org:service:AVTransport:"))
+    }) else {
+        return Ok(None);
+    };
+    let device = service
+        .ancestors()
+        .find(|node| node.has_tag_name("device"))
+        .ok_or_else(|| MediaError::internal("AVTransport service outside of a device"))?;
+
+    // Relative URLs resolve against URLBase if the description has one
+    let base = document
+        .descendants()
+        .find(|node| node.has_tag_name("URLBase"))
+        .and_then(|node| node.text())
+        .unwrap_or(location);
+    let control_url = Url::parse(base.trim())
+        .and_then(|base| base.join(child_text(service, "controlURL").unwrap_or_default()))
+        .map_err(MediaError::internal)?;
+
+    Ok(Some(Renderer {
+        name: child_text(device, "friendlyName")
+            .unwrap_or("DLNA Renderer")
+            .to_string(),
+        udn: child_text(device, "UDN").unwrap_or(location).to_string(),
+        control_url,
+    }))
+}
+
+/// Take an output argument of a SOAP answer, empty when the renderer left it out
+fn output(outputs: &mut HashMap<String, String>, name: &str) -> String {
+    outputs.remove(name).unwrap_or_default()
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+/// Parse the DIDL-Lite document describing the current track
+fn parse_metadata(didl: &str) -> Option<TrackMetadata> {
+    let document = Document::parse(didl).ok()?;
+    let item = document
+        .descendants()
+        .find(|node| node.has_tag_name("item"))?;
+    let text = |name: &str| child_text(item, name).map(str::to_string);
+    Some(TrackMetadata {
+        title: text("title"),
+        artist: text("artist").or_else(|| text("creator")),
+        album: text("album"),
+        album_art: text("albumArtURI"),
+        track_number: text("originalTrackNumber").and_then(|number| number.parse().ok()),
+        video: text("class").is_some_and(|class| class.starts_with("object.item.videoItem")),
+        episode: text("episodeNumber")
+            .and_then(|number| number.parse().ok())
+            .map(|episode| Episode {
+                season: text("episodeSeason").and_then(|season| season.parse().ok()),
+                episode,
+            }),
+    })
+}
+
+fn is_playing(transport_state: &str) -> bool {
+    matches!(transport_state, "PLAYING" | "TRANSITIONING")
+}
+
+/// Parse a UPnP time like `1:02:03` or `0:00:05.250` into milliseconds
+fn parse_time(time: &str) -> Option<u64> {
+    let mut parts = time.trim().splitn(3, ':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some((hours * 3600 + minutes * 60) * 1000 + (seconds * 1000.0) as u64)
+}
+
+/// Format milliseconds as the `H:MM:SS` a Seek target takes
+fn format_time(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
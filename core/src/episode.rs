@@ -0,0 +1,58 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Place of a video in a series
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct Episode {
+    /// `None` when only the episode number is known
+    pub season: Option<u32>,
+    pub episode: u32,
+}
+
+/// Find the season and episode numbers in a video title
+///
+/// Media sessions have no fields for them, streaming sites put them in the title
+/// or subtitle as `S2:E5`, `S02E05`, `Season 2 Episode 5` or `Episode 5`.
+///
+/// # Arguments
+/// * `text` - Title or subtitle of the video
+pub fn parse_episode(text: &str) -> Option<Episode> {
+    let text = text.to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let mut season = None;
+    for (index, word) in words.iter().enumerate() {
+        if let Some((season, episode)) = compact_episode(word) {
+            return Some(Episode {
+                season: Some(season),
+                episode,
+            });
+        }
+        let next = words.get(index + 1).and_then(|next| next.parse().ok());
+        match (*word, next) {
+            ("season", Some(number)) => season = Some(number),
+            ("episode" | "ep", Some(episode)) => return Some(Episode { season, episode }),
+            _ => {}
+        }
+        if let Some(number) = numbered(word, 's') {
+            season = Some(number);
+        } else if let Some(episode) = numbered(word, 'e').filter(|_| season.is_some()) {
+            return Some(Episode { season, episode });
+        }
+    }
+    None
+}
+
+/// Both numbers in one word, like `s02e05`
+fn compact_episode(word: &str) -> Option<(u32, u32)> {
+    let (season, episode) = word.strip_prefix('s')?.split_once('e')?;
+    Some((season.parse().ok()?, episode.parse().ok()?))
+}
+
+/// The number of a word like `s2` or `e5`
+fn numbered(word: &str, prefix: char) -> Option<u32> {
+    word.strip_prefix(prefix)?.parse().ok()
+}
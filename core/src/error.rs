@@ -0,0 +1,72 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+
+pub type MediaResult<T> = Result<T, MediaError>;
+
+/// Machine-readable failure reasons for media operations
+#[derive(Debug, Clone, Serialize, thiserror::Error, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MediaError {
+    #[error("No active media session")]
+    NoSession,
+
+    #[error("{operation} is not supported by the current session")]
+    Unsupported { operation: String },
+
+    #[error("The app rejected the {operation} request")]
+    Rejected { operation: String },
+
+    #[error("{operation} timed out")]
+    Timeout { operation: String },
+
+    #[error("Invalid input: {detail}")]
+    InvalidInput { detail: String },
+
+    #[error("WinRT error {code:#010x}: {detail}")]
+    WinRt { code: i32, detail: String },
+
+    #[error("Internal error: {detail}")]
+    Internal { detail: String },
+}
+
+impl MediaError {
+    pub fn unsupported(operation: impl Into<String>) -> Self {
+        Self::Unsupported {
+            operation: operation.into(),
+        }
+    }
+
+    pub fn rejected(operation: impl Into<String>) -> Self {
+        Self::Rejected {
+            operation: operation.into(),
+        }
+    }
+
+    pub fn timeout(operation: impl Into<String>) -> Self {
+        Self::Timeout {
+            operation: operation.into(),
+        }
+    }
+
+    pub fn invalid_input(detail: impl Into<String>) -> Self {
+        Self::InvalidInput {
+            detail: detail.into(),
+        }
+    }
+
+    pub fn internal(detail: impl ToString) -> Self {
+        Self::Internal {
+            detail: detail.to_string(),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl From<windows::core::Error> for MediaError {
+    fn from(error: windows::core::Error) -> Self {
+        Self::WinRt {
+            code: error.code().0,
+            detail: error.message(),
+        }
+    }
+}
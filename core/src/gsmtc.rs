@@ -0,0 +1,949 @@
+//! The sessions of Windows apps, read and controlled through the Global System
+//! Media Transport Controls
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::ads;
+use crate::backend::{BackendChange, ChangeNotifier, MediaBackend, SessionSnapshot};
+use crate::controller::Command;
+use crate::episode;
+use crate::error::{MediaError, MediaResult};
+use crate::media_manager::{
+    AutoRepeatMode, PlaybackType, SessionInfo, SessionState, SessionStatus, Timeline,
+    TrackControls, TrackInfo, TrackTimeline, track_id,
+};
+use crate::profile::{self, AppProfile};
+use crate::session_watcher::{SessionCallback, SessionEvent, SessionWatcher, WatchedSession};
+use crate::utils;
+use crate::winrt::{self, Wait};
+use windows::{
+    ApplicationModel::AppInfo,
+    Foundation::{TimeSpan, TypedEventHandler},
+    Media::{
+        Control::{
+            GlobalSystemMediaTransportControlsSession,
+            GlobalSystemMediaTransportControlsSessionManager,
+            GlobalSystemMediaTransportControlsSessionPlaybackStatus,
+        },
+        MediaPlaybackAutoRepeatMode, MediaPlaybackType,
+    },
+    Storage::Streams::{Buffer, DataReader, IRandomAccessStreamReference, InputStreamOptions},
+    core::HSTRING,
+};
+
+use GlobalSystemMediaTransportControlsSession as Session;
+use GlobalSystemMediaTransportControlsSessionManager as SessionManager;
+
+impl From<MediaPlaybackType> for PlaybackType {
+    fn from(playback_type: MediaPlaybackType) -> Self {
+        match playback_type {
+            MediaPlaybackType::Music => PlaybackType::Music,
+            MediaPlaybackType::Video => PlaybackType::Video,
+            MediaPlaybackType::Image => PlaybackType::Image,
+            _ => PlaybackType::Unknown,
+        }
+    }
+}
+
+impl From<MediaPlaybackAutoRepeatMode> for AutoRepeatMode {
+    fn from(mode: MediaPlaybackAutoRepeatMode) -> Self {
+        match mode {
+            MediaPlaybackAutoRepeatMode::Track => AutoRepeatMode::Track,
+            MediaPlaybackAutoRepeatMode::List => AutoRepeatMode::List,
+            _ => AutoRepeatMode::None, // Default case
+        }
+    }
+}
+
+impl Into<MediaPlaybackAutoRepeatMode> for AutoRepeatMode {
+    fn into(self) -> MediaPlaybackAutoRepeatMode {
+        match self {
+            AutoRepeatMode::Track => MediaPlaybackAutoRepeatMode::Track,
+            AutoRepeatMode::List => MediaPlaybackAutoRepeatMode::List,
+            AutoRepeatMode::None => MediaPlaybackAutoRepeatMode::None,
+        }
+    }
+}
+
+/// Bytes requested per read of a stream reference
+const STREAM_CHUNK: u32 = 256 * 1024;
+/// Streams are read until they end, this only guards against one that never does
+const MAX_STREAM_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Read the contents of a WinRT stream reference (artwork, app logos) into memory,
+/// however large they are, e.g. animated artwork
+///
+/// # Arguments
+/// * `timeout` - Longest wait for each read
+pub fn read_stream_reference(
+    reference: &IRandomAccessStreamReference,
+    timeout: Duration,
+) -> MediaResult<Vec<u8>> {
+    let stream = reference.OpenReadAsync()?.wait("OpenReadAsync", timeout)?;
+    let mut bytes = Vec::new();
+    loop {
+        let buf = Buffer::Create(STREAM_CHUNK)?;
+        let chunk = stream
+            .ReadAsync(&buf, STREAM_CHUNK, InputStreamOptions::ReadAhead)?
+            .wait("ReadAsync", timeout)?;
+        let length = chunk.Length()? as usize;
+        if length == 0 {
+            return Ok(bytes);
+        }
+
+        let start = bytes.len();
+        if start + length > MAX_STREAM_LENGTH {
+            return Err(MediaError::internal(format!(
+                "Stream is larger than {} bytes",
+                MAX_STREAM_LENGTH
+            )));
+        }
+        bytes.resize(start + length, 0);
+        DataReader::FromBuffer(&chunk)?.ReadBytes(&mut bytes[start..])?;
+    }
+}
+
+/// 100ns intervals between 1601 and 1970, the epochs of DateTime and Unix time
+const WINDOWS_TO_UNIX_EPOCH: i64 = 116_444_736_000_000_000;
+
+/// A TimeSpan in milliseconds, negative spans count as 0
+fn timespan_ms(span: TimeSpan) -> u64 {
+    // TimeSpans count 100ns intervals
+    (span.Duration / 10_000).max(0) as u64
+}
+
+/// A string property, `None` when missing or empty
+fn non_empty(value: windows::core::Result<HSTRING>) -> Option<String> {
+    value.ok().filter(|s| !s.is_empty()).map(|s| s.to_string())
+}
+
+/// What an app reported about its track when its track info was last read
+#[derive(Clone)]
+struct ReportedTrack {
+    title: String,
+    artist: String,
+    /// End time of the timeline in milliseconds
+    duration: u64,
+    track_id: String,
+}
+
+/// Backend for the media sessions of Windows apps
+pub struct MediaManager {
+    manager: SessionManager,
+    /// Raw artwork of the current track, refreshed whenever track info is read
+    artwork: Mutex<Option<Vec<u8>>>,
+    /// Last reported track of each session by lowercase source app id, so controls
+    /// and timelines are tagged without reading the properties again
+    reported: Mutex<HashMap<String, ReportedTrack>>,
+    /// Handlers of the current session, moved along whenever it changes
+    watcher: SessionWatcher<Session>,
+    session_changed: Option<SessionChangedSubscription>,
+    /// Set while watching
+    notify: Option<ChangeNotifier>,
+    /// Profiles by lowercase source app id
+    profiles: HashMap<String, AppProfile>,
+    /// Longest wait for an async call into an app's session
+    timeout: Duration,
+}
+
+impl MediaManager {
+    pub fn new() -> MediaResult<Self> {
+        let manager =
+            SessionManager::RequestAsync()?.wait("RequestAsync", winrt::DEFAULT_TIMEOUT)?;
+        Ok(Self {
+            manager,
+            timeout: winrt::DEFAULT_TIMEOUT,
+            artwork: Mutex::new(None),
+            reported: Mutex::new(HashMap::new()),
+            watcher: SessionWatcher::default(),
+            session_changed: None,
+            notify: None,
+            profiles: HashMap::new(),
+        })
+    }
+
+    /// Correct how the sessions of some apps are read and controlled
+    ///
+    /// # Arguments
+    /// * `profiles` - Profiles by source app id, compared case-insensitively
+    pub fn with_profiles(mut self, profiles: HashMap<String, AppProfile>) -> Self {
+        self.profiles = profiles
+            .into_iter()
+            .map(|(app_id, profile)| (app_id.to_lowercase(), profile))
+            .collect();
+        self
+    }
+
+    /// Give up on async calls into a session after a timeout instead of the default
+    ///
+    /// # Arguments
+    /// * `timeout` - Longest wait for each call, buggy apps may never answer
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn get_current_session(&self) -> MediaResult<Session> {
+        // GetCurrentSession returns null when nothing is playing
+        let current = self
+            .manager
+            .GetCurrentSession()
+            .map_err(|_| MediaError::NoSession)?;
+        if !self.is_excluded(&current) {
+            return Ok(current);
+        }
+
+        // The system picked an excluded app, fall back to another one, playing ones first
+        let sessions: Vec<Session> = self
+            .manager
+            .GetSessions()?
+            .into_iter()
+            .filter(|session| !self.is_excluded(session))
+            .collect();
+        let playing = sessions.iter().find(|session| {
+            session
+                .GetPlaybackInfo()
+                .and_then(|info| info.PlaybackStatus())
+                .is_ok_and(|status| {
+                    status == GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing
+                })
+        });
+        playing
+            .or(sessions.first())
+            .cloned()
+            .ok_or(MediaError::NoSession)
+    }
+
+    /// The session of an app, whether or not it is the current one
+    ///
+    /// # Arguments
+    /// * `app_id` - Source app id, compared case-insensitively
+    pub fn find_session(&self, app_id: &str) -> MediaResult<Session> {
+        self.manager
+            .GetSessions()?
+            .into_iter()
+            .find(|session| {
+                session
+                    .SourceAppUserModelId()
+                    .is_ok_and(|id| id.to_string().eq_ignore_ascii_case(app_id))
+            })
+            .ok_or(MediaError::NoSession)
+    }
+
+    fn profile(&self, session: &Session) -> Option<&AppProfile> {
+        let app_id = session
+            .SourceAppUserModelId()
+            .ok()?
+            .to_string()
+            .to_lowercase();
+        self.profiles.get(&app_id)
+    }
+
+    fn is_excluded(&self, session: &Session) -> bool {
+        self.profile(session).is_some_and(|profile| profile.exclude)
+    }
+
+    pub fn session_state(&self) -> SessionState {
+        match self.get_current_session() {
+            Ok(session) => SessionState {
+                state: SessionStatus::Active,
+                session: self.session_info(&session).ok(),
+            },
+            Err(_) => SessionState {
+                state: SessionStatus::None,
+                session: None,
+            },
+        }
+    }
+
+    /// Every session known to the system, the current one is not necessarily first
+    pub fn sessions(&self) -> MediaResult<Vec<SessionInfo>> {
+        let sessions = self.manager.GetSessions()?;
+        Ok(sessions
+            .into_iter()
+            .filter_map(|session| self.session_info(&session).ok())
+            .collect())
+    }
+
+    pub fn current_app_id(&self) -> MediaResult<String> {
+        let session = self.get_current_session()?;
+        Ok(session.SourceAppUserModelId()?.to_string())
+    }
+
+    pub fn session_info(&self, session: &Session) -> MediaResult<SessionInfo> {
+        let source_app_id = session.SourceAppUserModelId()?.to_string();
+
+        // Packaged apps have a display name, desktop apps only report their executable
+        let app_name = AppInfo::GetFromAppUserModelId(&HSTRING::from(&source_app_id))
+            .and_then(|info| info.DisplayInfo())
+            .and_then(|display| display.DisplayName())
+            .map(|name| name.to_string())
+            .unwrap_or_else(|_| utils::app_name_from_id(&source_app_id));
+
+        Ok(SessionInfo {
+            session_id: utils::session_id_from_app_id(&source_app_id),
+            source_app_id,
+            app_name,
+        })
+    }
+
+    pub fn is_playing(&self) -> MediaResult<bool> {
+        let session = self.get_current_session()?;
+        let status = session.GetPlaybackInfo()?.PlaybackStatus()?;
+        Ok(status == GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing)
+    }
+
+    pub fn toggle_play(&self, session: Option<&Session>) -> MediaResult<bool> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        let res = session
+            .TryTogglePlayPauseAsync()?
+            .wait("TryTogglePlayPauseAsync", self.timeout)?;
+        Ok(res)
+    }
+
+    pub fn play(&self, session: Option<&Session>) -> MediaResult<bool> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        let res = session.TryPlayAsync()?.wait("TryPlayAsync", self.timeout)?;
+        Ok(res)
+    }
+
+    pub fn pause(&self, session: Option<&Session>) -> MediaResult<bool> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        let res = session
+            .TryPauseAsync()?
+            .wait("TryPauseAsync", self.timeout)?;
+        Ok(res)
+    }
+
+    pub fn stop(&self, session: Option<&Session>) -> MediaResult<bool> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        let res = session.TryStopAsync()?.wait("TryStopAsync", self.timeout)?;
+        Ok(res)
+    }
+
+    pub fn fast_forward(&self, session: Option<&Session>) -> MediaResult<bool> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        let res = session
+            .TryFastForwardAsync()?
+            .wait("TryFastForwardAsync", self.timeout)?;
+        Ok(res)
+    }
+
+    pub fn rewind(&self, session: Option<&Session>) -> MediaResult<bool> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        let res = session
+            .TryRewindAsync()?
+            .wait("TryRewindAsync", self.timeout)?;
+        Ok(res)
+    }
+
+    pub fn next_track(&self, session: Option<&Session>) -> MediaResult<bool> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        let res = session
+            .TrySkipNextAsync()?
+            .wait("TrySkipNextAsync", self.timeout)?;
+        Ok(res)
+    }
+
+    pub fn previous_track(&self, session: Option<&Session>) -> MediaResult<bool> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        let res = session
+            .TrySkipPreviousAsync()?
+            .wait("TrySkipPreviousAsync", self.timeout)?;
+        Ok(res)
+    }
+
+    pub fn seek_to(&self, session: Option<&Session>, position_ms: u64) -> MediaResult<bool> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        if !session
+            .GetPlaybackInfo()?
+            .Controls()?
+            .IsPlaybackPositionEnabled()?
+        {
+            return Err(MediaError::unsupported("Seeking"));
+        }
+        // Convert milliseconds to 100-nanosecond units
+        let position_ns = position_ms as i64 * 10000;
+        let res = session
+            .TryChangePlaybackPositionAsync(position_ns)?
+            .wait("TryChangePlaybackPositionAsync", self.timeout)?;
+        Ok(res)
+    }
+
+    pub fn get_shuffle_state(&self, session: Option<&Session>) -> MediaResult<bool> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        let playback_info = session.GetPlaybackInfo()?;
+        let shuffle_state = playback_info.IsShuffleActive()?.Value()?;
+        Ok(shuffle_state)
+    }
+
+    pub fn toggle_shuffle(&self, session: Option<&Session>) -> MediaResult<()> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        if !session.GetPlaybackInfo()?.Controls()?.IsShuffleEnabled()? {
+            return Err(MediaError::unsupported("Shuffle"));
+        }
+        let shuffle_state = self.get_shuffle_state(Some(session))?;
+        session.TryChangeShuffleActiveAsync(!shuffle_state)?;
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn get_auto_repeat_mode(&self, session: Option<&Session>) -> MediaResult<AutoRepeatMode> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        let playback_info = session.GetPlaybackInfo()?;
+        let repeat_state = playback_info.AutoRepeatMode()?.Value()?;
+        let state = AutoRepeatMode::from(repeat_state);
+        Ok(state)
+    }
+
+    pub fn set_auto_repeat_mode(
+        &self,
+        session: Option<&Session>,
+        repeat_state: AutoRepeatMode,
+    ) -> MediaResult<()> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        if !session.GetPlaybackInfo()?.Controls()?.IsRepeatEnabled()? {
+            return Err(MediaError::unsupported("Repeat mode"));
+        }
+        let state: MediaPlaybackAutoRepeatMode = repeat_state.into();
+        session
+            .TryChangeAutoRepeatModeAsync(state)?
+            .wait("TryChangeAutoRepeatModeAsync", self.timeout)?;
+        Ok(())
+    }
+
+    fn thumbnail(&self, session: Option<&Session>) -> MediaResult<Vec<u8>> {
+        let session = match session {
+            Some(s) => s,
+            None => &self.get_current_session()?,
+        };
+        let properties = session
+            .TryGetMediaPropertiesAsync()?
+            .wait("TryGetMediaPropertiesAsync", self.timeout)?;
+
+        // Process thumbnail
+        let thumbnail = properties.Thumbnail()?;
+        read_stream_reference(&thumbnail, self.timeout)
+    }
+
+    /// Raw artwork of the current track, from the cache when available
+    pub fn artwork(&self) -> MediaResult<Vec<u8>> {
+        let mut cache = self.artwork.lock().map_err(MediaError::internal)?;
+        if let Some(bytes) = cache.as_ref() {
+            return Ok(bytes.clone());
+        }
+
+        let bytes = self.thumbnail(None)?;
+        *cache = Some(bytes.clone());
+        Ok(bytes)
+    }
+
+    pub fn track_info(&self) -> MediaResult<TrackInfo> {
+        let session = self.get_current_session()?;
+        let thumbnail_result = self.thumbnail(Some(&session));
+        let track = self.session_track_info(&session, thumbnail_result.as_deref().ok())?;
+        if let Ok(mut cache) = self.artwork.lock() {
+            *cache = thumbnail_result.ok();
+        }
+        Ok(track)
+    }
+
+    /// Track info of any session, the artwork cache only holds the current one's
+    fn session_track_info(
+        &self,
+        session: &Session,
+        thumbnail: Option<&[u8]>,
+    ) -> MediaResult<TrackInfo> {
+        let properties = session
+            .TryGetMediaPropertiesAsync()?
+            .wait("TryGetMediaPropertiesAsync", self.timeout)?;
+
+        // Get track metadata
+        let title = properties.Title()?.to_string();
+        let album = properties.AlbumTitle().ok().map(|s| s.to_string());
+        let artist = properties.Artist()?.to_string();
+        let album_artist = non_empty(properties.AlbumArtist());
+        let subtitle = non_empty(properties.Subtitle());
+
+        // Apps that don't report numbers leave them at 0
+        let track_number = properties
+            .TrackNumber()
+            .ok()
+            .filter(|n| *n > 0)
+            .map(|n| n as u32);
+        let album_track_count = properties
+            .AlbumTrackCount()
+            .ok()
+            .filter(|n| *n > 0)
+            .map(|n| n as u32);
+
+        let genres = properties
+            .Genres()
+            .map(|genres| genres.into_iter().map(|g| g.to_string()).collect())
+            .unwrap_or_default();
+        let playback_type = properties
+            .PlaybackType()
+            .and_then(|t| t.Value())
+            .map(PlaybackType::from)
+            .unwrap_or(PlaybackType::Unknown);
+
+        let episode = match playback_type {
+            PlaybackType::Video => episode::parse_episode(&title)
+                .or_else(|| subtitle.as_deref().and_then(episode::parse_episode)),
+            _ => None,
+        };
+
+        let duration = timespan_ms(session.GetTimelineProperties()?.EndTime()?);
+
+        let session_info = self.session_info(session)?;
+        // From what the app reports, so timelines can tell the track without normalizing
+        let track_id = track_id(&session_info.source_app_id, &title, &artist, duration);
+        self.remember_track(
+            &session_info.source_app_id,
+            ReportedTrack {
+                title: title.clone(),
+                artist: artist.clone(),
+                duration,
+                track_id: track_id.clone(),
+            },
+        );
+        let mut track = TrackInfo {
+            session: session_info,
+            track_id,
+            previous_track_id: None,
+            title,
+            artist,
+            thumbnail: None,
+            thumbnails: None,
+            album,
+            album_artist,
+            subtitle,
+            track_number,
+            album_track_count,
+            genres,
+            playback_type,
+            episode,
+            palette: None,
+            metadata: None,
+            duration,
+            is_advertisement: false,
+            stale: false,
+        };
+        let profile = self.profile(session);
+        if let Some(profile) = profile {
+            profile.normalize.apply(&mut track);
+        }
+        track.is_advertisement = ads::is_advertisement(&track);
+
+        if let Some(bytes) = thumbnail {
+            track.set_artwork(bytes);
+        }
+        let thumbnail_size = profile.and_then(|profile| profile.thumbnail_size);
+        if let (Some(bytes), Some(size)) = (thumbnail, thumbnail_size) {
+            match profile::fit_artwork(bytes, size) {
+                Ok(resized) => track.thumbnail = Some(utils::encode_image_to_base64(&resized)),
+                Err(e) => tracing::warn!("Failed to resize thumbnail: {}", e),
+            }
+        }
+        Ok(track)
+    }
+
+    pub fn track_controls(&self) -> MediaResult<TrackControls> {
+        self.session_track_controls(&self.get_current_session()?)
+    }
+
+    fn session_track_controls(&self, session: &Session) -> MediaResult<TrackControls> {
+        let session_info = self.session_info(session)?;
+        let track_id = self.session_track_id(session, &session_info.source_app_id)?;
+        let playback_info = session.GetPlaybackInfo()?;
+
+        let controls = playback_info.Controls()?;
+
+        let shuffle_enabled = controls.IsShuffleEnabled()?;
+        let auto_repeat_mode_enabled = controls.IsRepeatEnabled()?;
+        let next_enabled = controls.IsNextEnabled()?;
+        let prev_enabled = controls.IsPreviousEnabled()?;
+        let play_pause_enabled = controls.IsPlayPauseToggleEnabled()?;
+        let play_enabled = controls.IsPlayEnabled()?;
+        let pause_enabled = controls.IsPauseEnabled()?;
+        let stop_enabled = controls.IsStopEnabled()?;
+        let fast_forward_enabled = controls.IsFastForwardEnabled()?;
+        let rewind_enabled = controls.IsRewindEnabled()?;
+
+        let playing = match playback_info.PlaybackStatus()? {
+            GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing => true,
+            _ => false,
+        };
+
+        let shuffle = match shuffle_enabled {
+            true => self.get_shuffle_state(Some(session))?,
+            false => false,
+        };
+
+        let auto_repeat_mode = match auto_repeat_mode_enabled {
+            true => self.get_auto_repeat_mode(Some(session))?,
+            false => AutoRepeatMode::None,
+        };
+
+        let mut track_controls = TrackControls {
+            session: session_info,
+            track_id,
+            shuffle_enabled,
+            auto_repeat_mode_enabled,
+            next_enabled,
+            prev_enabled,
+            play_pause_enabled,
+            play_enabled,
+            pause_enabled,
+            stop_enabled,
+            fast_forward_enabled,
+            rewind_enabled,
+
+            shuffle,
+            auto_repeat_mode,
+            playing,
+            stale: false,
+        };
+        if let Some(profile) = self.profile(session) {
+            profile.capabilities.apply_to_controls(&mut track_controls);
+        }
+        Ok(track_controls)
+    }
+
+    pub fn track_timeline(&self) -> MediaResult<TrackTimeline> {
+        self.session_track_timeline(&self.get_current_session()?)
+    }
+
+    fn session_track_timeline(&self, session: &Session) -> MediaResult<TrackTimeline> {
+        let session_info = self.session_info(session)?;
+        let timeline = session.GetTimelineProperties()?;
+        let end_time = timespan_ms(timeline.EndTime()?);
+        let track_id = self.timeline_track_id(session, &session_info.source_app_id, end_time)?;
+        let playback = session.GetPlaybackInfo()?;
+        // A DateTime counts 100ns from 1601, zero when the app never set it
+        let last_updated = timeline.LastUpdatedTime()?.UniversalTime;
+
+        let mut track_timeline = TrackTimeline {
+            session: session_info,
+            progress: timespan_ms(timeline.Position()?),
+            start_time: timespan_ms(timeline.StartTime()?),
+            end_time,
+            min_seek_time: timespan_ms(timeline.MinSeekTime()?),
+            max_seek_time: timespan_ms(timeline.MaxSeekTime()?),
+            last_updated: (last_updated > WINDOWS_TO_UNIX_EPOCH)
+                .then(|| ((last_updated - WINDOWS_TO_UNIX_EPOCH) / 10_000) as u64),
+            playback_rate: playback
+                .PlaybackRate()
+                .and_then(|rate| rate.Value())
+                .unwrap_or(1.0),
+            seek_enabled: playback.Controls()?.IsPlaybackPositionEnabled()?,
+            timeline: Timeline::default(),
+        };
+        if let Some(profile) = self.profile(session) {
+            profile.capabilities.apply_to_timeline(&mut track_timeline);
+        }
+        Ok(track_timeline.unify(track_id))
+    }
+
+    /// `track_id` of a session's current track as of its last track info read, which
+    /// every change of the app's media properties causes
+    fn session_track_id(&self, session: &Session, source_app_id: &str) -> MediaResult<String> {
+        match self.reported_track(source_app_id) {
+            Some(reported) => Ok(reported.track_id),
+            None => Ok(self.read_reported_track(session, source_app_id)?.track_id),
+        }
+    }
+
+    /// `track_id` of the track a timeline belongs to, from the end time of that same
+    /// timeline with the title and artist last reported.
+    ///
+    /// Another end time means the track changed since, its properties are read again.
+    /// When they changed once more in between, the id matches no track and clients
+    /// ignore the timeline like any outdated one.
+    ///
+    /// # Arguments
+    /// * `duration` - End time of the timeline in milliseconds
+    fn timeline_track_id(
+        &self,
+        session: &Session,
+        source_app_id: &str,
+        duration: u64,
+    ) -> MediaResult<String> {
+        let reported = match self.reported_track(source_app_id) {
+            Some(reported) if reported.duration == duration => reported,
+            _ => self.read_reported_track(session, source_app_id)?,
+        };
+        Ok(track_id(
+            source_app_id,
+            &reported.title,
+            &reported.artist,
+            duration,
+        ))
+    }
+
+    /// The track a session's app last reported, `None` before its track info was read
+    fn reported_track(&self, source_app_id: &str) -> Option<ReportedTrack> {
+        self.reported
+            .lock()
+            .ok()?
+            .get(&source_app_id.to_lowercase())
+            .cloned()
+    }
+
+    fn remember_track(&self, source_app_id: &str, reported: ReportedTrack) {
+        if let Ok(mut tracks) = self.reported.lock() {
+            tracks.insert(source_app_id.to_lowercase(), reported);
+        }
+    }
+
+    /// Read just the properties identifying a session's track, without the rest of
+    /// the track info
+    fn read_reported_track(
+        &self,
+        session: &Session,
+        source_app_id: &str,
+    ) -> MediaResult<ReportedTrack> {
+        let properties = session
+            .TryGetMediaPropertiesAsync()?
+            .wait("TryGetMediaPropertiesAsync", self.timeout)?;
+        let title = properties.Title()?.to_string();
+        let artist = properties.Artist()?.to_string();
+        let duration = timespan_ms(session.GetTimelineProperties()?.EndTime()?);
+        let reported = ReportedTrack {
+            track_id: track_id(source_app_id, &title, &artist, duration),
+            title,
+            artist,
+            duration,
+        };
+        self.remember_track(source_app_id, reported.clone());
+        Ok(reported)
+    }
+
+    /// Run a command against a session, unless its app's profile turned it off
+    fn execute_on(&self, session: &Session, command: &Command) -> MediaResult<bool> {
+        if self
+            .profile(session)
+            .is_some_and(|profile| profile.capabilities.forbids(command))
+        {
+            return Err(MediaError::unsupported(command.name()));
+        }
+        let session = Some(session);
+        match command {
+            Command::TogglePlayPause => self.toggle_play(session),
+            Command::Play => self.play(session),
+            Command::Pause => self.pause(session),
+            Command::Stop => self.stop(session),
+            Command::FastForward => self.fast_forward(session),
+            Command::Rewind => self.rewind(session),
+            Command::NextTrack => self.next_track(session),
+            Command::PreviousTrack => self.previous_track(session),
+            Command::Seek { ms } => self.seek_to(session, *ms),
+            Command::SetRepeat { mode } => self
+                .set_auto_repeat_mode(session, mode.clone())
+                .map(|_| true),
+            Command::ToggleShuffle => self.toggle_shuffle(session).map(|_| true),
+        }
+    }
+
+    /// Handle changes of the current session
+    ///
+    /// # Returns
+    /// * `MediaResult<SessionChangedSubscription>` - Removes the handler when dropped
+    pub fn session_changed<F>(&self, mut callback: F) -> MediaResult<SessionChangedSubscription>
+    where
+        F: FnMut() -> () + Send + 'static,
+    {
+        let manager = &self.manager;
+
+        let handler = TypedEventHandler::new(move |_, _| {
+            callback();
+            windows::core::Result::Ok(())
+        });
+
+        let token = manager.CurrentSessionChanged(&handler)?;
+        Ok(SessionChangedSubscription {
+            manager: manager.clone(),
+            token,
+        })
+    }
+}
+
+impl MediaBackend for MediaManager {
+    fn session_state(&self) -> SessionState {
+        self.session_state()
+    }
+
+    fn sessions(&self) -> MediaResult<Vec<SessionInfo>> {
+        self.sessions()
+    }
+
+    fn current_app_id(&self) -> MediaResult<String> {
+        self.current_app_id()
+    }
+
+    fn is_playing(&self) -> MediaResult<bool> {
+        self.is_playing()
+    }
+
+    fn track_info(&self) -> MediaResult<TrackInfo> {
+        self.track_info()
+    }
+
+    fn track_info_without_artwork(&self) -> MediaResult<TrackInfo> {
+        self.session_track_info(&self.get_current_session()?, None)
+    }
+
+    fn track_controls(&self) -> MediaResult<TrackControls> {
+        self.track_controls()
+    }
+
+    fn track_timeline(&self) -> MediaResult<TrackTimeline> {
+        self.track_timeline()
+    }
+
+    fn artwork(&self) -> MediaResult<Vec<u8>> {
+        self.artwork()
+    }
+
+    fn execute(&self, command: &Command) -> MediaResult<bool> {
+        self.execute_on(&self.get_current_session()?, command)
+    }
+
+    fn session_snapshot(&self, app_id: &str, artwork: bool) -> MediaResult<SessionSnapshot> {
+        let session = self.find_session(app_id)?;
+        let thumbnail = match artwork {
+            true => self.thumbnail(Some(&session)).ok(),
+            false => None,
+        };
+        Ok(SessionSnapshot {
+            track_info: self.session_track_info(&session, thumbnail.as_deref())?,
+            track_controls: self.session_track_controls(&session).ok(),
+            track_timeline: self.session_track_timeline(&session).ok(),
+        })
+    }
+
+    fn execute_for(&self, app_id: &str, command: &Command) -> MediaResult<bool> {
+        self.execute_on(&self.find_session(app_id)?, command)
+    }
+
+    fn watch(&mut self, notify: ChangeNotifier) -> MediaResult<()> {
+        let on_change = notify.clone();
+        self.session_changed =
+            Some(self.session_changed(move || on_change(BackendChange::CurrentSession))?);
+        self.notify = Some(notify);
+        self.follow_session();
+        Ok(())
+    }
+
+    fn follow_session(&mut self) {
+        let Some(notify) = &self.notify else {
+            return;
+        };
+        // Resolved again on every session change, the handlers belong to this session
+        let session = self.get_current_session().ok();
+        self.watcher.watch(session, |event| {
+            let notify = notify.clone();
+            Box::new(move || notify(BackendChange::Session(event)))
+        });
+    }
+
+    fn unwatch(&mut self) {
+        self.watcher.clear();
+        self.session_changed = None;
+        self.notify = None;
+    }
+
+    fn handler_count(&self) -> usize {
+        self.watcher.handler_count() + usize::from(self.session_changed.is_some())
+    }
+}
+
+/// The session change handler, removed from the session manager that issued it when dropped
+pub struct SessionChangedSubscription {
+    manager: SessionManager,
+    token: i64,
+}
+
+impl Drop for SessionChangedSubscription {
+    fn drop(&mut self) {
+        if let Err(e) = self.manager.RemoveCurrentSessionChanged(self.token) {
+            tracing::error!("Failed to remove session change handler: {}", e);
+        }
+    }
+}
+
+impl WatchedSession for Session {
+    fn add_handler(&self, event: SessionEvent, mut callback: SessionCallback) -> MediaResult<i64> {
+        let token = match event {
+            SessionEvent::MediaProperties => {
+                self.MediaPropertiesChanged(&TypedEventHandler::new(move |_, _| {
+                    callback();
+                    windows::core::Result::Ok(())
+                }))?
+            }
+            SessionEvent::PlaybackInfo => {
+                self.PlaybackInfoChanged(&TypedEventHandler::new(move |_, _| {
+                    callback();
+                    windows::core::Result::Ok(())
+                }))?
+            }
+            SessionEvent::TimelineProperties => {
+                self.TimelinePropertiesChanged(&TypedEventHandler::new(move |_, _| {
+                    callback();
+                    windows::core::Result::Ok(())
+                }))?
+            }
+        };
+        Ok(token)
+    }
+
+    fn remove_handler(&self, event: SessionEvent, token: i64) -> MediaResult<()> {
+        match event {
+            SessionEvent::MediaProperties => self.RemoveMediaPropertiesChanged(token)?,
+            SessionEvent::PlaybackInfo => self.RemovePlaybackInfoChanged(token)?,
+            SessionEvent::TimelineProperties => self.RemoveTimelinePropertiesChanged(token)?,
+        }
+        Ok(())
+    }
+}
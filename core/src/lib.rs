@@ -0,0 +1,63 @@
+//! Control the media sessions of Windows apps through the Global System Media
+//! Transport Controls, without the server around it.
+//!
+//! [`MediaController`] owns a [`MediaBackend`] on a thread of its own. Commands go
+//! in through [`MediaController::command`], changes of the current session come out
+//! of [`MediaController::events`] once [`MediaController::watch`] was called:
+//!
+//! ```no_run
+//! # #[cfg(windows)]
+//! use media_controller_core::{Command, ManagerEvent, MediaController, MediaManager};
+//!
+//! # #[cfg(windows)]
+//! # fn main() -> media_controller_core::MediaResult<()> {
+//! let controller = MediaController::spawn(Box::new(MediaManager::new()?));
+//! let mut events = controller.events();
+//! controller.watch()?;
+//! controller.command(Command::Play)?;
+//!
+//! while let Ok(event) = events.blocking_recv() {
+//!     if let ManagerEvent::TrackChanged(track) = event {
+//!         println!("{} - {}", track.artist, track.title);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! # #[cfg(not(windows))]
+//! # fn main() {}
+//! ```
+//!
+//! Reads that have no command, like the artwork or the list of sessions, run on
+//! the same thread through [`MediaController::call`]. [`MockBackend`] stands in for
+//! the Windows sessions in tests and demos, [`DlnaBackend`] controls a UPnP/DLNA
+//! renderer on the network instead. Only the Windows sessions need Windows, the
+//! other backends build anywhere.
+
+pub mod ads;
+pub mod backend;
+pub mod controller;
+pub mod dlna;
+pub mod episode;
+pub mod error;
+#[cfg(windows)]
+mod gsmtc;
+pub mod media_manager;
+pub mod metadata;
+pub mod mock;
+pub mod normalize;
+pub mod palette;
+pub mod profile;
+pub mod recording;
+pub mod session_watcher;
+pub mod utils;
+#[cfg(windows)]
+pub mod winrt;
+
+pub use backend::MediaBackend;
+pub use controller::{Command, ManagerEvent, MediaController};
+pub use dlna::DlnaBackend;
+pub use error::{MediaError, MediaResult};
+#[cfg(windows)]
+pub use media_manager::MediaManager;
+pub use mock::MockBackend;
+pub use recording::ReplayBackend;
@@ -0,0 +1,404 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::episode::Episode;
+use crate::error::MediaError;
+use crate::metadata::TrackMetadata;
+use crate::palette::{self, Palette};
+use crate::utils;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// The WinRT backend is only built on Windows
+#[cfg(windows)]
+pub use crate::gsmtc::{MediaManager, SessionChangedSubscription, read_stream_reference};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TrackProgress {
+    pub position: u64,
+    pub duration: u64,
+}
+
+/// Identifies the media session an event belongs to
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema, ToSchema)]
+pub struct SessionInfo {
+    /// Stable identifier derived from the source app id
+    pub session_id: String,
+    /// App User Model ID of the app owning the session
+    pub source_app_id: String,
+    /// Friendly display name of the app
+    pub app_name: String,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStatus {
+    Active,
+    None,
+}
+
+/// Whether there is a media session to report on at all
+#[derive(Debug, Serialize, Clone, JsonSchema, ToSchema)]
+pub struct SessionState {
+    pub state: SessionStatus,
+    pub session: Option<SessionInfo>,
+}
+
+/// Bounding box of the `small` variant in TrackInfo
+pub const THUMBNAIL_SMALL_SIZE: u32 = 96;
+/// Bounding box of the `medium` variant in TrackInfo
+pub const THUMBNAIL_MEDIUM_SIZE: u32 = 300;
+
+/// Path of a thumbnail variant, relative to the server root
+///
+/// # Arguments
+/// * `size` - Bounding box in pixels, `None` for the original size
+/// * `version` - Artwork hash, changes the URL whenever the artwork does
+pub fn thumbnail_url(size: Option<u32>, version: &str) -> String {
+    match size {
+        Some(size) => format!("/thumbnail?w={size}&h={size}&v={version}"),
+        None => format!("/thumbnail?v={version}"),
+    }
+}
+
+/// URLs of the artwork resized by the `/thumbnail` route
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct ThumbnailVariants {
+    pub small: String,
+    pub medium: String,
+    pub full: String,
+}
+
+impl ThumbnailVariants {
+    /// # Arguments
+    /// * `version` - Hash of the artwork, so clients don't reuse a cached image across tracks
+    fn new(version: &str) -> Self {
+        Self {
+            small: thumbnail_url(Some(THUMBNAIL_SMALL_SIZE), version),
+            medium: thumbnail_url(Some(THUMBNAIL_MEDIUM_SIZE), version),
+            full: thumbnail_url(None, version),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct TrackInfo {
+    #[serde(flatten)]
+    pub session: SessionInfo,
+    /// Tells tracks apart, the `track_id` of the timeline and controls belonging to this track
+    #[serde(default)]
+    pub track_id: String,
+    /// `track_id` of the track before, `None` for the first one. Set by the event hub
+    #[serde(default)]
+    pub previous_track_id: Option<String>,
+    pub title: String,
+    pub artist: String,
+    pub thumbnail: Option<String>,
+    pub thumbnails: Option<ThumbnailVariants>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub subtitle: Option<String>,
+    /// 1-based position on the album, `None` when the app doesn't report it
+    pub track_number: Option<u32>,
+    pub album_track_count: Option<u32>,
+    pub genres: Vec<String>,
+    pub playback_type: PlaybackType,
+    /// Season and episode of a video, `None` for other media or when unknown
+    #[serde(default)]
+    pub episode: Option<Episode>,
+    /// Deprecated, read at another time than the position, use `timeline.duration` of
+    /// the `track_timeline` instead
+    pub duration: u64,
+    pub palette: Option<Palette>,
+    /// Details from online databases, filled in by the event hub when enabled
+    pub metadata: Option<TrackMetadata>,
+    /// Guessed from the title, artist and app, players don't flag ads
+    pub is_advertisement: bool,
+    /// Restored from before a restart, no live session has been seen yet
+    #[serde(default)]
+    pub stale: bool,
+}
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct TrackControls {
+    #[serde(flatten)]
+    pub session: SessionInfo,
+    /// `track_id` of the track the controls were read for
+    #[serde(default)]
+    pub track_id: String,
+    pub(crate) shuffle_enabled: bool,
+    pub(crate) auto_repeat_mode_enabled: bool,
+    pub(crate) next_enabled: bool,
+    pub(crate) prev_enabled: bool,
+    pub(crate) play_pause_enabled: bool,
+    pub(crate) play_enabled: bool,
+    pub(crate) pause_enabled: bool,
+    pub(crate) stop_enabled: bool,
+    pub(crate) fast_forward_enabled: bool,
+    pub(crate) rewind_enabled: bool,
+
+    pub(crate) shuffle: bool,
+    pub(crate) auto_repeat_mode: AutoRepeatMode,
+    pub(crate) playing: bool,
+    /// Restored from before a restart, no live session has been seen yet
+    #[serde(default)]
+    pub stale: bool,
+}
+
+impl TrackInfo {
+    /// Embed the artwork and its palette when it was extracted before, extracting
+    /// it here would hold up the track on large artwork
+    pub(crate) fn set_artwork(&mut self, bytes: &[u8]) {
+        let hash = utils::content_hash(bytes);
+        self.thumbnail = Some(utils::encode_image_to_base64(bytes));
+        self.palette = palette::cached_palette(&hash);
+        self.thumbnails = Some(ThumbnailVariants::new(&hash));
+    }
+
+    /// Show a placeholder instead of missing artwork, without a palette since it
+    /// says nothing about the track
+    pub fn set_placeholder(&mut self, bytes: &[u8]) {
+        self.thumbnail = Some(utils::encode_image_to_base64(bytes));
+        self.thumbnails = Some(ThumbnailVariants::new(&utils::content_hash(bytes)));
+    }
+
+    /// The track as read without its artwork, which is what takes long to read
+    pub fn without_artwork(mut self) -> Self {
+        self.thumbnail = None;
+        self.thumbnails = None;
+        self.palette = None;
+        self
+    }
+
+    /// Whether the app reported everything a track change usually has. Crossfading
+    /// apps send the title first and the artist and artwork in later changes
+    pub fn is_complete(&self) -> bool {
+        !self.title.is_empty() && !self.artist.is_empty() && self.thumbnail.is_some()
+    }
+}
+
+impl TrackControls {
+    pub fn playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    pub fn auto_repeat_mode(&self) -> &AutoRepeatMode {
+        &self.auto_repeat_mode
+    }
+
+    /// Whether play, pause and the toggle are accepted
+    pub fn play_pause_enabled(&self) -> bool {
+        self.play_pause_enabled || self.play_enabled || self.pause_enabled
+    }
+
+    pub fn next_enabled(&self) -> bool {
+        self.next_enabled
+    }
+
+    pub fn prev_enabled(&self) -> bool {
+        self.prev_enabled
+    }
+}
+
+/// Position and length of a track, read together so they always belong to the same track
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema)]
+pub struct Timeline {
+    /// `track_id` of the track the timeline belongs to, timelines of another track
+    /// than the current one are outdated
+    pub track_id: String,
+    /// Position in milliseconds when the timeline was read
+    pub position: u64,
+    /// Length in milliseconds, 0 when unknown, e.g. for live streams
+    pub duration: u64,
+    /// 1 for normal speed
+    pub rate: f64,
+    /// Unix timestamp in milliseconds when the app last updated the position,
+    /// `None` when the backend doesn't report it
+    pub updated_at: Option<u64>,
+    /// Ranges seeking can go to, empty when the app doesn't accept seeks
+    pub seekable: Vec<SeekRange>,
+}
+
+/// Range of positions in milliseconds, both ends included
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub struct SeekRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Identify a track by its app, title, artist and length, apps don't report a track id
+///
+/// # Arguments
+/// * `duration` - Length in milliseconds, whole seconds count so reads of the
+///   same track a few milliseconds apart agree
+///
+/// # Returns
+/// * `String` - Hash of the four, stable across restarts
+pub fn track_id(source_app_id: &str, title: &str, artist: &str, duration: u64) -> String {
+    utils::content_hash(
+        format!(
+            "{}\0{}\0{}\0{}",
+            source_app_id,
+            title,
+            artist,
+            duration / 1000
+        )
+        .as_bytes(),
+    )
+}
+
+/// The fields besides `timeline` are deprecated, they are kept for older clients
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct TrackTimeline {
+    #[serde(flatten)]
+    pub session: SessionInfo,
+    pub(crate) progress: u64,
+    // Recordings of older versions lack the fields below
+    /// Start of the seekable media in milliseconds, usually 0
+    #[serde(default)]
+    pub(crate) start_time: u64,
+    /// End of the media in milliseconds, the duration for most tracks
+    #[serde(default)]
+    pub(crate) end_time: u64,
+    /// Earliest position seeking can go to, e.g. the start of a live stream's buffer
+    #[serde(default)]
+    pub(crate) min_seek_time: u64,
+    /// Latest position seeking can go to, e.g. what is buffered so far
+    #[serde(default)]
+    pub(crate) max_seek_time: u64,
+    /// Unix timestamp in milliseconds when the app last updated the timeline,
+    /// `None` when the backend doesn't report it
+    #[serde(default)]
+    pub(crate) last_updated: Option<u64>,
+    /// 1 for normal speed
+    #[serde(default = "normal_rate")]
+    pub(crate) playback_rate: f64,
+    /// The app currently accepts seeks
+    #[serde(default)]
+    pub(crate) seek_enabled: bool,
+    /// Everything above in one object, referencing the track
+    #[serde(default)]
+    pub timeline: Timeline,
+}
+
+fn normal_rate() -> f64 {
+    1.0
+}
+
+impl TrackTimeline {
+    /// Fill in the unified timeline once the fields above are final
+    ///
+    /// # Arguments
+    /// * `track_id` - Id of the track the timeline was read for
+    pub(crate) fn unify(mut self, track_id: String) -> Self {
+        let seekable = match self.seek_enabled {
+            true => vec![SeekRange {
+                start: self.min_seek_time,
+                end: self.max_seek_time.max(self.min_seek_time),
+            }],
+            false => Vec::new(),
+        };
+        self.timeline = Timeline {
+            track_id,
+            position: self.progress,
+            duration: self.end_time,
+            rate: self.playback_rate,
+            updated_at: self.last_updated,
+            seekable,
+        };
+        self
+    }
+
+    /// Position in milliseconds when the timeline was read
+    pub fn progress(&self) -> u64 {
+        self.progress
+    }
+
+    pub fn playback_rate(&self) -> f64 {
+        self.playback_rate
+    }
+
+    pub fn start_time(&self) -> u64 {
+        self.start_time
+    }
+
+    pub fn end_time(&self) -> u64 {
+        self.end_time
+    }
+
+    pub fn min_seek_time(&self) -> u64 {
+        self.min_seek_time
+    }
+
+    pub fn max_seek_time(&self) -> u64 {
+        self.max_seek_time
+    }
+
+    pub fn seek_enabled(&self) -> bool {
+        self.seek_enabled
+    }
+
+    /// Position in milliseconds at a moment, extrapolated from the last update
+    /// while playing
+    ///
+    /// # Arguments
+    /// * `now` - Unix timestamp in milliseconds
+    /// * `playing` - Whether the track played since the update
+    pub fn position_at(&self, now: u64, playing: bool) -> u64 {
+        let elapsed = match self.last_updated {
+            Some(updated) if playing => now.saturating_sub(updated),
+            _ => return self.progress,
+        };
+        let position = self.progress + (elapsed as f64 * self.playback_rate) as u64;
+        match self.end_time {
+            0 => position,
+            end => position.min(end),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaybackType {
+    Unknown,
+    Music,
+    Video,
+    Image,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoRepeatMode {
+    None,
+    Track,
+    List,
+}
+
+impl FromStr for AutoRepeatMode {
+    type Err = MediaError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(AutoRepeatMode::None),
+            "track" => Ok(AutoRepeatMode::Track),
+            "list" => Ok(AutoRepeatMode::List),
+            _ => Err(MediaError::invalid_input(format!(
+                "Invalid auto-repeat mode: {}",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for AutoRepeatMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AutoRepeatMode::None => "none",
+            AutoRepeatMode::Track => "track",
+            AutoRepeatMode::List => "list",
+        })
+    }
+}
@@ -0,0 +1,35 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Extra track details from online databases, for sessions with sparse metadata
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct TrackMetadata {
+    pub album: Option<String>,
+    pub year: Option<u32>,
+    pub genres: Vec<String>,
+    /// URL of an image of the artist
+    pub artist_image: Option<String>,
+    /// Providers that contributed, e.g. `["musicbrainz"]`
+    pub sources: Vec<String>,
+}
+
+impl TrackMetadata {
+    /// Fill fields that are still empty from another provider's result
+    pub fn merge(&mut self, other: TrackMetadata) {
+        self.album = self.album.take().or(other.album);
+        self.year = self.year.or(other.year);
+        if self.genres.is_empty() {
+            self.genres = other.genres;
+        }
+        self.artist_image = self.artist_image.take().or(other.artist_image);
+        self.sources.extend(other.sources);
+    }
+
+    /// Whether no provider could add anything
+    pub fn is_complete(&self) -> bool {
+        self.album.is_some()
+            && self.year.is_some()
+            && !self.genres.is_empty()
+            && self.artist_image.is_some()
+    }
+}
@@ -0,0 +1,463 @@
+use std::io::Cursor;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use image::{ImageOutputFormat, Rgb, RgbImage};
+
+use crate::backend::{BackendChange, ChangeNotifier, MediaBackend};
+use crate::controller::Command;
+use crate::error::{MediaError, MediaResult};
+use crate::media_manager::{
+    AutoRepeatMode, PlaybackType, SessionInfo, SessionState, SessionStatus, Timeline,
+    TrackControls, TrackInfo, TrackTimeline, track_id,
+};
+use crate::session_watcher::SessionEvent;
+use crate::utils;
+
+/// App User Model ID of the mock session
+pub const MOCK_APP_ID: &str = "MediaController.Mock";
+const MOCK_APP_NAME: &str = "Mock Player";
+
+/// How often the playing track moves on, each tick reports the timeline
+const TICK: Duration = Duration::from_secs(1);
+/// Step of fast forward and rewind
+const SEEK_STEP_MS: u64 = 10_000;
+const ARTWORK_SIZE: u32 = 300;
+
+struct MockTrack {
+    title: &'static str,
+    artist: &'static str,
+    album: &'static str,
+    duration_ms: u64,
+    /// Base color of the generated artwork
+    color: [u8; 3],
+}
+
+impl MockTrack {
+    fn id(&self) -> String {
+        track_id(MOCK_APP_ID, self.title, self.artist, self.duration_ms)
+    }
+}
+
+/// The scripted library, played in order
+const LIBRARY: [MockTrack; 4] = [
+    MockTrack {
+        title: "Morning Static",
+        artist: "The Placeholders",
+        album: "Fixtures",
+        duration_ms: 184_000,
+        color: [230, 120, 60],
+    },
+    MockTrack {
+        title: "Loopback",
+        artist: "The Placeholders",
+        album: "Fixtures",
+        duration_ms: 203_000,
+        color: [60, 140, 220],
+    },
+    MockTrack {
+        title: "Null Island",
+        artist: "Sample Rate",
+        album: "Lorem Ipsum",
+        duration_ms: 156_000,
+        color: [90, 190, 110],
+    },
+    MockTrack {
+        title: "Half Duplex",
+        artist: "Sample Rate",
+        album: "Lorem Ipsum",
+        duration_ms: 241_000,
+        color: [170, 80, 200],
+    },
+];
+
+struct Playback {
+    /// Position in the library
+    index: usize,
+    playing: bool,
+    /// Position in milliseconds when playback last started, paused or seeked
+    position_ms: u64,
+    resumed_at: Option<Instant>,
+    shuffle: bool,
+    repeat: AutoRepeatMode,
+}
+
+impl Playback {
+    fn track(&self) -> &'static MockTrack {
+        &LIBRARY[self.index]
+    }
+
+    fn position(&self) -> u64 {
+        let elapsed = self
+            .resumed_at
+            .map(|at| at.elapsed().as_millis() as u64)
+            .unwrap_or_default();
+        (self.position_ms + elapsed).min(self.track().duration_ms)
+    }
+
+    fn play(&mut self) {
+        if !self.playing {
+            self.playing = true;
+            self.resumed_at = Some(Instant::now());
+        }
+    }
+
+    fn pause(&mut self) {
+        self.position_ms = self.position();
+        self.playing = false;
+        self.resumed_at = None;
+    }
+
+    fn seek(&mut self, ms: u64) {
+        self.position_ms = ms.min(self.track().duration_ms);
+        if self.playing {
+            self.resumed_at = Some(Instant::now());
+        }
+    }
+
+    fn jump(&mut self, index: usize) {
+        self.index = index;
+        self.seek(0);
+    }
+
+    /// The track after the current one, `None` at the end of the library
+    fn following(&self) -> Option<usize> {
+        if self.shuffle {
+            // A fixed order, so tests can predict it
+            return Some((self.index + LIBRARY.len() / 2 + 1) % LIBRARY.len());
+        }
+        match self.index + 1 {
+            next if next < LIBRARY.len() => Some(next),
+            _ if matches!(self.repeat, AutoRepeatMode::List) => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Move playback on by a tick
+    ///
+    /// # Returns
+    /// * `&'static [SessionEvent]` - What changed
+    fn advance(&mut self) -> &'static [SessionEvent] {
+        if !self.playing {
+            return &[];
+        }
+        if self.position() < self.track().duration_ms {
+            return &[SessionEvent::TimelineProperties];
+        }
+
+        if matches!(self.repeat, AutoRepeatMode::Track) {
+            self.seek(0);
+            return &[SessionEvent::TimelineProperties];
+        }
+        match self.following() {
+            Some(index) => self.jump(index),
+            None => {
+                // Stops at the start of the library, like players do at the end of a queue
+                self.jump(0);
+                self.pause();
+            }
+        }
+        &TRACK_CHANGED
+    }
+}
+
+const TRACK_CHANGED: [SessionEvent; 3] = [
+    SessionEvent::MediaProperties,
+    SessionEvent::PlaybackInfo,
+    SessionEvent::TimelineProperties,
+];
+const PLAYBACK_CHANGED: [SessionEvent; 2] =
+    [SessionEvent::PlaybackInfo, SessionEvent::TimelineProperties];
+
+struct Shared {
+    playback: Mutex<Playback>,
+    /// Set while watching
+    notify: Mutex<Option<ChangeNotifier>>,
+}
+
+impl Shared {
+    fn playback(&self) -> MediaResult<MutexGuard<'_, Playback>> {
+        self.playback.lock().map_err(MediaError::internal)
+    }
+
+    fn notify(&self, events: &[SessionEvent]) {
+        let notify = match self.notify.lock() {
+            Ok(notify) => notify.clone(),
+            Err(_) => return,
+        };
+        if let Some(notify) = notify {
+            for event in events {
+                notify(BackendChange::Session(*event));
+            }
+        }
+    }
+}
+
+/// A backend playing a scripted library of four tracks with generated artwork.
+///
+/// There is always exactly one session and every command is accepted, which makes
+/// it useful for frontend development and integration tests on any machine.
+pub struct MockBackend {
+    shared: Arc<Shared>,
+    /// Cleared to stop the ticker thread of the current watch
+    running: Option<Arc<AtomicBool>>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockBackend {
+    /// Start paused at the first track of the library
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                playback: Mutex::new(Playback {
+                    index: 0,
+                    playing: false,
+                    position_ms: 0,
+                    resumed_at: None,
+                    shuffle: false,
+                    repeat: AutoRepeatMode::None,
+                }),
+                notify: Mutex::new(None),
+            }),
+            running: None,
+        }
+    }
+
+    fn session_info(&self) -> SessionInfo {
+        SessionInfo {
+            session_id: utils::session_id_from_app_id(MOCK_APP_ID),
+            source_app_id: MOCK_APP_ID.to_string(),
+            app_name: MOCK_APP_NAME.to_string(),
+        }
+    }
+}
+
+impl Drop for MockBackend {
+    fn drop(&mut self) {
+        self.unwatch();
+    }
+}
+
+impl MediaBackend for MockBackend {
+    fn session_state(&self) -> SessionState {
+        SessionState {
+            state: SessionStatus::Active,
+            session: Some(self.session_info()),
+        }
+    }
+
+    fn sessions(&self) -> MediaResult<Vec<SessionInfo>> {
+        Ok(vec![self.session_info()])
+    }
+
+    fn current_app_id(&self) -> MediaResult<String> {
+        Ok(MOCK_APP_ID.to_string())
+    }
+
+    fn is_playing(&self) -> MediaResult<bool> {
+        Ok(self.shared.playback()?.playing)
+    }
+
+    fn track_info(&self) -> MediaResult<TrackInfo> {
+        let index = self.shared.playback()?.index;
+        let track = &LIBRARY[index];
+
+        let mut info = TrackInfo {
+            session: self.session_info(),
+            track_id: track.id(),
+            previous_track_id: None,
+            title: track.title.to_string(),
+            artist: track.artist.to_string(),
+            thumbnail: None,
+            thumbnails: None,
+            album: Some(track.album.to_string()),
+            album_artist: Some(track.artist.to_string()),
+            subtitle: None,
+            track_number: Some(index as u32 + 1),
+            album_track_count: Some(LIBRARY.len() as u32),
+            genres: Vec::new(),
+            playback_type: PlaybackType::Music,
+            episode: None,
+            duration: track.duration_ms,
+            palette: None,
+            metadata: None,
+            is_advertisement: false,
+            stale: false,
+        };
+        info.set_artwork(&artwork(track)?);
+        Ok(info)
+    }
+
+    fn track_controls(&self) -> MediaResult<TrackControls> {
+        let playback = self.shared.playback()?;
+        Ok(TrackControls {
+            session: self.session_info(),
+            track_id: playback.track().id(),
+            shuffle_enabled: true,
+            auto_repeat_mode_enabled: true,
+            next_enabled: true,
+            prev_enabled: true,
+            play_pause_enabled: true,
+            play_enabled: true,
+            pause_enabled: true,
+            stop_enabled: true,
+            fast_forward_enabled: true,
+            rewind_enabled: true,
+            shuffle: playback.shuffle,
+            auto_repeat_mode: playback.repeat.clone(),
+            playing: playback.playing,
+            stale: false,
+        })
+    }
+
+    fn track_timeline(&self) -> MediaResult<TrackTimeline> {
+        let playback = self.shared.playback()?;
+        let track = playback.track();
+        let duration = track.duration_ms;
+        let timeline = TrackTimeline {
+            session: self.session_info(),
+            progress: playback.position(),
+            start_time: 0,
+            end_time: duration,
+            min_seek_time: 0,
+            max_seek_time: duration,
+            last_updated: None,
+            playback_rate: 1.0,
+            seek_enabled: true,
+            timeline: Timeline::default(),
+        };
+        Ok(timeline.unify(track.id()))
+    }
+
+    fn artwork(&self) -> MediaResult<Vec<u8>> {
+        artwork(self.shared.playback()?.track())
+    }
+
+    fn execute(&self, command: &Command) -> MediaResult<bool> {
+        let changed: &[SessionEvent] = {
+            let mut playback = self.shared.playback()?;
+            match command {
+                Command::TogglePlayPause if playback.playing => {
+                    playback.pause();
+                    &PLAYBACK_CHANGED
+                }
+                Command::TogglePlayPause | Command::Play => {
+                    playback.play();
+                    &PLAYBACK_CHANGED
+                }
+                Command::Pause => {
+                    playback.pause();
+                    &PLAYBACK_CHANGED
+                }
+                Command::Stop => {
+                    playback.pause();
+                    playback.seek(0);
+                    &PLAYBACK_CHANGED
+                }
+                Command::FastForward => {
+                    let position = playback.position() + SEEK_STEP_MS;
+                    playback.seek(position);
+                    &[SessionEvent::TimelineProperties]
+                }
+                Command::Rewind => {
+                    let position = playback.position().saturating_sub(SEEK_STEP_MS);
+                    playback.seek(position);
+                    &[SessionEvent::TimelineProperties]
+                }
+                Command::Seek { ms } => {
+                    playback.seek(*ms);
+                    &[SessionEvent::TimelineProperties]
+                }
+                Command::NextTrack => {
+                    let index = playback.following().unwrap_or(0);
+                    playback.jump(index);
+                    &TRACK_CHANGED
+                }
+                Command::PreviousTrack => {
+                    let index = (playback.index + LIBRARY.len() - 1) % LIBRARY.len();
+                    playback.jump(index);
+                    &TRACK_CHANGED
+                }
+                Command::SetRepeat { mode } => {
+                    playback.repeat = mode.clone();
+                    &[SessionEvent::PlaybackInfo]
+                }
+                Command::ToggleShuffle => {
+                    playback.shuffle = !playback.shuffle;
+                    &[SessionEvent::PlaybackInfo]
+                }
+            }
+        };
+        self.shared.notify(changed);
+        Ok(true)
+    }
+
+    fn watch(&mut self, notify: ChangeNotifier) -> MediaResult<()> {
+        self.unwatch();
+        if let Ok(mut current) = self.shared.notify.lock() {
+            *current = Some(notify);
+        }
+
+        let running = Arc::new(AtomicBool::new(true));
+        let shared = Arc::clone(&self.shared);
+        let ticker = Arc::clone(&running);
+        thread::Builder::new()
+            .name("mock-backend".to_string())
+            .spawn(move || tick(&shared, &ticker))
+            .map_err(MediaError::internal)?;
+        self.running = Some(running);
+        Ok(())
+    }
+
+    fn unwatch(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.store(false, Ordering::Relaxed);
+        }
+        if let Ok(mut notify) = self.shared.notify.lock() {
+            *notify = None;
+        }
+    }
+
+    fn handler_count(&self) -> usize {
+        usize::from(self.running.is_some())
+    }
+}
+
+/// Play the library until the watch that started the thread ends
+fn tick(shared: &Shared, running: &AtomicBool) {
+    loop {
+        thread::sleep(TICK);
+        if !running.load(Ordering::Relaxed) {
+            return;
+        }
+        let changed = match shared.playback() {
+            Ok(mut playback) => playback.advance(),
+            Err(_) => return,
+        };
+        shared.notify(changed);
+    }
+}
+
+/// A diagonal gradient in the color of the track
+fn artwork(track: &MockTrack) -> MediaResult<Vec<u8>> {
+    let [red, green, blue] = track.color.map(u32::from);
+    let image = RgbImage::from_fn(ARTWORK_SIZE, ARTWORK_SIZE, |x, y| {
+        // From the full color in the top left corner to a quarter of it in the bottom right
+        let shade = 4 * ARTWORK_SIZE - (x + y) * 3 / 2;
+        let scale = |channel: u32| (channel * shade / (4 * ARTWORK_SIZE)) as u8;
+        Rgb([scale(red), scale(green), scale(blue)])
+    });
+
+    let mut bytes = Cursor::new(Vec::new());
+    image
+        .write_to(&mut bytes, ImageOutputFormat::Jpeg(85))
+        .map_err(MediaError::internal)?;
+    Ok(bytes.into_inner())
+}
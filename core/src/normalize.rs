@@ -0,0 +1,154 @@
+use serde::Deserialize;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::media_manager::TrackInfo;
+
+/// Bracketed parts of video titles that aren't part of the song title, lowercase
+const NOISE: [&str; 16] = [
+    "audio",
+    "hd",
+    "hq",
+    "4k",
+    "lyrics",
+    "lyric video",
+    "lyric",
+    "music video",
+    "video",
+    "visualizer",
+    "visualiser",
+    "mv",
+    "m/v",
+    "clip officiel",
+    "video oficial",
+    "videoclip",
+];
+/// What browsers append to the title of a tab
+const TAB_SUFFIXES: [&str; 3] = [" - YouTube Music", " - YouTube", " | SoundCloud"];
+/// What video sites append to the channel name of an artist
+const CHANNEL_SUFFIXES: [&str; 3] = [" - Topic", "VEVO", " Official"];
+/// Separators between the artist and the title of a video title
+const SEPARATORS: [&str; 3] = [" - ", " – ", " — "];
+
+/// Clean up the metadata of browser and video sessions so it reads like that of
+/// a music player, every step is off unless turned on
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct NormalizeRules {
+    /// Remove `(Official Video)`, `[HD]` and the like, and the browser's suffix, from titles
+    pub strip_noise: bool,
+    /// More bracketed parts to remove, compared case-insensitively
+    pub noise: Vec<String>,
+    /// Split `Artist - Title` titles of videos into artist and title, and remove
+    /// ` - Topic` and `VEVO` from channel names
+    pub split_artist: bool,
+    /// Compose to NFKC, which turns full-width letters and ligatures into plain
+    /// ones, drop zero-width characters and collapse whitespace
+    pub unicode: bool,
+    /// Spell everything with ASCII, for displays that lack the glyphs
+    pub transliterate: bool,
+}
+
+impl NormalizeRules {
+    /// Normalize the title, artist and album of a track in place
+    pub fn apply(&self, track: &mut TrackInfo) {
+        if self.unicode {
+            track.title = normalize_unicode(&track.title);
+            track.artist = normalize_unicode(&track.artist);
+            track.album = track.album.as_deref().map(normalize_unicode);
+        }
+        if self.strip_noise {
+            track.title = self.strip_noise(&track.title);
+        }
+        if self.split_artist {
+            match split_artist(&track.title) {
+                Some((artist, title)) => {
+                    track.artist = artist;
+                    track.title = title;
+                }
+                None => track.artist = strip_channel_suffix(&track.artist),
+            }
+        }
+        if self.transliterate {
+            track.title = deunicode::deunicode(&track.title);
+            track.artist = deunicode::deunicode(&track.artist);
+            track.album = track.album.as_deref().map(deunicode::deunicode);
+        }
+    }
+
+    /// Remove the tab suffix and bracketed noise, keeping `(Live)` or `[Remix]`
+    fn strip_noise(&self, title: &str) -> String {
+        let title = TAB_SUFFIXES
+            .iter()
+            .find_map(|suffix| title.strip_suffix(suffix))
+            .unwrap_or(title);
+
+        let mut stripped = String::with_capacity(title.len());
+        let mut rest = title;
+        while let Some(start) = rest.find(['(', '[']) {
+            let close = if rest[start..].starts_with('(') {
+                ')'
+            } else {
+                ']'
+            };
+            let Some(length) = rest[start..].find(close) else {
+                break;
+            };
+            let end = start + length;
+            stripped.push_str(&rest[..start]);
+            if !self.is_noise(&rest[start + 1..end]) {
+                stripped.push_str(&rest[start..=end]);
+            }
+            rest = &rest[end + 1..];
+        }
+        stripped.push_str(rest);
+
+        let stripped = collapse_whitespace(&stripped);
+        // A title that was nothing but noise is better than none
+        if stripped.is_empty() {
+            title.to_string()
+        } else {
+            stripped
+        }
+    }
+
+    fn is_noise(&self, part: &str) -> bool {
+        let part = part.trim().to_lowercase();
+        part.starts_with("official")
+            || NOISE.contains(&part.as_str())
+            || self.noise.iter().any(|noise| noise.to_lowercase() == part)
+    }
+}
+
+/// Artist and title of a `Artist - Title` video title
+fn split_artist(title: &str) -> Option<(String, String)> {
+    let (artist, title) = SEPARATORS
+        .iter()
+        .find_map(|separator| title.split_once(separator))?;
+    let (artist, title) = (artist.trim(), title.trim());
+    if artist.is_empty() || title.is_empty() {
+        return None;
+    }
+    Some((artist.to_string(), title.to_string()))
+}
+
+fn strip_channel_suffix(artist: &str) -> String {
+    CHANNEL_SUFFIXES
+        .iter()
+        .find_map(|suffix| artist.strip_suffix(suffix))
+        .filter(|stripped| !stripped.trim().is_empty())
+        .unwrap_or(artist)
+        .trim()
+        .to_string()
+}
+
+fn normalize_unicode(text: &str) -> String {
+    let composed: String = text
+        .nfkc()
+        .filter(|c| !matches!(c, '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}'))
+        .collect();
+    collapse_whitespace(&composed)
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
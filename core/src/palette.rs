@@ -0,0 +1,383 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// Number of color boxes produced by the median cut
+const MAX_BOXES: usize = 8;
+/// Palettes remembered, the oldest is dropped first
+const CACHE_SIZE: usize = 128;
+/// Side length the artwork is downscaled to before sampling
+const SAMPLE_SIZE: u32 = 64;
+
+const FALLBACK_DOMINANT: [u8; 3] = [0x1f, 0x29, 0x37];
+const FALLBACK_VIBRANT: [u8; 3] = [0x10, 0xb9, 0x81];
+const FALLBACK_MUTED: [u8; 3] = [0x4b, 0x55, 0x63];
+
+const BLACK: [u8; 3] = [0x00, 0x00, 0x00];
+const WHITE: [u8; 3] = [0xff, 0xff, 0xff];
+/// WCAG AAA contrast of body text
+const TEXT_CONTRAST: f32 = 7.0;
+/// WCAG AA contrast of secondary text
+const SECONDARY_TEXT_CONTRAST: f32 = 4.5;
+/// WCAG AA contrast of user interface components such as progress bars
+const COMPONENT_CONTRAST: f32 = 3.0;
+/// Highest relative luminance of a dark background
+const DARK_BACKGROUND_LUMINANCE: f32 = 0.03;
+/// Lowest relative luminance of a light background
+const LIGHT_BACKGROUND_LUMINANCE: f32 = 0.85;
+/// Steps of mixing a color towards black or white
+const MIX_STEPS: u8 = 20;
+
+/// Color scheme extracted from the track artwork, as `#rrggbb` hex strings
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct Palette {
+    /// Most common color of the artwork
+    pub dominant: String,
+    /// Most saturated, reasonably bright color, suited for accents
+    pub vibrant: String,
+    /// Low saturation color, suited for backgrounds
+    pub muted: String,
+    /// Black or white, whichever reads best on top of `dominant`
+    pub text: String,
+    /// Ready to use colors derived from the ones above
+    #[serde(default)]
+    pub theme: Theme,
+}
+
+/// Dark and light color schemes of the artwork, so every client themes itself alike
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct Theme {
+    pub dark: ThemeTokens,
+    pub light: ThemeTokens,
+}
+
+/// Colors of one color scheme as `#rrggbb` hex strings, contrasts follow WCAG 2
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct ThemeTokens {
+    /// Shade of the dominant color
+    pub background: String,
+    /// Text on `background`, at least 7:1 contrast
+    pub foreground: String,
+    /// Secondary text on `background`, at least 4.5:1 contrast
+    pub muted_foreground: String,
+    /// Filled part of progress bars and other accents, at least 3:1 against `background`
+    pub progress: String,
+    /// Black or white, whichever reads best on top of `progress`
+    pub on_progress: String,
+}
+
+impl Default for Theme {
+    /// Theme of the fallback colors, for palettes stored before themes existed
+    fn default() -> Self {
+        Theme::new(FALLBACK_DOMINANT, FALLBACK_VIBRANT, FALLBACK_MUTED)
+    }
+}
+
+impl Theme {
+    fn new(dominant: [u8; 3], vibrant: [u8; 3], muted: [u8; 3]) -> Self {
+        Theme {
+            dark: ThemeTokens::new(dominant, vibrant, muted, BLACK),
+            light: ThemeTokens::new(dominant, vibrant, muted, WHITE),
+        }
+    }
+}
+
+impl ThemeTokens {
+    /// Derive the tokens of a color scheme
+    ///
+    /// # Arguments
+    /// * `base` - `BLACK` for the dark scheme, `WHITE` for the light one
+    fn new(dominant: [u8; 3], vibrant: [u8; 3], muted: [u8; 3], base: [u8; 3]) -> Self {
+        let ink = if base == BLACK { WHITE } else { BLACK };
+        let background = (0..=MIX_STEPS)
+            .map(|step| mix(dominant, base, f32::from(step) / f32::from(MIX_STEPS)))
+            .find(|color| {
+                let luminance = relative_luminance(*color);
+                if base == BLACK {
+                    luminance <= DARK_BACKGROUND_LUMINANCE
+                } else {
+                    luminance >= LIGHT_BACKGROUND_LUMINANCE
+                }
+            })
+            .unwrap_or(base);
+
+        let foreground = with_contrast(mix(dominant, ink, 0.9), background, TEXT_CONTRAST, ink);
+        let muted_foreground = with_contrast(
+            mix(muted, ink, 0.4),
+            background,
+            SECONDARY_TEXT_CONTRAST,
+            ink,
+        );
+        let progress = with_contrast(vibrant, background, COMPONENT_CONTRAST, ink);
+
+        ThemeTokens {
+            background: to_hex(background),
+            foreground: to_hex(foreground),
+            muted_foreground: to_hex(muted_foreground),
+            progress: to_hex(progress),
+            on_progress: to_hex(readable_on(progress)),
+        }
+    }
+}
+
+/// Extracted palettes by content hash of the artwork, shared by every backend
+static CACHE: Mutex<VecDeque<(String, Palette)>> = Mutex::new(VecDeque::new());
+
+/// A palette extracted before, never decoding the artwork
+///
+/// # Arguments
+/// * `hash` - Content hash of the artwork, see [`utils::content_hash`]
+pub fn cached_palette(hash: &str) -> Option<Palette> {
+    let cache = CACHE.lock().ok()?;
+    cache
+        .iter()
+        .find(|(cached, _)| cached == hash)
+        .map(|(_, palette)| palette.clone())
+}
+
+/// Extract a palette and remember it for the artwork's hash. Decoding large
+/// artwork is slow, so this belongs on a blocking thread
+pub fn extract_cached(image_bytes: &[u8]) -> Result<Palette> {
+    let hash = utils::content_hash(image_bytes);
+    if let Some(palette) = cached_palette(&hash) {
+        return Ok(palette);
+    }
+
+    let palette = extract_palette(image_bytes)?;
+    if let Ok(mut cache) = CACHE.lock() {
+        if cache.len() >= CACHE_SIZE {
+            cache.pop_front();
+        }
+        cache.push_back((hash, palette.clone()));
+    }
+    Ok(palette)
+}
+
+/// A set of pixels that is split along its widest channel
+struct ColorBox {
+    pixels: Vec<[u8; 3]>,
+}
+
+impl ColorBox {
+    /// Channel with the widest range and the size of that range
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3)
+            .map(|channel| {
+                let (min, max) = self
+                    .pixels
+                    .iter()
+                    .fold((u8::MAX, u8::MIN), |(min, max), pixel| {
+                        (min.min(pixel[channel]), max.max(pixel[channel]))
+                    });
+                (channel, max.saturating_sub(min))
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap_or((0, 0))
+    }
+
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+        self.pixels.sort_unstable_by_key(|pixel| pixel[channel]);
+        let upper = self.pixels.split_off(self.pixels.len() / 2);
+        (self, ColorBox { pixels: upper })
+    }
+
+    fn average(&self) -> [u8; 3] {
+        let count = self.pixels.len().max(1) as u64;
+        let mut sum = [0u64; 3];
+        for pixel in &self.pixels {
+            for (total, channel) in sum.iter_mut().zip(pixel) {
+                *total += *channel as u64;
+            }
+        }
+        sum.map(|total| (total / count) as u8)
+    }
+}
+
+/// Extracts a color palette from image bytes using median cut quantization
+///
+/// # Arguments
+/// * `image_bytes` - Raw bytes of the image
+///
+/// # Returns
+/// * `Result<Palette>` - Dominant, vibrant, muted and text colors
+pub fn extract_palette(image_bytes: &[u8]) -> Result<Palette> {
+    let img = image::load_from_memory(image_bytes)?;
+    let small = img.resize(
+        SAMPLE_SIZE,
+        SAMPLE_SIZE,
+        image::imageops::FilterType::Triangle,
+    );
+    let pixels: Vec<[u8; 3]> = small.to_rgb8().pixels().map(|pixel| pixel.0).collect();
+
+    let swatches = median_cut(pixels);
+    Ok(palette_from_swatches(&swatches))
+}
+
+/// Quantize pixels into at most `MAX_BOXES` swatches of (color, population)
+fn median_cut(pixels: Vec<[u8; 3]>) -> Vec<([u8; 3], usize)> {
+    if pixels.is_empty() {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![ColorBox { pixels }];
+    while boxes.len() < MAX_BOXES {
+        // Always split the box with the widest color range
+        let Some((index, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, color_box)| color_box.pixels.len() > 1)
+            .map(|(index, color_box)| (index, color_box.widest_channel().1))
+            .filter(|&(_, range)| range > 0)
+            .max_by_key(|&(_, range)| range)
+        else {
+            break;
+        };
+
+        let (lower, upper) = boxes.swap_remove(index).split();
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|color_box| (color_box.average(), color_box.pixels.len()))
+        .collect()
+}
+
+fn palette_from_swatches(swatches: &[([u8; 3], usize)]) -> Palette {
+    let dominant = swatches
+        .iter()
+        .max_by_key(|(_, population)| *population)
+        .map(|(color, _)| *color)
+        .unwrap_or(FALLBACK_DOMINANT);
+
+    // Weight saturation by population so tiny specks don't win
+    let vibrant = swatches
+        .iter()
+        .filter(|(color, _)| {
+            let (_, s, v) = rgb_to_hsv(*color);
+            s >= 0.35 && (0.3..=0.95).contains(&v)
+        })
+        .max_by(|(a, a_pop), (b, b_pop)| {
+            let a_score = rgb_to_hsv(*a).1 * (*a_pop as f32).sqrt();
+            let b_score = rgb_to_hsv(*b).1 * (*b_pop as f32).sqrt();
+            a_score.total_cmp(&b_score)
+        })
+        .map(|(color, _)| *color)
+        .unwrap_or(FALLBACK_VIBRANT);
+
+    let muted = swatches
+        .iter()
+        .filter(|(color, _)| {
+            let (_, s, v) = rgb_to_hsv(*color);
+            s < 0.35 && (0.2..=0.8).contains(&v)
+        })
+        .max_by_key(|(_, population)| *population)
+        .map(|(color, _)| *color)
+        .unwrap_or(FALLBACK_MUTED);
+
+    Palette {
+        dominant: to_hex(dominant),
+        vibrant: to_hex(vibrant),
+        muted: to_hex(muted),
+        text: to_hex(readable_on(dominant)),
+        theme: Theme::new(dominant, vibrant, muted),
+    }
+}
+
+/// Black or white, whichever has the higher contrast on a background
+fn readable_on(background: [u8; 3]) -> [u8; 3] {
+    if contrast_ratio(BLACK, background) >= contrast_ratio(WHITE, background) {
+        BLACK
+    } else {
+        WHITE
+    }
+}
+
+/// Mix a color towards `ink` until it reaches a contrast against a background
+///
+/// # Arguments
+/// * `color` - The preferred color
+/// * `background` - Color it is shown on
+/// * `min_ratio` - WCAG contrast ratio to reach
+/// * `ink` - Black or white, whichever contrasts with the background
+fn with_contrast(color: [u8; 3], background: [u8; 3], min_ratio: f32, ink: [u8; 3]) -> [u8; 3] {
+    (0..=MIX_STEPS)
+        .map(|step| mix(color, ink, f32::from(step) / f32::from(MIX_STEPS)))
+        .find(|candidate| contrast_ratio(*candidate, background) >= min_ratio)
+        .unwrap_or(ink)
+}
+
+/// Blend two colors, `amount` 0 is `from` and 1 is `to`
+fn mix(from: [u8; 3], to: [u8; 3], amount: f32) -> [u8; 3] {
+    std::array::from_fn(|channel| {
+        let from = f32::from(from[channel]);
+        let to = f32::from(to[channel]);
+        (from + (to - from) * amount).round() as u8
+    })
+}
+
+/// WCAG contrast ratio (1-21)
+fn contrast_ratio(a: [u8; 3], b: [u8; 3]) -> f32 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn to_hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// WCAG relative luminance (0-1)
+fn relative_luminance(color: [u8; 3]) -> f32 {
+    let [r, g, b] = color.map(|channel| {
+        let c = channel as f32 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Convert RGB color values to HSV (Hue, Saturation, Value)
+///
+/// # Arguments
+///
+/// * `color` - RGB values in range 0-255
+///
+/// # Returns
+///
+/// * `(h, s, v)` - Hue (0-360), Saturation (0-1), Value (0-1)
+fn rgb_to_hsv(color: [u8; 3]) -> (f32, f32, f32) {
+    let [r, g, b] = color.map(|channel| channel as f32 / 255.0);
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    // Calculate hue
+    let h = if delta == 0.0 {
+        0.0 // No hue for grayscale colors
+    } else if max == r {
+        60.0 * (((g - b) / delta) % 6.0)
+    } else if max == g {
+        60.0 * (((b - r) / delta) + 2.0)
+    } else {
+        60.0 * (((r - g) / delta) + 4.0)
+    };
+
+    // Ensure positive hue
+    let h = if h < 0.0 { h + 360.0 } else { h };
+
+    // Calculate saturation
+    let s = if max == 0.0 { 0.0 } else { delta / max };
+
+    (h, s, max)
+}
@@ -0,0 +1,104 @@
+use std::io::Cursor;
+
+use image::ImageOutputFormat;
+use serde::Deserialize;
+
+use crate::controller::Command;
+use crate::media_manager::{TrackControls, TrackTimeline};
+use crate::normalize::NormalizeRules;
+
+const THUMBNAIL_QUALITY: u8 = 85;
+
+/// Corrections for an app whose media session misbehaves, keyed by source app id
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct AppProfile {
+    pub capabilities: CapabilityOverrides,
+    /// Always control the app with media keys, left to the server
+    pub media_keys: bool,
+    /// Bounding box in pixels of the base64 thumbnail in track info
+    pub thumbnail_size: Option<u32>,
+    /// Never pick the app's session as the current one
+    pub exclude: bool,
+    /// Cleanup of the app's titles, for browsers and video sites
+    pub normalize: NormalizeRules,
+    /// Also read the app's session on this interval in milliseconds while it is the
+    /// current one, for players whose change events don't fire reliably
+    pub poll_ms: Option<u64>,
+}
+
+/// Capability flags forced for an app, `None` keeps what the app reports
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct CapabilityOverrides {
+    /// Play, pause and the toggle
+    pub play_pause: Option<bool>,
+    pub stop: Option<bool>,
+    pub next: Option<bool>,
+    pub previous: Option<bool>,
+    /// Seeking, fast forward and rewind
+    pub seek: Option<bool>,
+    pub shuffle: Option<bool>,
+    pub repeat: Option<bool>,
+}
+
+impl CapabilityOverrides {
+    /// Whether a command was turned off for the app
+    pub fn forbids(&self, command: &Command) -> bool {
+        let flag = match command {
+            Command::TogglePlayPause | Command::Play | Command::Pause => self.play_pause,
+            Command::Stop => self.stop,
+            Command::NextTrack => self.next,
+            Command::PreviousTrack => self.previous,
+            Command::FastForward | Command::Rewind | Command::Seek { .. } => self.seek,
+            Command::ToggleShuffle => self.shuffle,
+            Command::SetRepeat { .. } => self.repeat,
+        };
+        flag == Some(false)
+    }
+
+    /// Force the flags of controls read from the app
+    pub fn apply_to_controls(&self, controls: &mut TrackControls) {
+        let flags = [
+            (self.play_pause, &mut controls.play_pause_enabled),
+            (self.play_pause, &mut controls.play_enabled),
+            (self.play_pause, &mut controls.pause_enabled),
+            (self.stop, &mut controls.stop_enabled),
+            (self.next, &mut controls.next_enabled),
+            (self.previous, &mut controls.prev_enabled),
+            (self.seek, &mut controls.fast_forward_enabled),
+            (self.seek, &mut controls.rewind_enabled),
+            (self.shuffle, &mut controls.shuffle_enabled),
+            (self.repeat, &mut controls.auto_repeat_mode_enabled),
+        ];
+        for (value, flag) in flags {
+            if let Some(value) = value {
+                *flag = value;
+            }
+        }
+    }
+
+    /// Force whether a timeline read from the app accepts seeks
+    pub fn apply_to_timeline(&self, timeline: &mut TrackTimeline) {
+        if let Some(seek) = self.seek {
+            timeline.seek_enabled = seek;
+        }
+    }
+}
+
+/// Downscale artwork to fit a square box, artwork that already fits is kept as is
+///
+/// # Arguments
+/// * `bytes` - Encoded artwork
+/// * `size` - Width and height of the box in pixels
+pub fn fit_artwork(bytes: &[u8], size: u32) -> anyhow::Result<Vec<u8>> {
+    let image = image::load_from_memory(bytes)?;
+    if image.width() <= size && image.height() <= size {
+        return Ok(bytes.to_vec());
+    }
+    let mut resized = Cursor::new(Vec::new());
+    // Resized artwork is re-encoded as JPEG, which has no alpha channel
+    image::DynamicImage::ImageRgb8(image.thumbnail(size, size).to_rgb8())
+        .write_to(&mut resized, ImageOutputFormat::Jpeg(THUMBNAIL_QUALITY))?;
+    Ok(resized.into_inner())
+}
@@ -0,0 +1,315 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::backend::{BackendChange, ChangeNotifier, MediaBackend};
+use crate::controller::{Command, ManagerEvent, MediaController};
+use crate::error::{MediaError, MediaResult};
+use crate::media_manager::{
+    SessionInfo, SessionState, SessionStatus, TrackControls, TrackInfo, TrackTimeline, track_id,
+};
+use crate::session_watcher::SessionEvent;
+use crate::utils;
+
+/// One line of a recording
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Milliseconds since the recording started
+    pub at: u64,
+    pub event: ManagerEvent,
+}
+
+/// Write every change of a controller to a file, one JSON object per line.
+///
+/// The current state is written first, so a replay starts where the recording did.
+///
+/// # Arguments
+/// * `controller` - Controller whose changes are recorded, until it stops
+/// * `path` - File to write, replaced if it exists
+pub fn record(controller: &MediaController, path: &Path) -> MediaResult<()> {
+    let file = File::create(path).map_err(MediaError::internal)?;
+    let mut writer = BufWriter::new(file);
+    // Subscribed before reading the state, so no change falls in between
+    let mut changes = controller.events();
+    let started = Instant::now();
+
+    let initial = controller.call(|backend| {
+        let mut events = vec![ManagerEvent::SessionChanged];
+        events.extend(
+            backend
+                .track_info()
+                .map(|track| ManagerEvent::TrackChanged(Box::new(track))),
+        );
+        events.extend(backend.track_controls().map(ManagerEvent::ControlsChanged));
+        events.extend(backend.track_timeline().map(ManagerEvent::TimelineChanged));
+        events
+    })?;
+    for event in initial {
+        write_event(&mut writer, RecordedEvent { at: 0, event })?;
+    }
+
+    tracing::info!("Recording media events to {}", path.display());
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        loop {
+            let event = match changes.blocking_recv() {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Recording missed {} media events", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            let at = started.elapsed().as_millis() as u64;
+            if let Err(e) = write_event(&mut writer, RecordedEvent { at, event }) {
+                tracing::error!("Failed to record to {}: {}", path.display(), e);
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+fn write_event(writer: &mut impl Write, event: RecordedEvent) -> MediaResult<()> {
+    serde_json::to_writer(&mut *writer, &event).map_err(MediaError::internal)?;
+    // Flushed per line, so a recording survives the process being killed
+    writer
+        .write_all(b"\n")
+        .and_then(|_| writer.flush())
+        .map_err(MediaError::internal)
+}
+
+/// Read a recording written by [`record`]
+pub fn load(path: &Path) -> MediaResult<Vec<RecordedEvent>> {
+    let file = File::open(path).map_err(MediaError::internal)?;
+    let mut events = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(MediaError::internal)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&line).map_err(|e| {
+            MediaError::invalid_input(format!("{} line {}: {}", path.display(), number + 1, e))
+        })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Latest state of the replayed session
+#[derive(Default)]
+struct ReplayState {
+    track: Option<TrackInfo>,
+    controls: Option<TrackControls>,
+    timeline: Option<TrackTimeline>,
+}
+
+impl ReplayState {
+    /// # Returns
+    /// * `BackendChange` - How the backend reports the event
+    fn apply(&mut self, event: ManagerEvent) -> BackendChange {
+        match event {
+            ManagerEvent::TrackChanged(mut track) => {
+                // Recordings of older versions lack the track id
+                if track.track_id.is_empty() {
+                    track.track_id = track_id(
+                        &track.session.source_app_id,
+                        &track.title,
+                        &track.artist,
+                        track.duration,
+                    );
+                }
+                self.track = Some(*track);
+                BackendChange::Session(SessionEvent::MediaProperties)
+            }
+            ManagerEvent::ControlsChanged(mut controls) => {
+                // and the track id of controls
+                if controls.track_id.is_empty() {
+                    controls.track_id = self.track_id();
+                }
+                self.controls = Some(controls);
+                BackendChange::Session(SessionEvent::PlaybackInfo)
+            }
+            ManagerEvent::TimelineChanged(mut timeline) => {
+                // and the unified timeline
+                if timeline.timeline.track_id.is_empty() {
+                    timeline = timeline.unify(self.track_id());
+                }
+                self.timeline = Some(timeline);
+                BackendChange::Session(SessionEvent::TimelineProperties)
+            }
+            ManagerEvent::SessionChanged => {
+                // The recording follows with the state of the new session, if any
+                *self = Self::default();
+                BackendChange::CurrentSession
+            }
+        }
+    }
+
+    /// Id of the replayed track, for events of older recordings that lack it
+    fn track_id(&self) -> String {
+        self.track
+            .as_ref()
+            .map(|track| track.track_id.clone())
+            .unwrap_or_default()
+    }
+
+    fn session(&self) -> Option<&SessionInfo> {
+        let track = self.track.as_ref().map(|track| &track.session);
+        let controls = self.controls.as_ref().map(|controls| &controls.session);
+        track.or(controls)
+    }
+}
+
+/// A backend playing back a recording, for reproducing what an app reported.
+///
+/// Events are fed in with their recorded timing divided by `speed`. The session
+/// is read-only, every command is refused as unsupported.
+pub struct ReplayBackend {
+    events: Arc<Vec<RecordedEvent>>,
+    speed: f64,
+    state: Arc<Mutex<ReplayState>>,
+    /// Cleared to stop the replay thread of the current watch
+    running: Option<Arc<AtomicBool>>,
+}
+
+impl ReplayBackend {
+    /// # Arguments
+    /// * `path` - Recording written by `--record`
+    /// * `speed` - `1.0` for real time, `10.0` to play ten times as fast
+    pub fn open(path: &Path, speed: f64) -> MediaResult<Self> {
+        if !(speed.is_finite() && speed > 0.0) {
+            return Err(MediaError::invalid_input("Replay speed must be above 0"));
+        }
+        Ok(Self {
+            events: Arc::new(load(path)?),
+            speed,
+            state: Arc::default(),
+            running: None,
+        })
+    }
+
+    fn state(&self) -> MediaResult<MutexGuard<'_, ReplayState>> {
+        self.state.lock().map_err(MediaError::internal)
+    }
+}
+
+impl Drop for ReplayBackend {
+    fn drop(&mut self) {
+        self.unwatch();
+    }
+}
+
+impl MediaBackend for ReplayBackend {
+    fn session_state(&self) -> SessionState {
+        let session = self.state().ok().and_then(|state| state.session().cloned());
+        SessionState {
+            state: match session {
+                Some(_) => SessionStatus::Active,
+                None => SessionStatus::None,
+            },
+            session,
+        }
+    }
+
+    fn sessions(&self) -> MediaResult<Vec<SessionInfo>> {
+        Ok(self.state()?.session().cloned().into_iter().collect())
+    }
+
+    fn current_app_id(&self) -> MediaResult<String> {
+        let state = self.state()?;
+        let session = state.session().ok_or(MediaError::NoSession)?;
+        Ok(session.source_app_id.clone())
+    }
+
+    fn is_playing(&self) -> MediaResult<bool> {
+        Ok(self.track_controls()?.playing())
+    }
+
+    fn track_info(&self) -> MediaResult<TrackInfo> {
+        self.state()?.track.clone().ok_or(MediaError::NoSession)
+    }
+
+    fn track_controls(&self) -> MediaResult<TrackControls> {
+        self.state()?.controls.clone().ok_or(MediaError::NoSession)
+    }
+
+    fn track_timeline(&self) -> MediaResult<TrackTimeline> {
+        self.state()?.timeline.clone().ok_or(MediaError::NoSession)
+    }
+
+    fn artwork(&self) -> MediaResult<Vec<u8>> {
+        let track = self.track_info()?;
+        track
+            .thumbnail
+            .as_deref()
+            .and_then(utils::decode_base64_image)
+            .ok_or_else(|| MediaError::unsupported("artwork"))
+    }
+
+    fn execute(&self, command: &Command) -> MediaResult<bool> {
+        Err(MediaError::unsupported(command.name()))
+    }
+
+    fn watch(&mut self, notify: ChangeNotifier) -> MediaResult<()> {
+        self.unwatch();
+        let running = Arc::new(AtomicBool::new(true));
+        let events = Arc::clone(&self.events);
+        let state = Arc::clone(&self.state);
+        let speed = self.speed;
+        let replaying = Arc::clone(&running);
+        thread::Builder::new()
+            .name("replay-backend".to_string())
+            .spawn(move || replay(&events, speed, &state, &notify, &replaying))
+            .map_err(MediaError::internal)?;
+        self.running = Some(running);
+        Ok(())
+    }
+
+    fn unwatch(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn handler_count(&self) -> usize {
+        usize::from(self.running.is_some())
+    }
+}
+
+/// Feed the recording in from the start, keeping its timing
+fn replay(
+    events: &[RecordedEvent],
+    speed: f64,
+    state: &Mutex<ReplayState>,
+    notify: &ChangeNotifier,
+    running: &AtomicBool,
+) {
+    if let Ok(mut state) = state.lock() {
+        *state = ReplayState::default();
+    }
+    let started = Instant::now();
+    for recorded in events {
+        let due = Duration::from_secs_f64(recorded.at as f64 / 1000.0 / speed);
+        if let Some(wait) = due.checked_sub(started.elapsed()) {
+            thread::sleep(wait);
+        }
+        if !running.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let change = match state.lock() {
+            Ok(mut state) => state.apply(recorded.event.clone()),
+            Err(_) => return,
+        };
+        notify(change);
+    }
+    tracing::info!("Replay finished after {} events", events.len());
+}
@@ -0,0 +1,284 @@
+use crate::error::MediaResult;
+
+/// Change notifications a media session offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// Title, artist or artwork changed
+    MediaProperties,
+    /// Playback status or available controls changed
+    PlaybackInfo,
+    /// Position or length changed
+    TimelineProperties,
+}
+
+const SESSION_EVENTS: [SessionEvent; 3] = [
+    SessionEvent::MediaProperties,
+    SessionEvent::PlaybackInfo,
+    SessionEvent::TimelineProperties,
+];
+
+pub type SessionCallback = Box<dyn FnMut() + Send + 'static>;
+
+/// A media session that change handlers can be registered on
+pub trait WatchedSession {
+    /// # Returns
+    /// * `MediaResult<i64>` - Token that removes the handler from this session
+    fn add_handler(&self, event: SessionEvent, callback: SessionCallback) -> MediaResult<i64>;
+
+    fn remove_handler(&self, event: SessionEvent, token: i64) -> MediaResult<()>;
+}
+
+/// A handler registered on a session, removed from that session when dropped.
+///
+/// A token is only valid on the session that issued it, so the guard keeps the
+/// session next to its token. Dropping it removes the handler even after another
+/// session became current, or while unwinding from a panic.
+pub struct Subscription<S: WatchedSession> {
+    session: S,
+    event: SessionEvent,
+    token: i64,
+}
+
+impl<S: WatchedSession + Clone> Subscription<S> {
+    /// Register a handler on a session
+    ///
+    /// # Arguments
+    /// * `session` - The session to watch
+    /// * `event` - The change to handle
+    /// * `callback` - Called on every change
+    pub fn new(session: &S, event: SessionEvent, callback: SessionCallback) -> MediaResult<Self> {
+        let token = session.add_handler(event, callback)?;
+        Ok(Self {
+            session: session.clone(),
+            event,
+            token,
+        })
+    }
+}
+
+impl<S: WatchedSession> Drop for Subscription<S> {
+    fn drop(&mut self) {
+        // Fails when the app already closed the session, which drops its handlers too
+        if let Err(e) = self.session.remove_handler(self.event, self.token) {
+            tracing::debug!("Failed to remove {:?} handler: {}", self.event, e);
+        }
+    }
+}
+
+/// Change handlers of the current media session
+pub struct SessionWatcher<S: WatchedSession> {
+    subscriptions: Vec<Subscription<S>>,
+}
+
+impl<S: WatchedSession> Default for SessionWatcher<S> {
+    fn default() -> Self {
+        Self {
+            subscriptions: Vec::new(),
+        }
+    }
+}
+
+impl<S: WatchedSession + Clone> SessionWatcher<S> {
+    /// Move the handlers to a session, removing them from the one watched before
+    ///
+    /// # Arguments
+    /// * `session` - The current session, `None` when there is none
+    /// * `callback` - Builds the handler for each event
+    pub fn watch(
+        &mut self,
+        session: Option<S>,
+        mut callback: impl FnMut(SessionEvent) -> SessionCallback,
+    ) {
+        self.clear();
+        let Some(session) = session else {
+            return;
+        };
+
+        for event in SESSION_EVENTS {
+            match Subscription::new(&session, event, callback(event)) {
+                Ok(subscription) => self.subscriptions.push(subscription),
+                Err(e) => tracing::error!("Failed to register {:?} handler: {}", event, e),
+            }
+        }
+    }
+
+    /// Remove every handler from the session that issued it
+    pub fn clear(&mut self) {
+        self.subscriptions.clear();
+    }
+
+    /// Handlers currently registered
+    pub fn handler_count(&self) -> usize {
+        self.subscriptions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::error::MediaError;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Call {
+        Add(u32, SessionEvent, i64),
+        Remove(u32, SessionEvent, i64),
+    }
+
+    /// Records calls and hands out tokens that are unique across sessions
+    #[derive(Clone)]
+    struct FakeSession {
+        id: u32,
+        log: Arc<Mutex<Vec<Call>>>,
+        failing: Option<SessionEvent>,
+    }
+
+    impl FakeSession {
+        fn new(id: u32, log: &Arc<Mutex<Vec<Call>>>) -> Self {
+            Self {
+                id,
+                log: Arc::clone(log),
+                failing: None,
+            }
+        }
+    }
+
+    impl WatchedSession for FakeSession {
+        fn add_handler(&self, event: SessionEvent, _: SessionCallback) -> MediaResult<i64> {
+            if self.failing == Some(event) {
+                return Err(MediaError::internal("registration failed"));
+            }
+            let mut log = self.log.lock().unwrap();
+            let token = log.len() as i64 + 1;
+            log.push(Call::Add(self.id, event, token));
+            Ok(token)
+        }
+
+        fn remove_handler(&self, event: SessionEvent, token: i64) -> MediaResult<()> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(Call::Remove(self.id, event, token));
+            Ok(())
+        }
+    }
+
+    fn noop(_: SessionEvent) -> SessionCallback {
+        Box::new(|| {})
+    }
+
+    fn removals(log: &Arc<Mutex<Vec<Call>>>) -> Vec<Call> {
+        log.lock()
+            .unwrap()
+            .iter()
+            .filter(|call| matches!(call, Call::Remove(..)))
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn registers_every_event_on_the_session() {
+        let log = Arc::default();
+        let mut watcher = SessionWatcher::default();
+        watcher.watch(Some(FakeSession::new(1, &log)), noop);
+
+        assert_eq!(watcher.handler_count(), 3);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                Call::Add(1, SessionEvent::MediaProperties, 1),
+                Call::Add(1, SessionEvent::PlaybackInfo, 2),
+                Call::Add(1, SessionEvent::TimelineProperties, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn removes_tokens_from_the_session_that_issued_them() {
+        let log = Arc::default();
+        let mut watcher = SessionWatcher::default();
+        watcher.watch(Some(FakeSession::new(1, &log)), noop);
+        watcher.watch(Some(FakeSession::new(2, &log)), noop);
+
+        assert_eq!(
+            removals(&log),
+            vec![
+                Call::Remove(1, SessionEvent::MediaProperties, 1),
+                Call::Remove(1, SessionEvent::PlaybackInfo, 2),
+                Call::Remove(1, SessionEvent::TimelineProperties, 3),
+            ]
+        );
+        assert_eq!(watcher.handler_count(), 3);
+    }
+
+    #[test]
+    fn clear_removes_everything_once() {
+        let log = Arc::default();
+        let mut watcher = SessionWatcher::default();
+        watcher.watch(Some(FakeSession::new(1, &log)), noop);
+        watcher.clear();
+        watcher.clear();
+
+        assert_eq!(removals(&log).len(), 3);
+        assert_eq!(watcher.handler_count(), 0);
+    }
+
+    #[test]
+    fn losing_the_session_removes_its_handlers() {
+        let log = Arc::default();
+        let mut watcher = SessionWatcher::default();
+        watcher.watch(Some(FakeSession::new(1, &log)), noop);
+        watcher.watch(None, noop);
+
+        assert_eq!(removals(&log).len(), 3);
+        assert_eq!(watcher.handler_count(), 0);
+    }
+
+    #[test]
+    fn failed_registrations_are_not_tracked() {
+        let log = Arc::default();
+        let mut session = FakeSession::new(1, &log);
+        session.failing = Some(SessionEvent::PlaybackInfo);
+
+        let mut watcher = SessionWatcher::default();
+        watcher.watch(Some(session), noop);
+        assert_eq!(watcher.handler_count(), 2);
+
+        watcher.clear();
+        assert!(
+            !removals(&log)
+                .iter()
+                .any(|call| matches!(call, Call::Remove(_, SessionEvent::PlaybackInfo, _)))
+        );
+    }
+
+    #[test]
+    fn dropping_a_subscription_removes_its_handler() {
+        let log = Arc::default();
+        let session = FakeSession::new(1, &log);
+        let subscription =
+            Subscription::new(&session, SessionEvent::TimelineProperties, Box::new(|| {})).unwrap();
+        assert!(removals(&log).is_empty());
+
+        drop(subscription);
+        assert_eq!(
+            removals(&log),
+            vec![Call::Remove(1, SessionEvent::TimelineProperties, 1)]
+        );
+    }
+
+    #[test]
+    fn handlers_are_removed_when_unwinding() {
+        let log = Arc::default();
+        let session = FakeSession::new(1, &log);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut watcher = SessionWatcher::default();
+            watcher.watch(Some(session), noop);
+            panic!("listener setup failed");
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(removals(&log).len(), 3);
+    }
+}
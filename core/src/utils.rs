@@ -0,0 +1,85 @@
+use base64::Engine;
+use base64::engine::general_purpose;
+
+/// Embed image bytes as a data URL, typed by their content
+pub fn encode_image_to_base64(bytes: &[u8]) -> String {
+    let encoder = general_purpose::STANDARD;
+    format!(
+        "data:{};base64,{}",
+        image_content_type(bytes),
+        encoder.encode(bytes)
+    )
+}
+
+/// MIME type of an image, detected from its magic bytes
+///
+/// # Arguments
+/// * `bytes` - The encoded image
+///
+/// # Returns
+/// * `&'static str` - `image/png`, `image/gif`, `image/webp` or `image/bmp`, and
+///   `image/jpeg` for anything else, which is what apps send most
+pub fn image_content_type(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        "image/webp"
+    } else if bytes.starts_with(b"BM") {
+        "image/bmp"
+    } else {
+        "image/jpeg"
+    }
+}
+
+/// Read the bytes back out of a data URL made by `encode_image_to_base64`
+pub fn decode_base64_image(data_url: &str) -> Option<Vec<u8>> {
+    let (_, encoded) = data_url.split_once(";base64,")?;
+    general_purpose::STANDARD.decode(encoded).ok()
+}
+
+/// Derive a stable, short session identifier from an App User Model ID
+///
+/// # Arguments
+/// * `app_id` - The App User Model ID of the session's source app
+///
+/// # Returns
+/// * `String` - 16 character hex FNV-1a hash of the app id
+pub fn session_id_from_app_id(app_id: &str) -> String {
+    content_hash(app_id.to_lowercase().as_bytes())
+}
+
+/// Short, stable hash of arbitrary bytes, not suitable for anything security related
+///
+/// # Arguments
+/// * `bytes` - The content to hash
+///
+/// # Returns
+/// * `String` - 16 character hex FNV-1a hash
+pub fn content_hash(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Best effort friendly name for apps without package display info
+///
+/// # Arguments
+/// * `app_id` - The App User Model ID, e.g. `Spotify.exe` or `Microsoft.ZuneMusic_8wekyb3d8bbwe!Microsoft.ZuneMusic`
+///
+/// # Returns
+/// * `String` - The app name without package family or executable extension
+pub fn app_name_from_id(app_id: &str) -> String {
+    let name = app_id.rsplit('!').next().unwrap_or(app_id);
+    let name = name.rsplit(['\\', '/']).next().unwrap_or(name);
+    let name = name
+        .strip_suffix(".exe")
+        .or_else(|| name.strip_suffix(".EXE"))
+        .unwrap_or(name);
+    let name = name.rsplit('.').next().unwrap_or(name);
+    name.to_string()
+}
@@ -0,0 +1,82 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use windows::core::RuntimeType;
+use windows_future::{
+    AsyncOperationCompletedHandler, AsyncOperationWithProgressCompletedHandler, IAsyncOperation,
+    IAsyncOperationWithProgress,
+};
+
+use crate::error::{MediaError, MediaResult};
+
+/// Longest a WinRT operation is waited for unless configured otherwise
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Block on a WinRT async operation like `.get()` does, but give up after a timeout
+///
+/// `.get()` waits forever, and some apps never complete requests to their session,
+/// which would freeze the media thread and every handler waiting on it.
+pub trait Wait {
+    type Output;
+
+    /// Wait for the operation to complete, cancelling it when it takes too long
+    ///
+    /// # Arguments
+    /// * `call` - Named in the log and the error, e.g. `TryPlayAsync`
+    /// * `timeout` - Longest wait
+    ///
+    /// # Returns
+    /// * `MediaResult<Self::Output>` - `MediaError::Timeout` when it didn't complete in time
+    fn wait(self, call: &str, timeout: Duration) -> MediaResult<Self::Output>;
+}
+
+impl<T: RuntimeType + 'static> Wait for IAsyncOperation<T> {
+    type Output = T;
+
+    fn wait(self, call: &str, timeout: Duration) -> MediaResult<T> {
+        let (sender, completed) = mpsc::sync_channel(1);
+        self.SetCompleted(&AsyncOperationCompletedHandler::new(move |_, _| {
+            sender.try_send(()).ok();
+            Ok(())
+        }))?;
+        finish(&completed, call, timeout, || self.Cancel())?;
+        Ok(self.GetResults()?)
+    }
+}
+
+impl<T: RuntimeType + 'static, P: RuntimeType + 'static> Wait
+    for IAsyncOperationWithProgress<T, P>
+{
+    type Output = T;
+
+    fn wait(self, call: &str, timeout: Duration) -> MediaResult<T> {
+        let (sender, completed) = mpsc::sync_channel(1);
+        self.SetCompleted(&AsyncOperationWithProgressCompletedHandler::new(
+            move |_, _| {
+                sender.try_send(()).ok();
+                Ok(())
+            },
+        ))?;
+        finish(&completed, call, timeout, || self.Cancel())?;
+        Ok(self.GetResults()?)
+    }
+}
+
+/// Wait for the completed handler, the handler runs right away for completed operations
+fn finish(
+    completed: &mpsc::Receiver<()>,
+    call: &str,
+    timeout: Duration,
+    cancel: impl FnOnce() -> windows::core::Result<()>,
+) -> MediaResult<()> {
+    if completed.recv_timeout(timeout).is_ok() {
+        return Ok(());
+    }
+    tracing::warn!(
+        "WinRT call {} did not complete within {:?}, cancelling it",
+        call,
+        timeout
+    );
+    cancel().ok();
+    Err(MediaError::timeout(call))
+}
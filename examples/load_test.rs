@@ -0,0 +1,150 @@
+//! Connects simulated Socket.IO clients to a running server and reports how long
+//! commands take to be acknowledged and answered while all of them are busy.
+//!
+//! ```bash
+//! cargo run --release --features bench --example load_test -- --clients 50
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use futures_util::FutureExt;
+use rust_socketio::Payload;
+use rust_socketio::asynchronous::{Client, ClientBuilder};
+use serde_json::json;
+use tokio::sync::oneshot;
+
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// URL of the Socket.IO server, including the base path if one is set
+    #[arg(long, default_value = "http://127.0.0.1:5174")]
+    server: String,
+
+    /// Simulated clients, connected at the same time
+    #[arg(long, default_value_t = 20)]
+    clients: usize,
+
+    /// Commands each client sends, one after another
+    #[arg(long, default_value_t = 50)]
+    commands: usize,
+
+    /// Function to call, read-only by default so playback isn't disturbed
+    #[arg(long, default_value = "get_state_snapshot")]
+    command: String,
+
+    /// Event answering the command, its arrival is the emit latency
+    #[arg(long, default_value = "state_snapshot")]
+    answer: String,
+}
+
+/// Latencies of one client
+#[derive(Default)]
+struct Samples {
+    acks: Vec<Duration>,
+    answers: Vec<Duration>,
+    failures: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Arc::new(Args::parse());
+    println!(
+        "{} clients sending {} x {} to {}",
+        args.clients, args.commands, args.command, args.server
+    );
+
+    let started = Instant::now();
+    let tasks: Vec<_> = (0..args.clients)
+        .map(|_| tokio::spawn(run_client(Arc::clone(&args))))
+        .collect();
+
+    let mut total = Samples::default();
+    for task in tasks {
+        let samples = task.await??;
+        total.acks.extend(samples.acks);
+        total.answers.extend(samples.answers);
+        total.failures += samples.failures;
+    }
+
+    let elapsed = started.elapsed();
+    println!(
+        "{} commands in {:.2?}, {:.0} per second, {} failed",
+        total.acks.len(),
+        elapsed,
+        total.acks.len() as f64 / elapsed.as_secs_f64(),
+        total.failures
+    );
+    report("ack", &mut total.acks);
+    report(&args.answer, &mut total.answers);
+    Ok(())
+}
+
+async fn run_client(args: Arc<Args>) -> Result<Samples> {
+    // Set right before a command is sent, so the answer can be timed against it
+    let sent_at: Arc<Mutex<Option<Instant>>> = Arc::default();
+    let answers: Arc<Mutex<Vec<Duration>>> = Arc::default();
+
+    let on_answer = {
+        let sent_at = Arc::clone(&sent_at);
+        let answers = Arc::clone(&answers);
+        move |_: Payload, _: Client| {
+            // The snapshot sent on connect isn't an answer to anything
+            if let Some(sent_at) = sent_at.lock().unwrap().take() {
+                answers.lock().unwrap().push(sent_at.elapsed());
+            }
+            async {}.boxed()
+        }
+    };
+    let client = ClientBuilder::new(args.server.as_str())
+        .on(args.answer.as_str(), on_answer)
+        .connect()
+        .await
+        .with_context(|| format!("Failed to connect to {}", args.server))?;
+
+    let mut samples = Samples::default();
+    for _ in 0..args.commands {
+        let (sender, receiver) = oneshot::channel();
+        let sender = Arc::new(Mutex::new(Some(sender)));
+        let on_ack = move |_: Payload, _: Client| {
+            if let Some(sender) = sender.lock().unwrap().take() {
+                sender.send(()).ok();
+            }
+            async {}.boxed()
+        };
+
+        let started = Instant::now();
+        *sent_at.lock().unwrap() = Some(started);
+        let emitted = client
+            .emit_with_ack(args.command.as_str(), json!({}), ACK_TIMEOUT, on_ack)
+            .await;
+        match (emitted, tokio::time::timeout(ACK_TIMEOUT, receiver).await) {
+            (Ok(()), Ok(Ok(()))) => samples.acks.push(started.elapsed()),
+            _ => samples.failures += 1,
+        }
+    }
+
+    client.disconnect().await.ok();
+    samples.answers = std::mem::take(&mut answers.lock().unwrap());
+    Ok(samples)
+}
+
+fn report(name: &str, samples: &mut [Duration]) {
+    if samples.is_empty() {
+        println!("{:>16}: no samples", name);
+        return;
+    }
+    samples.sort_unstable();
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+    println!(
+        "{:>16}: p50 {:.2?}  p95 {:.2?}  p99 {:.2?}  max {:.2?}",
+        name,
+        percentile(50),
+        percentile(95),
+        percentile(99),
+        samples[samples.len() - 1]
+    );
+}
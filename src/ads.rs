@@ -0,0 +1,55 @@
+use std::sync::Mutex;
+
+use crate::error::MediaResult;
+use crate::media_manager::TrackInfo;
+use crate::volume::Volume;
+
+/// Mutes the app owning the session during ads and restores its level afterwards
+#[derive(Default)]
+pub struct AdMuter {
+    /// App id and previous level of the muted app
+    muted: Mutex<Option<(String, f32)>>,
+}
+
+impl AdMuter {
+    /// Mute or unmute for a new track
+    pub fn update(&self, track: &TrackInfo) {
+        let Ok(mut muted) = self.muted.lock() else {
+            return;
+        };
+
+        if track.is_advertisement {
+            if muted.is_some() {
+                return;
+            }
+            let app_id = &track.session.source_app_id;
+            match mute(app_id) {
+                Ok(level) => {
+                    tracing::info!("Muted {} during an ad", app_id);
+                    *muted = Some((app_id.clone(), level));
+                }
+                Err(e) => tracing::error!("Failed to mute {} during an ad: {}", app_id, e),
+            }
+            return;
+        }
+
+        // Also restores the app when another session took over mid-ad
+        if let Some((app_id, level)) = muted.take() {
+            match Volume::app(&app_id).and_then(|volume| volume.set_level(level)) {
+                Ok(()) => tracing::info!("Unmuted {} after an ad", app_id),
+                Err(e) => tracing::error!("Failed to unmute {} after an ad: {}", app_id, e),
+            }
+        }
+    }
+}
+
+/// Silence an app
+///
+/// # Returns
+/// * `MediaResult<f32>` - The level it had before
+fn mute(app_id: &str) -> MediaResult<f32> {
+    let volume = Volume::app(app_id)?;
+    let level = volume.level()?;
+    volume.set_level(0.0)?;
+    Ok(level)
+}
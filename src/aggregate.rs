@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use tokio::time::MissedTickBehavior;
+
+use crate::config::AggregateConfig;
+use crate::events::{EventHub, MediaEvent};
+
+/// Broadcast every session whenever one of them changed, only the current session
+/// is watched so the others are read on a timer
+pub async fn run(config: AggregateConfig, hub: EventHub) {
+    tracing::info!("Broadcasting every media session");
+    let mut ticker = tokio::time::interval(Duration::from_millis(config.interval_ms.max(250)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut last = None;
+    loop {
+        ticker.tick().await;
+        let reader = hub.clone();
+        let feeds = match tokio::task::spawn_blocking(move || reader.session_feeds()).await {
+            Ok(Ok(feeds)) => feeds,
+            Ok(Err(e)) => {
+                tracing::debug!("Failed to read the media sessions: {}", e);
+                continue;
+            }
+            Err(e) => {
+                tracing::error!("Session feed task failed: {}", e);
+                continue;
+            }
+        };
+        // Track info has no equality, its payload has
+        let payload = serde_json::to_value(&feeds).ok();
+        if last != payload {
+            hub.publish_sessions(feeds);
+            last = payload;
+        }
+    }
+}
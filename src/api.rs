@@ -0,0 +1,379 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::Instant;
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+use crate::clients::ClientInfo;
+use crate::error::MediaError;
+use crate::events::{
+    ClientTarget, CommandAck, EmittedState, EventHub, HandlerCounts, MediaCommand, MediaEvent,
+};
+use crate::history::{self, ExportFormat};
+use crate::media_manager::SessionState;
+use crate::persistence::HistoryEntry;
+use crate::scheduler::{SleepTimerRequest, SleepTimerStatus};
+use crate::timings::{self, Operation};
+
+/// Seconds `GET /api/state` waits for a change by default
+const DEFAULT_POLL_TIMEOUT: u64 = 30;
+/// Longest wait of `GET /api/state`, proxies tend to cut idle requests after a minute
+const MAX_POLL_TIMEOUT: u64 = 55;
+
+/// OpenAPI description of the routes under `/api`
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "media-controller"),
+    paths(
+        events_handler,
+        state_handler,
+        status_handler,
+        command_handler,
+        kick_handler,
+        sleep_timer_handler,
+        set_sleep_timer_handler,
+        cancel_sleep_timer_handler,
+        history_handler,
+        history_export_handler,
+    )
+)]
+struct ApiDoc;
+
+/// REST and streaming routes under `/api`
+pub fn router(hub: EventHub) -> Router {
+    // Relative to `/api/docs/`, so the UI keeps working below a base path
+    let swagger_ui = SwaggerUi::new("/api/docs").config(Config::new(["../openapi.json"]));
+
+    Router::new()
+        .route("/api/openapi.json", get(openapi_handler))
+        .merge(swagger_ui)
+        .route("/api/events", get(events_handler))
+        .route("/api/state", get(state_handler))
+        .route("/api/status", get(status_handler))
+        .route("/api/command", post(command_handler))
+        .route("/api/admin/kick", post(kick_handler))
+        .route("/api/history", get(history_handler))
+        .route("/api/history/export", get(history_export_handler))
+        .route(
+            "/api/sleep-timer",
+            get(sleep_timer_handler)
+                .post(set_sleep_timer_handler)
+                .delete(cancel_sleep_timer_handler),
+        )
+        .with_state(hub)
+}
+
+async fn openapi_handler() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Server-sent events stream of every media event, starting with the current state
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    responses((
+        status = 200,
+        description = "Events named by their code with the Socket.IO payload as data",
+        body = String,
+        content_type = "text/event-stream",
+    ))
+)]
+async fn events_handler(
+    State(hub): State<EventHub>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = hub.subscribe();
+    let listener = hub.clients().listen();
+
+    // Send the current state first so consumers don't sit empty until the next change
+    let initial = tokio::task::spawn_blocking(move || hub.current_state())
+        .await
+        .unwrap_or_default();
+
+    // Ending the stream on shutdown lets the server drain instead of waiting on it
+    let updates = BroadcastStream::new(receiver)
+        .filter_map(|event| event.ok())
+        .take_while(|event| !matches!(event, MediaEvent::ServerShutdown))
+        .chain(tokio_stream::once(MediaEvent::ServerShutdown));
+    let stream = tokio_stream::iter(initial)
+        .chain(updates)
+        .filter_map(|event| {
+            Event::default()
+                .event(event.name())
+                .json_data(event.payload())
+                .ok()
+        })
+        // Dropped with the stream when the consumer disconnects
+        .map(move |event| {
+            let _ = &listener;
+            Ok(event)
+        });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+struct StateQuery {
+    /// `etag` of the state the client has, the request is answered once it changes
+    since: Option<String>,
+    /// Seconds to wait for a change, at most 55
+    #[serde(default = "default_poll_timeout")]
+    timeout: u64,
+}
+
+fn default_poll_timeout() -> u64 {
+    DEFAULT_POLL_TIMEOUT
+}
+
+/// Long-poll for the state, for networks that break WebSockets and event streams
+#[utoipa::path(
+    get,
+    path = "/api/state",
+    params(StateQuery),
+    responses(
+        (status = 200, description = "The state differs from `since`", body = EmittedState),
+        (status = 304, description = "Nothing changed before the timeout, poll again"),
+    )
+)]
+async fn state_handler(State(hub): State<EventHub>, Query(query): Query<StateQuery>) -> Response {
+    // Subscribed before reading the state so no change slips in between
+    let mut receiver = hub.subscribe();
+    let deadline = Instant::now() + Duration::from_secs(query.timeout.min(MAX_POLL_TIMEOUT));
+    loop {
+        let reader = hub.clone();
+        let Ok(state) = tokio::task::spawn_blocking(move || reader.emitted_state()).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+        let etag = format!("\"{}\"", state.etag);
+        if query.since.as_deref() != Some(state.etag.as_str()) {
+            return ([(header::ETAG, etag)], Json(state)).into_response();
+        }
+        if !next_state_event(&mut receiver, deadline).await {
+            return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+        }
+    }
+}
+
+/// Wait for a state event
+///
+/// # Returns
+/// * `bool` - `false` when the deadline passed or the server is shutting down
+async fn next_state_event(
+    receiver: &mut broadcast::Receiver<MediaEvent>,
+    deadline: Instant,
+) -> bool {
+    loop {
+        match tokio::time::timeout_at(deadline, receiver.recv()).await {
+            Ok(Ok(MediaEvent::ServerShutdown)) | Ok(Err(RecvError::Closed)) | Err(_) => {
+                return false;
+            }
+            Ok(Ok(event)) if event.is_state() => return true,
+            // Missed events may have changed the state
+            Ok(Err(RecvError::Lagged(_))) => return true,
+            Ok(Ok(_)) => {}
+        }
+    }
+}
+
+/// Payload of `GET /api/status`
+#[derive(Debug, Serialize, ToSchema)]
+struct ServerStatus {
+    version: &'static str,
+    /// Seconds since the service started
+    uptime: u64,
+    session: Option<SessionState>,
+    clients: Vec<ClientInfo>,
+    handlers: HandlerCounts,
+    /// Slowest of the recent commands and media calls, slowest first
+    slowest: Vec<Operation>,
+}
+
+/// Debug overview of the service and who is connected to it
+#[utoipa::path(
+    get,
+    path = "/api/status",
+    responses((status = 200, body = ServerStatus))
+)]
+async fn status_handler(State(hub): State<EventHub>) -> Response {
+    let session = hub.session_state().await.ok();
+
+    Json(ServerStatus {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime: hub.uptime().as_secs(),
+        session,
+        clients: hub.clients().list(),
+        handlers: hub.handler_counts(),
+        slowest: timings::slowest(),
+    })
+    .into_response()
+}
+
+/// Answer of `POST /api/command`
+#[derive(Debug, Serialize, ToSchema)]
+struct CommandResult {
+    #[serde(flatten)]
+    ack: CommandAck,
+    /// Event answering the command, e.g. `state_snapshot` for `get_state_snapshot`
+    #[schema(value_type = Option<Object>)]
+    event: Option<MediaEvent>,
+}
+
+/// Run any function, with the same `{ "event": "<code>", "data": <payload> }` body as the plain WebSocket
+#[utoipa::path(
+    post,
+    path = "/api/command",
+    request_body(content = Object, description = "Function code and payload"),
+    responses(
+        (status = 200, body = CommandResult),
+        (status = 400, description = "Invalid payload", body = CommandResult),
+        (status = 409, description = "No session, or the session doesn't support it", body = CommandResult),
+        (status = 504, description = "The app didn't answer in time", body = CommandResult),
+    )
+)]
+async fn command_handler(
+    State(hub): State<EventHub>,
+    Json(command): Json<MediaCommand>,
+) -> Response {
+    let (status, result) = execute(hub, command).await;
+    (status, Json(result)).into_response()
+}
+
+/// Disconnect a Socket.IO or WebSocket client
+#[utoipa::path(
+    post,
+    path = "/api/admin/kick",
+    request_body = ClientTarget,
+    responses(
+        (status = 200, body = CommandAck),
+        (status = 400, description = "No client with this id", body = CommandAck),
+    )
+)]
+async fn kick_handler(State(hub): State<EventHub>, Json(target): Json<ClientTarget>) -> Response {
+    run_command(hub, MediaCommand::AdminKick(target)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/sleep-timer",
+    responses((status = 200, body = SleepTimerStatus))
+)]
+async fn sleep_timer_handler(State(hub): State<EventHub>) -> Response {
+    Json(hub.sleep_timer_status()).into_response()
+}
+
+/// Recently played tracks, newest first, kept across restarts
+#[utoipa::path(
+    get,
+    path = "/api/history",
+    responses((status = 200, body = Vec<HistoryEntry>))
+)]
+async fn history_handler(State(hub): State<EventHub>) -> Response {
+    Json(hub.history()).into_response()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+struct ExportQuery {
+    /// `json` or `csv`, JSON when left out
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// Every played track kept, newest first, as a file download
+#[utoipa::path(
+    get,
+    path = "/api/history/export",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "JSON array or CSV with a header row", body = Vec<HistoryEntry>)
+    )
+)]
+async fn history_export_handler(
+    State(hub): State<EventHub>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let format = query.format;
+    let disposition = format!("attachment; filename=\"history.{}\"", format.extension());
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        history::export(hub.full_history(), format),
+    )
+        .into_response()
+}
+
+/// Pause playback at a time, replacing the running timer
+#[utoipa::path(
+    post,
+    path = "/api/sleep-timer",
+    request_body = SleepTimerRequest,
+    responses(
+        (status = 200, body = CommandAck),
+        (status = 400, description = "Neither or both of minutes and at, or over a day ahead", body = CommandAck),
+    )
+)]
+async fn set_sleep_timer_handler(
+    State(hub): State<EventHub>,
+    Json(request): Json<SleepTimerRequest>,
+) -> Response {
+    run_command(hub, MediaCommand::SetSleepTimer(request)).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/sleep-timer",
+    responses((status = 200, body = CommandAck))
+)]
+async fn cancel_sleep_timer_handler(State(hub): State<EventHub>) -> Response {
+    run_command(hub, MediaCommand::CancelSleepTimer).await
+}
+
+/// Execute a command and answer with its acknowledgement
+async fn run_command(hub: EventHub, command: MediaCommand) -> Response {
+    let (status, result) = execute(hub, command).await;
+    (status, Json(result.ack)).into_response()
+}
+
+async fn execute(hub: EventHub, command: MediaCommand) -> (StatusCode, CommandResult) {
+    let span = timings::command_span("api", command.name());
+    match tokio::task::spawn_blocking(move || span.in_scope(|| hub.execute(command))).await {
+        Ok(Ok(event)) => (
+            StatusCode::OK,
+            CommandResult {
+                ack: CommandAck::ok(),
+                event,
+            },
+        ),
+        Ok(Err(e)) => {
+            let status = match e {
+                MediaError::InvalidInput { .. } => StatusCode::BAD_REQUEST,
+                MediaError::NoSession | MediaError::Unsupported { .. } => StatusCode::CONFLICT,
+                MediaError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let ack = CommandAck::error(e);
+            (status, CommandResult { ack, event: None })
+        }
+        Err(e) => {
+            let ack = CommandAck::error(e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                CommandResult { ack, event: None },
+            )
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+
+#[cfg(windows)]
+use anyhow::Context;
+use anyhow::Result;
+use axum::{
+    Router,
+    extract::Path,
+    http::{StatusCode, header},
+    response::IntoResponse,
+    routing::get,
+};
+#[cfg(windows)]
+use windows::{
+    ApplicationModel::AppInfo,
+    Foundation::Size,
+    Storage::{
+        FileProperties::ThumbnailMode,
+        StorageFile,
+        Streams::{Buffer, DataReader, InputStreamOptions},
+    },
+    Win32::{
+        Foundation::CloseHandle,
+        System::{
+            Diagnostics::ToolHelp::{
+                CreateToolhelp32Snapshot, PROCESSENTRY32W, Process32FirstW, Process32NextW,
+                TH32CS_SNAPPROCESS,
+            },
+            Threading::{
+                OpenProcess, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+                QueryFullProcessImageNameW,
+            },
+        },
+    },
+    core::{HSTRING, Interface, PWSTR},
+};
+
+#[cfg(windows)]
+use crate::media_manager::read_stream_reference;
+use crate::utils;
+#[cfg(windows)]
+use crate::winrt::{DEFAULT_TIMEOUT, Wait};
+
+const ICON_SIZE: f32 = 256.0;
+const ICON_CACHE_DIR: &str = "app-icons";
+
+/// Route serving the icon of the app owning a media session as PNG
+pub fn router() -> Router {
+    Router::new().route("/app-icon/{app_id}", get(app_icon_handler))
+}
+
+async fn app_icon_handler(Path(app_id): Path<String>) -> impl IntoResponse {
+    let result = tokio::task::spawn_blocking(move || {
+        let icon = load_app_icon(&app_id);
+        if let Err(e) = &icon {
+            tracing::warn!("Failed to get app icon for {}: {}", app_id, e);
+        }
+        icon
+    })
+    .await;
+
+    match result {
+        Ok(Ok(bytes)) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "image/png"),
+                (header::CACHE_CONTROL, "public, max-age=86400"),
+            ],
+            bytes,
+        )
+            .into_response(),
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Get the icon for an app, from the disk cache when it was extracted before
+///
+/// # Arguments
+/// * `app_id` - App User Model ID of the session's source app
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - PNG encoded icon
+pub fn load_app_icon(app_id: &str) -> Result<Vec<u8>> {
+    let path = cache_path(app_id);
+    if let Ok(bytes) = std::fs::read(&path) {
+        return Ok(bytes);
+    }
+
+    let icon = extract_icon(app_id)?;
+    let png = encode_png(&icon)?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).ok();
+    }
+    if let Err(e) = std::fs::write(&path, &png) {
+        tracing::warn!("Failed to cache app icon at {}: {}", path.display(), e);
+    }
+
+    Ok(png)
+}
+
+fn cache_path(app_id: &str) -> PathBuf {
+    utils::cache_dir()
+        .join(ICON_CACHE_DIR)
+        .join(format!("{}.png", utils::session_id_from_app_id(app_id)))
+}
+
+fn encode_png(bytes: &[u8]) -> Result<Vec<u8>> {
+    let image = image::load_from_memory(bytes)?;
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, image::ImageFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+#[cfg(windows)]
+fn extract_icon(app_id: &str) -> Result<Vec<u8>> {
+    packaged_app_logo(app_id).or_else(|_| desktop_app_icon(app_id))
+}
+
+/// Icons are only extracted on Windows, cached ones are still served
+#[cfg(not(windows))]
+fn extract_icon(app_id: &str) -> Result<Vec<u8>> {
+    anyhow::bail!("No cached icon for {}", app_id)
+}
+
+/// Logo of a packaged (UWP/MSIX) app, resolved through AppInfo
+#[cfg(windows)]
+fn packaged_app_logo(app_id: &str) -> Result<Vec<u8>> {
+    let info = AppInfo::GetFromAppUserModelId(&HSTRING::from(app_id))?;
+    let logo = info.DisplayInfo()?.GetLogo(Size {
+        Width: ICON_SIZE,
+        Height: ICON_SIZE,
+    })?;
+    Ok(read_stream_reference(&logo.cast()?, DEFAULT_TIMEOUT)?)
+}
+
+/// Shell icon of a desktop app's executable, found through its running process
+#[cfg(windows)]
+fn desktop_app_icon(app_id: &str) -> Result<Vec<u8>> {
+    let path = find_process_path(app_id)?;
+    let file = StorageFile::GetFileFromPathAsync(&HSTRING::from(path))?
+        .wait("GetFileFromPathAsync", DEFAULT_TIMEOUT)?;
+    let thumbnail = file
+        .GetThumbnailAsyncOverloadDefaultSizeDefaultOptions(ThumbnailMode::SingleItem)?
+        .wait("GetThumbnailAsync", DEFAULT_TIMEOUT)?;
+
+    let buf = Buffer::Create(thumbnail.Size()? as u32)?;
+    let stream = thumbnail
+        .ReadAsync(&buf, buf.Capacity()?, InputStreamOptions::ReadAhead)?
+        .wait("ReadAsync", DEFAULT_TIMEOUT)?;
+
+    let byte_reader = DataReader::FromBuffer(&stream)?;
+    let length = byte_reader.UnconsumedBufferLength()? as usize;
+    let mut bytes = vec![0u8; length];
+    byte_reader.ReadBytes(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// Find the running processes whose executable matches the app id
+///
+/// # Arguments
+/// * `app_id` - App User Model ID of a desktop app, e.g. `Spotify.exe`
+///
+/// # Returns
+/// * `Result<Vec<u32>>` - Process ids, empty when the app isn't running
+#[cfg(windows)]
+pub fn find_process_ids(app_id: &str) -> Result<Vec<u32>> {
+    let exe_name = app_id.rsplit(['\\', '/']).next().unwrap_or(app_id);
+    let exe_name = if exe_name.to_lowercase().ends_with(".exe") {
+        exe_name.to_lowercase()
+    } else {
+        format!("{}.exe", exe_name.to_lowercase())
+    };
+
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0)?;
+        let mut entry = PROCESSENTRY32W {
+            dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+            ..Default::default()
+        };
+
+        let mut process_ids = Vec::new();
+        let mut next = Process32FirstW(snapshot, &mut entry);
+        while next.is_ok() {
+            let len = entry
+                .szExeFile
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(entry.szExeFile.len());
+            let name = String::from_utf16_lossy(&entry.szExeFile[..len]);
+            if name.to_lowercase() == exe_name {
+                process_ids.push(entry.th32ProcessID);
+            }
+            next = Process32NextW(snapshot, &mut entry);
+        }
+        CloseHandle(snapshot).ok();
+
+        Ok(process_ids)
+    }
+}
+
+/// Find the executable path of a running process matching the app id
+#[cfg(windows)]
+fn find_process_path(app_id: &str) -> Result<String> {
+    let process_id = find_process_ids(app_id)?
+        .into_iter()
+        .next()
+        .context("No running process found for app")?;
+
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id)?;
+
+        let mut buffer = [0u16; 1024];
+        let mut size = buffer.len() as u32;
+        let result = QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buffer.as_mut_ptr()),
+            &mut size,
+        );
+        CloseHandle(process).ok();
+        result?;
+
+        Ok(String::from_utf16_lossy(&buffer[..size as usize]))
+    }
+}
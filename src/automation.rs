@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::clients::DevicePresence;
+use crate::config::{AutomationConfig, AutomationRule};
+use crate::events::{EventHub, MediaCommand};
+
+/// What a rule does when its device connects or disconnects
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomationAction {
+    Play,
+    Pause,
+    /// Play again, but only if this device's `pause` stopped playback
+    Resume,
+    Stop,
+}
+
+/// Run the rules whenever a paired device connects or disconnects
+pub async fn run(config: AutomationConfig, hub: EventHub) -> Result<()> {
+    tracing::info!(
+        "Automation running with {} rule(s) for {} paired device(s)",
+        config.rules.len(),
+        config.devices.len()
+    );
+
+    let mut receiver = hub.clients().presence();
+    // Devices whose rules paused playback, so resuming doesn't start music that was never playing
+    let mut paused_by = HashSet::new();
+    loop {
+        let presence = match receiver.recv().await {
+            Ok(presence) => presence,
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("Automation lagged behind by {} presence changes", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+
+        for rule in config
+            .rules
+            .iter()
+            .filter(|rule| rule.device == presence.device)
+        {
+            if let Some(action) = action_for(rule, &presence) {
+                apply(&hub, &presence.device, action, &mut paused_by).await;
+            }
+        }
+    }
+}
+
+fn action_for(rule: &AutomationRule, presence: &DevicePresence) -> Option<AutomationAction> {
+    if presence.connected {
+        rule.on_connect
+    } else {
+        rule.on_disconnect
+    }
+}
+
+async fn apply(
+    hub: &EventHub,
+    device: &str,
+    action: AutomationAction,
+    paused_by: &mut HashSet<String>,
+) {
+    let command = match action {
+        AutomationAction::Play => MediaCommand::Play,
+        AutomationAction::Stop => MediaCommand::Stop,
+        AutomationAction::Pause => {
+            if !is_playing(hub).await {
+                return;
+            }
+            paused_by.insert(device.to_string());
+            MediaCommand::Pause
+        }
+        AutomationAction::Resume => {
+            if !paused_by.remove(device) {
+                return;
+            }
+            MediaCommand::Play
+        }
+    };
+
+    tracing::info!("Device {} triggered {:?}", device, action);
+    let hub = hub.clone();
+    match tokio::task::spawn_blocking(move || hub.execute(command)).await {
+        Ok(Err(e)) => tracing::error!("Automation for {} failed: {}", device, e),
+        Err(e) => tracing::error!("Automation task for {} failed: {}", device, e),
+        Ok(Ok(_)) => {}
+    }
+}
+
+async fn is_playing(hub: &EventHub) -> bool {
+    let hub = hub.clone();
+    tokio::task::spawn_blocking(move || hub.state_snapshot())
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .and_then(|snapshot| snapshot.track_controls)
+        .is_some_and(|controls| controls.playing())
+}
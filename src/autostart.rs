@@ -0,0 +1,188 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand};
+use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+use windows::Win32::System::Registry::{
+    HKEY_CURRENT_USER, REG_SZ, RRF_RT_REG_SZ, RegDeleteKeyValueW, RegGetValueW, RegSetKeyValueW,
+};
+use windows::core::HSTRING;
+
+use crate::config::DEFAULT_CONFIG_FILE;
+
+/// Programs started when the user signs in, without administrator rights
+const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+const VALUE_NAME: &str = "media-controller";
+
+/// Start the server when signing in to Windows
+#[derive(Args, Debug)]
+pub struct AutostartArgs {
+    #[command(subcommand)]
+    command: AutostartCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum AutostartCommand {
+    /// Start this executable when signing in, run it again after moving the executable
+    Enable {
+        /// Config file the server starts with, `config.toml` of the current directory
+        /// when it has one
+        #[arg(long, short)]
+        config: Option<PathBuf>,
+
+        /// More server flags, after `--`, e.g. `-- -f --single-port`
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Stop starting the server when signing in
+    Disable,
+    /// Print the command started when signing in
+    Status,
+}
+
+pub fn run(args: AutostartArgs) -> Result<()> {
+    match args.command {
+        AutostartCommand::Enable { config, args } => {
+            let command = command_line(config.as_deref(), &args)?;
+            write_run_value(&command)?;
+            println!("The server starts when signing in, with: {}", command);
+        }
+        AutostartCommand::Disable => match delete_run_value()? {
+            true => println!("The server no longer starts when signing in"),
+            false => println!("The server wasn't started when signing in"),
+        },
+        AutostartCommand::Status => match read_run_value()? {
+            Some(command) => println!("The server starts when signing in, with: {}", command),
+            None => println!("The server doesn't start when signing in"),
+        },
+    }
+    Ok(())
+}
+
+/// The command of the Run key, with absolute paths as it starts in another directory
+///
+/// # Arguments
+/// * `config` - Config file to pass on, the default one of the current directory if `None`
+/// * `args` - Further server flags
+fn command_line(config: Option<&Path>, args: &[String]) -> Result<String> {
+    let executable = std::env::current_exe().context("Failed to find this executable")?;
+    let config = match config {
+        Some(path) => Some(
+            std::fs::canonicalize(path)
+                .with_context(|| format!("Config file {} not found", path.display()))?,
+        ),
+        None => std::fs::canonicalize(DEFAULT_CONFIG_FILE).ok(),
+    };
+
+    let mut parts = vec![quote(&executable.to_string_lossy())];
+    if let Some(config) = config {
+        parts.push("--config".to_string());
+        parts.push(quote(&plain_path(&config)));
+    }
+    parts.extend(args.iter().map(|arg| quote(arg)));
+    Ok(parts.join(" "))
+}
+
+/// Windows paths without the `\\?\` prefix `canonicalize` adds, which not every program accepts
+fn plain_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    path.strip_prefix(r"\\?\").unwrap_or(&path).to_string()
+}
+
+/// Quote an argument of a command line when it has spaces or quotes, so that
+/// `CommandLineToArgvW` splits it back out unchanged.
+///
+/// Backslashes are only special in front of a quote, where they are doubled, like
+/// before the closing quote of `"C:\My Dir\\"`.
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\u{b}', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => {
+                backslashes += 1;
+                continue;
+            }
+            '"' => quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1)),
+            _ => quoted.extend(std::iter::repeat_n('\\', backslashes)),
+        }
+        backslashes = 0;
+        quoted.push(c);
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+fn write_run_value(command: &str) -> Result<()> {
+    let data: Vec<u16> = command.encode_utf16().chain([0]).collect();
+    let result = unsafe {
+        RegSetKeyValueW(
+            HKEY_CURRENT_USER,
+            &HSTRING::from(RUN_KEY),
+            &HSTRING::from(VALUE_NAME),
+            REG_SZ.0,
+            Some(data.as_ptr().cast()),
+            (data.len() * size_of::<u16>()) as u32,
+        )
+    };
+    result.ok().context("Failed to write the Run registry key")
+}
+
+/// # Returns
+/// * `Result<bool>` - Whether there was a value to delete
+fn delete_run_value() -> Result<bool> {
+    let result = unsafe {
+        RegDeleteKeyValueW(
+            HKEY_CURRENT_USER,
+            &HSTRING::from(RUN_KEY),
+            &HSTRING::from(VALUE_NAME),
+        )
+    };
+    if result == ERROR_FILE_NOT_FOUND {
+        return Ok(false);
+    }
+    result
+        .ok()
+        .context("Failed to delete from the Run registry key")?;
+    Ok(true)
+}
+
+fn read_run_value() -> Result<Option<String>> {
+    let mut size = 0u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            &HSTRING::from(RUN_KEY),
+            &HSTRING::from(VALUE_NAME),
+            RRF_RT_REG_SZ,
+            None,
+            None,
+            Some(&mut size),
+        )
+    };
+    if result == ERROR_FILE_NOT_FOUND {
+        return Ok(None);
+    }
+    result.ok().context("Failed to read the Run registry key")?;
+
+    let mut data = vec![0u16; (size as usize).div_ceil(size_of::<u16>())];
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            &HSTRING::from(RUN_KEY),
+            &HSTRING::from(VALUE_NAME),
+            RRF_RT_REG_SZ,
+            None,
+            Some(data.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+    };
+    result.ok().context("Failed to read the Run registry key")?;
+
+    let command = String::from_utf16_lossy(&data);
+    Ok(Some(command.trim_end_matches('\0').to_string()))
+}
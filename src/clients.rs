@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::{Notify, broadcast, watch};
+use utoipa::ToSchema;
+
+use crate::error::{MediaError, MediaResult};
+use crate::utils;
+
+/// A connected client, as listed by `/api/status`
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct ClientInfo {
+    pub id: String,
+    /// `socket.io` or `websocket`
+    pub transport: &'static str,
+    /// Peer address, the proxy's when behind a reverse proxy
+    pub address: Option<String>,
+    /// Unix timestamp in milliseconds
+    pub connected_at: u64,
+    pub last_command: Option<LastCommand>,
+    /// Round-trip time of the last answered heartbeat in milliseconds
+    pub latency: Option<u64>,
+    /// Name of the paired device, set by a `hello` with a known pairing token
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct LastCommand {
+    /// Code of the command
+    pub name: &'static str,
+    /// Unix timestamp in milliseconds
+    pub at: u64,
+}
+
+/// A paired device came online with its first client or went offline with its last
+#[derive(Debug, Clone)]
+pub struct DevicePresence {
+    pub device: String,
+    pub connected: bool,
+}
+
+struct Client {
+    info: ClientInfo,
+    kick: Arc<Notify>,
+}
+
+/// Counts as a listener until dropped, for event streams that don't accept commands
+pub struct ListenerGuard {
+    listeners: Arc<watch::Sender<usize>>,
+}
+
+impl Drop for ListenerGuard {
+    fn drop(&mut self) {
+        self.listeners.send_modify(|count| *count -= 1);
+    }
+}
+
+/// Clients connected over any transport that accepts commands
+#[derive(Clone)]
+pub struct ClientRegistry {
+    clients: Arc<Mutex<HashMap<String, Client>>>,
+    presence: broadcast::Sender<DevicePresence>,
+    /// Registered clients plus event streams, anyone who sees media changes
+    listeners: Arc<watch::Sender<usize>>,
+}
+
+impl Default for ClientRegistry {
+    fn default() -> Self {
+        Self {
+            clients: Arc::default(),
+            presence: broadcast::channel(16).0,
+            listeners: Arc::new(watch::channel(0).0),
+        }
+    }
+}
+
+impl ClientRegistry {
+    /// Track a newly connected client
+    ///
+    /// # Arguments
+    /// * `id` - Unique id of the connection
+    /// * `transport` - Transport the client connected with
+    /// * `address` - Peer address, if known
+    ///
+    /// # Returns
+    /// * `Arc<Notify>` - Notified when the client is kicked, the transport must then disconnect it
+    pub fn register(
+        &self,
+        id: String,
+        transport: &'static str,
+        address: Option<SocketAddr>,
+    ) -> Arc<Notify> {
+        let kick = Arc::new(Notify::new());
+        let client = Client {
+            info: ClientInfo {
+                id: id.clone(),
+                transport,
+                address: address.map(|address| address.to_string()),
+                connected_at: utils::unix_millis(),
+                last_command: None,
+                latency: None,
+                device: None,
+            },
+            kick: Arc::clone(&kick),
+        };
+        let Ok(mut clients) = self.clients.lock() else {
+            return kick;
+        };
+        // Ids are unique, a reused one replaces the client instead of adding one
+        if clients.insert(id, client).is_none() {
+            self.listeners.send_modify(|count| *count += 1);
+        }
+        kick
+    }
+
+    pub fn unregister(&self, id: &str) {
+        let Ok(mut clients) = self.clients.lock() else {
+            return;
+        };
+        let Some(client) = clients.remove(id) else {
+            return;
+        };
+        self.listeners.send_modify(|count| *count -= 1);
+        let Some(device) = client.info.device else {
+            return;
+        };
+        if !is_online(&clients, &device) {
+            tracing::info!("Paired device {} disconnected", device);
+            self.announce(device, false);
+        }
+    }
+
+    /// Mark a client as a paired device
+    ///
+    /// # Arguments
+    /// * `id` - Id of the connection
+    /// * `device` - Name of the device its pairing token belongs to
+    pub fn identify(&self, id: &str, device: &str) {
+        let Ok(mut clients) = self.clients.lock() else {
+            return;
+        };
+        let already_online = is_online(&clients, device);
+        let Some(client) = clients.get_mut(id) else {
+            return;
+        };
+        client.info.device = Some(device.to_string());
+        if !already_online {
+            tracing::info!("Paired device {} connected", device);
+            self.announce(device.to_string(), true);
+        }
+    }
+
+    /// Count an event stream as a listener while the guard lives
+    pub fn listen(&self) -> ListenerGuard {
+        self.listeners.send_modify(|count| *count += 1);
+        ListenerGuard {
+            listeners: Arc::clone(&self.listeners),
+        }
+    }
+
+    /// Number of connected clients and event streams
+    pub fn listeners(&self) -> watch::Receiver<usize> {
+        self.listeners.subscribe()
+    }
+
+    /// Paired devices coming online or going offline
+    pub fn presence(&self) -> broadcast::Receiver<DevicePresence> {
+        self.presence.subscribe()
+    }
+
+    fn announce(&self, device: String, connected: bool) {
+        // Sending only fails when automation isn't running
+        self.presence
+            .send(DevicePresence { device, connected })
+            .ok();
+    }
+
+    /// Remember the latest command of a client
+    pub fn record_command(&self, id: &str, name: &'static str) {
+        let Ok(mut clients) = self.clients.lock() else {
+            return;
+        };
+        if let Some(client) = clients.get_mut(id) {
+            client.info.last_command = Some(LastCommand {
+                name,
+                at: utils::unix_millis(),
+            });
+        }
+    }
+
+    /// Remember the round-trip time of a client's last heartbeat
+    pub fn record_latency(&self, id: &str, latency: Duration) {
+        let Ok(mut clients) = self.clients.lock() else {
+            return;
+        };
+        if let Some(client) = clients.get_mut(id) {
+            client.info.latency = Some(latency.as_millis() as u64);
+        }
+    }
+
+    /// Connected clients, oldest connection first
+    pub fn list(&self) -> Vec<ClientInfo> {
+        let mut clients: Vec<ClientInfo> = self
+            .clients
+            .lock()
+            .map(|clients| clients.values().map(|client| client.info.clone()).collect())
+            .unwrap_or_default();
+        clients.sort_by_key(|client| client.connected_at);
+        clients
+    }
+
+    /// Ask the transport of a client to disconnect it
+    pub fn kick(&self, id: &str) -> MediaResult<()> {
+        let clients = self.clients.lock().map_err(MediaError::internal)?;
+        let client = clients
+            .get(id)
+            .ok_or_else(|| MediaError::invalid_input(format!("No client with id {}", id)))?;
+
+        tracing::info!("Kicking client {} ({})", id, client.info.transport);
+        // Stores a permit, so a kick isn't lost if the transport isn't waiting yet
+        client.kick.notify_one();
+        Ok(())
+    }
+}
+
+/// Whether any connected client is the given device
+fn is_online(clients: &HashMap<String, Client>, device: &str) -> bool {
+    clients
+        .values()
+        .any(|client| client.info.device.as_deref() == Some(device))
+}
@@ -0,0 +1,658 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use media_controller_core::profile::AppProfile;
+
+use crate::automation::AutomationAction;
+
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Settings loaded from the TOML config file
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub mqtt: Option<MqttConfig>,
+    pub media_keys: MediaKeysConfig,
+    pub lyrics: LyricsConfig,
+    pub metadata: MetadataConfig,
+    pub spotify: Option<SpotifyConfig>,
+    pub snapcast: Option<SnapcastConfig>,
+    pub display: Option<DisplayConfig>,
+    /// Files holding the current track, for OBS sources and scripts
+    pub now_playing: Option<NowPlayingConfig>,
+    /// Broadcast every media session rather than only the current one
+    pub aggregate: Option<AggregateConfig>,
+    /// Hours the volume is capped, e.g. at night
+    pub quiet_hours: Option<QuietHoursConfig>,
+    /// Only one session plays at a time, the others are paused
+    pub conflicts: Option<ConflictsConfig>,
+    /// MPRIS player on the D-Bus session bus, only on Linux
+    pub mpris: Option<MprisConfig>,
+    /// Mirror another machine's media in this PC's media flyout
+    pub publisher: Option<PublisherConfig>,
+    pub logging: LoggingConfig,
+    pub heartbeat: HeartbeatConfig,
+    pub idle: IdleConfig,
+    /// Pause playback after the host went without input for a while
+    pub inactivity: Option<InactivityConfig>,
+    /// Pause and resume playback with the lock of the workstation
+    pub lock: LockConfig,
+    /// Changes of the default audio output
+    pub output_devices: OutputDevicesConfig,
+    pub thumbnails: ThumbnailsConfig,
+    #[cfg(windows)]
+    pub winrt: WinRtConfig,
+    pub hooks: HooksConfig,
+    pub webhooks: Vec<WebhookConfig>,
+    /// Phone notifications through ntfy or Gotify
+    pub push: Vec<PushConfig>,
+    pub plugins: PluginsConfig,
+    pub automation: AutomationConfig,
+    /// Tracks skipped automatically, e.g. ads or disliked songs
+    pub skip: Vec<SkipRule>,
+    pub ads: AdsConfig,
+    /// Previews of seeks while a client drags its progress slider
+    pub scrubbing: ScrubbingConfig,
+    pub notifications: NotificationsConfig,
+    /// Corrections for misbehaving apps by source app id, e.g. `[apps."Spotify.exe"]`
+    pub apps: HashMap<String, AppProfile>,
+    /// Other servers to control from this one, `--peer` adds to them
+    pub peers: Vec<PeerConfig>,
+    /// File the config was loaded from, `None` for the defaults
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+/// GET-triggerable `/hook/<name>` endpoints, each needs a token to be enabled
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Token accepted by every hook without its own
+    pub token: Option<String>,
+    /// Tokens by hook name, e.g. `next = "..."`
+    pub tokens: HashMap<String, String>,
+}
+
+impl HooksConfig {
+    /// Token of a hook, `None` when the hook is disabled
+    pub fn token(&self, hook: &str) -> Option<&str> {
+        self.tokens
+            .get(hook)
+            .or(self.token.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// Handling of detected ad breaks
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct AdsConfig {
+    /// Mute the app during ads and restore its volume afterwards
+    pub mute: bool,
+}
+
+/// Seek previews sent while a progress slider is dragged, the release commits with `seek`
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ScrubbingConfig {
+    /// Seek to previews as they come in, so the drag is audible, rather than only on release
+    pub live: bool,
+    /// Mute the app while seeking live and restore its volume after the release
+    pub mute: bool,
+}
+
+impl Default for ScrubbingConfig {
+    fn default() -> Self {
+        Self {
+            live: false,
+            mute: true,
+        }
+    }
+}
+
+/// Native toast notifications mirroring track changes
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    pub enabled: bool,
+    /// App User Model ID the toasts are shown as, must be registered with a Start menu shortcut
+    pub app_id: Option<String>,
+    /// Templates of the two lines of the toast, with the fields of the `track_info` payload
+    pub title: Option<String>,
+    pub body: Option<String>,
+}
+
+/// Another media-controller server to federate with
+#[derive(Debug, Deserialize, Clone)]
+pub struct PeerConfig {
+    /// Addresses the peer in `peer_command`, defaults to the host of the URL
+    pub name: Option<String>,
+    /// URL of its Socket.IO server, including the base path if one is set
+    pub url: String,
+}
+
+impl PeerConfig {
+    /// Parse a `--peer` argument
+    ///
+    /// # Arguments
+    /// * `value` - A URL, or `name=URL` to name the peer
+    pub fn parse(value: &str) -> Self {
+        match value.split_once('=') {
+            Some((name, url)) if !name.contains("://") => Self {
+                name: Some(name.to_string()),
+                url: url.to_string(),
+            },
+            _ => Self {
+                name: None,
+                url: value.to_string(),
+            },
+        }
+    }
+
+    pub fn name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        let address = self
+            .url
+            .split_once("://")
+            .map_or(self.url.as_str(), |(_, rest)| rest);
+        address.split('/').next().unwrap_or(address).to_string()
+    }
+}
+
+/// Regex patterns a track must all match to be skipped, at least one is required
+#[derive(Debug, Deserialize, Clone)]
+pub struct SkipRule {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    /// Matched against the app name and the source app id
+    pub app: Option<String>,
+}
+
+/// Paired devices and the rules run when they connect or disconnect
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct AutomationConfig {
+    /// Pairing tokens by device name, a client sends its token with `hello`
+    pub devices: HashMap<String, String>,
+    pub rules: Vec<AutomationRule>,
+}
+
+impl AutomationConfig {
+    /// Name of the device a pairing token belongs to
+    pub fn device(&self, token: &str) -> Option<&str> {
+        self.devices
+            .iter()
+            .find(|(_, device_token)| device_token.as_str() == token)
+            .map(|(device, _)| device.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AutomationRule {
+    /// Name of a paired device
+    pub device: String,
+    pub on_connect: Option<AutomationAction>,
+    pub on_disconnect: Option<AutomationAction>,
+}
+
+/// Rhai scripts reacting to media events
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct PluginsConfig {
+    pub enabled: bool,
+    /// Directory of the `.rhai` scripts, `%LOCALAPPDATA%/media-controller/plugins` by default
+    pub directory: Option<PathBuf>,
+}
+
+/// Outgoing webhook, POSTed a JSON payload on track, playback and session changes
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Signs the body as `X-Media-Controller-Signature: sha256=<hmac>`
+    pub secret: Option<String>,
+    /// `track_changed`, `playback_changed`, `session_changed` and/or `position_alarm`, all
+    /// when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Attempts per delivery, with doubling delays in between
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: u32,
+    /// Template of the body instead of the JSON payload, with its fields
+    pub template: Option<String>,
+    /// Content type of the templated body
+    #[serde(default = "default_webhook_content_type")]
+    pub content_type: String,
+}
+
+/// ntfy topic or Gotify server that track changes, sleep timer pauses and position alarms
+/// are pushed to
+#[derive(Debug, Deserialize, Clone)]
+pub struct PushConfig {
+    pub service: PushService,
+    /// Topic URL for ntfy, e.g. `https://ntfy.sh/my-topic`, server URL for Gotify
+    pub url: String,
+    /// Access token for ntfy, app token for Gotify
+    pub token: Option<String>,
+    /// `track_changed`, `sleep_timer` and/or `position_alarm`, all when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// 1 to 5 for ntfy, 0 to 10 for Gotify
+    #[serde(default = "default_push_priority")]
+    pub priority: u8,
+    /// Templates of the title and message, with `event`, the default `title` and
+    /// `message` and the `track` or `alarm` fields
+    pub title: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PushService {
+    Ntfy,
+    Gotify,
+}
+
+/// Pauses playback once nobody touched the keyboard or mouse for a while, e.g. when
+/// falling asleep to a podcast
+#[derive(Debug, Deserialize, Clone)]
+pub struct InactivityConfig {
+    /// Minutes without input before playback pauses
+    #[serde(default = "default_inactivity_minutes")]
+    pub minutes: u64,
+}
+
+/// Playback follows the lock screen, e.g. music stops when walking away from the PC
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct LockConfig {
+    /// Pause playback when the workstation locks
+    pub pause_on_lock: bool,
+    /// Resume on unlock what the lock paused
+    pub resume_on_unlock: bool,
+}
+
+/// Follows the default output device, e.g. to pause when headphones are turned off
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct OutputDevicesConfig {
+    pub enabled: bool,
+    /// Parts of the names of the devices whose disconnect pauses playback, compared
+    /// case-insensitively, `*` for every device
+    pub pause_on_disconnect: Vec<String>,
+}
+
+impl OutputDevicesConfig {
+    /// Whether losing a device pauses playback
+    ///
+    /// # Arguments
+    /// * `device` - Friendly name of the device, e.g. `Headphones (WH-1000XM4)`
+    pub fn pauses_for(&self, device: &str) -> bool {
+        let device = device.to_lowercase();
+        self.pause_on_disconnect
+            .iter()
+            .any(|pattern| pattern == "*" || device.contains(&pattern.to_lowercase()))
+    }
+}
+
+/// Liveness checks of connected clients
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    /// Seconds between `server_ping`s
+    pub interval_seconds: u64,
+    /// Unanswered pings in a row before a client is disconnected
+    pub max_missed: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval_seconds: 15,
+            max_missed: 3,
+        }
+    }
+}
+
+/// Removing the media session handlers while nobody listens
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct IdleConfig {
+    pub enabled: bool,
+    /// Seconds after the last client left before the handlers are removed
+    pub grace_seconds: u64,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            grace_seconds: 30,
+        }
+    }
+}
+
+/// Artwork that isn't readable yet when a track starts
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ThumbnailsConfig {
+    /// Reads of the artwork after a track without one, 0 turns retrying off
+    pub retry_attempts: u32,
+    /// Milliseconds before the first retry, doubled for every further one
+    pub retry_delay_ms: u64,
+    /// Image sent as the artwork of tracks that have none
+    pub placeholder: Option<PathBuf>,
+}
+
+impl Default for ThumbnailsConfig {
+    fn default() -> Self {
+        Self {
+            retry_attempts: 3,
+            retry_delay_ms: 500,
+            placeholder: None,
+        }
+    }
+}
+
+/// TCP endpoint pushing fixed-layout frames to tiny displays
+#[derive(Debug, Deserialize, Clone)]
+pub struct DisplayConfig {
+    #[serde(default = "default_display_port")]
+    pub port: u16,
+    /// Milliseconds between frames
+    #[serde(default = "default_display_interval_ms")]
+    pub interval_ms: u64,
+}
+
+/// Aggregate mode, for dashboards showing every session at once
+#[derive(Debug, Deserialize, Clone)]
+pub struct AggregateConfig {
+    /// Milliseconds between reads of the sessions that aren't current
+    #[serde(default = "default_aggregate_interval_ms")]
+    pub interval_ms: u64,
+}
+
+/// Calls into the media sessions of apps
+#[cfg(windows)]
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct WinRtConfig {
+    /// Longest wait for an async call like play or reading the track, some apps never answer
+    pub timeout_ms: u64,
+}
+
+#[cfg(windows)]
+impl Default for WinRtConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: media_controller_core::winrt::DEFAULT_TIMEOUT.as_millis() as u64,
+        }
+    }
+}
+
+#[cfg(windows)]
+impl WinRtConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+/// Files rewritten on every track change, each is only written when its path is set
+#[derive(Debug, Deserialize, Clone)]
+pub struct NowPlayingConfig {
+    /// The template filled with the current track, empty without one
+    pub text: Option<PathBuf>,
+    /// The current track info without the artwork, `null` without one
+    pub json: Option<PathBuf>,
+    /// Artwork of the current track, converted to the format of the extension
+    pub artwork: Option<PathBuf>,
+    /// Template of the text file, with the fields of the `track_info` payload
+    #[serde(default = "default_now_playing_template")]
+    pub template: String,
+}
+
+/// Pauses sessions that start playing alongside another
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConflictsConfig {
+    /// Source app ids, highest priority first, e.g. `["Spotify.exe", "chrome.exe"]`.
+    /// Unlisted apps come last, and between equals the one that started last wins
+    #[serde(default)]
+    pub priority: Vec<String>,
+    /// Milliseconds between checks of the sessions
+    #[serde(default = "default_conflicts_interval_ms")]
+    pub interval_ms: u64,
+}
+
+impl ConflictsConfig {
+    /// Position of an app in the priority list, compared case-insensitively
+    pub fn rank(&self, source_app_id: &str) -> usize {
+        self.priority
+            .iter()
+            .position(|app| app.eq_ignore_ascii_case(source_app_id))
+            .unwrap_or(self.priority.len())
+    }
+}
+
+/// Publishes the controlled media as an MPRIS player, for desktop media controls
+#[derive(Debug, Deserialize, Clone)]
+pub struct MprisConfig {
+    /// The player owns the bus name `org.mpris.MediaPlayer2.<name>`
+    #[serde(default = "default_mpris_name")]
+    pub name: String,
+    /// Player name shown by desktop media controls
+    #[serde(default = "default_mpris_identity")]
+    pub identity: String,
+}
+
+/// Registers a media session of the server's own that shows the media of a peer or
+/// of a server on MQTT, set either `peer` or `mqtt_prefix`
+#[derive(Debug, Deserialize, Clone)]
+pub struct PublisherConfig {
+    /// Name of a peer of `[[peers]]`
+    pub peer: Option<String>,
+    /// Topic prefix of the mirrored server on the broker of `[mqtt]`
+    pub mqtt_prefix: Option<String>,
+}
+
+/// Hours the system volume is capped and playback may be refused, see the Quiet Hours
+/// section of the README
+#[derive(Debug, Deserialize, Clone)]
+pub struct QuietHoursConfig {
+    /// Local time they start, e.g. `22:00`
+    pub start: String,
+    /// Local time they end, e.g. `07:00`, on the next day when before the start
+    pub end: String,
+    /// Highest system volume in percent
+    #[serde(default = "default_quiet_hours_max_volume")]
+    pub max_volume: u8,
+    /// Refuse `play`, and `toggle_play_pause` while paused
+    #[serde(default)]
+    pub refuse_play: bool,
+    /// Secret of `quiet_hours_override`, overrides are refused without one
+    pub override_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Per-module levels like `RUST_LOG`, e.g. `info,media_controller::mqtt=debug`
+    pub filter: Option<String>,
+}
+
+/// Spotify Web API app credentials, the user logs in at `/spotify/login`
+#[derive(Debug, Deserialize, Clone)]
+pub struct SpotifyConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Must match a redirect URI registered for the Spotify app
+    #[serde(default = "default_spotify_redirect_uri")]
+    pub redirect_uri: String,
+    /// Seeds the token store, skipping the login
+    pub refresh_token: Option<String>,
+}
+
+/// Multi-room outputs of a Snapcast server
+#[derive(Debug, Deserialize, Clone)]
+pub struct SnapcastConfig {
+    /// Base URL of the Snapcast HTTP control API, e.g. `http://192.168.1.10:1780`
+    pub url: String,
+}
+
+/// Metadata enrichment from MusicBrainz and, with credentials, the Spotify Web API
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct MetadataConfig {
+    pub enabled: bool,
+    /// Enrich every track instead of only those missing an album or artwork
+    pub always: bool,
+    pub spotify_client_id: Option<String>,
+    pub spotify_client_secret: Option<String>,
+}
+
+/// Lyrics lookup for the current track
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct LyricsConfig {
+    pub enabled: bool,
+    /// Musixmatch is only queried when LRCLIB has no lyrics and a key is set
+    pub musixmatch_api_key: Option<String>,
+}
+
+/// Control apps that don't register a media session through synthesized media keys
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct MediaKeysConfig {
+    /// Send media keys when no media session is available
+    pub fallback: bool,
+    /// Source app ids that are always controlled with media keys, e.g. `game.exe`
+    pub apps: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MqttConfig {
+    /// Broker URL, e.g. `mqtt://192.168.1.10:1883`
+    pub broker_url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    /// Prefix for every published and subscribed topic
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub topic_prefix: String,
+    /// Publish Home Assistant MQTT discovery configs on connect
+    #[serde(default)]
+    pub discovery: bool,
+    #[serde(default = "default_mqtt_discovery_prefix")]
+    pub discovery_prefix: String,
+    /// Payload templates by topic, e.g. `track`, rendered with the fields of the JSON payload
+    #[serde(default)]
+    pub templates: HashMap<String, String>,
+}
+
+fn default_spotify_redirect_uri() -> String {
+    format!(
+        "http://127.0.0.1:{}/spotify/callback",
+        crate::utils::DEFAULT_SOCKETIO_PORT
+    )
+}
+
+fn default_display_port() -> u16 {
+    crate::utils::DEFAULT_DISPLAY_PORT
+}
+
+fn default_display_interval_ms() -> u64 {
+    500
+}
+
+fn default_aggregate_interval_ms() -> u64 {
+    1000
+}
+
+fn default_now_playing_template() -> String {
+    String::from("{{ artist }} — {{ title }}")
+}
+
+fn default_inactivity_minutes() -> u64 {
+    45
+}
+
+fn default_conflicts_interval_ms() -> u64 {
+    1000
+}
+
+fn default_mpris_name() -> String {
+    String::from("media_controller")
+}
+
+fn default_mpris_identity() -> String {
+    String::from("Media Controller")
+}
+
+fn default_quiet_hours_max_volume() -> u8 {
+    30
+}
+
+fn default_webhook_max_attempts() -> u32 {
+    4
+}
+
+fn default_webhook_content_type() -> String {
+    String::from("application/json")
+}
+
+fn default_push_priority() -> u8 {
+    3
+}
+
+fn default_mqtt_client_id() -> String {
+    String::from("media-controller")
+}
+
+fn default_mqtt_topic_prefix() -> String {
+    String::from("media")
+}
+
+fn default_mqtt_discovery_prefix() -> String {
+    String::from("homeassistant")
+}
+
+impl Config {
+    /// Load the config from the given path, or from `config.toml` in the working
+    /// directory if it exists. Falls back to the defaults when no file is found.
+    ///
+    /// # Arguments
+    /// * `path` - Explicit config path, which must exist when provided
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let default = PathBuf::from(DEFAULT_CONFIG_FILE);
+                if !default.exists() {
+                    return Ok(Self::default());
+                }
+                default
+            }
+        };
+
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let mut config: Self = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+
+        config.source = Some(path);
+        Ok(config)
+    }
+
+    /// Profile of an app, source app ids compare case-insensitively
+    pub fn app_profile(&self, app_id: &str) -> Option<&AppProfile> {
+        self.apps
+            .iter()
+            .find(|(id, _)| id.eq_ignore_ascii_case(app_id))
+            .map(|(_, profile)| profile)
+    }
+}
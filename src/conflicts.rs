@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::time::MissedTickBehavior;
+
+use crate::config::ConflictsConfig;
+use crate::events::{EventHub, MediaCommand, SessionFeed};
+use crate::media_manager::SessionInfo;
+
+/// Payload of the `session_conflict_resolved` event
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct ConflictResolved {
+    /// The session that keeps playing
+    pub kept: SessionInfo,
+    /// Sessions that were playing along and got paused
+    pub paused: Vec<SessionInfo>,
+}
+
+/// Keep a single session playing, pausing the others whenever a second one starts
+pub async fn run(config: ConflictsConfig, hub: EventHub) {
+    tracing::info!("Pausing sessions that play alongside another");
+    let mut ticker = tokio::time::interval(Duration::from_millis(config.interval_ms.max(250)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut playing = HashSet::new();
+    loop {
+        ticker.tick().await;
+        let reader = hub.clone();
+        let feeds = match tokio::task::spawn_blocking(move || reader.session_feeds()).await {
+            Ok(Ok(feeds)) => feeds,
+            Ok(Err(e)) => {
+                tracing::debug!("Failed to read the media sessions: {}", e);
+                continue;
+            }
+            Err(e) => {
+                tracing::error!("Session conflict task failed: {}", e);
+                continue;
+            }
+        };
+
+        let mut sessions: Vec<SessionInfo> = feeds
+            .into_iter()
+            .filter(is_playing)
+            .map(|feed| feed.session)
+            .collect();
+        if sessions.len() < 2 {
+            playing = sessions
+                .into_iter()
+                .map(|session| session.session_id)
+                .collect();
+            continue;
+        }
+
+        // The app ranked highest wins, between equals the one that just started
+        sessions.sort_by_key(|session| {
+            (
+                config.rank(&session.source_app_id),
+                playing.contains(&session.session_id),
+            )
+        });
+        let kept = sessions.remove(0);
+        let paused = pause_all(&hub, sessions).await;
+        playing = HashSet::from([kept.session_id.clone()]);
+        if paused.is_empty() {
+            continue;
+        }
+
+        tracing::info!(
+            "{} kept playing, paused {}",
+            kept.app_name,
+            paused
+                .iter()
+                .map(|session| session.app_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        hub.publish_conflict_resolved(ConflictResolved { kept, paused });
+    }
+}
+
+fn is_playing(feed: &SessionFeed) -> bool {
+    feed.track_controls
+        .as_ref()
+        .is_some_and(|controls| controls.playing())
+}
+
+/// Pause every session
+///
+/// # Returns
+/// The sessions that were paused
+async fn pause_all(hub: &EventHub, sessions: Vec<SessionInfo>) -> Vec<SessionInfo> {
+    let hub = hub.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        sessions
+            .into_iter()
+            .filter(|session| {
+                match hub.execute_pinned(&session.source_app_id, MediaCommand::Pause) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        tracing::warn!("Failed to pause {}: {}", session.app_name, e);
+                        false
+                    }
+                }
+            })
+            .collect()
+    })
+    .await;
+    result.unwrap_or_else(|e| {
+        tracing::error!("Session conflict task failed: {}", e);
+        Vec::new()
+    })
+}
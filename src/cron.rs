@@ -0,0 +1,172 @@
+use anyhow::{Context, Result, bail};
+#[cfg(windows)]
+use windows::Win32::System::SystemInformation::GetLocalTime;
+
+/// Wall clock time in the user's time zone, to the minute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub minute: u32,
+    pub hour: u32,
+    pub day: u32,
+    pub month: u32,
+    /// 0 is Sunday
+    pub weekday: u32,
+}
+
+impl LocalTime {
+    #[cfg(windows)]
+    pub fn now() -> Self {
+        let time = unsafe { GetLocalTime() };
+        Self {
+            minute: time.wMinute as u32,
+            hour: time.wHour as u32,
+            day: time.wDay as u32,
+            month: time.wMonth as u32,
+            weekday: time.wDayOfWeek as u32,
+        }
+    }
+
+    /// Other systems get UTC, there is no time zone to read without Windows here
+    #[cfg(not(windows))]
+    pub fn now() -> Self {
+        let minutes = crate::utils::unix_millis() / 60_000;
+        let days = minutes / (24 * 60);
+        let (month, day) = month_and_day(days);
+        Self {
+            minute: (minutes % 60) as u32,
+            hour: (minutes / 60 % 24) as u32,
+            day,
+            month,
+            // 1970-01-01 was a Thursday
+            weekday: ((days + 4) % 7) as u32,
+        }
+    }
+}
+
+/// Month and day of month of a day counted from 1970-01-01, see
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+#[cfg(not(windows))]
+fn month_and_day(days: u64) -> (u32, u32) {
+    // Years start in March here, so the leap day ends them
+    let z = days + 719_468;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = match month_from_march {
+        0..=9 => month_from_march + 3,
+        _ => month_from_march - 9,
+    };
+    (month as u32, day as u32)
+}
+
+/// A five field cron expression: `minute hour day-of-month month day-of-week`
+///
+/// Fields accept `*`, single values, ranges (`1-5`), steps (`*/15`, `0-30/10`)
+/// and comma separated lists of those. Day of week counts from 0 (Sunday), 7 is
+/// also Sunday.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day-of-month and day-of-week were both restricted
+    either_day: bool,
+}
+
+impl CronExpr {
+    /// Parse a cron expression
+    ///
+    /// # Arguments
+    /// * `expr` - e.g. `0 7 * * 1-5` for 7:00 on weekdays
+    pub fn parse(expr: &str) -> Result<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!("Expected 5 fields, got {}", fields.len());
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7).context("Invalid day of week")?;
+        // Fold 7 onto Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59).context("Invalid minute")?,
+            hours: parse_field(hour, 0, 23).context("Invalid hour")?,
+            days: parse_field(day, 1, 31).context("Invalid day of month")?,
+            months: parse_field(month, 1, 12).context("Invalid month")?,
+            weekdays,
+            either_day: day != "*" && weekday != "*",
+        })
+    }
+
+    /// Whether the expression fires at the given minute
+    pub fn matches(&self, time: &LocalTime) -> bool {
+        let day = bit(self.days, time.day);
+        let weekday = bit(self.weekdays, time.weekday);
+        // Like cron, a restricted day of month and day of week match either one
+        let day_matches = if self.either_day {
+            day || weekday
+        } else {
+            day && weekday
+        };
+
+        bit(self.minutes, time.minute)
+            && bit(self.hours, time.hour)
+            && bit(self.months, time.month)
+            && day_matches
+    }
+}
+
+fn bit(mask: u64, value: u32) -> bool {
+    value < 64 && mask & (1 << value) != 0
+}
+
+/// Parse one field into a bit mask of the values it selects
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().context("Invalid step")?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            bail!("Step must be at least 1");
+        }
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (parse_value(start, min, max)?, parse_value(end, min, max)?),
+                None => {
+                    let value = parse_value(range, min, max)?;
+                    // `5/10` means every 10 starting at 5, like cron
+                    (value, if part.contains('/') { max } else { value })
+                }
+            },
+        };
+        if start > end {
+            bail!("Range {}-{} is backwards", start, end);
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_value(value: &str, min: u32, max: u32) -> Result<u32> {
+    let value: u32 = value
+        .parse()
+        .with_context(|| format!("{:?} is not a number", value))?;
+    if !(min..=max).contains(&value) {
+        bail!("{} is outside {}-{}", value, min, max);
+    }
+    Ok(value)
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result, bail};
+use clap::{Args, Subcommand};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::utils::DEFAULT_SOCKETIO_PORT;
+
+/// Run a single command against a running server
+#[derive(Args, Debug)]
+pub struct CtlArgs {
+    /// URL of the Socket.IO server, including the base path if one is set
+    #[arg(long, default_value_t = format!("http://127.0.0.1:{}", DEFAULT_SOCKETIO_PORT))]
+    server: String,
+
+    #[command(subcommand)]
+    command: CtlCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum CtlCommand {
+    /// Toggle between play and pause
+    PlayPause,
+    Play,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    /// Jump to a position, e.g. `90s`, `1:30` or `90000ms`
+    Seek {
+        position: String,
+    },
+    /// Toggle shuffle
+    Shuffle,
+    /// Set the repeat mode
+    Repeat {
+        #[arg(value_parser = ["none", "track", "list"])]
+        mode: String,
+    },
+    /// Show the current track
+    Status {
+        /// Print the raw state snapshot
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Answer of `POST /api/command`
+#[derive(Debug, Deserialize)]
+pub struct CommandResult {
+    ok: bool,
+    error: Option<String>,
+    /// The event answering the command as `{ event, data }`
+    pub event: Option<Value>,
+}
+
+pub async fn run(args: CtlArgs) -> Result<()> {
+    let (event, data) = match &args.command {
+        CtlCommand::PlayPause => ("toggle_play_pause", None),
+        CtlCommand::Play => ("play", None),
+        CtlCommand::Pause => ("pause", None),
+        CtlCommand::Stop => ("stop", None),
+        CtlCommand::Next => ("next_track", None),
+        CtlCommand::Previous => ("previous_track", None),
+        CtlCommand::Seek { position } => {
+            let position = parse_position(position)?;
+            ("seek", Some(json!({ "position": position })))
+        }
+        CtlCommand::Shuffle => ("toggle_shuffle", None),
+        CtlCommand::Repeat { mode } => ("set_repeat_mode", Some(json!(mode))),
+        CtlCommand::Status { .. } => ("get_state_snapshot", None),
+    };
+
+    let result = send(&args.server, event, data).await?;
+    if let CtlCommand::Status { json } = args.command {
+        let snapshot = result
+            .event
+            .and_then(|mut event| event.get_mut("data").map(Value::take))
+            .context("Server sent no state snapshot")?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&snapshot)?);
+        } else {
+            print_status(&snapshot);
+        }
+    }
+    Ok(())
+}
+
+/// Run a function on the server
+///
+/// # Arguments
+/// * `server` - URL of the Socket.IO server
+/// * `event` - Function code, e.g. `next_track`
+/// * `data` - Payload of the function, if it takes one
+pub async fn send(server: &str, event: &str, data: Option<Value>) -> Result<CommandResult> {
+    let url = format!("{}/api/command", server.trim_end_matches('/'));
+    let body = match data {
+        Some(data) => json!({ "event": event, "data": data }),
+        None => json!({ "event": event }),
+    };
+
+    let response = reqwest::Client::new()
+        .post(&url)
+        .json(&body)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach the server at {}", server))?;
+    let status = response.status();
+    let result: CommandResult = response
+        .json()
+        .await
+        .with_context(|| format!("Unexpected answer from {} ({})", url, status))?;
+
+    if !result.ok {
+        bail!(result.error.unwrap_or_else(|| status.to_string()));
+    }
+    Ok(result)
+}
+
+fn print_status(snapshot: &Value) {
+    let track = &snapshot["track_info"];
+    if track.is_null() {
+        println!("Nothing playing");
+        return;
+    }
+
+    let playing = snapshot["track_controls"]["playing"]
+        .as_bool()
+        .unwrap_or_default();
+    let progress = snapshot["track_timeline"]["progress"]
+        .as_u64()
+        .unwrap_or_default();
+    println!(
+        "{} {} - {}",
+        if playing { "Playing" } else { "Paused" },
+        track["title"].as_str().unwrap_or_default(),
+        track["artist"].as_str().unwrap_or_default()
+    );
+    println!(
+        "{} / {} on {}",
+        format_position(progress),
+        format_position(track["duration"].as_u64().unwrap_or_default()),
+        track["app_name"].as_str().unwrap_or_default()
+    );
+}
+
+/// Parse a position into milliseconds
+///
+/// # Arguments
+/// * `value` - `90` or `90s` in seconds, `1:30` as minutes and seconds, or `90000ms`
+fn parse_position(value: &str) -> Result<u64> {
+    let invalid = || {
+        format!(
+            "Invalid position {:?}, use e.g. 90s, 1:30 or 90000ms",
+            value
+        )
+    };
+
+    if let Some(ms) = value.strip_suffix("ms") {
+        return ms.parse().with_context(invalid);
+    }
+    if let Some((minutes, seconds)) = value.split_once(':') {
+        let minutes: u64 = minutes.parse().with_context(invalid)?;
+        let seconds: u64 = seconds.parse().with_context(invalid)?;
+        return Ok((minutes * 60 + seconds) * 1000);
+    }
+    let seconds: f64 = value
+        .strip_suffix('s')
+        .unwrap_or(value)
+        .parse()
+        .with_context(invalid)?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        bail!(invalid());
+    }
+    Ok((seconds * 1000.0) as u64)
+}
+
+/// Format milliseconds as `m:ss`
+pub fn format_position(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
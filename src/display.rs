@@ -0,0 +1,332 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+
+use crate::config::DisplayConfig;
+use crate::events::{EventHub, MediaEvent};
+use crate::ip_filter::IpAllowlist;
+use crate::media_manager::{SessionStatus, TrackInfo};
+use crate::thumbnail::{self, BitmapFormat};
+use crate::utils;
+
+const MAGIC: [u8; 2] = *b"MC";
+/// Bumped whenever the frame layout changes
+const FRAME_VERSION: u8 = 1;
+/// Bytes of the title and artist fields, NUL padded with at least one NUL
+const TEXT_LENGTH: usize = 64;
+const FRAME_LENGTH: usize = 14 + 2 * TEXT_LENGTH;
+
+const FLAG_PLAYING: u8 = 1;
+const FLAG_SESSION: u8 = 1 << 1;
+
+const ART_MAGIC: [u8; 2] = *b"MA";
+/// Sent by a display to ask for the artwork, followed by format, width and height
+const ART_REQUEST: u8 = b'A';
+const ART_REQUEST_LENGTH: usize = 6;
+
+/// Artwork a display asked for
+#[derive(Debug, Clone, Copy)]
+struct ArtRequest {
+    format: BitmapFormat,
+    width: u16,
+    height: u16,
+}
+
+impl ArtRequest {
+    fn parse(request: [u8; ART_REQUEST_LENGTH]) -> Option<Self> {
+        let format = match request[1] {
+            0 => BitmapFormat::Rgb565,
+            1 => BitmapFormat::Indexed,
+            _ => return None,
+        };
+        // Clamped like the bitmap, so the answer states the real size
+        let size =
+            |bytes: [u8; 2]| u16::from_le_bytes(bytes).clamp(1, thumbnail::MAX_BITMAP_SIZE as u16);
+        (request[0] == ART_REQUEST).then_some(Self {
+            format,
+            width: size([request[2], request[3]]),
+            height: size([request[4], request[5]]),
+        })
+    }
+
+    fn format_code(self) -> u8 {
+        match self.format {
+            BitmapFormat::Rgb565 => 0,
+            BitmapFormat::Indexed => 1,
+        }
+    }
+}
+
+/// What the frames show, kept up to date from the hub's events
+#[derive(Debug, Default)]
+struct DisplayState {
+    session: bool,
+    /// Timelines of other tracks are late ones of the previous track
+    track_id: String,
+    title: String,
+    artist: String,
+    duration: u64,
+    /// RGB565 accent of the artwork
+    accent: u16,
+    playing: bool,
+    progress: u64,
+    progress_at: Option<Instant>,
+}
+
+impl DisplayState {
+    fn apply(&mut self, event: MediaEvent) {
+        match event {
+            MediaEvent::SessionState(state) if state.state != SessionStatus::Active => {
+                *self = Self::default();
+            }
+            MediaEvent::TrackInfo(Some(track)) => self.set_track(&track),
+            MediaEvent::TrackInfo(None) => *self = Self::default(),
+            MediaEvent::TrackControls(controls) => {
+                // Pausing keeps the position it reached
+                self.progress = self.position();
+                self.progress_at = Some(Instant::now());
+                self.playing = controls.playing();
+            }
+            MediaEvent::TrackTimeline(timeline) => {
+                let timeline = timeline.timeline;
+                if timeline.track_id != self.track_id {
+                    return;
+                }
+                self.progress = timeline.position;
+                self.duration = timeline.duration;
+                self.progress_at = Some(Instant::now());
+            }
+            _ => {}
+        }
+    }
+
+    fn set_track(&mut self, track: &TrackInfo) {
+        self.session = !track.stale;
+        self.track_id = track.track_id.clone();
+        self.title = track.title.clone();
+        self.artist = track.artist.clone();
+        // Until the timeline of the track arrives
+        self.duration = track.duration;
+        self.accent = track
+            .palette
+            .as_ref()
+            .and_then(|palette| rgb565(&palette.theme.dark.progress))
+            .unwrap_or_default();
+    }
+
+    /// Progress extrapolated from the last timeline event while playing
+    fn position(&self) -> u64 {
+        let elapsed = match self.progress_at {
+            Some(at) if self.playing => at.elapsed().as_millis() as u64,
+            _ => 0,
+        };
+        (self.progress + elapsed).min(self.duration.max(self.progress))
+    }
+
+    /// Little-endian frame, see the Display Protocol section of the README
+    fn frame(&self) -> [u8; FRAME_LENGTH] {
+        let mut frame = [0; FRAME_LENGTH];
+        let mut flags = 0;
+        if self.playing {
+            flags |= FLAG_PLAYING;
+        }
+        if self.session {
+            flags |= FLAG_SESSION;
+        }
+        frame[0..2].copy_from_slice(&MAGIC);
+        frame[2] = FRAME_VERSION;
+        frame[3] = flags;
+        frame[4..8].copy_from_slice(&saturate(self.position()).to_le_bytes());
+        frame[8..12].copy_from_slice(&saturate(self.duration).to_le_bytes());
+        frame[12..14].copy_from_slice(&self.accent.to_le_bytes());
+        write_text(&mut frame[14..14 + TEXT_LENGTH], &self.title);
+        write_text(&mut frame[14 + TEXT_LENGTH..], &self.artist);
+        frame
+    }
+}
+
+/// Accept display connections and push a frame to each at a fixed rate
+///
+/// # Arguments
+/// * `config` - Port and frame interval
+/// * `bind` - Address the other servers are bound to
+/// * `allowlist` - Peers allowed to connect
+/// * `hub` - Source of the state
+pub async fn run(
+    config: DisplayConfig,
+    bind: IpAddr,
+    allowlist: IpAllowlist,
+    hub: EventHub,
+) -> Result<()> {
+    let listener = TcpListener::bind((bind, config.port))
+        .await
+        .with_context(|| format!("Failed to bind display port {}", config.port))?;
+    tracing::info!("Display protocol listening on {}:{}", bind, config.port);
+
+    let state = Arc::new(Mutex::new(DisplayState::default()));
+    tokio::spawn(follow_events(hub.clone(), Arc::clone(&state)));
+
+    let interval = Duration::from_millis(config.interval_ms.max(50));
+    loop {
+        let (stream, address) = listener.accept().await?;
+        if !allowlist.allows(address.ip()) {
+            tracing::warn!("Rejected display connection from {}", address);
+            continue;
+        }
+        tokio::spawn(serve_display(
+            stream,
+            address,
+            interval,
+            hub.clone(),
+            Arc::clone(&state),
+        ));
+    }
+}
+
+async fn follow_events(hub: EventHub, state: Arc<Mutex<DisplayState>>) {
+    let mut receiver = hub.subscribe();
+    let initial = tokio::task::spawn_blocking(move || hub.current_state())
+        .await
+        .unwrap_or_default();
+    for event in initial {
+        if let Ok(mut state) = state.lock() {
+            state.apply(event);
+        }
+    }
+
+    loop {
+        match receiver.recv().await {
+            Ok(event) => {
+                if let Ok(mut state) = state.lock() {
+                    state.apply(event);
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("Display protocol lagged behind by {} events", skipped);
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+async fn serve_display(
+    stream: TcpStream,
+    address: SocketAddr,
+    interval: Duration,
+    hub: EventHub,
+    state: Arc<Mutex<DisplayState>>,
+) {
+    let id = format!("display-{}", address);
+    tracing::info!("display connected: {}", id);
+    stream.set_nodelay(true).ok();
+    let kick = hub.clients().register(id.clone(), "display", Some(address));
+
+    let (reader, mut writer) = stream.into_split();
+    let (requests, mut received) = mpsc::channel(1);
+    let read_task = tokio::spawn(read_requests(reader, requests));
+
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let frame = match state.lock() {
+                    Ok(state) => state.frame(),
+                    Err(_) => break,
+                };
+                if writer.write_all(&frame).await.is_err() {
+                    break;
+                }
+            }
+            request = received.recv() => {
+                // The display closed its side of the connection
+                let Some(request) = request else {
+                    break;
+                };
+                if writer.write_all(&art_frame(&hub, request).await).await.is_err() {
+                    break;
+                }
+            }
+            _ = kick.notified() => break,
+        }
+    }
+    read_task.abort();
+
+    hub.clients().unregister(&id);
+    tracing::info!("display disconnected: {}", id);
+}
+
+async fn read_requests(mut reader: OwnedReadHalf, requests: mpsc::Sender<ArtRequest>) {
+    let mut request = [0; ART_REQUEST_LENGTH];
+    while reader.read_exact(&mut request).await.is_ok() {
+        match ArtRequest::parse(request) {
+            Some(request) => {
+                if requests.send(request).await.is_err() {
+                    return;
+                }
+            }
+            None => tracing::debug!("Invalid display request {:?}", request),
+        }
+    }
+}
+
+/// The artwork as a bitmap frame, with no pixels when there is none
+async fn art_frame(hub: &EventHub, request: ArtRequest) -> Vec<u8> {
+    let hub = hub.clone();
+    let bitmap = tokio::task::spawn_blocking(move || {
+        let artwork = hub.artwork().ok()?;
+        let width = u32::from(request.width);
+        let height = u32::from(request.height);
+        match thumbnail::render_bitmap(&artwork, request.format, width, height) {
+            Ok(bitmap) => Some(bitmap),
+            Err(e) => {
+                tracing::warn!("Failed to render display artwork: {}", e);
+                None
+            }
+        }
+    })
+    .await
+    .ok()
+    .flatten()
+    .unwrap_or_default();
+
+    let mut frame = Vec::with_capacity(12 + bitmap.len());
+    frame.extend_from_slice(&ART_MAGIC);
+    frame.push(FRAME_VERSION);
+    frame.push(request.format_code());
+    frame.extend_from_slice(&request.width.to_le_bytes());
+    frame.extend_from_slice(&request.height.to_le_bytes());
+    frame.extend_from_slice(&(bitmap.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&bitmap);
+    frame
+}
+
+/// Milliseconds as u32, which covers 49 days
+fn saturate(ms: u64) -> u32 {
+    u32::try_from(ms).unwrap_or(u32::MAX)
+}
+
+/// Convert a `#rrggbb` color to RGB565
+fn rgb565(hex: &str) -> Option<u16> {
+    let [_, r, g, b] = u32::from_str_radix(hex.strip_prefix('#')?, 16)
+        .ok()?
+        .to_be_bytes();
+    Some(utils::rgb565(r, g, b))
+}
+
+/// Copy as much of the text as fits, cut at a character boundary and NUL terminated
+fn write_text(field: &mut [u8], text: &str) {
+    let mut end = text.len().min(field.len() - 1);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    field[..end].copy_from_slice(&text.as_bytes()[..end]);
+}
@@ -0,0 +1,2217 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+use tokio::sync::broadcast::{self, error::RecvError};
+use utoipa::ToSchema;
+
+use crate::ads::AdMuter;
+use crate::clients::ClientRegistry;
+use crate::config::Config;
+use crate::conflicts::ConflictResolved;
+use crate::cron::LocalTime;
+use crate::error::{MediaError, MediaResult};
+use crate::inactivity::{Inactivity, InactivityStatus, InactivityUpdate};
+use crate::lyrics::{Lyrics, LyricsService};
+use crate::media_keys::{self, MediaKey};
+#[cfg(windows)]
+use crate::media_manager::MediaManager;
+use crate::media_manager::{
+    AutoRepeatMode, SessionInfo, SessionState, SessionStatus, ThumbnailVariants, TrackControls,
+    TrackInfo, TrackTimeline,
+};
+use crate::metadata::MetadataService;
+use crate::output_device::OutputDeviceChange;
+use crate::peers::{self, PeerCommand, PeerEvent, PeerRegistry, PeerStatus};
+use crate::persistence::{Bookmark, HistoryEntry, StateStore};
+use crate::pins::PinnedSessions;
+use crate::position_alarms::{
+    AlarmWatch, PositionAlarm, PositionAlarmRequest, PositionAlarmTarget,
+};
+use crate::protocol::{ClientHello, EventFilter, ServerHello};
+use crate::queue::{Queue, QueueService};
+use crate::quiet_hours::{QuietHours, QuietHoursOverride, QuietHoursStatus};
+use crate::scheduler::{
+    Schedule, ScheduleRequest, ScheduleTarget, ScheduledAction, Scheduler, SleepTimerRequest,
+    SleepTimerStatus,
+};
+use crate::session_lock::{LockStatus, SessionLock};
+use crate::skip::{SkipList, TrackSkipped};
+use crate::snapcast::{OutputGroup, SnapcastClient};
+use crate::spotify::{self, SpotifyClient, SpotifyDevice};
+use crate::utils;
+use crate::volume::{self, Volume, VolumeTarget};
+use media_controller_core::palette::{self, Palette};
+use media_controller_core::{Command, ManagerEvent, MediaBackend, MediaController};
+
+const CHANNEL_CAPACITY: usize = 64;
+/// Longest accepted play/pause fade
+const MAX_FADE: Duration = Duration::from_secs(10);
+
+/// A media state change, shared by every transport that broadcasts to clients
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum MediaEvent {
+    SessionState(SessionState),
+    /// `None` when there is no active session
+    TrackInfo(Option<TrackInfo>),
+    TrackControls(TrackControls),
+    TrackTimeline(TrackTimeline),
+    /// `None` when no provider has lyrics for the current track
+    Lyrics(Option<Lyrics>),
+    Queue(Queue),
+    SpotifyDevices(Vec<SpotifyDevice>),
+    SleepTimerStatus(SleepTimerStatus),
+    Schedules(Vec<Schedule>),
+    /// Saved track positions, newest first
+    Bookmarks(Vec<Bookmark>),
+    /// Alarms that haven't gone off yet, oldest first
+    PositionAlarms(Vec<PositionAlarm>),
+    /// The current track played through the position of an alarm
+    PositionAlarm(PositionAlarm),
+    /// Quiet hours started, ended or were overridden
+    QuietHoursChanged(QuietHoursStatus),
+    StateSnapshot(Box<StateSnapshot>),
+    /// Answer to a client's `hello`
+    Hello(ServerHello),
+    /// Custom event emitted by a plugin script
+    PluginEvent(PluginEvent),
+    /// The current track matched a skip rule and was skipped
+    TrackSkipped(TrackSkipped),
+    /// Multi-room outputs of the Snapcast server
+    Outputs(Vec<OutputGroup>),
+    /// Federated servers and whether they are connected
+    Peers(Vec<PeerStatus>),
+    /// A state event of a federated server
+    PeerEvent(PeerEvent),
+    /// Every media session with its state, in aggregate mode
+    Sessions(Vec<SessionFeed>),
+    /// Artwork of the current track that was missing from its `track_info`
+    ThumbnailUpdated(ThumbnailUpdate),
+    /// Sessions playing alongside another were paused
+    SessionConflictResolved(ConflictResolved),
+    /// The workstation was locked or unlocked
+    LockChanged(LockStatus),
+    /// The default audio output changed, e.g. headphones were turned off
+    OutputDeviceChanged(OutputDeviceChange),
+    /// The countdown to pausing an unattended host started, moved or stopped
+    InactivityStatus(InactivityStatus),
+    /// The server is stopping, transports close their connections after it
+    ServerShutdown,
+    /// The media session manager was recreated, the current state follows
+    Resync,
+    /// A command failed, only sent to the client that issued it
+    Error(CommandError),
+}
+
+impl MediaEvent {
+    /// Event code, matching the names emitted over Socket.IO
+    pub fn name(&self) -> &'static str {
+        match self {
+            MediaEvent::SessionState(_) => "session_state",
+            MediaEvent::TrackInfo(_) => "track_info",
+            MediaEvent::TrackControls(_) => "track_controls",
+            MediaEvent::TrackTimeline(_) => "track_timeline",
+            MediaEvent::Lyrics(_) => "lyrics",
+            MediaEvent::Queue(_) => "queue",
+            MediaEvent::SpotifyDevices(_) => "spotify_devices",
+            MediaEvent::SleepTimerStatus(_) => "sleep_timer_status",
+            MediaEvent::Schedules(_) => "schedules",
+            MediaEvent::Bookmarks(_) => "bookmarks",
+            MediaEvent::PositionAlarms(_) => "position_alarms",
+            MediaEvent::PositionAlarm(_) => "position_alarm",
+            MediaEvent::QuietHoursChanged(_) => "quiet_hours_changed",
+            MediaEvent::StateSnapshot(_) => "state_snapshot",
+            MediaEvent::Hello(_) => "hello",
+            MediaEvent::PluginEvent(_) => "plugin_event",
+            MediaEvent::TrackSkipped(_) => "track_skipped",
+            MediaEvent::Outputs(_) => "outputs",
+            MediaEvent::Peers(_) => "peers",
+            MediaEvent::PeerEvent(_) => "peer_event",
+            MediaEvent::Sessions(_) => "sessions",
+            MediaEvent::ThumbnailUpdated(_) => "thumbnail_updated",
+            MediaEvent::SessionConflictResolved(_) => "session_conflict_resolved",
+            MediaEvent::LockChanged(_) => "lock_changed",
+            MediaEvent::OutputDeviceChanged(_) => "output_device_changed",
+            MediaEvent::InactivityStatus(_) => "inactivity_status",
+            MediaEvent::ServerShutdown => "server_shutdown",
+            MediaEvent::Resync => "resync",
+            MediaEvent::Error(_) => "error",
+        }
+    }
+
+    /// Event payload without the event code envelope
+    pub fn payload(&self) -> serde_json::Value {
+        let value = match self {
+            MediaEvent::SessionState(state) => serde_json::to_value(state),
+            MediaEvent::TrackInfo(info) => serde_json::to_value(info),
+            MediaEvent::TrackControls(controls) => serde_json::to_value(controls),
+            MediaEvent::TrackTimeline(timeline) => serde_json::to_value(timeline),
+            MediaEvent::Lyrics(lyrics) => serde_json::to_value(lyrics),
+            MediaEvent::Queue(queue) => serde_json::to_value(queue),
+            MediaEvent::SpotifyDevices(devices) => serde_json::to_value(devices),
+            MediaEvent::SleepTimerStatus(status) => serde_json::to_value(status),
+            MediaEvent::Schedules(schedules) => serde_json::to_value(schedules),
+            MediaEvent::Bookmarks(bookmarks) => serde_json::to_value(bookmarks),
+            MediaEvent::PositionAlarms(alarms) => serde_json::to_value(alarms),
+            MediaEvent::PositionAlarm(alarm) => serde_json::to_value(alarm),
+            MediaEvent::QuietHoursChanged(status) => serde_json::to_value(status),
+            MediaEvent::StateSnapshot(snapshot) => serde_json::to_value(snapshot),
+            MediaEvent::Hello(hello) => serde_json::to_value(hello),
+            MediaEvent::PluginEvent(event) => serde_json::to_value(event),
+            MediaEvent::TrackSkipped(skipped) => serde_json::to_value(skipped),
+            MediaEvent::Outputs(groups) => serde_json::to_value(groups),
+            MediaEvent::Peers(peers) => serde_json::to_value(peers),
+            MediaEvent::PeerEvent(event) => serde_json::to_value(event),
+            MediaEvent::Sessions(feeds) => serde_json::to_value(feeds),
+            MediaEvent::ThumbnailUpdated(update) => serde_json::to_value(update),
+            MediaEvent::SessionConflictResolved(resolved) => serde_json::to_value(resolved),
+            MediaEvent::LockChanged(status) => serde_json::to_value(status),
+            MediaEvent::OutputDeviceChanged(change) => serde_json::to_value(change),
+            MediaEvent::InactivityStatus(status) => serde_json::to_value(status),
+            MediaEvent::ServerShutdown | MediaEvent::Resync => Ok(serde_json::Value::Null),
+            MediaEvent::Error(error) => serde_json::to_value(error),
+        };
+        value.unwrap_or_default()
+    }
+
+    /// Source app of a state event, `None` for other events and when there is no session
+    pub fn source_app_id(&self) -> Option<&str> {
+        match self {
+            MediaEvent::SessionState(state) => state
+                .session
+                .as_ref()
+                .map(|session| session.source_app_id.as_str()),
+            MediaEvent::TrackInfo(track) => track
+                .as_ref()
+                .map(|track| track.session.source_app_id.as_str()),
+            MediaEvent::TrackControls(controls) => Some(&controls.session.source_app_id),
+            MediaEvent::TrackTimeline(timeline) => Some(&timeline.session.source_app_id),
+            MediaEvent::ThumbnailUpdated(update) => Some(&update.session.source_app_id),
+            _ => None,
+        }
+    }
+
+    /// Events describing the current state, rebroadcast only when they change
+    pub fn is_state(&self) -> bool {
+        matches!(
+            self,
+            MediaEvent::SessionState(_)
+                | MediaEvent::TrackInfo(_)
+                | MediaEvent::TrackControls(_)
+                | MediaEvent::TrackTimeline(_)
+        )
+    }
+}
+
+/// Everything a client needs to render, so startup takes a single event
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct StateSnapshot {
+    pub session: SessionState,
+    /// Every session known to the system, including the current one
+    pub sessions: Vec<SessionInfo>,
+    pub track_info: Option<TrackInfo>,
+    pub track_controls: Option<TrackControls>,
+    pub track_timeline: Option<TrackTimeline>,
+    /// System volume between 0 and 1, `None` when it can't be read
+    pub volume: Option<f32>,
+    pub sleep_timer: SleepTimerStatus,
+    /// `None` unless pausing on inactivity is configured
+    pub inactivity: Option<InactivityStatus>,
+    pub capabilities: Capabilities,
+}
+
+/// Payload of `GET /api/state`, the state events every client was sent last
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct EmittedState {
+    /// Content hash of `events`, passed as `since` to wait for the next change
+    pub etag: String,
+    /// Payload of the last `session_state`, `track_info`, `track_controls` and
+    /// `track_timeline` by event code
+    #[schema(value_type = Object)]
+    pub events: BTreeMap<&'static str, serde_json::Value>,
+}
+
+/// One session of the `sessions` event, the state a dashboard card needs
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct SessionFeed {
+    pub session: SessionInfo,
+    /// `None` when the app doesn't report a track
+    pub track_info: Option<TrackInfo>,
+    pub track_controls: Option<TrackControls>,
+    pub track_timeline: Option<TrackTimeline>,
+}
+
+/// Payload of the `thumbnail_updated` event
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct ThumbnailUpdate {
+    #[serde(flatten)]
+    pub session: SessionInfo,
+    /// The track it belongs to
+    pub title: String,
+    pub artist: String,
+    pub thumbnail: Option<String>,
+    pub thumbnails: Option<ThumbnailVariants>,
+    pub palette: Option<Palette>,
+}
+
+/// Optional features of the server, depending on its config
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct Capabilities {
+    pub lyrics: bool,
+    pub metadata: bool,
+    pub spotify: bool,
+    pub media_keys: bool,
+    /// Snapcast outputs can be listed and adjusted
+    pub outputs: bool,
+    /// Other servers can be controlled through this one
+    pub peers: bool,
+    /// Every session is broadcast in a `sessions` event
+    pub sessions: bool,
+    /// Quiet hours are configured
+    pub quiet_hours: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SeekPosition {
+    /// Position in milliseconds
+    pub position: u64,
+}
+
+/// Optional payload of `toggle_play_pause`
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Fade {
+    /// Length of the fade in milliseconds
+    pub fade_ms: u64,
+    #[serde(default)]
+    pub target: VolumeTarget,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct VolumeLevel {
+    /// System volume between 0 and 1
+    pub level: f32,
+}
+
+#[derive(Debug, Deserialize, JsonSchema, ToSchema)]
+pub struct ClientTarget {
+    pub id: String,
+}
+
+/// Payload of `pin_session`
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SessionTarget {
+    /// Source app id of the session, e.g. `Spotify.exe`
+    pub app_id: String,
+}
+
+/// Payload of the `session_command` function
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SessionCommand {
+    /// `session_id` of a session in the `sessions` event
+    pub session_id: String,
+    /// Function code, e.g. `pause`
+    pub command: String,
+    /// Payload of the function, if it takes one
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PlaylistTarget {
+    pub playlist_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct QueueTarget {
+    /// Spotify URI, e.g. `spotify:track:...`
+    pub uri: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct OutputVolume {
+    /// Id of a Snapcast output or group
+    pub id: String,
+    /// Between 0 and 1
+    pub level: f32,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct OutputMute {
+    /// Id of a Snapcast output or group
+    pub id: String,
+    pub muted: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TransferTarget {
+    pub device_id: String,
+    /// Start playing on the new device
+    #[serde(default)]
+    pub play: bool,
+}
+
+/// A control command, accepted from any transport
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum MediaCommand {
+    GetMediaDetails,
+    /// Fades the volume around the toggle when set
+    TogglePlayPause(Option<Fade>),
+    Play,
+    Pause,
+    Stop,
+    FastForward,
+    Rewind,
+    NextTrack,
+    PreviousTrack,
+    Seek(SeekPosition),
+    SetRepeatMode(AutoRepeatMode),
+    ToggleShuffle,
+    GetLyrics,
+    GetQueue,
+    SpotifySaveTrack,
+    SpotifyAddToPlaylist(PlaylistTarget),
+    SpotifyAddToQueue(QueueTarget),
+    SpotifyGetDevices,
+    SpotifyTransferPlayback(TransferTarget),
+    GetOutputs,
+    SetOutputVolume(OutputVolume),
+    SetOutputMute(OutputMute),
+    SetSleepTimer(SleepTimerRequest),
+    CancelSleepTimer,
+    /// Skip the pause of an unattended host until there is input again
+    CancelInactivityPause,
+    AddSchedule(ScheduleRequest),
+    ListSchedules,
+    RemoveSchedule(ScheduleTarget),
+    /// Save the position in the current track
+    BookmarkPosition,
+    /// Seek the current track to its bookmark and play it
+    ResumeBookmark,
+    ListBookmarks,
+    /// Go off when the current track plays through a position
+    AddPositionAlarm(PositionAlarmRequest),
+    ListPositionAlarms,
+    RemovePositionAlarm(PositionAlarmTarget),
+    /// Turn quiet hours on or off against the schedule, needs the override token
+    QuietHoursOverride(QuietHoursOverride),
+    AdminKick(ClientTarget),
+    /// Every current state event, answered by the transport since it takes several events
+    FullState,
+    GetStateSnapshot,
+    /// Negotiates the events a connection receives, answered by the transport
+    Hello(ClientHello),
+    SetVolume(VolumeLevel),
+    ListPeers,
+    /// Run a function on a federated server, this one or all of them
+    PeerCommand(PeerCommand),
+    /// Run a function against one session rather than the current one
+    SessionCommand(SessionCommand),
+}
+
+impl MediaCommand {
+    /// Event code of the command
+    pub fn name(&self) -> &'static str {
+        match self {
+            MediaCommand::GetMediaDetails => "get_media_details",
+            MediaCommand::TogglePlayPause(_) => "toggle_play_pause",
+            MediaCommand::Play => "play",
+            MediaCommand::Pause => "pause",
+            MediaCommand::Stop => "stop",
+            MediaCommand::FastForward => "fast_forward",
+            MediaCommand::Rewind => "rewind",
+            MediaCommand::NextTrack => "next_track",
+            MediaCommand::PreviousTrack => "previous_track",
+            MediaCommand::Seek(_) => "seek",
+            MediaCommand::SetRepeatMode(_) => "set_repeat_mode",
+            MediaCommand::ToggleShuffle => "toggle_shuffle",
+            MediaCommand::GetLyrics => "get_lyrics",
+            MediaCommand::GetQueue => "get_queue",
+            MediaCommand::SpotifySaveTrack => "spotify_save_track",
+            MediaCommand::SpotifyAddToPlaylist(_) => "spotify_add_to_playlist",
+            MediaCommand::SpotifyAddToQueue(_) => "spotify_add_to_queue",
+            MediaCommand::SpotifyGetDevices => "spotify_get_devices",
+            MediaCommand::SpotifyTransferPlayback(_) => "spotify_transfer_playback",
+            MediaCommand::GetOutputs => "get_outputs",
+            MediaCommand::SetOutputVolume(_) => "set_output_volume",
+            MediaCommand::SetOutputMute(_) => "set_output_mute",
+            MediaCommand::SetSleepTimer(_) => "set_sleep_timer",
+            MediaCommand::CancelSleepTimer => "cancel_sleep_timer",
+            MediaCommand::CancelInactivityPause => "cancel_inactivity_pause",
+            MediaCommand::AddSchedule(_) => "add_schedule",
+            MediaCommand::ListSchedules => "list_schedules",
+            MediaCommand::RemoveSchedule(_) => "remove_schedule",
+            MediaCommand::BookmarkPosition => "bookmark_position",
+            MediaCommand::ResumeBookmark => "resume_bookmark",
+            MediaCommand::ListBookmarks => "list_bookmarks",
+            MediaCommand::AddPositionAlarm(_) => "add_position_alarm",
+            MediaCommand::ListPositionAlarms => "list_position_alarms",
+            MediaCommand::RemovePositionAlarm(_) => "remove_position_alarm",
+            MediaCommand::QuietHoursOverride(_) => "quiet_hours_override",
+            MediaCommand::AdminKick(_) => "admin_kick",
+            MediaCommand::FullState => "full_state",
+            MediaCommand::GetStateSnapshot => "get_state_snapshot",
+            MediaCommand::Hello(_) => "hello",
+            MediaCommand::SetVolume(_) => "set_volume",
+            MediaCommand::ListPeers => "list_peers",
+            MediaCommand::PeerCommand(_) => "peer_command",
+            MediaCommand::SessionCommand(_) => "session_command",
+        }
+    }
+
+    /// Commands served by online services rather than the media session
+    fn is_remote(&self) -> bool {
+        matches!(
+            self,
+            MediaCommand::GetLyrics
+                | MediaCommand::GetQueue
+                | MediaCommand::SpotifySaveTrack
+                | MediaCommand::SpotifyAddToPlaylist(_)
+                | MediaCommand::SpotifyAddToQueue(_)
+                | MediaCommand::SpotifyGetDevices
+                | MediaCommand::SpotifyTransferPlayback(_)
+                | MediaCommand::GetOutputs
+                | MediaCommand::SetOutputVolume(_)
+                | MediaCommand::SetOutputMute(_)
+        )
+    }
+}
+
+/// Payload of the `plugin_event` event
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct PluginEvent {
+    /// File name of the script without extension
+    pub plugin: String,
+    pub name: String,
+    pub data: serde_json::Value,
+}
+
+/// Payload of the `error` event
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct CommandError {
+    /// Code of the command that failed
+    pub command: &'static str,
+    pub message: String,
+    #[serde(flatten)]
+    pub error: MediaError,
+}
+
+impl CommandError {
+    pub fn new(command: &'static str, error: MediaError) -> Self {
+        Self {
+            command,
+            message: error.to_string(),
+            error,
+        }
+    }
+}
+
+/// Result of a command, sent back to clients that asked for an acknowledgement
+#[derive(Debug, Serialize, Clone, JsonSchema, ToSchema)]
+pub struct CommandAck {
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+impl CommandAck {
+    pub fn ok() -> Self {
+        Self {
+            ok: true,
+            error: None,
+        }
+    }
+
+    pub fn error(error: impl ToString) -> Self {
+        Self {
+            ok: false,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Start a service that depends on configuration, logging instead of failing
+/// so the rest of the hub keeps working
+fn optional_service<T>(name: &str, service: Option<anyhow::Result<T>>) -> Option<Arc<T>> {
+    match service? {
+        Ok(service) => Some(Arc::new(service)),
+        Err(e) => {
+            tracing::error!("Failed to start {} service: {}", name, e);
+            None
+        }
+    }
+}
+
+fn spotify_error(error: anyhow::Error) -> MediaError {
+    MediaError::internal(format!("Spotify request failed: {:#}", error))
+}
+
+fn snapcast_error(error: anyhow::Error) -> MediaError {
+    MediaError::internal(format!("Snapcast request failed: {:#}", error))
+}
+
+/// A function sent by code and payload, like the events of a transport
+fn parse_command(name: &str, data: Option<serde_json::Value>) -> MediaResult<MediaCommand> {
+    serde_json::from_value(serde_json::json!({ "event": name, "data": data }))
+        .map_err(|e| MediaError::invalid_input(format!("Invalid {} command: {}", name, e)))
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct HandlerCounts {
+    /// Handlers registered on the media session
+    pub media_listeners: usize,
+    /// Transports subscribed to hub events
+    pub subscribers: usize,
+}
+
+/// Owns a single MediaManager and republishes its change events to any number
+/// of subscribers.
+#[derive(Clone)]
+pub struct EventHub {
+    sender: broadcast::Sender<MediaEvent>,
+    config: Arc<Config>,
+    /// The MediaManager, owned by a thread of its own
+    media: MediaController,
+    /// Whether the session handlers should be registered, not while idle or polling
+    watching: Arc<AtomicBool>,
+    /// `None` when lyrics are disabled
+    lyrics: Option<Arc<LyricsService>>,
+    /// `None` when metadata enrichment is disabled
+    metadata: Option<Arc<MetadataService>>,
+    queue: Arc<QueueService>,
+    /// `None` when Spotify isn't configured
+    spotify: Option<Arc<SpotifyClient>>,
+    /// `None` when Snapcast isn't configured
+    snapcast: Option<Arc<SnapcastClient>>,
+    /// `None` when no skip rules are configured
+    skip_list: Option<Arc<SkipList>>,
+    /// `None` when no peers are configured
+    peers: Option<Arc<PeerRegistry>>,
+    /// `None` unless muting ads is enabled
+    ad_muter: Option<Arc<AdMuter>>,
+    /// `None` when quiet hours aren't configured
+    quiet_hours: Option<Arc<QuietHours>>,
+    /// `None` unless playback pauses when the workstation locks
+    session_lock: Option<Arc<SessionLock>>,
+    /// `None` unless playback pauses when the host goes without input
+    inactivity: Option<Arc<Inactivity>>,
+    /// Position of the current track at the last check of the position alarms
+    alarm_watch: Arc<AlarmWatch>,
+    /// Artwork of tracks without one, `None` unless configured
+    placeholder: Option<Arc<Vec<u8>>>,
+    /// Source app, title and artist of the track whose artwork is being read again
+    thumbnail_retry: Arc<Mutex<Option<(String, String, String)>>>,
+    /// Ids of the last broadcast track and the one before it
+    track_ids: Arc<Mutex<(String, Option<String>)>>,
+    /// Last known state, served until a session shows up after a restart
+    state_store: Arc<StateStore>,
+    /// Held while fading, overlapping fades would restore each other's levels
+    fade_lock: Arc<Mutex<()>>,
+    clients: ClientRegistry,
+    /// Watchers of the sessions sockets are pinned to
+    pins: PinnedSessions,
+    started_at: Instant,
+    /// Runtime the hub was created on, the media thread has none of its own
+    runtime: Handle,
+    scheduler: Scheduler,
+    /// Payload of the last broadcast state events by event code
+    last_emitted: Arc<Mutex<HashMap<&'static str, serde_json::Value>>>,
+}
+
+impl EventHub {
+    /// Start the media thread and the configured services
+    ///
+    /// # Arguments
+    /// * `backend` - Source of the media sessions, moved to a thread of its own
+    /// * `config` - Configuration of the optional services
+    pub fn new(backend: Box<dyn MediaBackend>, config: Arc<Config>) -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+
+        let lyrics = optional_service(
+            "lyrics",
+            config
+                .lyrics
+                .enabled
+                .then(|| LyricsService::new(config.lyrics.clone())),
+        );
+        let metadata = optional_service(
+            "metadata",
+            config
+                .metadata
+                .enabled
+                .then(|| MetadataService::new(&config.metadata)),
+        );
+        let spotify = optional_service("spotify", config.spotify.clone().map(SpotifyClient::new));
+        let queue = Arc::new(QueueService::new(spotify.clone()));
+        let snapcast = optional_service(
+            "snapcast",
+            config.snapcast.as_ref().map(SnapcastClient::new),
+        );
+        let skip_list = optional_service(
+            "skip list",
+            (!config.skip.is_empty()).then(|| SkipList::new(&config.skip)),
+        );
+        let peers = optional_service(
+            "peers",
+            (!config.peers.is_empty()).then(|| PeerRegistry::new(&config.peers)),
+        );
+        let quiet_hours = optional_service(
+            "quiet hours",
+            config.quiet_hours.as_ref().map(QuietHours::new),
+        );
+        let placeholder =
+            config
+                .thumbnails
+                .placeholder
+                .as_ref()
+                .and_then(|path| match std::fs::read(path) {
+                    Ok(bytes) => Some(Arc::new(bytes)),
+                    Err(e) => {
+                        tracing::error!("Failed to read placeholder {}: {}", path.display(), e);
+                        None
+                    }
+                });
+
+        Self {
+            sender,
+            config,
+            media: MediaController::spawn(backend),
+            watching: Arc::new(AtomicBool::new(true)),
+            lyrics,
+            metadata,
+            queue,
+            spotify,
+            snapcast,
+            skip_list,
+            peers,
+            ad_muter: config.ads.mute.then(Arc::default),
+            quiet_hours,
+            session_lock: config
+                .lock
+                .pause_on_lock
+                .then(|| Arc::new(SessionLock::new(&config.lock))),
+            inactivity: config
+                .inactivity
+                .as_ref()
+                .map(|inactivity| Arc::new(Inactivity::new(inactivity))),
+            alarm_watch: Arc::default(),
+            placeholder,
+            thumbnail_retry: Arc::default(),
+            track_ids: Arc::default(),
+            state_store: Arc::new(StateStore::load()),
+            fade_lock: Arc::new(Mutex::new(())),
+            clients: ClientRegistry::default(),
+            pins: PinnedSessions::default(),
+            started_at: Instant::now(),
+            // The hub is created on the runtime, timers must be spawned there too
+            runtime: Handle::current(),
+            scheduler: Scheduler::new(Handle::current()),
+            last_emitted: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<MediaEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Broadcast a custom event of a plugin to every client
+    pub fn publish_plugin_event(&self, event: PluginEvent) {
+        self.publish(MediaEvent::PluginEvent(event));
+    }
+
+    /// Relay a state event of a federated server to every client
+    pub fn publish_peer_event(&self, event: PeerEvent) {
+        self.publish(MediaEvent::PeerEvent(event));
+    }
+
+    /// Broadcast the Snapcast outputs to every client
+    pub fn publish_outputs(&self, groups: Vec<OutputGroup>) {
+        self.publish(MediaEvent::Outputs(groups));
+    }
+
+    /// Broadcast every session to the clients of aggregate mode
+    pub fn publish_sessions(&self, feeds: Vec<SessionFeed>) {
+        self.publish(MediaEvent::Sessions(feeds));
+    }
+
+    /// Tell every client which sessions were paused for playing alongside another
+    pub fn publish_conflict_resolved(&self, resolved: ConflictResolved) {
+        self.publish(MediaEvent::SessionConflictResolved(resolved));
+    }
+
+    /// Tell every client that the default audio output changed
+    pub fn publish_output_device(&self, change: OutputDeviceChange) {
+        self.publish(MediaEvent::OutputDeviceChanged(change));
+    }
+
+    /// Tell every client which federated servers are connected
+    pub fn publish_peers(&self, peers: Vec<PeerStatus>) {
+        self.publish(MediaEvent::Peers(peers));
+    }
+
+    /// Republish the changes of the media session and start watching it
+    ///
+    /// # Arguments
+    /// * `watch` - Whether to register the session handlers, not when the session
+    ///   is polled instead
+    pub fn start(&self, watch: bool) -> MediaResult<()> {
+        // Subscribed before watching, so the first changes aren't missed
+        let mut changes = self.media.events();
+        let hub = self.clone();
+        std::thread::spawn(move || {
+            loop {
+                match changes.blocking_recv() {
+                    Ok(change) => hub.handle_change(change),
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Missed {} media session changes, resending state", skipped);
+                        hub.publish_current_state();
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+        self.watching.store(watch, Ordering::Relaxed);
+        if watch {
+            self.media.watch()?;
+        }
+
+        let hub = self.clone();
+        self.scheduler
+            .start_schedules(move |action| hub.run_schedule(action));
+        Ok(())
+    }
+
+    fn handle_change(&self, change: ManagerEvent) {
+        match change {
+            ManagerEvent::TrackChanged(track) => self.publish_track_info(*track),
+            ManagerEvent::ControlsChanged(controls) => {
+                self.publish(MediaEvent::TrackControls(controls))
+            }
+            ManagerEvent::TimelineChanged(timeline) => {
+                self.publish(MediaEvent::TrackTimeline(timeline))
+            }
+            ManagerEvent::SessionChanged => self.publish_current_state(),
+        }
+    }
+
+    /// Cheap call into the session manager, failing once it is broken
+    #[cfg(windows)]
+    pub async fn check_media_manager(&self) -> MediaResult<()> {
+        self.media
+            .call_async(|manager| manager.sessions().map(|_| ()))
+            .await?
+    }
+
+    /// Replace a broken session manager without disconnecting any client
+    ///
+    /// Every client gets a `resync` event followed by the whole current state.
+    #[cfg(windows)]
+    pub fn restart_media_manager(&self) -> MediaResult<()> {
+        tracing::warn!("Recreating the media session manager");
+        let manager = MediaManager::new()?
+            .with_profiles(self.config.apps.clone())
+            .with_timeout(self.config.winrt.timeout());
+        self.media.replace(Box::new(manager))?;
+        if !self.watching.load(Ordering::Relaxed) {
+            self.media.unwatch()?;
+        }
+
+        // Changes may have been lost while it was broken, so nothing counts as sent
+        if let Ok(mut last_emitted) = self.last_emitted.lock() {
+            last_emitted.clear();
+        }
+        self.publish(MediaEvent::Resync);
+        self.publish_current_state();
+        Ok(())
+    }
+
+    /// Register or remove the media session handlers, used to go idle while no
+    /// client listens
+    ///
+    /// # Arguments
+    /// * `watching` - Whether changes of the current session should be broadcast
+    pub fn set_watching(&self, watching: bool) -> MediaResult<()> {
+        self.watching.store(watching, Ordering::Relaxed);
+        if !watching {
+            return self.media.unwatch();
+        }
+        self.media.watch()?;
+        // Changes made while idle went unseen
+        self.publish_current_state();
+        Ok(())
+    }
+
+    /// Tell clients the server is stopping, remove every media session handler and
+    /// write the last known state to disk
+    pub fn shutdown(&self) {
+        self.publish(MediaEvent::ServerShutdown);
+        if let Err(e) = self.media.unwatch() {
+            tracing::error!("Failed to remove media session handlers: {}", e);
+        }
+        self.persist_state();
+    }
+
+    /// The state events broadcast last, read from the current session before
+    /// anything was broadcast
+    pub fn emitted_state(&self) -> EmittedState {
+        let mut events: BTreeMap<&'static str, serde_json::Value> = self
+            .last_emitted
+            .lock()
+            .map(|last_emitted| {
+                last_emitted
+                    .iter()
+                    .map(|(name, payload)| (*name, payload.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if events.is_empty() {
+            events = self
+                .current_state()
+                .iter()
+                .filter(|event| event.is_state())
+                .map(|event| (event.name(), event.payload()))
+                .collect();
+        }
+        let etag = utils::content_hash(&serde_json::to_vec(&events).unwrap_or_default());
+        EmittedState { etag, events }
+    }
+
+    /// Collect the current state of the active session, used to prime new subscribers
+    pub fn current_state(&self) -> Vec<MediaEvent> {
+        let hub = self.clone();
+        self.media
+            .call(move |manager| hub.current_state_of(manager))
+            .unwrap_or_default()
+    }
+
+    fn current_state_of(&self, manager: &dyn MediaBackend) -> Vec<MediaEvent> {
+        let mut events = Vec::new();
+        let state = manager.session_state();
+        let active = state.state == SessionStatus::Active;
+        events.push(MediaEvent::SessionState(state));
+
+        let sleep_timer = self.scheduler.sleep_timer_status();
+        if sleep_timer.active {
+            events.push(MediaEvent::SleepTimerStatus(sleep_timer));
+        }
+        if let Some(quiet_hours) = &self.quiet_hours {
+            events.push(MediaEvent::QuietHoursChanged(quiet_hours.status()));
+        }
+        if let Some(lock) = &self.session_lock {
+            events.push(MediaEvent::LockChanged(lock.status()));
+        }
+        if let Some(inactivity) = &self.inactivity {
+            events.push(MediaEvent::InactivityStatus(inactivity.status()));
+        }
+
+        if !active {
+            let stale = self.state_store.stale_events();
+            if stale.is_empty() {
+                events.push(MediaEvent::TrackInfo(None));
+            }
+            events.extend(stale);
+            return events;
+        }
+        self.state_store.mark_live();
+
+        if let Ok(info) = manager.track_info() {
+            events.push(MediaEvent::TrackInfo(Some(self.for_clients(info))));
+        }
+        if let Ok(controls) = manager.track_controls() {
+            events.push(MediaEvent::TrackControls(controls));
+        }
+        if let Ok(timeline) = manager.track_timeline() {
+            events.push(MediaEvent::TrackTimeline(timeline));
+        }
+        events
+    }
+
+    /// The state events of an app's session, as if it were the current one, for
+    /// clients pinned to it
+    ///
+    /// # Arguments
+    /// * `app_id` - Source app id, compared case-insensitively
+    /// * `artwork` - Whether the track comes with its artwork
+    pub fn pinned_state(&self, app_id: &str, artwork: bool) -> Vec<MediaEvent> {
+        let app_id = app_id.to_string();
+        let snapshot = match self
+            .media
+            .call(move |manager| manager.session_snapshot(&app_id, artwork))
+        {
+            Ok(Ok(snapshot)) => snapshot,
+            // The app isn't running, its session comes back when it is restarted
+            Ok(Err(MediaError::NoSession)) => {
+                return vec![
+                    MediaEvent::SessionState(SessionState {
+                        state: SessionStatus::None,
+                        session: None,
+                    }),
+                    MediaEvent::TrackInfo(None),
+                ];
+            }
+            Ok(Err(e)) | Err(e) => {
+                tracing::error!("Failed to read pinned session: {}", e);
+                return Vec::new();
+            }
+        };
+
+        let mut events = vec![
+            MediaEvent::SessionState(SessionState {
+                state: SessionStatus::Active,
+                session: Some(snapshot.track_info.session.clone()),
+            }),
+            MediaEvent::TrackInfo(Some(self.for_clients(snapshot.track_info))),
+        ];
+        events.extend(snapshot.track_controls.map(MediaEvent::TrackControls));
+        events.extend(snapshot.track_timeline.map(MediaEvent::TrackTimeline));
+        events
+    }
+
+    /// Changes of an app's session for a socket pinned to it, read by one watcher
+    /// shared with every other socket pinned to the app
+    ///
+    /// # Arguments
+    /// * `app_id` - Source app id, compared case-insensitively
+    pub fn follow_pinned(&self, app_id: &str) -> broadcast::Receiver<MediaEvent> {
+        self.pins.follow(self, app_id)
+    }
+
+    /// Read the current session when its app has a poll interval and publish what
+    /// changed, its change events may never have fired
+    ///
+    /// # Arguments
+    /// * `interval` - Poll interval of apps without one of their own, `None` to poll
+    ///   only those with one. Also follows which session is current, for servers
+    ///   without session handlers
+    ///
+    /// # Returns
+    /// * `MediaResult<Option<Duration>>` - Poll interval of the current app, `None`
+    ///   when it isn't polled or there is no session
+    pub fn poll_current_session(
+        &self,
+        interval: Option<Duration>,
+    ) -> MediaResult<Option<Duration>> {
+        if interval.is_some() {
+            let state = self.media.call(|manager| manager.session_state())?;
+            let active = state.state == SessionStatus::Active;
+            // Like the handler of current session changes, without a session it is
+            // all that is published
+            if !self.was_emitted(&MediaEvent::SessionState(state)) {
+                self.publish_current_state();
+            }
+            if !active {
+                return Ok(None);
+            }
+        }
+
+        let config = Arc::clone(&self.config);
+        let fallback_ms = interval.map(|interval| interval.as_millis() as u64);
+        let read = self.media.call(
+            move |manager| -> MediaResult<Option<(u64, TrackInfo, TrackControls, TrackTimeline)>> {
+                let app_id = manager.current_app_id()?;
+                let poll_ms = config.app_profile(&app_id).and_then(|p| p.poll_ms);
+                let Some(poll_ms) = poll_ms.or(fallback_ms) else {
+                    return Ok(None);
+                };
+                Ok(Some((
+                    poll_ms,
+                    manager.track_info_without_artwork()?,
+                    manager.track_controls()?,
+                    manager.track_timeline()?,
+                )))
+            },
+        )?;
+        let (poll_ms, track, controls, timeline) = match read {
+            Ok(Some(read)) => read,
+            Ok(None) | Err(MediaError::NoSession) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        // Publishing the same track again would look up its lyrics and metadata again,
+        // and only a new track is worth reading the artwork for
+        let known = self
+            .track_ids
+            .lock()
+            .is_ok_and(|track_ids| track_ids.0 == track.track_id);
+        if !known {
+            let track = self.media.call(|manager| manager.track_info())??;
+            self.publish_track_info(track);
+        }
+        // Skipped like those of change events when nothing changed
+        self.publish(MediaEvent::TrackControls(controls));
+        self.publish(MediaEvent::TrackTimeline(timeline));
+        Ok(Some(Duration::from_millis(poll_ms)))
+    }
+
+    /// Every session with its state, for the `sessions` event of aggregate mode
+    pub fn session_feeds(&self) -> MediaResult<Vec<SessionFeed>> {
+        let feeds = self.media.call(|manager| {
+            let mut sessions = manager.sessions()?;
+            // Sessions of the same app can't be told apart
+            let mut seen = HashSet::new();
+            sessions.retain(|session| seen.insert(session.session_id.clone()));
+            let feeds: Vec<SessionFeed> = sessions
+                .into_iter()
+                .map(
+                    |session| match manager.session_snapshot(&session.source_app_id, true) {
+                        Ok(snapshot) => SessionFeed {
+                            session,
+                            track_info: Some(snapshot.track_info),
+                            track_controls: snapshot.track_controls,
+                            track_timeline: snapshot.track_timeline,
+                        },
+                        Err(_) => SessionFeed {
+                            session,
+                            track_info: None,
+                            track_controls: None,
+                            track_timeline: None,
+                        },
+                    },
+                )
+                .collect();
+            MediaResult::Ok(feeds)
+        })??;
+        Ok(feeds
+            .into_iter()
+            .map(|mut feed| {
+                feed.track_info = feed.track_info.map(|track| self.for_clients(track));
+                feed
+            })
+            .collect())
+    }
+
+    /// The state snapshot followed by the separate state events older clients expect
+    pub fn initial_events(&self) -> Vec<MediaEvent> {
+        let mut events = Vec::new();
+        match self.state_snapshot() {
+            Ok(snapshot) => events.push(MediaEvent::StateSnapshot(Box::new(snapshot))),
+            Err(e) => tracing::error!("Failed to collect state snapshot: {}", e),
+        }
+        events.extend(self.current_state());
+        events
+    }
+
+    /// The whole state in one payload, sent on connect and on request
+    pub fn state_snapshot(&self) -> MediaResult<StateSnapshot> {
+        let hub = self.clone();
+        self.media
+            .call(move |manager| hub.state_snapshot_of(manager))
+    }
+
+    fn state_snapshot_of(&self, manager: &dyn MediaBackend) -> StateSnapshot {
+        let session = manager.session_state();
+        let active = session.state == SessionStatus::Active;
+
+        if !active {
+            let mut snapshot = StateSnapshot {
+                sessions: manager.sessions().unwrap_or_default(),
+                track_info: None,
+                track_controls: None,
+                track_timeline: None,
+                session,
+                volume: volume::system_volume().ok(),
+                sleep_timer: self.scheduler.sleep_timer_status(),
+                inactivity: self.inactivity_status(),
+                capabilities: self.capabilities(),
+            };
+            for event in self.state_store.stale_events() {
+                match event {
+                    MediaEvent::TrackInfo(track) => snapshot.track_info = track,
+                    MediaEvent::TrackControls(controls) => snapshot.track_controls = Some(controls),
+                    _ => {}
+                }
+            }
+            return snapshot;
+        }
+        self.state_store.mark_live();
+
+        StateSnapshot {
+            sessions: manager.sessions().unwrap_or_default(),
+            track_info: manager
+                .track_info()
+                .ok()
+                .map(|track| self.for_clients(track)),
+            track_controls: manager.track_controls().ok(),
+            track_timeline: manager.track_timeline().ok(),
+            session,
+            volume: volume::system_volume().ok(),
+            sleep_timer: self.scheduler.sleep_timer_status(),
+            inactivity: self.inactivity_status(),
+            capabilities: self.capabilities(),
+        }
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            lyrics: self.lyrics.is_some(),
+            metadata: self.metadata.is_some(),
+            spotify: self.spotify.is_some(),
+            media_keys: self.config.media_keys.fallback
+                || !self.config.media_keys.apps.is_empty()
+                || self.config.apps.values().any(|profile| profile.media_keys),
+            outputs: self.snapcast.is_some(),
+            peers: self.peers.is_some(),
+            sessions: self.config.aggregate.is_some(),
+            quiet_hours: self.quiet_hours.is_some(),
+        }
+    }
+
+    /// Answer a client's `hello`, pairing it when it sent a device token
+    ///
+    /// # Arguments
+    /// * `id` - Id of the connection
+    /// * `hello` - What the client asked for
+    ///
+    /// # Returns
+    /// * `MediaResult<(EventFilter, ServerHello)>` - The filter for the client's events and the answer to send
+    pub fn hello(&self, id: &str, hello: &ClientHello) -> MediaResult<(EventFilter, ServerHello)> {
+        let (filter, mut reply) = EventFilter::negotiate(hello, self.capabilities())?;
+        if let Some(token) = &hello.device_token {
+            let device = self
+                .config
+                .automation
+                .device(token)
+                .ok_or_else(|| MediaError::invalid_input("Unknown device token"))?;
+            self.clients.identify(id, device);
+            reply.device = Some(device.to_string());
+        }
+        Ok((filter, reply))
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The media thread, for reading its changes directly
+    pub fn media(&self) -> &MediaController {
+        &self.media
+    }
+
+    /// Clients connected over Socket.IO or the plain WebSocket
+    pub fn clients(&self) -> &ClientRegistry {
+        &self.clients
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub async fn session_state(&self) -> MediaResult<SessionState> {
+        self.media
+            .call_async(|manager| manager.session_state())
+            .await
+    }
+
+    /// Registered media session handlers and event subscribers
+    pub fn handler_counts(&self) -> HandlerCounts {
+        HandlerCounts {
+            media_listeners: self.media.handler_count(),
+            subscribers: self.sender.receiver_count(),
+        }
+    }
+
+    /// Played tracks, newest first, kept across restarts
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.state_store.history()
+    }
+
+    /// Every played track kept, newest first
+    pub fn full_history(&self) -> Vec<HistoryEntry> {
+        self.state_store.full_history()
+    }
+
+    /// Write the last known state to disk
+    pub fn persist_state(&self) {
+        self.state_store.flush();
+    }
+
+    pub fn sleep_timer_status(&self) -> SleepTimerStatus {
+        self.scheduler.sleep_timer_status()
+    }
+
+    /// Raw artwork of the current track
+    pub fn artwork(&self) -> MediaResult<Vec<u8>> {
+        self.media.call(|manager| manager.artwork())?
+    }
+
+    /// Image sent instead of missing artwork, if configured
+    pub fn placeholder(&self) -> Option<Vec<u8>> {
+        self.placeholder.as_ref().map(|bytes| bytes.to_vec())
+    }
+
+    /// Run a command against the current session.
+    ///
+    /// # Returns
+    /// * `MediaResult<Option<MediaEvent>>` - An event to send back to the requesting client only
+    pub fn execute(&self, command: MediaCommand) -> MediaResult<Option<MediaEvent>> {
+        self.refuse_in_quiet_hours(&command)?;
+        match command {
+            MediaCommand::TogglePlayPause(Some(fade)) => return self.toggle_with_fade(fade),
+            MediaCommand::SetSleepTimer(request) => return self.set_sleep_timer(&request),
+            MediaCommand::CancelSleepTimer => {
+                let status = self.scheduler.cancel_sleep_timer()?;
+                self.publish(MediaEvent::SleepTimerStatus(status));
+                return Ok(None);
+            }
+            MediaCommand::CancelInactivityPause => {
+                let inactivity = self
+                    .inactivity
+                    .as_ref()
+                    .ok_or_else(|| MediaError::unsupported("cancel_inactivity_pause"))?;
+                self.publish(MediaEvent::InactivityStatus(inactivity.cancel()?));
+                return Ok(None);
+            }
+            MediaCommand::AddSchedule(request) => {
+                let schedules = self.scheduler.add_schedule(request)?;
+                self.publish(MediaEvent::Schedules(schedules));
+                return Ok(None);
+            }
+            MediaCommand::ListSchedules => {
+                return Ok(Some(MediaEvent::Schedules(self.scheduler.schedules()?)));
+            }
+            MediaCommand::RemoveSchedule(ScheduleTarget { id }) => {
+                let schedules = self.scheduler.remove_schedule(id)?;
+                self.publish(MediaEvent::Schedules(schedules));
+                return Ok(None);
+            }
+            MediaCommand::BookmarkPosition => return self.bookmark_position(),
+            MediaCommand::ResumeBookmark => return self.resume_bookmark(),
+            MediaCommand::ListBookmarks => {
+                return Ok(Some(MediaEvent::Bookmarks(self.state_store.bookmarks())));
+            }
+            MediaCommand::AddPositionAlarm(request) => return self.add_position_alarm(request),
+            MediaCommand::ListPositionAlarms => {
+                return Ok(Some(MediaEvent::PositionAlarms(
+                    self.state_store.position_alarms(),
+                )));
+            }
+            MediaCommand::RemovePositionAlarm(PositionAlarmTarget { id }) => {
+                let alarms = self
+                    .state_store
+                    .remove_position_alarm(id)
+                    .ok_or_else(|| MediaError::invalid_input("No position alarm with this id"))?;
+                self.publish(MediaEvent::PositionAlarms(alarms));
+                return Ok(None);
+            }
+            MediaCommand::QuietHoursOverride(request) => {
+                return self.override_quiet_hours(&request);
+            }
+            MediaCommand::AdminKick(ClientTarget { id }) => {
+                self.clients.kick(&id)?;
+                return Ok(None);
+            }
+            MediaCommand::FullState => return Err(MediaError::unsupported("full_state")),
+            MediaCommand::Hello(_) => return Err(MediaError::unsupported("hello")),
+            MediaCommand::SetVolume(VolumeLevel { level }) => {
+                volume::set_system_volume(self.capped_volume(level))?;
+                return Ok(None);
+            }
+            MediaCommand::GetStateSnapshot => {
+                return Ok(Some(MediaEvent::StateSnapshot(Box::new(
+                    self.state_snapshot()?,
+                ))));
+            }
+            MediaCommand::ListPeers => {
+                return Ok(Some(MediaEvent::Peers(self.peer_registry()?.status())));
+            }
+            MediaCommand::PeerCommand(command) => return self.execute_on_peer(command),
+            MediaCommand::SessionCommand(command) => return self.execute_on_session(command),
+            // Lookups that hit the network must not hold up the media thread
+            command if command.is_remote() => return self.execute_remote(command),
+            _ => {}
+        }
+
+        if let Some(key) = MediaKey::for_command(&command) {
+            let app_id = self.media.call(|manager| manager.current_app_id())?;
+            let media_keys_config = &self.config.media_keys;
+            let forced = app_id
+                .as_deref()
+                .ok()
+                .and_then(|app_id| self.config.app_profile(app_id))
+                .is_some_and(|profile| profile.media_keys);
+            if forced
+                || media_keys::should_use_media_keys(
+                    &app_id,
+                    media_keys_config.fallback,
+                    &media_keys_config.apps,
+                )
+            {
+                media_keys::send_media_key(key)?;
+                return Ok(None);
+            }
+        }
+
+        let command = match command {
+            MediaCommand::GetMediaDetails => {
+                let track = match self.media.call(|manager| manager.track_info())? {
+                    Ok(track) => Some(self.for_clients(track)),
+                    Err(MediaError::NoSession) => None,
+                    Err(e) => return Err(e),
+                };
+                return Ok(Some(MediaEvent::TrackInfo(track)));
+            }
+            MediaCommand::TogglePlayPause(_) => Command::TogglePlayPause,
+            MediaCommand::Play => Command::Play,
+            MediaCommand::Pause => Command::Pause,
+            MediaCommand::Stop => Command::Stop,
+            MediaCommand::FastForward => Command::FastForward,
+            MediaCommand::Rewind => Command::Rewind,
+            MediaCommand::NextTrack => Command::NextTrack,
+            MediaCommand::PreviousTrack => Command::PreviousTrack,
+            MediaCommand::Seek(SeekPosition { position }) => Command::Seek { ms: position },
+            MediaCommand::SetRepeatMode(mode) => Command::SetRepeat { mode },
+            MediaCommand::ToggleShuffle => Command::ToggleShuffle,
+            MediaCommand::GetLyrics
+            | MediaCommand::GetQueue
+            | MediaCommand::SpotifySaveTrack
+            | MediaCommand::SpotifyAddToPlaylist(_)
+            | MediaCommand::SpotifyAddToQueue(_)
+            | MediaCommand::SpotifyGetDevices
+            | MediaCommand::SpotifyTransferPlayback(_)
+            | MediaCommand::GetOutputs
+            | MediaCommand::SetOutputVolume(_)
+            | MediaCommand::SetOutputMute(_)
+            | MediaCommand::SetSleepTimer(_)
+            | MediaCommand::CancelSleepTimer
+            | MediaCommand::CancelInactivityPause
+            | MediaCommand::AddSchedule(_)
+            | MediaCommand::ListSchedules
+            | MediaCommand::RemoveSchedule(_)
+            | MediaCommand::BookmarkPosition
+            | MediaCommand::ResumeBookmark
+            | MediaCommand::ListBookmarks
+            | MediaCommand::AddPositionAlarm(_)
+            | MediaCommand::ListPositionAlarms
+            | MediaCommand::RemovePositionAlarm(_)
+            | MediaCommand::QuietHoursOverride(_)
+            | MediaCommand::AdminKick(_)
+            | MediaCommand::FullState
+            | MediaCommand::GetStateSnapshot
+            | MediaCommand::Hello(_)
+            | MediaCommand::SetVolume(_)
+            | MediaCommand::ListPeers
+            | MediaCommand::PeerCommand(_)
+            | MediaCommand::SessionCommand(_) => {
+                unreachable!("handled before calling the manager")
+            }
+        };
+        self.media.command(command)?;
+        Ok(None)
+    }
+
+    /// Run a command for a client pinned to an app, playback commands go to that
+    /// app's session and everything else to `execute`
+    ///
+    /// # Arguments
+    /// * `app_id` - Source app id of the pinned session
+    /// * `command` - Command to run, a play/pause fade is left out
+    pub fn execute_pinned(
+        &self,
+        app_id: &str,
+        command: MediaCommand,
+    ) -> MediaResult<Option<MediaEvent>> {
+        self.refuse_in_quiet_hours(&command)?;
+        let command = match command {
+            MediaCommand::GetMediaDetails => {
+                let track =
+                    self.pinned_state(app_id, true)
+                        .into_iter()
+                        .find_map(|event| match event {
+                            MediaEvent::TrackInfo(track) => track,
+                            _ => None,
+                        });
+                return Ok(Some(MediaEvent::TrackInfo(track)));
+            }
+            MediaCommand::TogglePlayPause(_) => Command::TogglePlayPause,
+            MediaCommand::Play => Command::Play,
+            MediaCommand::Pause => Command::Pause,
+            MediaCommand::Stop => Command::Stop,
+            MediaCommand::FastForward => Command::FastForward,
+            MediaCommand::Rewind => Command::Rewind,
+            MediaCommand::NextTrack => Command::NextTrack,
+            MediaCommand::PreviousTrack => Command::PreviousTrack,
+            MediaCommand::Seek(SeekPosition { position }) => Command::Seek { ms: position },
+            MediaCommand::SetRepeatMode(mode) => Command::SetRepeat { mode },
+            MediaCommand::ToggleShuffle => Command::ToggleShuffle,
+            command => return self.execute(command),
+        };
+        self.media.command_for(app_id, command)?;
+        Ok(None)
+    }
+
+    /// Run a function against the session with an id, as if the client were pinned to it
+    fn execute_on_session(&self, command: SessionCommand) -> MediaResult<Option<MediaEvent>> {
+        let sessions = self.media.call(|manager| manager.sessions())??;
+        let session = sessions
+            .into_iter()
+            .find(|session| session.session_id == command.session_id)
+            .ok_or_else(|| {
+                MediaError::invalid_input(format!("No session with id {}", command.session_id))
+            })?;
+        let target = parse_command(&command.command, command.data)?;
+        self.execute_pinned(&session.source_app_id, target)
+    }
+
+    /// Toggle playback, fading the volume out before pausing and back in after resuming
+    fn toggle_with_fade(&self, fade: Fade) -> MediaResult<Option<MediaEvent>> {
+        let duration = Duration::from_millis(fade.fade_ms);
+        if duration > MAX_FADE {
+            return Err(MediaError::invalid_input(format!(
+                "Fade is longer than {} seconds",
+                MAX_FADE.as_secs()
+            )));
+        }
+
+        let (playing, app_id) = self
+            .media
+            .call(|manager| (manager.is_playing(), manager.current_app_id()))?;
+        // Without a session there is no playback state to fade around
+        let (Ok(playing), Ok(app_id)) = (playing, app_id) else {
+            return self.execute(MediaCommand::TogglePlayPause(None));
+        };
+
+        let _fading = self.fade_lock.lock().map_err(MediaError::internal)?;
+        let volume = Volume::for_target(fade.target, &app_id)?;
+        let level = volume.level()?;
+
+        if playing {
+            volume.fade(0.0, duration)?;
+            let result = self.execute(MediaCommand::TogglePlayPause(None));
+            // Leave the volume where it was so the next playback isn't silent
+            volume.set_level(level)?;
+            return result;
+        }
+
+        volume.set_level(0.0)?;
+        if let Err(e) = self.execute(MediaCommand::TogglePlayPause(None)) {
+            volume.set_level(level)?;
+            return Err(e);
+        }
+        volume.fade(level, duration)?;
+        Ok(None)
+    }
+
+    /// Save the position in the current track and tell every client
+    fn bookmark_position(&self) -> MediaResult<Option<MediaEvent>> {
+        let (track, controls, timeline) = self.media.call(|manager| {
+            (
+                manager.track_info(),
+                manager.track_controls(),
+                manager.track_timeline(),
+            )
+        })?;
+        let (track, timeline) = (track?, timeline?);
+        // The timeline is only updated now and then while playing
+        let playing = controls.is_ok_and(|controls| controls.playing());
+        let position = timeline.position_at(utils::unix_millis(), playing);
+
+        let bookmarks = self
+            .state_store
+            .add_bookmark(Bookmark::new(&track, position));
+        tracing::info!("Bookmarked {} at {} ms", track.title, position);
+        self.publish(MediaEvent::Bookmarks(bookmarks));
+        Ok(None)
+    }
+
+    /// Jump back to the bookmark of the current track, e.g. a podcast episode
+    /// that was stopped earlier
+    fn resume_bookmark(&self) -> MediaResult<Option<MediaEvent>> {
+        let track = self.media.call(|manager| manager.track_info())??;
+        let bookmark = self
+            .state_store
+            .bookmark_for(&track)
+            .ok_or_else(|| MediaError::invalid_input("The current track has no bookmark"))?;
+        self.execute(MediaCommand::Seek(SeekPosition {
+            position: bookmark.position,
+        }))?;
+        self.execute(MediaCommand::Play)
+    }
+
+    /// Set an alarm at a position of the current track and tell every client
+    fn add_position_alarm(&self, request: PositionAlarmRequest) -> MediaResult<Option<MediaEvent>> {
+        let (track, timeline) = self
+            .media
+            .call(|manager| (manager.track_info(), manager.track_timeline()))?;
+        let (track, timeline) = (track?, timeline?);
+        if timeline.end_time() > 0 && request.position > timeline.end_time() {
+            return Err(MediaError::invalid_input(
+                "The position is past the end of the track",
+            ));
+        }
+
+        tracing::info!(
+            "Position alarm for {} at {} ms",
+            track.title,
+            request.position
+        );
+        let alarms = self.state_store.add_position_alarm(&track, request);
+        self.publish(MediaEvent::PositionAlarms(alarms));
+        Ok(None)
+    }
+
+    /// Whether an alarm waits to go off
+    pub fn has_position_alarms(&self) -> bool {
+        !self.state_store.position_alarms().is_empty()
+    }
+
+    /// Set off the alarms the current track played through since the last check,
+    /// pausing when one of them asks to
+    pub fn check_position_alarms(&self) -> MediaResult<()> {
+        if !self.has_position_alarms() {
+            return Ok(());
+        }
+        // The timeline tells the track by its id, the rest of the track isn't needed
+        let (playing, timeline) = self
+            .media
+            .call(|manager| (manager.is_playing(), manager.track_timeline()))?;
+        // Nothing plays through a position without a session
+        let Ok(timeline) = timeline else {
+            return Ok(());
+        };
+        let position = timeline.position_at(utils::unix_millis(), playing.unwrap_or(false));
+        let track_id = &timeline.timeline.track_id;
+        let Some((from, to)) = self.alarm_watch.advance(track_id, position) else {
+            return Ok(());
+        };
+
+        let alarms = self.state_store.take_position_alarms(track_id, from, to);
+        if alarms.is_empty() {
+            return Ok(());
+        }
+        let pause = alarms.iter().any(|alarm| alarm.pause);
+        for alarm in alarms {
+            tracing::info!("Position alarm of {} at {} ms", alarm.title, alarm.position);
+            self.publish(MediaEvent::PositionAlarm(alarm));
+        }
+        self.publish(MediaEvent::PositionAlarms(
+            self.state_store.position_alarms(),
+        ));
+        if pause {
+            self.execute(MediaCommand::Pause)?;
+        }
+        Ok(())
+    }
+
+    /// Start or end quiet hours on schedule and bring the volume down to their cap
+    pub fn check_quiet_hours(&self) -> MediaResult<()> {
+        let Some(quiet_hours) = &self.quiet_hours else {
+            return Ok(());
+        };
+        if let Some(status) = quiet_hours.update(LocalTime::now()) {
+            tracing::info!(
+                "Quiet hours {}",
+                if status.active { "started" } else { "ended" }
+            );
+            self.publish(MediaEvent::QuietHoursChanged(status));
+        }
+        let Some(cap) = quiet_hours.volume_cap() else {
+            return Ok(());
+        };
+        if volume::system_volume()? > cap {
+            volume::set_system_volume(cap)?;
+        }
+        Ok(())
+    }
+
+    fn inactivity_status(&self) -> Option<InactivityStatus> {
+        self.inactivity
+            .as_ref()
+            .map(|inactivity| inactivity.status())
+    }
+
+    /// Count down to pausing while the host goes without input, and pause once it ran out
+    pub fn check_inactivity(&self) -> MediaResult<()> {
+        let Some(inactivity) = &self.inactivity else {
+            return Ok(());
+        };
+        let playing = self.media.call(|manager| manager.is_playing())?;
+        match inactivity.update(playing.unwrap_or(false))? {
+            Some(InactivityUpdate::Status(status)) => {
+                self.publish(MediaEvent::InactivityStatus(status));
+            }
+            Some(InactivityUpdate::Pause) => {
+                tracing::info!("No input for a while, pausing");
+                self.execute(MediaCommand::Pause)?;
+                self.publish(MediaEvent::InactivityStatus(inactivity.status()));
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Pause playback when the workstation locks and resume it on unlock, if it was
+    /// paused by the lock and resuming is configured
+    pub fn set_locked(&self, locked: bool) -> MediaResult<()> {
+        let Some(lock) = &self.session_lock else {
+            return Ok(());
+        };
+        let mut status = LockStatus {
+            locked,
+            paused_by_lock: false,
+        };
+        let mut result = Ok(());
+        if locked {
+            // Nothing playing, nothing to resume later
+            let playing = self.media.call(|manager| manager.is_playing())?;
+            if playing.unwrap_or(false) {
+                result = self.execute(MediaCommand::Pause).map(|_| ());
+                status.paused_by_lock = result.is_ok();
+            }
+        } else if lock.status().paused_by_lock && lock.config().resume_on_unlock {
+            result = self.execute(MediaCommand::Play).map(|_| ());
+        }
+        tracing::info!(
+            "Workstation {}, paused by the lock: {}",
+            if locked { "locked" } else { "unlocked" },
+            status.paused_by_lock
+        );
+        lock.set_status(status);
+        self.publish(MediaEvent::LockChanged(status));
+        result
+    }
+
+    fn override_quiet_hours(
+        &self,
+        request: &QuietHoursOverride,
+    ) -> MediaResult<Option<MediaEvent>> {
+        let quiet_hours = self
+            .quiet_hours
+            .as_ref()
+            .ok_or_else(|| MediaError::unsupported("quiet_hours_override"))?;
+        let status = quiet_hours.set_override(request)?;
+        tracing::info!(
+            "Quiet hours overridden, now {}",
+            if status.active { "active" } else { "inactive" }
+        );
+        self.publish(MediaEvent::QuietHoursChanged(status));
+        self.check_quiet_hours()?;
+        Ok(None)
+    }
+
+    /// Fail commands that would start playback during quiet hours set to refuse it
+    fn refuse_in_quiet_hours(&self, command: &MediaCommand) -> MediaResult<()> {
+        if !self
+            .quiet_hours
+            .as_ref()
+            .is_some_and(|quiet_hours| quiet_hours.refuses_play())
+        {
+            return Ok(());
+        }
+        let starts_playback = match command {
+            MediaCommand::Play | MediaCommand::ResumeBookmark => true,
+            MediaCommand::TogglePlayPause(_) => !self
+                .media
+                .call(|manager| manager.is_playing())?
+                .unwrap_or(false),
+            _ => false,
+        };
+        if starts_playback {
+            return Err(MediaError::invalid_input(
+                "Playback is refused during quiet hours",
+            ));
+        }
+        Ok(())
+    }
+
+    /// A volume level lowered to the cap of quiet hours while they are active
+    fn capped_volume(&self, level: f32) -> f32 {
+        match self
+            .quiet_hours
+            .as_ref()
+            .and_then(|quiet_hours| quiet_hours.volume_cap())
+        {
+            Some(cap) => level.min(cap),
+            None => level,
+        }
+    }
+
+    fn set_sleep_timer(&self, request: &SleepTimerRequest) -> MediaResult<Option<MediaEvent>> {
+        let hub = self.clone();
+        let fade = request.fade_seconds.map(Duration::from_secs);
+        let status = self
+            .scheduler
+            .set_sleep_timer(request, move || hub.fire_sleep_timer(fade))?;
+        self.publish(MediaEvent::SleepTimerStatus(status));
+        Ok(None)
+    }
+
+    /// Pause playback, fading the system volume out first when asked to
+    fn fire_sleep_timer(&self, fade: Option<Duration>) {
+        tracing::info!("Sleep timer fired");
+
+        let mut restore = None;
+        if let Some(fade) = fade {
+            match volume::system_volume() {
+                Ok(level) => {
+                    restore = Some(level);
+                    if let Err(e) = volume::fade_system_volume(0.0, fade) {
+                        tracing::error!("Failed to fade out volume: {}", e);
+                    }
+                }
+                Err(e) => tracing::error!("Failed to read volume: {}", e),
+            }
+        }
+
+        if let Err(e) = self.execute(MediaCommand::Pause) {
+            tracing::error!("Sleep timer failed to pause: {}", e);
+        }
+
+        // Leave the volume where it was so the next playback isn't silent
+        if let Some(Err(e)) = restore.map(volume::set_system_volume) {
+            tracing::error!("Failed to restore volume: {}", e);
+        }
+
+        self.publish(MediaEvent::SleepTimerStatus(SleepTimerStatus::default()));
+    }
+
+    fn run_schedule(&self, action: ScheduledAction) {
+        let result = match action {
+            ScheduledAction::Play => self.execute(MediaCommand::Play).map(|_| ()),
+            ScheduledAction::Pause => self.execute(MediaCommand::Pause).map(|_| ()),
+            ScheduledAction::SetVolume { level } => {
+                volume::set_system_volume(self.capped_volume(level))
+            }
+        };
+        if let Err(e) = result {
+            tracing::error!("Scheduled action failed: {}", e);
+        }
+    }
+
+    /// Run a command backed by an online service
+    fn execute_remote(&self, command: MediaCommand) -> MediaResult<Option<MediaEvent>> {
+        let event = match command {
+            MediaCommand::GetLyrics => Some(MediaEvent::Lyrics(self.current_lyrics()?)),
+            MediaCommand::GetQueue => Some(MediaEvent::Queue(self.current_queue()?)),
+            MediaCommand::SpotifySaveTrack => {
+                self.spotify_session()?
+                    .save_current_track()
+                    .map_err(spotify_error)?;
+                None
+            }
+            MediaCommand::SpotifyAddToPlaylist(PlaylistTarget { playlist_id }) => {
+                self.spotify_session()?
+                    .add_current_to_playlist(&playlist_id)
+                    .map_err(spotify_error)?;
+                None
+            }
+            MediaCommand::SpotifyAddToQueue(QueueTarget { uri }) => {
+                self.spotify_session()?
+                    .add_to_queue(&uri)
+                    .map_err(spotify_error)?;
+                None
+            }
+            MediaCommand::SpotifyGetDevices => {
+                let devices = self.spotify_client()?.devices().map_err(spotify_error)?;
+                Some(MediaEvent::SpotifyDevices(devices))
+            }
+            MediaCommand::SpotifyTransferPlayback(TransferTarget { device_id, play }) => {
+                self.spotify_client()?
+                    .transfer_playback(&device_id, play)
+                    .map_err(spotify_error)?;
+                None
+            }
+            MediaCommand::GetOutputs => {
+                let groups = self.snapcast_client()?.outputs().map_err(snapcast_error)?;
+                Some(MediaEvent::Outputs(groups))
+            }
+            MediaCommand::SetOutputVolume(OutputVolume { id, level }) => {
+                let snapcast = self.snapcast_client()?;
+                snapcast.set_volume(&id, level).map_err(snapcast_error)?;
+                self.refresh_outputs(&snapcast);
+                None
+            }
+            MediaCommand::SetOutputMute(OutputMute { id, muted }) => {
+                let snapcast = self.snapcast_client()?;
+                snapcast.set_mute(&id, muted).map_err(snapcast_error)?;
+                self.refresh_outputs(&snapcast);
+                None
+            }
+            _ => unreachable!("{} is not a remote command", command.name()),
+        };
+        Ok(event)
+    }
+
+    /// Run a function on this server, a peer or all of them
+    ///
+    /// # Returns
+    /// * `MediaResult<Option<MediaEvent>>` - The local answer, answers of peers stay there
+    fn execute_on_peer(&self, command: PeerCommand) -> MediaResult<Option<MediaEvent>> {
+        let peers = self.peer_registry()?;
+        if command.peer != peers::LOCAL_PEER && command.peer != peers::ALL_PEERS {
+            peers.send(&command.peer, &command.command, command.data)?;
+            return Ok(None);
+        }
+
+        let local = parse_command(&command.command, command.data.clone())?;
+        if command.peer == peers::LOCAL_PEER {
+            return self.execute(local);
+        }
+
+        // Every machine gets the command even when one of them fails
+        let remote = peers.send(peers::ALL_PEERS, &command.command, command.data);
+        let answer = self.execute(local)?;
+        remote?;
+        Ok(answer)
+    }
+
+    /// Federated servers, if any are configured
+    pub fn peers(&self) -> Option<Arc<PeerRegistry>> {
+        self.peers.clone()
+    }
+
+    fn peer_registry(&self) -> MediaResult<Arc<PeerRegistry>> {
+        self.peers()
+            .ok_or_else(|| MediaError::unsupported("peer control"))
+    }
+
+    /// The Spotify client, if Spotify is configured
+    pub fn spotify(&self) -> Option<Arc<SpotifyClient>> {
+        self.spotify.clone()
+    }
+
+    fn spotify_client(&self) -> MediaResult<Arc<SpotifyClient>> {
+        self.spotify()
+            .ok_or_else(|| MediaError::unsupported("Spotify control"))
+    }
+
+    /// The Snapcast client, if Snapcast is configured
+    pub fn snapcast(&self) -> Option<Arc<SnapcastClient>> {
+        self.snapcast.clone()
+    }
+
+    fn snapcast_client(&self) -> MediaResult<Arc<SnapcastClient>> {
+        self.snapcast()
+            .ok_or_else(|| MediaError::unsupported("Snapcast control"))
+    }
+
+    /// Show every client a change right away instead of at the next poll
+    fn refresh_outputs(&self, snapcast: &SnapcastClient) {
+        match snapcast.outputs() {
+            Ok(groups) => self.publish_outputs(groups),
+            Err(e) => tracing::warn!("Failed to get Snapcast status: {:#}", e),
+        }
+    }
+
+    /// The Spotify client, only when Spotify owns the current session
+    fn spotify_session(&self) -> MediaResult<Arc<SpotifyClient>> {
+        let app_id = self.media.call(|manager| manager.current_app_id())??;
+        if !spotify::is_spotify_app(&app_id) {
+            return Err(MediaError::unsupported("Spotify control"));
+        }
+        self.spotify_client()
+    }
+
+    /// Lyrics of the current track, `None` when there is no session or no lyrics
+    fn current_lyrics(&self) -> MediaResult<Option<Lyrics>> {
+        let service = self
+            .lyrics
+            .as_ref()
+            .ok_or_else(|| MediaError::unsupported("lyrics"))?;
+
+        let track = self.media.call(|manager| manager.track_info())?;
+        match track {
+            Ok(track) => Ok(service.lyrics(&track)),
+            Err(MediaError::NoSession) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Queue of the app owning the current session
+    fn current_queue(&self) -> MediaResult<Queue> {
+        let app_id = self.media.call(|manager| manager.current_app_id())??;
+        self.queue.queue(&app_id)
+    }
+
+    fn publish(&self, event: MediaEvent) {
+        let event = match event {
+            MediaEvent::TrackInfo(Some(track)) => {
+                MediaEvent::TrackInfo(Some(self.with_previous_track(track)))
+            }
+            event => event,
+        };
+        // Sessions fire change events without visible changes, each one would
+        // resend the whole payload including the base64 thumbnail
+        if event.is_state() && !self.record_emitted(&event) {
+            tracing::trace!("Skipping unchanged {}", event.name());
+            return;
+        }
+        self.state_store.record(&event);
+        // Sending only fails when nobody is subscribed, which is fine
+        self.sender.send(event).ok();
+    }
+
+    /// Whether a state event is the same as the last one broadcast with its code
+    fn was_emitted(&self, event: &MediaEvent) -> bool {
+        self.last_emitted
+            .lock()
+            .is_ok_and(|last_emitted| last_emitted.get(event.name()) == Some(&event.payload()))
+    }
+
+    /// Remember a state event as the last one broadcast
+    ///
+    /// # Returns
+    /// * `bool` - Whether it differs from the previous event with the same code
+    fn record_emitted(&self, event: &MediaEvent) -> bool {
+        let Ok(mut last_emitted) = self.last_emitted.lock() else {
+            return true;
+        };
+        let payload = event.payload();
+        if last_emitted.get(event.name()) == Some(&payload) {
+            return false;
+        }
+        last_emitted.insert(event.name(), payload);
+        true
+    }
+
+    /// Tell clients which track a track change follows, republishing the same
+    /// track keeps the one before it
+    fn with_previous_track(&self, mut track: TrackInfo) -> TrackInfo {
+        let Ok(mut track_ids) = self.track_ids.lock() else {
+            return track;
+        };
+        let (current, previous) = &mut *track_ids;
+        if *current != track.track_id {
+            let replaced = std::mem::replace(current, track.track_id.clone());
+            *previous = Some(replaced).filter(|id| !id.is_empty());
+        }
+        track.previous_track_id = previous.clone();
+        track
+    }
+
+    fn publish_current_state(&self) {
+        for event in self.current_state() {
+            self.publish(event);
+        }
+    }
+
+    fn publish_track_info(&self, track: TrackInfo) {
+        self.skip_if_listed(&track);
+        if let Some(ad_muter) = &self.ad_muter {
+            ad_muter.update(&track);
+        }
+        self.publish_lyrics(&track);
+        self.lookup_metadata(&track);
+        self.retry_thumbnail(&track);
+        self.extract_palette(&track);
+        self.publish(MediaEvent::TrackInfo(Some(self.for_clients(track))));
+    }
+
+    /// Extract the palette of artwork that wasn't seen before on the blocking pool
+    /// and republish the track info with it, decoding large artwork takes a while
+    fn extract_palette(&self, track: &TrackInfo) {
+        if track.stale || track.thumbnail.is_none() || track.palette.is_some() {
+            return;
+        }
+
+        let hub = self.clone();
+        let track = track.clone();
+        self.runtime.spawn_blocking(move || {
+            let palette = match hub.artwork() {
+                Ok(bytes) => palette::extract_cached(&bytes),
+                Err(e) => {
+                    tracing::debug!("No artwork to extract a palette from: {}", e);
+                    return;
+                }
+            };
+            let palette = match palette {
+                Ok(palette) => palette,
+                Err(e) => {
+                    tracing::error!("Failed to extract color palette: {}", e);
+                    return;
+                }
+            };
+
+            // The track may have changed while the artwork was decoded
+            let current = match hub.media.call(|manager| manager.track_info()) {
+                Ok(track) => track.ok(),
+                Err(_) => return,
+            };
+            if let Some(mut current) = current.filter(|c| {
+                c.session.source_app_id == track.session.source_app_id
+                    && c.title == track.title
+                    && c.artist == track.artist
+                    && c.thumbnail.is_some()
+            }) {
+                current.palette = Some(palette);
+                hub.publish(MediaEvent::TrackInfo(Some(hub.for_clients(current))));
+            }
+        });
+    }
+
+    /// Read the artwork of a track that came without one again, apps often set it
+    /// a moment after the track, and send it as `thumbnail_updated` once it is there
+    fn retry_thumbnail(&self, track: &TrackInfo) {
+        let config = &self.config.thumbnails;
+        if track.thumbnail.is_some() || track.stale || config.retry_attempts == 0 {
+            return;
+        }
+        let key = (
+            track.session.source_app_id.clone(),
+            track.title.clone(),
+            track.artist.clone(),
+        );
+        // Metadata changes of the same track would start more retries
+        match self.thumbnail_retry.lock() {
+            Ok(mut retrying) if retrying.as_ref() != Some(&key) => *retrying = Some(key.clone()),
+            _ => return,
+        }
+
+        let hub = self.clone();
+        let attempts = config.retry_attempts;
+        let delay = Duration::from_millis(config.retry_delay_ms);
+        std::thread::spawn(move || {
+            match hub.read_thumbnail_again(&key, attempts, delay) {
+                Some(track) => {
+                    hub.publish(MediaEvent::ThumbnailUpdated(ThumbnailUpdate {
+                        session: track.session.clone(),
+                        title: track.title.clone(),
+                        artist: track.artist.clone(),
+                        thumbnail: track.thumbnail.clone(),
+                        thumbnails: track.thumbnails.clone(),
+                        palette: track.palette.clone(),
+                    }));
+                    hub.extract_palette(&track);
+                }
+                None => tracing::debug!("No artwork arrived for {} - {}", key.2, key.1),
+            }
+            if let Ok(mut retrying) = hub.thumbnail_retry.lock() {
+                retrying.take_if(|retried| *retried == key);
+            }
+        });
+    }
+
+    /// Read the track with doubling delays until it has artwork
+    ///
+    /// # Arguments
+    /// * `key` - Source app, title and artist of the track
+    /// * `attempts` - Reads before giving up
+    /// * `delay` - Wait before the first read
+    ///
+    /// # Returns
+    /// * `Option<TrackInfo>` - The track with its artwork, `None` when it never
+    ///   arrived or another track took over
+    fn read_thumbnail_again(
+        &self,
+        key: &(String, String, String),
+        attempts: u32,
+        mut delay: Duration,
+    ) -> Option<TrackInfo> {
+        for _ in 0..attempts {
+            std::thread::sleep(delay);
+            delay *= 2;
+            let current = self.media.call(|manager| manager.track_info()).ok()?.ok()?;
+            // Another track took over, it retries on its own
+            if (
+                &current.session.source_app_id,
+                &current.title,
+                &current.artist,
+            ) != (&key.0, &key.1, &key.2)
+            {
+                return None;
+            }
+            if current.thumbnail.is_some() {
+                return Some(current);
+            }
+        }
+        None
+    }
+
+    /// A track as clients get it, with cached metadata and the placeholder for
+    /// missing artwork
+    fn for_clients(&self, track: TrackInfo) -> TrackInfo {
+        self.with_placeholder(self.with_cached_metadata(track))
+    }
+
+    fn with_placeholder(&self, mut track: TrackInfo) -> TrackInfo {
+        if let (None, Some(placeholder)) = (&track.thumbnail, &self.placeholder) {
+            track.set_placeholder(placeholder);
+        }
+        track
+    }
+
+    /// Skip a new track matching a skip rule and tell clients why
+    fn skip_if_listed(&self, track: &TrackInfo) {
+        let Some(skipped) = self
+            .skip_list
+            .as_ref()
+            .and_then(|skip_list| skip_list.check(track))
+        else {
+            return;
+        };
+
+        tracing::info!(
+            "Skipping {} - {} (skip rule {})",
+            skipped.artist,
+            skipped.title,
+            skipped.rule
+        );
+        let hub = self.clone();
+        // Called from the session's change handler, which shouldn't block on the command
+        std::thread::spawn(move || {
+            if let Err(e) = hub.execute(MediaCommand::NextTrack) {
+                tracing::error!("Failed to skip track: {}", e);
+                return;
+            }
+            hub.publish(MediaEvent::TrackSkipped(skipped));
+        });
+    }
+
+    /// Attach metadata that was already looked up, never blocking on the network
+    fn with_cached_metadata(&self, mut track: TrackInfo) -> TrackInfo {
+        let service = self
+            .metadata
+            .as_ref()
+            .filter(|service| service.wants(&track));
+        if let Some(service) = service {
+            track.metadata = service.cached(&track).flatten();
+        }
+        track
+    }
+
+    /// Look up metadata for a new track in the background and republish the
+    /// track info once it arrives
+    fn lookup_metadata(&self, track: &TrackInfo) {
+        let Some(service) = self.metadata.clone() else {
+            return;
+        };
+        if !service.wants(track) || service.cached(track).is_some() {
+            return;
+        }
+
+        let hub = self.clone();
+        let track = track.clone();
+        std::thread::spawn(move || {
+            let Some(metadata) = service.lookup(&track) else {
+                return;
+            };
+
+            // The track may have changed while the providers were queried
+            let current = match hub.media.call(|manager| manager.track_info()) {
+                Ok(track) => track.ok(),
+                Err(_) => return,
+            };
+            if let Some(mut current) =
+                current.filter(|c| c.title == track.title && c.artist == track.artist)
+            {
+                current.metadata = Some(metadata);
+                hub.publish(MediaEvent::TrackInfo(Some(hub.with_placeholder(current))));
+            }
+        });
+    }
+
+    /// Look up lyrics for a new track in the background and broadcast them
+    fn publish_lyrics(&self, track: &TrackInfo) {
+        let Some(service) = self.lyrics.clone() else {
+            return;
+        };
+        let hub = self.clone();
+        let track = track.clone();
+        std::thread::spawn(move || {
+            hub.publish(MediaEvent::Lyrics(service.lyrics(&track)));
+        });
+    }
+}
@@ -0,0 +1,51 @@
+use axum::{Router, http::StatusCode, routing::get_service};
+use tower_http::services::ServeDir;
+
+const FRONTEND_DIR: &str = "client/dist";
+
+/// The built client, compiled into the binary so a single executable serves it
+#[cfg(feature = "bundled-frontend")]
+#[derive(rust_embed::Embed)]
+#[folder = "client/dist"]
+struct Bundled;
+
+/// Static frontend files
+///
+/// # Arguments
+/// * `directory` - Directory to serve the files from, `None` for the bundled frontend,
+///   or `client/dist` when the binary was built without it
+pub fn router(directory: Option<String>) -> Router {
+    #[cfg(feature = "bundled-frontend")]
+    if directory.is_none() {
+        tracing::debug!("Serving the bundled frontend");
+        return Router::new().fallback(bundled);
+    }
+
+    let directory = directory.unwrap_or_else(|| FRONTEND_DIR.to_string());
+    tracing::debug!("Serving frontend from directory: {}", directory);
+
+    let files = get_service(ServeDir::new(directory))
+        .handle_error(|_| async { (StatusCode::INTERNAL_SERVER_ERROR, "Static file error") });
+    Router::new().fallback_service(files)
+}
+
+/// Serve a bundled file, directories serve their `index.html` like `ServeDir` does
+#[cfg(feature = "bundled-frontend")]
+async fn bundled(uri: axum::http::Uri) -> axum::response::Response {
+    use axum::http::header;
+    use axum::response::IntoResponse;
+
+    let path = uri.path().trim_start_matches('/');
+    let path = match path {
+        "" => "index.html".to_string(),
+        path if path.ends_with('/') => format!("{}index.html", path),
+        path => path.to_string(),
+    };
+    match Bundled::get(&path) {
+        Some(file) => {
+            let mime = file.metadata.mimetype().to_string();
+            ([(header::CONTENT_TYPE, mime)], file.data).into_response()
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
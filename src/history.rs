@@ -0,0 +1,220 @@
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use axum::body::Body;
+use clap::{Args, Subcommand};
+use serde::Deserialize;
+use tokio_stream::StreamExt;
+use utoipa::ToSchema;
+
+use crate::persistence::{HistoryEntry, StateStore};
+
+/// Columns of an exported CSV, in order
+const CSV_HEADER: [&str; 5] = ["title", "artist", "album", "app_name", "played_at"];
+/// App name of imported scrobbles, Last.fm doesn't record the player
+const LASTFM_APP_NAME: &str = "Last.fm";
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Manage the playback history kept by the server
+#[derive(Args, Debug)]
+pub struct HistoryArgs {
+    #[command(subcommand)]
+    command: HistoryCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    /// Add the tracks of an export or a Last.fm CSV dump, run it while the server is stopped
+    Import {
+        /// CSV or JSON file from `/api/history/export`, or a Last.fm CSV dump
+        file: PathBuf,
+    },
+}
+
+/// File format of `/api/history/export`
+#[derive(Debug, Deserialize, Default, Clone, Copy, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+pub fn run(args: HistoryArgs) -> Result<()> {
+    match args.command {
+        HistoryCommand::Import { file } => {
+            let entries = read_file(&file)?;
+            let found = entries.len();
+            let store = StateStore::load();
+            let added = store.import_history(entries);
+            store.flush();
+            println!(
+                "Imported {} of {} tracks, the others were already in the history",
+                added, found
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Stream the history in a format, one chunk per track
+///
+/// # Arguments
+/// * `entries` - Tracks, newest first
+/// * `format` - CSV with a header row, or a JSON array
+pub fn export(entries: Vec<HistoryEntry>, format: ExportFormat) -> Body {
+    let (header, footer) = match format {
+        ExportFormat::Json => ("[".to_string(), "]"),
+        ExportFormat::Csv => (csv_row(CSV_HEADER.map(String::from)), ""),
+    };
+    let rows = entries
+        .into_iter()
+        .enumerate()
+        .map(move |(index, entry)| match format {
+            ExportFormat::Json => {
+                let separator = if index == 0 { "" } else { "," };
+                let json = serde_json::to_string(&entry).unwrap_or_default();
+                format!("{}{}", separator, json)
+            }
+            ExportFormat::Csv => csv_row([
+                entry.title,
+                entry.artist,
+                entry.album.unwrap_or_default(),
+                entry.app_name,
+                entry.played_at.to_string(),
+            ]),
+        });
+    let chunks = std::iter::once(header)
+        .chain(rows)
+        .chain(std::iter::once(footer.to_string()));
+    Body::from_stream(tokio_stream::iter(chunks).map(Ok::<_, Infallible>))
+}
+
+fn csv_row(fields: [String; 5]) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    // Writing to a Vec can't fail
+    writer.write_record(&fields).ok();
+    writer
+        .into_inner()
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_default()
+}
+
+/// Read the tracks of an export or a Last.fm dump, told apart by their content
+fn read_file(path: &Path) -> Result<Vec<HistoryEntry>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if contents.trim_start().starts_with('[') {
+        return serde_json::from_str(&contents)
+            .with_context(|| format!("{} is no history export", path.display()));
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    let mut records = reader.records().peekable();
+    let exported = match records.peek() {
+        Some(Ok(first)) => first.iter().eq(CSV_HEADER),
+        _ => false,
+    };
+    if exported {
+        records.next();
+    }
+
+    let mut entries = Vec::new();
+    for (line, record) in records.enumerate() {
+        let record = record.with_context(|| format!("Invalid CSV in {}", path.display()))?;
+        let entry = if exported {
+            exported_entry(&record)
+        } else {
+            lastfm_entry(&record)
+        };
+        match entry {
+            Some(entry) => entries.push(entry),
+            None => eprintln!("Skipped line {} of {}", line + 1, path.display()),
+        }
+    }
+    if entries.is_empty() {
+        bail!("{} holds no tracks", path.display());
+    }
+    Ok(entries)
+}
+
+/// A row of `/api/history/export?format=csv`
+fn exported_entry(record: &csv::StringRecord) -> Option<HistoryEntry> {
+    Some(HistoryEntry {
+        title: record.get(0)?.to_string(),
+        artist: record.get(1)?.to_string(),
+        album: record
+            .get(2)
+            .filter(|album| !album.is_empty())
+            .map(String::from),
+        app_name: record.get(3)?.to_string(),
+        played_at: record.get(4)?.parse().ok()?,
+    })
+}
+
+/// A row of a Last.fm dump: artist, album, title and the UTC time it was scrobbled
+fn lastfm_entry(record: &csv::StringRecord) -> Option<HistoryEntry> {
+    Some(HistoryEntry {
+        artist: record.get(0)?.to_string(),
+        album: record
+            .get(1)
+            .filter(|album| !album.is_empty())
+            .map(String::from),
+        title: record.get(2)?.to_string(),
+        app_name: LASTFM_APP_NAME.to_string(),
+        played_at: parse_scrobble_time(record.get(3)?)?,
+    })
+}
+
+/// Unix timestamp in milliseconds of a scrobble, given in seconds or as `06 Sep 2023 18:31`
+fn parse_scrobble_time(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(seconds * 1000);
+    }
+
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let [day, month, year, time] = parts[..] else {
+        return None;
+    };
+    let (hour, minute) = time.split_once(':')?;
+    let month = MONTHS
+        .iter()
+        .position(|name| month.to_lowercase().starts_with(name))? as i64
+        + 1;
+    let days = days_from_civil(year.parse().ok()?, month, day.parse().ok()?);
+    let minutes = days * 24 * 60 + hour.parse::<i64>().ok()? * 60 + minute.parse::<i64>().ok()?;
+    u64::try_from(minutes * 60_000).ok()
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
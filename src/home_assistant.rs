@@ -0,0 +1,191 @@
+use rumqttc::{AsyncClient, QoS};
+use serde_json::{Value, json};
+
+use crate::config::MqttConfig;
+
+/// Payload published on the availability topic while connected
+pub const ONLINE_PAYLOAD: &str = "online";
+/// Payload published as the last will when the connection drops
+pub const OFFLINE_PAYLOAD: &str = "offline";
+
+enum Component {
+    Sensor,
+    Button,
+    Number,
+}
+
+impl Component {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Component::Sensor => "sensor",
+            Component::Button => "button",
+            Component::Number => "number",
+        }
+    }
+}
+
+/// Publish Home Assistant MQTT discovery configs for the bridge topics.
+///
+/// Home Assistant has no MQTT `media_player` platform, so the controller shows
+/// up as a single device grouping sensors for the track metadata, buttons for
+/// the transport controls and number entities for seeking and the volume.
+pub async fn publish_discovery(client: &AsyncClient, config: &MqttConfig) {
+    let prefix = config.topic_prefix.trim_end_matches('/');
+    let node_id = discovery_node_id(&config.client_id);
+
+    let device = json!({
+        "identifiers": [node_id],
+        "name": "Media Controller",
+        "manufacturer": "frstycodes",
+        "model": "media-controller",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+
+    let entities = [
+        (
+            Component::Sensor,
+            "title",
+            json!({
+                "name": "Title",
+                "icon": "mdi:music",
+                "state_topic": format!("{prefix}/track"),
+                "value_template": "{{ value_json.title }}",
+            }),
+        ),
+        (
+            Component::Sensor,
+            "artist",
+            json!({
+                "name": "Artist",
+                "icon": "mdi:account-music",
+                "state_topic": format!("{prefix}/track"),
+                "value_template": "{{ value_json.artist }}",
+            }),
+        ),
+        (
+            Component::Sensor,
+            "album",
+            json!({
+                "name": "Album",
+                "icon": "mdi:album",
+                "state_topic": format!("{prefix}/track"),
+                "value_template": "{{ value_json.album }}",
+            }),
+        ),
+        (
+            Component::Sensor,
+            "state",
+            json!({
+                "name": "State",
+                "icon": "mdi:play-pause",
+                "state_topic": format!("{prefix}/state"),
+                "value_template": "{{ 'playing' if value_json.playing else 'paused' }}",
+            }),
+        ),
+        (
+            Component::Button,
+            "play_pause",
+            json!({
+                "name": "Play/Pause",
+                "icon": "mdi:play-pause",
+                "command_topic": format!("{prefix}/cmd/play_pause"),
+            }),
+        ),
+        (
+            Component::Button,
+            "next",
+            json!({
+                "name": "Next",
+                "icon": "mdi:skip-next",
+                "command_topic": format!("{prefix}/cmd/next"),
+            }),
+        ),
+        (
+            Component::Button,
+            "previous",
+            json!({
+                "name": "Previous",
+                "icon": "mdi:skip-previous",
+                "command_topic": format!("{prefix}/cmd/previous"),
+            }),
+        ),
+        (
+            Component::Number,
+            "position",
+            json!({
+                "name": "Position",
+                "icon": "mdi:timeline-clock",
+                "mode": "box",
+                "min": 0,
+                "max": 86_400_000,
+                "step": 1000,
+                "unit_of_measurement": "ms",
+                "state_topic": format!("{prefix}/position"),
+                "value_template": "{{ value_json.progress }}",
+                "command_topic": format!("{prefix}/cmd/seek"),
+            }),
+        ),
+        (
+            Component::Number,
+            "volume",
+            json!({
+                "name": "Volume",
+                "icon": "mdi:volume-high",
+                "mode": "slider",
+                "min": 0,
+                "max": 100,
+                "step": 1,
+                "unit_of_measurement": "%",
+                // The topics carry scalars between 0 and 1
+                "state_topic": format!("{prefix}/volume"),
+                "value_template": "{{ (value | float * 100) | round }}",
+                "command_topic": format!("{prefix}/cmd/volume"),
+                "command_template": "{{ value / 100 }}",
+            }),
+        ),
+    ];
+
+    for (component, key, mut payload) in entities {
+        if let Some(entity) = payload.as_object_mut() {
+            entity.insert("unique_id".into(), Value::from(format!("{node_id}_{key}")));
+            entity.insert(
+                "availability_topic".into(),
+                Value::from(availability_topic(config)),
+            );
+            entity.insert("device".into(), device.clone());
+        }
+
+        let topic = format!(
+            "{}/{}/{}/{}/config",
+            config.discovery_prefix,
+            component.as_str(),
+            node_id,
+            key
+        );
+
+        if let Err(e) = client
+            .publish(&topic, QoS::AtLeastOnce, true, payload.to_string())
+            .await
+        {
+            tracing::error!("Failed to publish discovery config {}: {}", topic, e);
+        }
+    }
+
+    tracing::info!("Published Home Assistant discovery configs");
+}
+
+/// Topic carrying the online/offline state of the bridge
+pub fn availability_topic(config: &MqttConfig) -> String {
+    format!("{}/availability", config.topic_prefix.trim_end_matches('/'))
+}
+
+/// Home Assistant only accepts `[a-zA-Z0-9_-]` in discovery node ids
+fn discovery_node_id(client_id: &str) -> String {
+    client_id
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
@@ -0,0 +1,130 @@
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+};
+use serde::Deserialize;
+
+use crate::error::MediaError;
+use crate::events::{EventHub, MediaCommand, VolumeLevel};
+use crate::timings;
+
+/// Token name of the intent endpoint in `[hooks.tokens]`
+const INTENT_HOOK: &str = "intent";
+
+#[derive(Debug, Deserialize)]
+struct HookParams {
+    token: Option<String>,
+}
+
+/// What a voice assistant routine asks for, e.g. `{"action":"play","query":"Daft Punk"}`
+#[derive(Debug, Deserialize)]
+struct Intent {
+    action: String,
+    /// What to play, searched on Spotify when it is configured
+    query: Option<String>,
+    /// Volume in percent for the `volume` action
+    level: Option<u8>,
+}
+
+/// Webhooks for Stream Deck "Website" actions, IFTTT and the like, which can
+/// only fire a plain GET without a body
+pub fn router(hub: EventHub) -> Router {
+    Router::new()
+        .route("/hook/{name}", get(hook_handler).post(hook_handler))
+        .route("/intent", post(intent_handler))
+        .with_state(hub)
+}
+
+async fn hook_handler(
+    State(hub): State<EventHub>,
+    Path(name): Path<String>,
+    Query(params): Query<HookParams>,
+) -> StatusCode {
+    // Unknown and disabled hooks look the same, so names can't be probed
+    let (Some(command), Some(token)) = (command_for(&name), hub.config().hooks.token(&name)) else {
+        return StatusCode::NOT_FOUND;
+    };
+    if params.token.as_deref() != Some(token) {
+        tracing::warn!("Rejected hook {} with a wrong token", name);
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    execute(hub, &name, command).await
+}
+
+/// Intents of IFTTT applets for Alexa or Google Assistant, which can POST a JSON body
+async fn intent_handler(
+    State(hub): State<EventHub>,
+    Query(params): Query<HookParams>,
+    Json(intent): Json<Intent>,
+) -> StatusCode {
+    let Some(token) = hub.config().hooks.token(INTENT_HOOK) else {
+        return StatusCode::NOT_FOUND;
+    };
+    if params.token.as_deref() != Some(token) {
+        tracing::warn!("Rejected intent {} with a wrong token", intent.action);
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    // Without Spotify a query can only resume whatever was playing
+    let query = intent.query.as_deref().map(str::trim).unwrap_or_default();
+    if intent.action == "play" && !query.is_empty() {
+        if let Some(spotify) = hub.spotify() {
+            let query = query.to_string();
+            return match tokio::task::spawn_blocking(move || spotify.play_search(&query)).await {
+                Ok(Ok(name)) => {
+                    tracing::info!("Intent played {} on Spotify", name);
+                    StatusCode::NO_CONTENT
+                }
+                Ok(Err(e)) => {
+                    tracing::error!("Intent search failed: {:#}", e);
+                    StatusCode::BAD_GATEWAY
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+        }
+    }
+
+    let command = match (intent.action.as_str(), intent.level) {
+        ("volume", Some(level)) => MediaCommand::SetVolume(VolumeLevel {
+            level: f32::from(level.min(100)) / 100.0,
+        }),
+        ("volume", None) => return StatusCode::UNPROCESSABLE_ENTITY,
+        (action, _) => match command_for(&action.replace('_', "-")) {
+            Some(command) => command,
+            None => return StatusCode::UNPROCESSABLE_ENTITY,
+        },
+    };
+    execute(hub, &intent.action, command).await
+}
+
+async fn execute(hub: EventHub, name: &str, command: MediaCommand) -> StatusCode {
+    let span = timings::command_span("hook", command.name());
+    match tokio::task::spawn_blocking(move || span.in_scope(|| hub.execute(command))).await {
+        Ok(Ok(_)) => StatusCode::NO_CONTENT,
+        Ok(Err(e)) => {
+            tracing::error!("Hook {} failed: {}", name, e);
+            match e {
+                MediaError::NoSession | MediaError::Unsupported { .. } => StatusCode::CONFLICT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn command_for(name: &str) -> Option<MediaCommand> {
+    let command = match name {
+        "play-pause" => MediaCommand::TogglePlayPause(None),
+        "play" => MediaCommand::Play,
+        "pause" => MediaCommand::Pause,
+        "stop" => MediaCommand::Stop,
+        "next" => MediaCommand::NextTrack,
+        "previous" => MediaCommand::PreviousTrack,
+        "shuffle" => MediaCommand::ToggleShuffle,
+        _ => return None,
+    };
+    Some(command)
+}
@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use crate::config::IdleConfig;
+use crate::events::EventHub;
+
+/// Remove the media session handlers once the last client left and register them
+/// again for the next one, so an unattended server does no work on media changes
+pub async fn run(config: IdleConfig, hub: EventHub) {
+    let grace = Duration::from_secs(config.grace_seconds);
+    let mut listeners = hub.clients().listeners();
+    loop {
+        if listeners.wait_for(|count| *count == 0).await.is_err() {
+            return;
+        }
+        // A reload or a flaky connection shouldn't tear the handlers down
+        if let Ok(returned) =
+            tokio::time::timeout(grace, listeners.wait_for(|count| *count > 0)).await
+        {
+            if returned.is_err() {
+                return;
+            }
+            continue;
+        }
+
+        tracing::info!(
+            "No clients for {}s, removing media session handlers",
+            grace.as_secs()
+        );
+        set_watching(&hub, false).await;
+        if listeners.wait_for(|count| *count > 0).await.is_err() {
+            return;
+        }
+        tracing::info!("Client connected, registering media session handlers");
+        set_watching(&hub, true).await;
+    }
+}
+
+async fn set_watching(hub: &EventHub, watching: bool) {
+    let hub = hub.clone();
+    match tokio::task::spawn_blocking(move || hub.set_watching(watching)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => tracing::error!("Failed to update media session handlers: {}", e),
+        Err(e) => tracing::error!("Idle task failed: {}", e),
+    }
+}
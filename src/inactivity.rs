@@ -0,0 +1,175 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::time::MissedTickBehavior;
+#[cfg(windows)]
+use windows::Win32::System::SystemInformation::GetTickCount;
+#[cfg(windows)]
+use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+use crate::config::InactivityConfig;
+use crate::error::{MediaError, MediaResult};
+use crate::events::EventHub;
+use crate::utils;
+
+/// Interval between checks of the last input, the pause fires up to this late
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Input only moves the announced pause when it shifts it by more than this,
+/// so clients aren't sent a new countdown for every check while someone is typing
+const RESCHEDULE_THRESHOLD_MS: u64 = 60_000;
+
+/// Payload of the `inactivity_status` event
+#[derive(Debug, Serialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+pub struct InactivityStatus {
+    /// Playback is counting down to a pause
+    pub active: bool,
+    /// Unix timestamp in milliseconds the pause is due unless there is input
+    pub pauses_at: Option<u64>,
+    /// `cancel_inactivity_pause` skipped the pause until there is input again
+    pub cancelled: bool,
+}
+
+/// What a check of the last input asks the hub to do
+pub enum InactivityUpdate {
+    /// Tell clients about the new countdown
+    Status(InactivityStatus),
+    /// The host was idle for long enough, pause
+    Pause,
+}
+
+/// Pauses playback after the host went without input for a while
+pub struct Inactivity {
+    timeout: Duration,
+    state: Mutex<InactivityState>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct InactivityState {
+    status: InactivityStatus,
+    /// Tick count of the last input when the pause was cancelled
+    cancelled_at: Option<u32>,
+}
+
+impl Inactivity {
+    pub fn new(config: &InactivityConfig) -> Self {
+        Self {
+            timeout: Duration::from_secs(config.minutes * 60),
+            state: Mutex::new(InactivityState::default()),
+        }
+    }
+
+    pub fn status(&self) -> InactivityStatus {
+        self.state
+            .lock()
+            .map(|state| state.status)
+            .unwrap_or_default()
+    }
+
+    /// Count down while playing, from the last input of the host
+    ///
+    /// # Arguments
+    /// * `playing` - Whether the current session plays
+    ///
+    /// # Returns
+    /// * `Option<InactivityUpdate>` - `None` when nothing changed enough to tell clients
+    pub fn update(&self, playing: bool) -> MediaResult<Option<InactivityUpdate>> {
+        let (last_input, idle) = last_input()?;
+        let mut state = self.state.lock().map_err(MediaError::internal)?;
+        let previous = state.status;
+
+        if !playing {
+            *state = InactivityState::default();
+        } else if state.cancelled_at == Some(last_input) {
+            state.status = InactivityStatus {
+                active: false,
+                pauses_at: None,
+                cancelled: true,
+            };
+        } else if idle >= self.timeout {
+            *state = InactivityState::default();
+            return Ok(Some(InactivityUpdate::Pause));
+        } else {
+            let remaining = (self.timeout - idle).as_millis() as u64;
+            state.cancelled_at = None;
+            state.status = InactivityStatus {
+                active: true,
+                pauses_at: Some(utils::unix_millis() + remaining),
+                cancelled: false,
+            };
+        }
+
+        let moved = match (previous.pauses_at, state.status.pauses_at) {
+            (Some(before), Some(after)) => before.abs_diff(after) > RESCHEDULE_THRESHOLD_MS,
+            (before, after) => before != after,
+        };
+        if !moved && previous.active == state.status.active {
+            // Keep the announced time, clients already count down to it
+            state.status.pauses_at = previous.pauses_at;
+            if previous == state.status {
+                return Ok(None);
+            }
+        }
+        Ok(Some(InactivityUpdate::Status(state.status)))
+    }
+
+    /// Skip the pause until there is input again
+    pub fn cancel(&self) -> MediaResult<InactivityStatus> {
+        let (last_input, _) = last_input()?;
+        let mut state = self.state.lock().map_err(MediaError::internal)?;
+        if !state.status.active {
+            return Err(MediaError::invalid_input(
+                "No inactivity pause is counting down",
+            ));
+        }
+        state.cancelled_at = Some(last_input);
+        state.status = InactivityStatus {
+            active: false,
+            pauses_at: None,
+            cancelled: true,
+        };
+        Ok(state.status)
+    }
+}
+
+/// Tick count of the last keyboard or mouse input and the time since
+#[cfg(windows)]
+fn last_input() -> MediaResult<(u32, Duration)> {
+    let mut info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    if !unsafe { GetLastInputInfo(&mut info) }.as_bool() {
+        return Err(windows::core::Error::from_win32().into());
+    }
+    // Both wrap around after 49 days
+    let idle = unsafe { GetTickCount() }.wrapping_sub(info.dwTime);
+    Ok((info.dwTime, Duration::from_millis(u64::from(idle))))
+}
+
+/// Only Windows tells the last input
+#[cfg(not(windows))]
+fn last_input() -> MediaResult<(u32, Duration)> {
+    Err(MediaError::unsupported("reading the last input"))
+}
+
+/// Pause playback once the host went without input for the configured time
+pub async fn run(hub: EventHub) {
+    if cfg!(not(windows)) {
+        tracing::warn!("Inactivity pauses need Windows, ignoring [inactivity]");
+        return;
+    }
+
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        let hub = hub.clone();
+        match tokio::task::spawn_blocking(move || hub.check_inactivity()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to check for inactivity: {}", e),
+            Err(e) => tracing::error!("Inactivity task failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Networks that are always allowed: loopback, RFC 1918 and their IPv6 counterparts
+const DEFAULT_RANGES: [&str; 7] = [
+    "127.0.0.0/8",
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+/// A network in CIDR notation
+#[derive(Debug, Clone, Copy)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for IpRange {
+    type Err = anyhow::Error;
+
+    /// Parse `10.0.0.0/8` or a single address like `203.0.113.7`
+    fn from_str(value: &str) -> Result<Self> {
+        let (address, prefix) = match value.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value.trim(), None),
+        };
+        let network: IpAddr = address
+            .parse()
+            .with_context(|| format!("Invalid IP address {:?}", address))?;
+
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .with_context(|| format!("Invalid prefix length {:?}", prefix))?,
+            None => max,
+        };
+        if prefix > max {
+            bail!("Prefix length {} is longer than {} bits", prefix, max);
+        }
+        Ok(Self { network, prefix })
+    }
+}
+
+impl IpRange {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Peers allowed to reach any route, including the Socket.IO handshake
+#[derive(Debug, Clone)]
+pub struct IpAllowlist {
+    ranges: Arc<Vec<IpRange>>,
+}
+
+impl IpAllowlist {
+    /// The default private ranges plus any extra ones
+    ///
+    /// # Arguments
+    /// * `extra` - Additional ranges in CIDR notation, e.g. from `--allow-ip`
+    pub fn new(extra: &[String]) -> Result<Self> {
+        let ranges = DEFAULT_RANGES
+            .iter()
+            .copied()
+            .chain(extra.iter().map(String::as_str))
+            .map(IpRange::from_str)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            ranges: Arc::new(ranges),
+        })
+    }
+
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+}
+
+/// Middleware rejecting requests from peers outside the allowlist
+///
+/// The peer address is the TCP connection's, forwarded headers are ignored since
+/// anyone can set them. Behind a reverse proxy the proxy's address is checked.
+pub async fn filter(
+    State(allowlist): State<IpAllowlist>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !allowlist.allows(peer.ip()) {
+        tracing::warn!("Rejected request from {} to {}", peer, request.uri().path());
+        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+    }
+    next.run(request).await
+}
@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, fmt::MakeWriter, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+use crate::timings::TimingLayer;
+
+const DEFAULT_FILTER: &str = "info";
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable text
+    #[default]
+    Pretty,
+    /// One JSON object per line
+    Json,
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Install the global subscriber, writing to stdout and optionally to a daily rotated file
+///
+/// # Arguments
+/// * `format` - Output format for every destination
+/// * `file` - Log file path, rotated daily by appending the date to the file name
+/// * `filter` - Directives like `info,media_controller::mqtt=debug`, `RUST_LOG` takes precedence
+///
+/// # Returns
+/// * `Result<Option<WorkerGuard>>` - Keeps the file writer flushing, hold it until exit
+pub fn init(
+    format: LogFormat,
+    file: Option<&Path>,
+    filter: Option<&str>,
+) -> Result<Option<WorkerGuard>> {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => {
+            EnvFilter::try_new(filter.unwrap_or(DEFAULT_FILTER)).context("Invalid log filter")?
+        }
+    };
+
+    let mut layers = vec![fmt_layer(format, std::io::stdout, true)];
+    let mut guard = None;
+    if let Some(file) = file {
+        let dir = file
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let name = file.file_name().context("Log file path has no file name")?;
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create log directory {}", dir.display()))?;
+
+        let (writer, worker) =
+            tracing_appender::non_blocking(tracing_appender::rolling::daily(dir, name));
+        layers.push(fmt_layer(format, writer, false));
+        guard = Some(worker);
+    }
+    layers.push(TimingLayer.boxed());
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(filter)
+        .try_init()
+        .context("Failed to install the log subscriber")?;
+    Ok(guard)
+}
+
+fn fmt_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+    match format {
+        LogFormat::Pretty => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
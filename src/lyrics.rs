@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::LyricsConfig;
+use crate::media_manager::TrackInfo;
+use crate::utils;
+
+const LRCLIB_URL: &str = "https://lrclib.net/api/get";
+const MUSIXMATCH_URL: &str = "https://api.musixmatch.com/ws/1.1";
+const LYRICS_CACHE_DIR: &str = "lyrics";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A single line of synced lyrics
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct LyricLine {
+    /// Start of the line in milliseconds
+    pub time: u64,
+    pub text: String,
+}
+
+/// Lyrics of a track, with timestamps when the provider has synced lyrics
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Lyrics {
+    pub title: String,
+    pub artist: String,
+    /// Provider the lyrics came from, e.g. `lrclib`
+    pub source: String,
+    /// Whether `lines` carry real timestamps
+    pub synced: bool,
+    /// Synced lines, ordered by time. Empty when only plain lyrics are available
+    pub lines: Vec<LyricLine>,
+    pub plain: String,
+    /// Raw LRC document when synced
+    pub lrc: Option<String>,
+}
+
+/// Fetches lyrics from the configured providers and caches the results in
+/// memory and on disk.
+pub struct LyricsService {
+    config: LyricsConfig,
+    client: reqwest::blocking::Client,
+    /// Results per track, `None` when no provider had the track
+    cache: Mutex<HashMap<String, Option<Lyrics>>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LrclibResponse {
+    plain_lyrics: Option<String>,
+    synced_lyrics: Option<String>,
+}
+
+impl LyricsService {
+    pub fn new(config: LyricsConfig) -> Result<Self> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("media-controller/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+
+        Ok(Self {
+            config,
+            client,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Lyrics for a track, from the cache or the first provider that has them
+    ///
+    /// # Arguments
+    /// * `track` - The track to look up
+    ///
+    /// # Returns
+    /// * `Option<Lyrics>` - `None` when no provider has lyrics for the track
+    pub fn lyrics(&self, track: &TrackInfo) -> Option<Lyrics> {
+        let key = utils::track_key(&track.artist, &track.title);
+        if let Some(cached) = self.cache.lock().ok().and_then(|c| c.get(&key).cloned()) {
+            return cached;
+        }
+
+        let lyrics = utils::read_json_cache(LYRICS_CACHE_DIR, &key).or_else(|| {
+            let lyrics = self.fetch(track);
+            if let Some(lyrics) = &lyrics {
+                utils::write_json_cache(LYRICS_CACHE_DIR, &key, lyrics);
+            }
+            lyrics
+        });
+
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(key, lyrics.clone());
+        }
+        lyrics
+    }
+
+    fn fetch(&self, track: &TrackInfo) -> Option<Lyrics> {
+        match self.fetch_lrclib(track) {
+            Ok(Some(lyrics)) => return Some(lyrics),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("LRCLIB lyrics lookup failed: {:#}", e),
+        }
+
+        let api_key = self.config.musixmatch_api_key.as_deref()?;
+        match self.fetch_musixmatch(track, api_key) {
+            Ok(lyrics) => lyrics,
+            Err(e) => {
+                tracing::warn!("Musixmatch lyrics lookup failed: {:#}", e);
+                None
+            }
+        }
+    }
+
+    fn fetch_lrclib(&self, track: &TrackInfo) -> Result<Option<Lyrics>> {
+        let duration = (track.duration / 1000).to_string();
+        let mut query = vec![
+            ("track_name", track.title.as_str()),
+            ("artist_name", track.artist.as_str()),
+            ("duration", duration.as_str()),
+        ];
+        if let Some(album) = &track.album {
+            query.push(("album_name", album.as_str()));
+        }
+
+        let response = self.client.get(LRCLIB_URL).query(&query).send()?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body: LrclibResponse = response.error_for_status()?.json()?;
+
+        Ok(build_lyrics(
+            track,
+            "lrclib",
+            body.synced_lyrics,
+            body.plain_lyrics,
+        ))
+    }
+
+    fn fetch_musixmatch(&self, track: &TrackInfo, api_key: &str) -> Result<Option<Lyrics>> {
+        let query = [
+            ("q_track", track.title.as_str()),
+            ("q_artist", track.artist.as_str()),
+            ("apikey", api_key),
+        ];
+
+        // Subtitles are the synced lyrics, only available on some plans
+        let subtitle: serde_json::Value = self
+            .client
+            .get(format!("{}/matcher.subtitle.get", MUSIXMATCH_URL))
+            .query(&query)
+            .query(&[("subtitle_format", "lrc")])
+            .send()?
+            .error_for_status()?
+            .json()?;
+        let synced = subtitle
+            .pointer("/message/body/subtitle/subtitle_body")
+            .and_then(|body| body.as_str())
+            .map(String::from);
+
+        let lyrics: serde_json::Value = self
+            .client
+            .get(format!("{}/matcher.lyrics.get", MUSIXMATCH_URL))
+            .query(&query)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        let plain = lyrics
+            .pointer("/message/body/lyrics/lyrics_body")
+            .and_then(|body| body.as_str())
+            .map(String::from);
+
+        Ok(build_lyrics(track, "musixmatch", synced, plain))
+    }
+}
+
+fn build_lyrics(
+    track: &TrackInfo,
+    source: &str,
+    synced: Option<String>,
+    plain: Option<String>,
+) -> Option<Lyrics> {
+    let synced = synced.filter(|lrc| !lrc.trim().is_empty());
+    let lines = synced.as_deref().map(parse_lrc).unwrap_or_default();
+    let plain = match plain.filter(|plain| !plain.trim().is_empty()) {
+        Some(plain) => plain,
+        None if !lines.is_empty() => lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => return None,
+    };
+
+    Some(Lyrics {
+        title: track.title.clone(),
+        artist: track.artist.clone(),
+        source: source.to_string(),
+        synced: !lines.is_empty(),
+        lines,
+        plain,
+        lrc: synced,
+    })
+}
+
+/// Parse an LRC document into timed lines, skipping metadata tags
+///
+/// # Arguments
+/// * `lrc` - LRC text, lines like `[01:23.45]Some words`
+///
+/// # Returns
+/// * `Vec<LyricLine>` - Lines ordered by time
+fn parse_lrc(lrc: &str) -> Vec<LyricLine> {
+    let mut lines = Vec::new();
+    for raw in lrc.lines() {
+        let mut rest = raw.trim();
+        let mut times = Vec::new();
+
+        // A line can carry several timestamps when it repeats
+        while let Some(tag) = rest.strip_prefix('[') {
+            let Some((stamp, after)) = tag.split_once(']') else {
+                break;
+            };
+            match parse_timestamp(stamp) {
+                Some(time) => times.push(time),
+                None => break,
+            }
+            rest = after;
+        }
+
+        let text = rest.trim();
+        for time in times {
+            lines.push(LyricLine {
+                time,
+                text: text.to_string(),
+            });
+        }
+    }
+    lines.sort_by_key(|line| line.time);
+    lines
+}
+
+/// Parse `mm:ss`, `mm:ss.xx` or `mm:ss.xxx` into milliseconds
+fn parse_timestamp(stamp: &str) -> Option<u64> {
+    let (minutes, seconds) = stamp.split_once(':')?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, "0"));
+    let seconds: u64 = seconds.trim().parse().ok()?;
+    let millis = match fraction.len() {
+        1 => fraction.parse::<u64>().ok()? * 100,
+        2 => fraction.parse::<u64>().ok()? * 10,
+        3 => fraction.parse::<u64>().ok()?,
+        _ => return None,
+    };
+    Some(minutes * 60_000 + seconds * 1000 + millis)
+}
@@ -1,59 +1,518 @@
-use anyhow::Result;
-use axum::{
-    Router,
-    extract::State,
-    http::StatusCode,
-    response::Json,
-    routing::{get, get_service},
-};
-use clap::Parser;
+use anyhow::{Context, Result};
+use axum::{Router, extract::State, http::HeaderMap, middleware, response::Json, routing::get};
+use clap::{Parser, Subcommand, ValueEnum};
 use socketioxide::SocketIo;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
 use tower::ServiceBuilder;
-use tower_http::{cors::CorsLayer, services::ServeDir};
-use tracing_subscriber::FmtSubscriber;
+use tower_http::cors::CorsLayer;
 use utils::{DEFAULT_FRONTEND_PORT, DEFAULT_SOCKETIO_PORT, ServerConfig, ServerInfo};
 
 // Import our modules
-mod media_manager;
+mod ads;
+mod aggregate;
+mod api;
+mod app_icon;
+mod automation;
+#[cfg(windows)]
+mod autostart;
+mod clients;
+mod config;
+mod conflicts;
+mod cron;
+mod ctl;
+mod display;
+mod events;
+mod frontend;
+mod history;
+mod home_assistant;
+mod hooks;
+mod idle;
+mod inactivity;
+mod ip_filter;
+mod logging;
+mod lyrics;
+mod media_keys;
+mod metadata;
+#[cfg(target_os = "linux")]
+mod mpris;
+mod mqtt;
+#[cfg(windows)]
+mod notifications;
+mod now_playing;
+mod output_device;
+mod overlay;
+mod peers;
+mod persistence;
+mod pins;
+mod plugins;
+mod polling;
+mod position_alarms;
+mod protocol;
+#[cfg(windows)]
+mod publisher;
+mod push;
+mod queue;
+mod quiet_hours;
+mod rate_limit;
+mod scheduler;
+mod schema;
+mod scrub;
+mod session_lock;
+mod skip;
+mod snapcast;
 mod socket_io;
+mod spotify;
+#[cfg(windows)]
+mod supervisor;
+mod templates;
+mod thumbnail;
+mod timings;
+mod tui;
+mod update;
 mod utils;
+mod volume;
+mod webhooks;
+mod websocket;
 
+// The media control itself lives in the core crate
+#[cfg(windows)]
+use media_controller_core::winrt;
+use media_controller_core::{error, media_manager};
+
+use clients::ListenerGuard;
+use events::EventHub;
+use ip_filter::IpAllowlist;
+use logging::LogFormat;
+use media_controller_core::{DlnaBackend, MediaBackend, MockBackend, ReplayBackend, recording};
+#[cfg(windows)]
+use media_manager::MediaManager;
 use socket_io::on_connect;
 
 /// Media Broadcast CLI
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Enable the frontend server
     #[arg(long, short, default_value_t = false)]
     frontend: bool,
 
-    #[arg(long, short = 'd', default_value_t = FRONTEND_DIR.to_string())]
-    /// Path to the frontend files directory
-    frontend_directory: String,
+    #[arg(long, short = 'd')]
+    /// Path to the frontend files directory, instead of the bundled frontend or
+    /// `client/dist` without one
+    frontend_directory: Option<String>,
 
     /// Port for the frontend server
     #[arg(long, default_value_t = DEFAULT_FRONTEND_PORT)]
     frontend_port: u16,
 
-    /// Port for the Socket.IO server
+    /// Port for the Socket.IO server, and for everything in single-port mode
     #[arg(long, default_value_t = DEFAULT_SOCKETIO_PORT)]
     socketio_port: u16,
+
+    /// Serve the frontend, REST routes and Socket.IO on the Socket.IO port
+    #[arg(long, default_value_t = false)]
+    single_port: bool,
+
+    /// Address both servers listen on, e.g. `127.0.0.1`, an interface IP or `::`
+    #[arg(long, default_value = "0.0.0.0")]
+    bind: IpAddr,
+
+    /// Path prefix for every route, e.g. `/media` behind a reverse proxy
+    #[arg(long, default_value = "")]
+    base_path: String,
+
+    /// Allow clients from this CIDR range besides loopback and private networks, repeatable
+    #[arg(long = "allow-ip", value_name = "CIDR")]
+    allow_ips: Vec<String>,
+
+    /// Path to the config file (defaults to config.toml if present)
+    #[arg(long, short)]
+    config: Option<PathBuf>,
+
+    /// Log output format
+    #[arg(long, value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+
+    /// Also write logs to this file, rotated daily
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
+    /// Where media sessions come from
+    #[arg(long, value_enum, default_value_t = DEFAULT_BACKEND)]
+    backend: Backend,
+
+    /// How changes of the media session are noticed
+    #[arg(long, value_enum, default_value_t = Mode::Events)]
+    mode: Mode,
+
+    /// Write every media event with its timing to this file, for replaying it later
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+
+    /// Recording played by `--backend replay`
+    #[arg(long, value_name = "FILE", required_if_eq("backend", "replay"))]
+    replay: Option<PathBuf>,
+
+    /// How much faster than recorded the replay runs
+    #[arg(long, default_value_t = 1.0)]
+    replay_speed: f64,
+
+    /// Part of the name of the renderer `--backend dlna` controls, the first one found by default
+    #[arg(long, value_name = "NAME")]
+    dlna_renderer: Option<String>,
+
+    /// Another server to control through this one, as URL or name=URL, repeatable
+    #[arg(long = "peer", value_name = "URL")]
+    peers: Vec<String>,
+
+    /// Tell on startup when a newer release is out
+    #[arg(long, default_value_t = false)]
+    check_updates: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    /// Sessions of the apps playing on this PC
+    #[cfg(windows)]
+    Windows,
+    /// A scripted library playing on its own, for frontend development and tests
+    Mock,
+    /// Events recorded with `--record`, played back with their timing
+    Replay,
+    /// A UPnP/DLNA media renderer on the network, e.g. a smart TV
+    Dlna,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Mode {
+    /// The change events of the session, apps with a `poll_ms` are polled on top
+    Events,
+    /// Read the current session every second, for players whose change events
+    /// never fire
+    Polling,
 }
 
-const FRONTEND_DIR: &str = "client/dist";
+#[cfg(windows)]
+const DEFAULT_BACKEND: Backend = Backend::Windows;
+/// Only Windows has sessions of its own, elsewhere the server starts with the mock library
+#[cfg(not(windows))]
+const DEFAULT_BACKEND: Backend = Backend::Mock;
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the JSON Schema of every event and function payload, for generating client types
+    GenerateTypes {
+        /// Write the schema to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Control a running server from the command line
+    Ctl(ctl::CtlArgs),
+    /// Show and control a running server in the terminal
+    Tui(tui::TuiArgs),
+    /// Import played tracks into the history
+    History(history::HistoryArgs),
+    /// Replace this executable with the latest release
+    Update(update::UpdateArgs),
+    /// Start the server when signing in to Windows
+    #[cfg(windows)]
+    Autostart(autostart::AutostartArgs),
+}
+
+/// How long open connections get to close after Ctrl+C before they are dropped
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing::subscriber::set_global_default(FmtSubscriber::default()).ok();
     let args = Args::parse();
 
-    let config = ServerConfig::new(args.socketio_port);
+    match args.command {
+        Some(Command::GenerateTypes { output }) => return schema::generate(output.as_deref()),
+        Some(Command::Ctl(ctl_args)) => return ctl::run(ctl_args).await,
+        Some(Command::Tui(tui_args)) => return tui::run(tui_args).await,
+        Some(Command::History(history_args)) => return history::run(history_args),
+        #[cfg(windows)]
+        Some(Command::Autostart(autostart_args)) => return autostart::run(autostart_args),
+        Some(Command::Update(update_args)) => {
+            // The blocking HTTP client can't run on the runtime's threads
+            return tokio::task::spawn_blocking(move || update::run(update_args)).await?;
+        }
+        None => {}
+    }
+
+    // The config holds the log filter, so it is loaded before logging starts
+    let mut app_config = config::Config::load(args.config.as_deref())?;
+    app_config.peers.extend(
+        args.peers
+            .iter()
+            .map(|peer| config::PeerConfig::parse(peer)),
+    );
+    let app_config = Arc::new(app_config);
+    let _log_guard = logging::init(
+        args.log_format,
+        args.log_file.as_deref(),
+        app_config.logging.filter.as_deref(),
+    )?;
+    if let Some(path) = &app_config.source {
+        tracing::info!("Loaded config from {}", path.display());
+    }
+    update::remove_replaced();
+    if args.check_updates {
+        tokio::task::spawn_blocking(update::notify_if_outdated);
+    }
+
+    let config = ServerConfig::new(args.bind, args.socketio_port, &args.base_path);
+    let allowlist = IpAllowlist::new(&args.allow_ips)?;
+
+    let backend: Box<dyn MediaBackend> = match args.backend {
+        #[cfg(windows)]
+        Backend::Windows => Box::new(
+            MediaManager::new()?
+                .with_profiles(app_config.apps.clone())
+                .with_timeout(app_config.winrt.timeout()),
+        ),
+        Backend::Mock => {
+            tracing::info!("Using the mock media backend");
+            Box::new(MockBackend::new())
+        }
+        Backend::Replay => {
+            let path = args
+                .replay
+                .as_deref()
+                .context("--backend replay needs --replay <FILE>")?;
+            tracing::info!(
+                "Replaying {} at {}x speed",
+                path.display(),
+                args.replay_speed
+            );
+            Box::new(ReplayBackend::open(path, args.replay_speed)?)
+        }
+        Backend::Dlna => Box::new(DlnaBackend::discover(args.dlna_renderer.as_deref())?),
+    };
+    let hub = EventHub::new(backend, Arc::clone(&app_config));
+    hub.start(args.mode == Mode::Events)?;
+
+    // Services reacting to every media change count as listeners for as long as the
+    // server runs, so idling never removes the session handlers they need
+    let mut followers = Vec::new();
+
+    if let Some(path) = &args.record {
+        if args.mode == Mode::Polling {
+            anyhow::bail!("--record records change events, which --mode polling has none of");
+        }
+        recording::record(hub.media(), path)?;
+        followers.push(hub.clients().listen());
+    }
+
+    // Both are applied by the hub itself on every track change
+    if !app_config.skip.is_empty() || app_config.ads.mute {
+        followers.push(hub.clients().listen());
+    }
+
+    if let Some(snapcast) = hub.snapcast() {
+        tokio::spawn(snapcast::run(snapcast, hub.clone()));
+    }
+
+    if let Some(peers) = hub.peers() {
+        tokio::spawn(peers::run(peers, hub.clone()));
+    }
+
+    if let Some(mqtt_config) = app_config.mqtt.clone() {
+        let hub = hub.clone();
+        spawn_follower(hub.clients().listen(), async move {
+            if let Err(e) = mqtt::run(mqtt_config, hub).await {
+                tracing::error!("MQTT bridge error: {}", e);
+            }
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(mpris_config) = app_config.mpris.clone() {
+        let (server, hub) = (config.clone(), hub.clone());
+        spawn_follower(hub.clients().listen(), async move {
+            if let Err(e) = mpris::run(mpris_config, server, hub).await {
+                tracing::error!("MPRIS player error: {:#}", e);
+            }
+        });
+    }
+
+    #[cfg(windows)]
+    if let Some(publisher_config) = app_config.publisher.clone() {
+        let (mqtt_config, hub) = (app_config.mqtt.clone(), hub.clone());
+        spawn_follower(hub.clients().listen(), async move {
+            if let Err(e) = publisher::run(publisher_config, mqtt_config, hub).await {
+                tracing::error!("SMTC publisher error: {:#}", e);
+            }
+        });
+    }
+    #[cfg(not(windows))]
+    if app_config.publisher.is_some() {
+        tracing::warn!("The SMTC publisher needs Windows, ignoring [publisher]");
+    }
+
+    if !app_config.webhooks.is_empty() {
+        let webhooks = app_config.webhooks.clone();
+        let hub = hub.clone();
+        spawn_follower(hub.clients().listen(), async move {
+            if let Err(e) = webhooks::run(webhooks, hub).await {
+                tracing::error!("Webhook publisher error: {}", e);
+            }
+        });
+    }
+
+    if !app_config.push.is_empty() {
+        let targets = app_config.push.clone();
+        let hub = hub.clone();
+        spawn_follower(hub.clients().listen(), async move {
+            if let Err(e) = push::run(targets, hub).await {
+                tracing::error!("Push notifications error: {}", e);
+            }
+        });
+    }
+
+    if !app_config.automation.rules.is_empty() {
+        let automation = app_config.automation.clone();
+        let hub = hub.clone();
+        tokio::spawn(async move {
+            if let Err(e) = automation::run(automation, hub).await {
+                tracing::error!("Automation error: {}", e);
+            }
+        });
+    }
+
+    if let Some(now_playing_config) = app_config.now_playing.clone() {
+        let hub = hub.clone();
+        spawn_follower(hub.clients().listen(), async move {
+            if let Err(e) = now_playing::run(now_playing_config, hub).await {
+                tracing::error!("Now-playing writer error: {}", e);
+            }
+        });
+    }
+
+    #[cfg(windows)]
+    if app_config.notifications.enabled {
+        let notifications = app_config.notifications.clone();
+        let hub = hub.clone();
+        spawn_follower(hub.clients().listen(), async move {
+            if let Err(e) = notifications::run(notifications, hub).await {
+                tracing::error!("Notifications error: {}", e);
+            }
+        });
+    }
+    #[cfg(not(windows))]
+    if app_config.notifications.enabled {
+        tracing::warn!("Toast notifications need Windows, ignoring [notifications]");
+    }
+
+    if app_config.plugins.enabled {
+        let directory = app_config
+            .plugins
+            .directory
+            .clone()
+            .unwrap_or_else(|| utils::data_dir().join("plugins"));
+        match plugins::start(directory, hub.clone()) {
+            Ok(()) => followers.push(hub.clients().listen()),
+            Err(e) => tracing::error!("Failed to load plugins: {:#}", e),
+        }
+    }
+
+    if let Some(display_config) = app_config.display.clone() {
+        let (bind, allowlist, hub) = (args.bind, allowlist.clone(), hub.clone());
+        spawn_follower(hub.clients().listen(), async move {
+            if let Err(e) = display::run(display_config, bind, allowlist, hub).await {
+                tracing::error!("Display protocol error: {:#}", e);
+            }
+        });
+    }
+
+    if let Some(aggregate_config) = app_config.aggregate.clone() {
+        tokio::spawn(aggregate::run(aggregate_config, hub.clone()));
+    }
+
+    if let Some(conflicts_config) = app_config.conflicts.clone() {
+        spawn_follower(
+            hub.clients().listen(),
+            conflicts::run(conflicts_config, hub.clone()),
+        );
+    }
+
+    if app_config.quiet_hours.is_some() {
+        tokio::spawn(quiet_hours::run(hub.clone()));
+    }
+
+    if app_config.inactivity.is_some() {
+        tokio::spawn(inactivity::run(hub.clone()));
+    }
+
+    tokio::spawn(position_alarms::run(hub.clone()));
+
+    // Without change events every app is polled
+    let poll_interval = (args.mode == Mode::Polling).then_some(polling::MODE_INTERVAL);
+    if poll_interval.is_some()
+        || app_config
+            .apps
+            .values()
+            .any(|profile| profile.poll_ms.is_some())
+    {
+        tokio::spawn(polling::run(hub.clone(), poll_interval));
+    }
+
+    if app_config.output_devices.enabled {
+        let output_devices = app_config.output_devices.clone();
+        let hub = hub.clone();
+        tokio::spawn(async move {
+            if let Err(e) = output_device::run(output_devices, hub).await {
+                tracing::error!("Output device watcher error: {}", e);
+            }
+        });
+    }
+
+    if app_config.lock.pause_on_lock {
+        let hub = hub.clone();
+        tokio::spawn(async move {
+            if let Err(e) = session_lock::run(hub).await {
+                tracing::error!("Session lock error: {}", e);
+            }
+        });
+    }
+
+    if app_config.idle.enabled && args.mode == Mode::Polling {
+        tracing::warn!("Idling removes session handlers, which --mode polling has none of");
+    } else if app_config.idle.enabled {
+        tokio::spawn(idle::run(app_config.idle.clone(), hub.clone()));
+    }
+
+    // Restarting means recreating the Windows session manager
+    #[cfg(windows)]
+    if args.backend == Backend::Windows {
+        tokio::spawn(supervisor::run(hub.clone()));
+    }
+
+    let (shutdown_sender, shutdown) = watch::channel(false);
+    tokio::spawn(shutdown_on_ctrl_c(hub.clone(), shutdown_sender));
+
+    if args.single_port {
+        let dir = args.frontend_directory;
+        serve_single_port(config, allowlist, hub, args.socketio_port, dir, shutdown).await?;
+        return Ok(());
+    }
 
     let config_for_socketio = config.clone();
+    let allowlist_for_socketio = allowlist.clone();
+    let shutdown_for_socketio = shutdown.clone();
     let server_task = tokio::spawn(async move {
-        let port = args.frontend_port;
-        if let Err(e) = serve_socket_io(config_for_socketio, port).await {
+        let port = args.socketio_port;
+        let allowlist = allowlist_for_socketio;
+        let shutdown = shutdown_for_socketio;
+        if let Err(e) = serve_socket_io(config_for_socketio, allowlist, hub, port, shutdown).await {
             tracing::error!("Socket.IO server error: {}", e);
         }
     });
@@ -63,7 +522,7 @@ async fn main() -> Result<()> {
         let dir = args.frontend_directory;
 
         let task = tokio::spawn(async move {
-            if let Err(e) = serve_react_app(config, port, dir).await {
+            if let Err(e) = serve_react_app(config, allowlist, port, dir, shutdown).await {
                 eprintln!("Frontend service error: {}", e);
             }
         });
@@ -76,71 +535,202 @@ async fn main() -> Result<()> {
 }
 
 // Handler for server-info endpoint
-async fn server_info_handler(State(config): State<ServerConfig>) -> Json<ServerInfo> {
-    let socketio_url = config.get_url().await;
-    Json(ServerInfo { socketio_url })
+async fn server_info_handler(
+    State(config): State<ServerConfig>,
+    headers: HeaderMap,
+) -> Json<ServerInfo> {
+    let socketio_url = config.public_url(&headers).await;
+    Json(ServerInfo {
+        socketio_url,
+        socketio_path: config.socketio_path(),
+        base_path: config.base_path.clone(),
+    })
 }
 
-async fn serve_react_app(config: ServerConfig, port: u16, frontend_dir: String) -> Result<()> {
-    tracing::debug!("Serving frontend from directory: {}", frontend_dir);
-
-    let react_app = get_service(ServeDir::new(frontend_dir))
-        .handle_error(|_| async { (StatusCode::INTERNAL_SERVER_ERROR, "Static file error") });
-
-    let app = axum::Router::new()
+/// Static frontend files and the `/server-info` endpoint
+fn frontend_router(config: ServerConfig, frontend_dir: Option<String>) -> Router {
+    Router::new()
         .route("/server-info", get(server_info_handler))
-        .route("/health", get(|| async { "OK" }))
-        .fallback_service(react_app)
-        .with_state(config.clone());
+        .with_state(config)
+        .merge(frontend::router(frontend_dir))
+}
 
-    let (listener, actual_port) = utils::try_bind(port).await?;
+/// REST, streaming and asset routes served next to Socket.IO
+fn service_router(hub: EventHub) -> Router {
+    Router::new()
+        .route("/health", get(|| async { "OK" }))
+        .merge(api::router(hub.clone()))
+        .merge(app_icon::router())
+        .merge(hooks::router(hub.clone()))
+        .merge(overlay::router())
+        .merge(spotify::router(hub.clone()))
+        .merge(thumbnail::router(hub.clone()))
+        .merge(websocket::router(hub))
+}
 
-    if actual_port != port {
-        println!(
-            "Frontend port {} was unavailable, using port {} instead",
-            port, actual_port
-        );
+/// Serve a router under the base path
+fn with_base_path(router: Router, config: &ServerConfig) -> Router {
+    match config.base_path.as_str() {
+        "" => router,
+        base_path => Router::new().nest(base_path, router),
     }
-
-    utils::print_urls("Frontend", actual_port);
-
-    axum::serve(listener, app).await?;
-    Ok(())
 }
 
-async fn serve_socket_io(config: ServerConfig, port: u16) -> Result<()> {
-    let (layer, io) = SocketIo::new_layer();
+/// Mount the Socket.IO server under `/socket.io` below the base path
+fn with_socket_io(router: Router, hub: EventHub, config: &ServerConfig) -> Router {
+    let (layer, io) = SocketIo::builder()
+        .req_path(config.socketio_path())
+        .with_state(hub)
+        .build_layer();
     io.ns("/", on_connect);
 
     let layer = ServiceBuilder::new()
         .layer(CorsLayer::permissive())
         .layer(layer);
 
-    let app = Router::new()
-        .layer(CorsLayer::permissive())
-        .route("/health", get(|| async { "OK" }))
-        .layer(layer);
+    router.layer(layer)
+}
+
+/// Reject clients outside the allowlist, wrapping every route and the Socket.IO handshake
+fn with_ip_filter(router: Router, allowlist: &IpAllowlist) -> Router {
+    router.layer(middleware::from_fn_with_state(
+        allowlist.clone(),
+        ip_filter::filter,
+    ))
+}
 
-    let (listener, actual_port) = utils::try_bind(port).await?;
+/// Wait for Ctrl+C, then tell clients and remove the media session handlers
+/// before the servers start draining
+/// Spawn a service that reacts to every media change, counted as a listener until it
+/// ends so idling never removes the session handlers it needs
+///
+/// # Arguments
+/// * `listener` - Taken from the hub's clients before the service moves the hub
+/// * `service` - The service, running until the server stops or it fails
+fn spawn_follower(listener: ListenerGuard, service: impl Future<Output = ()> + Send + 'static) {
+    tokio::spawn(async move {
+        service.await;
+        drop(listener);
+    });
+}
+
+async fn shutdown_on_ctrl_c(hub: EventHub, sender: watch::Sender<bool>) {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        tracing::error!("Failed to listen for Ctrl+C: {}", e);
+        // Dropping the sender would read as a shutdown
+        std::future::pending::<()>().await;
+    }
+
+    tracing::info!("Shutting down");
+    tokio::task::spawn_blocking(move || hub.shutdown())
+        .await
+        .ok();
+    sender.send(true).ok();
+}
+
+async fn shutdown_requested(mut shutdown: watch::Receiver<bool>) {
+    shutdown.wait_for(|stop| *stop).await.ok();
+}
+
+/// Serve a router, exposing peer addresses to the IP filter, until shutdown
+///
+/// Connections get `DRAIN_TIMEOUT` to close on their own before they are dropped.
+async fn serve(listener: TcpListener, app: Router, shutdown: watch::Receiver<bool>) -> Result<()> {
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_requested(shutdown.clone()));
+
+    let drain_timeout = async {
+        shutdown_requested(shutdown).await;
+        tokio::time::sleep(DRAIN_TIMEOUT).await;
+    };
+    tokio::select! {
+        result = server => result?,
+        _ = drain_timeout => {
+            tracing::warn!("Connections still open after {:?}, dropping them", DRAIN_TIMEOUT);
+        }
+    }
+    Ok(())
+}
+
+/// Bind the listener clients connect to and share its address through `/server-info`
+async fn bind_socket_io(config: &ServerConfig, name: &str, port: u16) -> Result<TcpListener> {
+    let (listener, actual_port) = utils::try_bind(config.bind, port).await?;
 
     // Update the shared configuration with the actual Socket.IO port
     // Use the first network IP if available, otherwise use localhost
-    let host = utils::get_local_ips()
-        .first()
-        .map(|ip| ip.to_string())
+    let host = utils::get_local_ips(config.bind)
+        .into_iter()
+        .next()
         .unwrap_or_else(|| String::from("localhost"));
 
     config.set_info(host.clone(), actual_port).await;
 
     if actual_port != port {
         println!(
-            "SocketIO port {} was unavailable, using port {} instead",
+            "{} port {} was unavailable, using port {} instead",
+            name, port, actual_port
+        );
+    }
+
+    utils::print_urls(name, config.bind, actual_port, &config.base_path);
+    Ok(listener)
+}
+
+async fn serve_react_app(
+    config: ServerConfig,
+    allowlist: IpAllowlist,
+    port: u16,
+    frontend_dir: Option<String>,
+    shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let app =
+        frontend_router(config.clone(), frontend_dir).route("/health", get(|| async { "OK" }));
+    let app = with_ip_filter(with_base_path(app, &config), &allowlist);
+
+    let (listener, actual_port) = utils::try_bind(config.bind, port).await?;
+
+    if actual_port != port {
+        println!(
+            "Frontend port {} was unavailable, using port {} instead",
             port, actual_port
         );
     }
 
-    utils::print_urls("SocketIO", actual_port);
+    utils::print_urls("Frontend", config.bind, actual_port, &config.base_path);
 
-    axum::serve(listener, app).await?;
-    Ok(())
+    serve(listener, app, shutdown).await
+}
+
+async fn serve_socket_io(
+    config: ServerConfig,
+    allowlist: IpAllowlist,
+    hub: EventHub,
+    port: u16,
+    shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let app = with_base_path(service_router(hub.clone()), &config);
+    let app = with_ip_filter(with_socket_io(app, hub, &config), &allowlist);
+    let listener = bind_socket_io(&config, "SocketIO", port).await?;
+
+    serve(listener, app, shutdown).await
+}
+
+/// Serve the frontend, REST routes and Socket.IO from a single listener
+async fn serve_single_port(
+    config: ServerConfig,
+    allowlist: IpAllowlist,
+    hub: EventHub,
+    port: u16,
+    frontend_dir: Option<String>,
+    shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let app = service_router(hub.clone()).merge(frontend_router(config.clone(), frontend_dir));
+    let app = with_socket_io(with_base_path(app, &config), hub, &config);
+    let app = with_ip_filter(app, &allowlist);
+    let listener = bind_socket_io(&config, "Media Controller", port).await?;
+
+    serve(listener, app, shutdown).await
 }
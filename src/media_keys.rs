@@ -0,0 +1,97 @@
+#[cfg(windows)]
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    INPUT, INPUT_0, INPUT_KEYBOARD, KEYBD_EVENT_FLAGS, KEYBDINPUT, KEYEVENTF_KEYUP, SendInput,
+    VIRTUAL_KEY, VK_MEDIA_NEXT_TRACK, VK_MEDIA_PLAY_PAUSE, VK_MEDIA_PREV_TRACK, VK_MEDIA_STOP,
+};
+
+use crate::error::{MediaError, MediaResult};
+use crate::events::MediaCommand;
+
+/// Hardware media keys that can be synthesized for apps without a GSMTC session
+#[derive(Debug, Clone, Copy)]
+pub enum MediaKey {
+    PlayPause,
+    Next,
+    Previous,
+    Stop,
+}
+
+impl MediaKey {
+    /// The media key equivalent of a command, if there is one
+    pub fn for_command(command: &MediaCommand) -> Option<Self> {
+        match command {
+            MediaCommand::TogglePlayPause(_) => Some(MediaKey::PlayPause),
+            MediaCommand::NextTrack => Some(MediaKey::Next),
+            MediaCommand::PreviousTrack => Some(MediaKey::Previous),
+            MediaCommand::Stop => Some(MediaKey::Stop),
+            _ => None,
+        }
+    }
+
+    #[cfg(windows)]
+    fn virtual_key(self) -> VIRTUAL_KEY {
+        match self {
+            MediaKey::PlayPause => VK_MEDIA_PLAY_PAUSE,
+            MediaKey::Next => VK_MEDIA_NEXT_TRACK,
+            MediaKey::Previous => VK_MEDIA_PREV_TRACK,
+            MediaKey::Stop => VK_MEDIA_STOP,
+        }
+    }
+}
+
+/// Synthesize a media key press and release with SendInput
+#[cfg(windows)]
+pub fn send_media_key(key: MediaKey) -> MediaResult<()> {
+    tracing::info!("Sending media key {:?}", key);
+    let vk = key.virtual_key();
+    let inputs = [
+        keyboard_input(vk, KEYBD_EVENT_FLAGS(0)),
+        keyboard_input(vk, KEYEVENTF_KEYUP),
+    ];
+
+    let sent = unsafe { SendInput(&inputs, std::mem::size_of::<INPUT>() as i32) };
+    if sent as usize != inputs.len() {
+        return Err(windows::core::Error::from_win32().into());
+    }
+    Ok(())
+}
+
+/// Media keys can only be synthesized on Windows
+#[cfg(not(windows))]
+pub fn send_media_key(key: MediaKey) -> MediaResult<()> {
+    Err(MediaError::unsupported(format!("Media key {:?}", key)))
+}
+
+#[cfg(windows)]
+fn keyboard_input(vk: VIRTUAL_KEY, flags: KEYBD_EVENT_FLAGS) -> INPUT {
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: vk,
+                wScan: 0,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    }
+}
+
+/// Whether a command should be sent as a media key instead of through GSMTC
+///
+/// # Arguments
+/// * `current_app` - Source app id of the current session, `Err(NoSession)` without one
+/// * `fallback` - Use media keys when no session is available
+/// * `apps` - Source app ids that are always controlled with media keys
+pub fn should_use_media_keys(
+    current_app: &MediaResult<String>,
+    fallback: bool,
+    apps: &[String],
+) -> bool {
+    match current_app {
+        Ok(app_id) => apps.iter().any(|app| app.eq_ignore_ascii_case(app_id)),
+        Err(MediaError::NoSession) => fallback,
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::MetadataConfig;
+use crate::media_manager::TrackInfo;
+use crate::utils;
+use media_controller_core::metadata::TrackMetadata;
+
+const MUSICBRAINZ_URL: &str = "https://musicbrainz.org/ws/2/recording";
+const SPOTIFY_TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+const SPOTIFY_API_URL: &str = "https://api.spotify.com/v1";
+const METADATA_CACHE_DIR: &str = "metadata";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A source of track metadata, queried in order until the metadata is complete
+pub trait MetadataProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Look up a track, `None` when the provider doesn't know it
+    fn lookup(&self, track: &TrackInfo) -> Result<Option<TrackMetadata>>;
+}
+
+/// Queries the configured providers and caches the merged results in memory
+/// and on disk.
+pub struct MetadataService {
+    providers: Vec<Box<dyn MetadataProvider>>,
+    /// Enrich every track, not only those with sparse metadata
+    always: bool,
+    cache: Mutex<HashMap<String, Option<TrackMetadata>>>,
+}
+
+impl MetadataService {
+    pub fn new(config: &MetadataConfig) -> Result<Self> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!(
+                "media-controller/",
+                env!("CARGO_PKG_VERSION"),
+                " (https://github.com/frstycodes/media-controller)"
+            ))
+            .build()?;
+
+        let mut providers: Vec<Box<dyn MetadataProvider>> = Vec::new();
+        if let (Some(client_id), Some(client_secret)) =
+            (&config.spotify_client_id, &config.spotify_client_secret)
+        {
+            providers.push(Box::new(SpotifyProvider {
+                client: client.clone(),
+                client_id: client_id.clone(),
+                client_secret: client_secret.clone(),
+                token: Mutex::new(None),
+            }));
+        }
+        providers.push(Box::new(MusicBrainzProvider { client }));
+
+        Ok(Self {
+            providers,
+            always: config.always,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Whether a track should be enriched at all
+    pub fn wants(&self, track: &TrackInfo) -> bool {
+        self.always || is_sparse(track)
+    }
+
+    /// Metadata already looked up for a track, without touching the network
+    ///
+    /// # Returns
+    /// * `Option<Option<TrackMetadata>>` - Outer `None` when the track wasn't looked up yet
+    pub fn cached(&self, track: &TrackInfo) -> Option<Option<TrackMetadata>> {
+        let key = utils::track_key(&track.artist, &track.title);
+        if let Some(cached) = self.cache.lock().ok()?.get(&key).cloned() {
+            return Some(cached);
+        }
+
+        let metadata = utils::read_json_cache::<TrackMetadata>(METADATA_CACHE_DIR, &key)?;
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(key, Some(metadata.clone()));
+        }
+        Some(Some(metadata))
+    }
+
+    /// Look up a track with every provider, from the cache when possible
+    pub fn lookup(&self, track: &TrackInfo) -> Option<TrackMetadata> {
+        if let Some(cached) = self.cached(track) {
+            return cached;
+        }
+
+        let mut metadata: Option<TrackMetadata> = None;
+        for provider in &self.providers {
+            match provider.lookup(track) {
+                Ok(Some(found)) => match metadata.as_mut() {
+                    Some(metadata) => metadata.merge(found),
+                    None => metadata = Some(found),
+                },
+                Ok(None) => {}
+                Err(e) => tracing::warn!("{} metadata lookup failed: {:#}", provider.name(), e),
+            }
+            if metadata.as_ref().is_some_and(TrackMetadata::is_complete) {
+                break;
+            }
+        }
+
+        let key = utils::track_key(&track.artist, &track.title);
+        if let Some(metadata) = &metadata {
+            utils::write_json_cache(METADATA_CACHE_DIR, &key, metadata);
+        }
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(key, metadata.clone());
+        }
+        metadata
+    }
+}
+
+/// Browsers and some players only report a title and artist
+fn is_sparse(track: &TrackInfo) -> bool {
+    track.album.as_deref().is_none_or(str::is_empty) || track.thumbnail.is_none()
+}
+
+struct MusicBrainzProvider {
+    client: reqwest::blocking::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct MusicBrainzResponse {
+    #[serde(default)]
+    recordings: Vec<MusicBrainzRecording>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct MusicBrainzRecording {
+    first_release_date: Option<String>,
+    #[serde(default)]
+    releases: Vec<MusicBrainzNamed>,
+    #[serde(default)]
+    tags: Vec<MusicBrainzNamed>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MusicBrainzNamed {
+    #[serde(alias = "title")]
+    name: String,
+}
+
+impl MetadataProvider for MusicBrainzProvider {
+    fn name(&self) -> &'static str {
+        "musicbrainz"
+    }
+
+    fn lookup(&self, track: &TrackInfo) -> Result<Option<TrackMetadata>> {
+        let query = format!(
+            "recording:\"{}\" AND artist:\"{}\"",
+            lucene_escape(&track.title),
+            lucene_escape(&track.artist)
+        );
+        let response: MusicBrainzResponse = self
+            .client
+            .get(MUSICBRAINZ_URL)
+            .query(&[("query", query.as_str()), ("fmt", "json"), ("limit", "1")])
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        let Some(recording) = response.recordings.into_iter().next() else {
+            return Ok(None);
+        };
+
+        Ok(Some(TrackMetadata {
+            album: recording.releases.into_iter().next().map(|r| r.name),
+            year: recording.first_release_date.as_deref().and_then(parse_year),
+            genres: recording.tags.into_iter().map(|tag| tag.name).collect(),
+            artist_image: None,
+            sources: vec![self.name().to_string()],
+        }))
+    }
+}
+
+struct SpotifyProvider {
+    client: reqwest::blocking::Client,
+    client_id: String,
+    client_secret: String,
+    /// Client credentials token and when it expires
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpotifyToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+impl SpotifyProvider {
+    fn access_token(&self) -> Result<String> {
+        let mut token = self
+            .token
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Spotify token lock poisoned: {}", e))?;
+        let valid = token
+            .as_ref()
+            .filter(|(_, expires_at)| Instant::now() < *expires_at);
+        if let Some((access_token, _)) = valid {
+            return Ok(access_token.clone());
+        }
+
+        let response: SpotifyToken = self
+            .client
+            .post(SPOTIFY_TOKEN_URL)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[("grant_type", "client_credentials")])
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        // Refresh a minute early so requests in flight don't fail
+        let expires_at =
+            Instant::now() + Duration::from_secs(response.expires_in.saturating_sub(60));
+        *token = Some((response.access_token.clone(), expires_at));
+        Ok(response.access_token)
+    }
+
+    fn get(&self, url: &str, query: &[(&str, &str)]) -> Result<serde_json::Value> {
+        Ok(self
+            .client
+            .get(url)
+            .bearer_auth(self.access_token()?)
+            .query(query)
+            .send()?
+            .error_for_status()?
+            .json()?)
+    }
+}
+
+impl MetadataProvider for SpotifyProvider {
+    fn name(&self) -> &'static str {
+        "spotify"
+    }
+
+    fn lookup(&self, track: &TrackInfo) -> Result<Option<TrackMetadata>> {
+        let query = format!("track:{} artist:{}", track.title, track.artist);
+        let search = self.get(
+            &format!("{}/search", SPOTIFY_API_URL),
+            &[("q", query.as_str()), ("type", "track"), ("limit", "1")],
+        )?;
+
+        let Some(found) = search.pointer("/tracks/items/0") else {
+            return Ok(None);
+        };
+
+        let mut metadata = TrackMetadata {
+            album: found
+                .pointer("/album/name")
+                .and_then(|name| name.as_str())
+                .map(String::from),
+            year: found
+                .pointer("/album/release_date")
+                .and_then(|date| date.as_str())
+                .and_then(parse_year),
+            sources: vec![self.name().to_string()],
+            ..Default::default()
+        };
+
+        // Genres and images live on the artist, not the track
+        if let Some(artist_id) = found.pointer("/artists/0/id").and_then(|id| id.as_str()) {
+            let artist = self
+                .get(&format!("{}/artists/{}", SPOTIFY_API_URL, artist_id), &[])
+                .context("Failed to fetch Spotify artist")?;
+            metadata.genres = artist
+                .get("genres")
+                .and_then(|genres| genres.as_array())
+                .map(|genres| {
+                    genres
+                        .iter()
+                        .filter_map(|genre| genre.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default();
+            metadata.artist_image = artist
+                .pointer("/images/0/url")
+                .and_then(|url| url.as_str())
+                .map(String::from);
+        }
+
+        Ok(Some(metadata))
+    }
+}
+
+/// Year of a `YYYY`, `YYYY-MM` or `YYYY-MM-DD` date
+fn parse_year(date: &str) -> Option<u32> {
+    date.get(..4)?.parse().ok()
+}
+
+fn lucene_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,456 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use tokio::sync::broadcast::error::RecvError;
+use zbus::fdo;
+use zbus::object_server::{InterfaceRef, SignalEmitter};
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+use zbus::{connection, interface};
+
+use crate::config::MprisConfig;
+use crate::events::{EventHub, MediaCommand, MediaEvent, SeekPosition, VolumeLevel};
+use crate::media_manager::{AutoRepeatMode, TrackControls, TrackInfo, TrackTimeline};
+use crate::utils::{self, ServerConfig};
+use crate::volume;
+
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+/// Prefix of the track ids, MPRIS wants a D-Bus object path per track
+const TRACK_PATH: &str = "/com/frstycodes/media_controller/track";
+
+/// Publish the controlled media as an MPRIS player on the D-Bus session bus,
+/// so GNOME and KDE media controls and `playerctl` can control it
+pub async fn run(config: MprisConfig, server: ServerConfig, hub: EventHub) -> Result<()> {
+    let bus_name = format!("org.mpris.MediaPlayer2.{}", config.name);
+    let connection = connection::Builder::session()?
+        .name(bus_name.as_str())?
+        .serve_at(
+            OBJECT_PATH,
+            Root {
+                identity: config.identity,
+            },
+        )?
+        .serve_at(
+            OBJECT_PATH,
+            Player {
+                hub: hub.clone(),
+                server,
+                state: PlayerState::default(),
+            },
+        )?
+        .build()
+        .await
+        .context("Failed to connect to the D-Bus session bus")?;
+    tracing::info!("Published MPRIS player {}", bus_name);
+
+    let player = connection
+        .object_server()
+        .interface::<_, Player>(OBJECT_PATH)
+        .await?;
+
+    // Subscribed before reading the state so no change slips in between
+    let mut receiver = hub.subscribe();
+    let reader = hub.clone();
+    let initial = tokio::task::spawn_blocking(move || reader.current_state())
+        .await
+        .unwrap_or_default();
+    for event in initial {
+        apply_event(&player, event).await;
+    }
+
+    loop {
+        match receiver.recv().await {
+            Ok(MediaEvent::ServerShutdown) | Err(RecvError::Closed) => break,
+            Ok(event) => apply_event(&player, event).await,
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("MPRIS player lagged behind by {} events", skipped);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Update the player and tell D-Bus which properties changed
+async fn apply_event(player: &InterfaceRef<Player>, event: MediaEvent) {
+    let emitter = player.signal_emitter();
+    let mut player = player.get_mut().await;
+    let result = match event {
+        MediaEvent::TrackInfo(track) => {
+            player.state.track = track;
+            player.metadata_changed(emitter).await
+        }
+        MediaEvent::TrackControls(controls) => {
+            player.state.controls = Some(controls);
+            // Every flag comes with the controls, so all of them may have changed
+            futures_util::try_join!(
+                player.playback_status_changed(emitter),
+                player.loop_status_changed(emitter),
+                player.shuffle_changed(emitter),
+                player.can_play_changed(emitter),
+                player.can_pause_changed(emitter),
+                player.can_go_next_changed(emitter),
+                player.can_go_previous_changed(emitter),
+            )
+            .map(|_| ())
+        }
+        MediaEvent::TrackTimeline(timeline) => {
+            // Position isn't signaled as changed, players only announce jumps
+            let position = micros(timeline.progress());
+            player.state.timeline = Some(timeline);
+            futures_util::try_join!(
+                Player::seeked(emitter, position),
+                player.can_seek_changed(emitter),
+                player.rate_changed(emitter),
+            )
+            .map(|_| ())
+        }
+        _ => return,
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to signal MPRIS changes: {}", e);
+    }
+}
+
+/// `org.mpris.MediaPlayer2`, the player itself rather than its media
+struct Root {
+    identity: String,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    /// There is no window to raise
+    fn raise(&self) {}
+
+    /// Stopping the server is up to whoever started it
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        self.identity.clone()
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Last state events, properties are read from them
+#[derive(Default)]
+struct PlayerState {
+    track: Option<TrackInfo>,
+    controls: Option<TrackControls>,
+    timeline: Option<TrackTimeline>,
+}
+
+/// `org.mpris.MediaPlayer2.Player`, the controlled media
+struct Player {
+    hub: EventHub,
+    /// Origin of the artwork URLs
+    server: ServerConfig,
+    state: PlayerState,
+}
+
+impl Player {
+    async fn execute(&self, command: MediaCommand) -> fdo::Result<()> {
+        let hub = self.hub.clone();
+        match tokio::task::spawn_blocking(move || hub.execute(command)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(fdo::Error::Failed(e.to_string())),
+            Err(e) => Err(fdo::Error::Failed(e.to_string())),
+        }
+    }
+
+    fn playing(&self) -> bool {
+        self.state
+            .controls
+            .as_ref()
+            .is_some_and(|controls| controls.playing())
+    }
+
+    /// Position in milliseconds, extrapolated from the last timeline while playing
+    fn position_ms(&self) -> u64 {
+        self.state
+            .timeline
+            .as_ref()
+            .map(|timeline| timeline.position_at(utils::unix_millis(), self.playing()))
+            .unwrap_or_default()
+    }
+
+    fn controls_allow(&self, allowed: impl Fn(&TrackControls) -> bool) -> bool {
+        self.state.controls.as_ref().is_some_and(allowed)
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    async fn next(&self) -> fdo::Result<()> {
+        self.execute(MediaCommand::NextTrack).await
+    }
+
+    async fn previous(&self) -> fdo::Result<()> {
+        self.execute(MediaCommand::PreviousTrack).await
+    }
+
+    async fn pause(&self) -> fdo::Result<()> {
+        self.execute(MediaCommand::Pause).await
+    }
+
+    async fn play_pause(&self) -> fdo::Result<()> {
+        self.execute(MediaCommand::TogglePlayPause(None)).await
+    }
+
+    async fn stop(&self) -> fdo::Result<()> {
+        self.execute(MediaCommand::Stop).await
+    }
+
+    async fn play(&self) -> fdo::Result<()> {
+        self.execute(MediaCommand::Play).await
+    }
+
+    /// # Arguments
+    /// * `offset` - Microseconds to move forward, negative moves back
+    async fn seek(&self, offset: i64) -> fdo::Result<()> {
+        let position = micros(self.position_ms()).saturating_add(offset).max(0);
+        self.execute(MediaCommand::Seek(SeekPosition {
+            position: millis(position),
+        }))
+        .await
+    }
+
+    /// # Arguments
+    /// * `track_id` - Ignored when it isn't the current track, as MPRIS asks
+    /// * `position` - Microseconds from the start
+    async fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> fdo::Result<()> {
+        let current = self.state.track.as_ref().map(track_id_of);
+        if current.as_deref() != Some(track_id.as_str()) || position < 0 {
+            return Ok(());
+        }
+        self.execute(MediaCommand::Seek(SeekPosition {
+            position: millis(position),
+        }))
+        .await
+    }
+
+    fn open_uri(&self, _uri: String) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(String::from(
+            "The player can't open URIs",
+        )))
+    }
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        let status = match (&self.state.track, self.playing()) {
+            (None, _) => "Stopped",
+            (Some(_), true) => "Playing",
+            (Some(_), false) => "Paused",
+        };
+        status.to_string()
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> String {
+        let mode = self
+            .state
+            .controls
+            .as_ref()
+            .map(|controls| controls.auto_repeat_mode());
+        let status = match mode {
+            Some(AutoRepeatMode::Track) => "Track",
+            Some(AutoRepeatMode::List) => "Playlist",
+            Some(AutoRepeatMode::None) | None => "None",
+        };
+        status.to_string()
+    }
+
+    #[zbus(property)]
+    async fn set_loop_status(&self, status: String) -> fdo::Result<()> {
+        let mode = match status.as_str() {
+            "Track" => AutoRepeatMode::Track,
+            "Playlist" => AutoRepeatMode::List,
+            "None" => AutoRepeatMode::None,
+            _ => return Err(fdo::Error::InvalidArgs(status)),
+        };
+        self.execute(MediaCommand::SetRepeatMode(mode)).await
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        self.state
+            .timeline
+            .as_ref()
+            .map(|timeline| timeline.playback_rate())
+            .unwrap_or(1.0)
+    }
+
+    #[zbus(property)]
+    fn set_rate(&self, _rate: f64) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(String::from(
+            "The playback rate can't be changed",
+        )))
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        self.controls_allow(|controls| controls.shuffle())
+    }
+
+    #[zbus(property)]
+    async fn set_shuffle(&self, shuffle: bool) -> fdo::Result<()> {
+        // Sessions only offer a toggle
+        if shuffle == self.shuffle() {
+            return Ok(());
+        }
+        self.execute(MediaCommand::ToggleShuffle).await
+    }
+
+    #[zbus(property)]
+    async fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let Some(track) = &self.state.track else {
+            return HashMap::new();
+        };
+        let mut metadata = HashMap::new();
+        let mut insert = |key: &str, value: Value<'_>| {
+            if let Ok(value) = OwnedValue::try_from(value) {
+                metadata.insert(key.to_string(), value);
+            }
+        };
+
+        if let Ok(path) = ObjectPath::try_from(track_id_of(track)) {
+            insert("mpris:trackid", Value::from(path));
+        }
+        insert("mpris:length", Value::from(micros(track.duration)));
+        insert("xesam:title", Value::from(track.title.as_str()));
+        insert("xesam:artist", Value::from(vec![track.artist.as_str()]));
+        if let Some(album) = &track.album {
+            insert("xesam:album", Value::from(album.as_str()));
+        }
+        if let Some(album_artist) = &track.album_artist {
+            insert(
+                "xesam:albumArtist",
+                Value::from(vec![album_artist.as_str()]),
+            );
+        }
+        if !track.genres.is_empty() {
+            insert("xesam:genre", Value::from(track.genres.clone()));
+        }
+        if let Some(number) = track.track_number {
+            insert("xesam:trackNumber", Value::from(number as i32));
+        }
+        if let Some(thumbnails) = &track.thumbnails {
+            let url = format!(
+                "{}{}{}",
+                self.server.get_url().await,
+                self.server.base_path,
+                thumbnails.medium
+            );
+            insert("mpris:artUrl", Value::from(url));
+        }
+        metadata
+    }
+
+    #[zbus(property)]
+    async fn volume(&self) -> f64 {
+        tokio::task::spawn_blocking(volume::system_volume)
+            .await
+            .ok()
+            .and_then(|level| level.ok())
+            .map(f64::from)
+            .unwrap_or(1.0)
+    }
+
+    #[zbus(property)]
+    async fn set_volume(&self, level: f64) -> fdo::Result<()> {
+        self.execute(MediaCommand::SetVolume(VolumeLevel {
+            level: level.clamp(0.0, 1.0) as f32,
+        }))
+        .await
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        micros(self.position_ms())
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        self.controls_allow(|controls| controls.next_enabled())
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        self.controls_allow(|controls| controls.prev_enabled())
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        self.controls_allow(|controls| controls.play_pause_enabled())
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        self.controls_allow(|controls| controls.play_pause_enabled())
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        self.state
+            .timeline
+            .as_ref()
+            .is_some_and(|timeline| timeline.seek_enabled())
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+/// Object path identifying a track, the same for every read of it
+fn track_id_of(track: &TrackInfo) -> String {
+    let key = format!(
+        "{}\n{}\n{}",
+        track.session.source_app_id, track.title, track.artist
+    );
+    format!("{}/{}", TRACK_PATH, utils::content_hash(key.as_bytes()))
+}
+
+fn micros(millis: u64) -> i64 {
+    i64::try_from(millis.saturating_mul(1000)).unwrap_or(i64::MAX)
+}
+
+fn millis(micros: i64) -> u64 {
+    u64::try_from(micros / 1000).unwrap_or_default()
+}
@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::MqttConfig;
+use crate::events::{
+    CommandError, EventHub, Fade, MediaCommand, MediaEvent, SeekPosition, VolumeLevel,
+};
+use crate::home_assistant::{self, OFFLINE_PAYLOAD, ONLINE_PAYLOAD};
+use crate::media_manager::AutoRepeatMode;
+use crate::scheduler::SleepTimerRequest;
+use crate::templates::Template;
+use crate::timings;
+use crate::volume::{self, VolumeTarget};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Payload templates by topic relative to the prefix
+type Templates = HashMap<String, Template>;
+
+// Topics relative to the configured prefix
+const SESSION_TOPIC: &str = "session";
+const TRACK_TOPIC: &str = "track";
+const STATE_TOPIC: &str = "state";
+const POSITION_TOPIC: &str = "position";
+const VOLUME_TOPIC: &str = "volume";
+const LYRICS_TOPIC: &str = "lyrics";
+const QUEUE_TOPIC: &str = "queue";
+const SPOTIFY_DEVICES_TOPIC: &str = "spotify/devices";
+const SLEEP_TIMER_TOPIC: &str = "sleep_timer";
+const INACTIVITY_TOPIC: &str = "inactivity";
+const SCHEDULES_TOPIC: &str = "schedules";
+const BOOKMARKS_TOPIC: &str = "bookmarks";
+const POSITION_ALARMS_TOPIC: &str = "position_alarms";
+const POSITION_ALARM_TOPIC: &str = "position_alarm";
+const QUIET_HOURS_TOPIC: &str = "quiet_hours";
+const LOCK_TOPIC: &str = "lock";
+const OUTPUTS_TOPIC: &str = "outputs";
+const SKIPPED_TOPIC: &str = "skipped";
+const CONFLICT_TOPIC: &str = "conflict";
+const OUTPUT_DEVICE_TOPIC: &str = "output_device";
+const ERROR_TOPIC: &str = "error";
+const COMMAND_TOPIC: &str = "cmd";
+
+/// Interval between reads of the system volume, which the hub has no events for
+const VOLUME_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Bridge the event hub to an MQTT broker.
+///
+/// Track info, playback state, timeline and system volume are published as retained
+/// topics and commands are received on `<prefix>/cmd/<command>`.
+pub async fn run(config: MqttConfig, hub: EventHub) -> Result<()> {
+    let mut options = connect_options(&config, &config.client_id)?;
+    options.set_last_will(LastWill::new(
+        home_assistant::availability_topic(&config),
+        OFFLINE_PAYLOAD,
+        QoS::AtLeastOnce,
+        true,
+    ));
+
+    let (client, mut eventloop) = AsyncClient::new(options, 32);
+    let prefix = config.topic_prefix.trim_end_matches('/').to_string();
+
+    let templates = config
+        .templates
+        .iter()
+        .map(|(topic, source)| Template::new(source).map(|template| (topic.clone(), template)))
+        .collect::<Result<HashMap<_, _>>>()?;
+    let templates = Arc::new(templates);
+
+    tokio::spawn(publish_events(
+        client.clone(),
+        hub.clone(),
+        prefix.clone(),
+        Arc::clone(&templates),
+    ));
+    tokio::spawn(publish_volume(client.clone(), prefix.clone()));
+
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                tracing::info!("Connected to MQTT broker {}", config.broker_url);
+                let topic = format!("{}/{}/#", prefix, COMMAND_TOPIC);
+                if let Err(e) = client.subscribe(topic, QoS::AtLeastOnce).await {
+                    tracing::error!("Failed to subscribe to MQTT commands: {}", e);
+                }
+
+                let availability = home_assistant::availability_topic(&config);
+                if let Err(e) = client
+                    .publish(availability, QoS::AtLeastOnce, true, ONLINE_PAYLOAD)
+                    .await
+                {
+                    tracing::error!("Failed to publish MQTT availability: {}", e);
+                }
+
+                if config.discovery {
+                    home_assistant::publish_discovery(&client, &config).await;
+                }
+                publish_current_state(&client, &hub, &prefix, &templates).await;
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let (topic, payload) = (&publish.topic, &publish.payload);
+                handle_command(&client, &hub, &prefix, &templates, topic, payload).await;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("MQTT connection error: {}", e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Broker URL, credentials and keep alive of the `[mqtt]` config
+///
+/// # Arguments
+/// * `client_id` - Has to differ between connections to the same broker
+pub fn connect_options(config: &MqttConfig, client_id: &str) -> Result<MqttOptions> {
+    let separator = if config.broker_url.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    let url = format!("{}{}client_id={}", config.broker_url, separator, client_id);
+
+    let mut options = MqttOptions::parse_url(url)?;
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+    Ok(options)
+}
+
+async fn publish_events(
+    client: AsyncClient,
+    hub: EventHub,
+    prefix: String,
+    templates: Arc<Templates>,
+) {
+    let mut receiver = hub.subscribe();
+    loop {
+        match receiver.recv().await {
+            Ok(event) => publish_event(&client, &prefix, &templates, &event).await,
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("MQTT bridge lagged behind by {} events", skipped);
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Publish the system volume whenever it changed since the last read
+async fn publish_volume(client: AsyncClient, prefix: String) {
+    let topic = format!("{}/{}", prefix, VOLUME_TOPIC);
+    let mut published = None;
+    let mut interval = tokio::time::interval(VOLUME_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let level = match tokio::task::spawn_blocking(volume::system_volume).await {
+            Ok(Ok(level)) => level,
+            Ok(Err(e)) => {
+                tracing::debug!("Failed to read the system volume: {}", e);
+                continue;
+            }
+            Err(e) => {
+                tracing::error!("System volume task failed: {}", e);
+                continue;
+            }
+        };
+
+        // Whole percents, so rounding noise of the scalar isn't republished
+        let level = (level * 100.0).round() / 100.0;
+        if published == Some(level) {
+            continue;
+        }
+        match client
+            .publish(&topic, QoS::AtLeastOnce, true, level.to_string())
+            .await
+        {
+            Ok(()) => published = Some(level),
+            Err(e) => tracing::error!("Failed to publish to {}: {}", topic, e),
+        }
+    }
+}
+
+async fn publish_current_state(
+    client: &AsyncClient,
+    hub: &EventHub,
+    prefix: &str,
+    templates: &Templates,
+) {
+    let hub = hub.clone();
+    let events = tokio::task::spawn_blocking(move || hub.current_state())
+        .await
+        .unwrap_or_default();
+
+    for event in events {
+        publish_event(client, prefix, templates, &event).await;
+    }
+}
+
+async fn publish_event(
+    client: &AsyncClient,
+    prefix: &str,
+    templates: &Templates,
+    event: &MediaEvent,
+) {
+    let mut payload = event.payload();
+    let topic = match event {
+        MediaEvent::SessionState(_) => SESSION_TOPIC,
+        MediaEvent::TrackInfo(_) => {
+            // The base64 artwork is far too large for most brokers and consumers
+            match payload.as_object_mut() {
+                Some(track) => {
+                    track.remove("thumbnail");
+                }
+                None => payload = serde_json::json!({}),
+            }
+            TRACK_TOPIC
+        }
+        MediaEvent::TrackControls(_) => STATE_TOPIC,
+        MediaEvent::TrackTimeline(_) => POSITION_TOPIC,
+        MediaEvent::Lyrics(_) => LYRICS_TOPIC,
+        MediaEvent::Queue(_) => QUEUE_TOPIC,
+        MediaEvent::SpotifyDevices(_) => SPOTIFY_DEVICES_TOPIC,
+        MediaEvent::SleepTimerStatus(_) => SLEEP_TIMER_TOPIC,
+        MediaEvent::InactivityStatus(_) => INACTIVITY_TOPIC,
+        MediaEvent::Schedules(_) => SCHEDULES_TOPIC,
+        MediaEvent::Bookmarks(_) => BOOKMARKS_TOPIC,
+        MediaEvent::PositionAlarms(_) => POSITION_ALARMS_TOPIC,
+        MediaEvent::QuietHoursChanged(_) => QUIET_HOURS_TOPIC,
+        MediaEvent::LockChanged(_) => LOCK_TOPIC,
+        MediaEvent::Outputs(_) => OUTPUTS_TOPIC,
+        // Retained topics already hold the whole state
+        MediaEvent::StateSnapshot(_) | MediaEvent::Hello(_) => return,
+        // Every session's state would be too large for a single topic
+        MediaEvent::Sessions(_) => return,
+        // Artwork is left out of the track topic
+        MediaEvent::ThumbnailUpdated(_) => return,
+        // Plugins talk to Socket.IO and WebSocket clients
+        MediaEvent::PluginEvent(_) => return,
+        // Each peer publishes its own state to its broker
+        MediaEvent::Peers(_) | MediaEvent::PeerEvent(_) => return,
+        // The broker publishes the last will once the connection drops
+        MediaEvent::ServerShutdown => return,
+        // Followed by the state events, which update the retained topics
+        MediaEvent::Resync => return,
+        MediaEvent::TrackSkipped(_) => SKIPPED_TOPIC,
+        MediaEvent::PositionAlarm(_) => POSITION_ALARM_TOPIC,
+        MediaEvent::SessionConflictResolved(_) => CONFLICT_TOPIC,
+        MediaEvent::OutputDeviceChanged(_) => OUTPUT_DEVICE_TOPIC,
+        MediaEvent::Error(_) => ERROR_TOPIC,
+    };
+    // Errors, skips, conflicts and alarms are one-off notifications, everything else is
+    // current state
+    let retain = !matches!(
+        event,
+        MediaEvent::Error(_)
+            | MediaEvent::TrackSkipped(_)
+            | MediaEvent::SessionConflictResolved(_)
+            | MediaEvent::PositionAlarm(_)
+    );
+
+    let payload = match templates.get(topic) {
+        Some(template) => match template.render(&payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!("Failed to render the MQTT {} payload: {:#}", topic, e);
+                return;
+            }
+        },
+        None => payload.to_string(),
+    };
+    let topic = format!("{}/{}", prefix, topic);
+    if let Err(e) = client
+        .publish(&topic, QoS::AtLeastOnce, retain, payload)
+        .await
+    {
+        tracing::error!("Failed to publish to {}: {}", topic, e);
+    }
+}
+
+/// Map a `<prefix>/cmd/<command>` message onto a media command and run it
+async fn handle_command(
+    client: &AsyncClient,
+    hub: &EventHub,
+    prefix: &str,
+    templates: &Templates,
+    topic: &str,
+    payload: &[u8],
+) {
+    let Some(name) = topic
+        .strip_prefix(prefix)
+        .and_then(|topic| topic.strip_prefix('/'))
+        .and_then(|topic| topic.strip_prefix(COMMAND_TOPIC))
+        .and_then(|topic| topic.strip_prefix('/'))
+    else {
+        return;
+    };
+
+    let payload = String::from_utf8_lossy(payload);
+    let payload = payload.trim();
+
+    let command = match name {
+        "play_pause" => {
+            // An optional payload is the fade length in milliseconds
+            let fade = payload.parse().ok().map(|fade_ms| Fade {
+                fade_ms,
+                target: VolumeTarget::System,
+            });
+            MediaCommand::TogglePlayPause(fade)
+        }
+        "play" => MediaCommand::Play,
+        "pause" => MediaCommand::Pause,
+        "stop" => MediaCommand::Stop,
+        "fast_forward" => MediaCommand::FastForward,
+        "rewind" => MediaCommand::Rewind,
+        "next" => MediaCommand::NextTrack,
+        "previous" => MediaCommand::PreviousTrack,
+        "shuffle" => MediaCommand::ToggleShuffle,
+        "repeat" => match AutoRepeatMode::from_str(payload) {
+            Ok(mode) => MediaCommand::SetRepeatMode(mode),
+            Err(e) => {
+                tracing::error!("Invalid MQTT repeat mode {}: {}", payload, e);
+                return;
+            }
+        },
+        "sleep_timer" => match payload.parse::<u64>() {
+            Ok(0) => MediaCommand::CancelSleepTimer,
+            Ok(minutes) => MediaCommand::SetSleepTimer(SleepTimerRequest {
+                minutes: Some(minutes),
+                at: None,
+                fade_seconds: None,
+            }),
+            Err(e) => {
+                tracing::error!("Invalid MQTT sleep timer {}: {}", payload, e);
+                return;
+            }
+        },
+        "seek" => match payload.parse::<u64>() {
+            Ok(position) => MediaCommand::Seek(SeekPosition { position }),
+            Err(e) => {
+                tracing::error!("Invalid MQTT seek position {}: {}", payload, e);
+                return;
+            }
+        },
+        "volume" => match payload.parse::<f32>() {
+            Ok(level) => MediaCommand::SetVolume(VolumeLevel { level }),
+            Err(e) => {
+                tracing::error!("Invalid MQTT volume {}: {}", payload, e);
+                return;
+            }
+        },
+        _ => {
+            tracing::warn!("Unknown MQTT command: {}", name);
+            return;
+        }
+    };
+
+    let command_name = command.name();
+    let hub_for_command = hub.clone();
+    let span = timings::command_span("mqtt", command_name);
+    let result =
+        tokio::task::spawn_blocking(move || span.in_scope(|| hub_for_command.execute(command)));
+    match result.await {
+        Ok(Err(e)) => {
+            tracing::error!("Failed to execute MQTT command {}: {}", name, e);
+            let event = MediaEvent::Error(CommandError::new(command_name, e));
+            publish_event(client, prefix, templates, &event).await;
+        }
+        Err(e) => tracing::error!("MQTT command task for {} failed: {}", name, e),
+        Ok(Ok(_)) => {}
+    }
+}
@@ -0,0 +1,199 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use media_controller_core::utils::{content_hash, decode_base64_image, image_content_type};
+use tokio::sync::broadcast::error::RecvError;
+use windows::Data::Xml::Dom::XmlDocument;
+use windows::Foundation::TypedEventHandler;
+use windows::UI::Notifications::{
+    ToastActivatedEventArgs, ToastNotification, ToastNotificationManager, ToastNotifier,
+};
+use windows::core::{HSTRING, Interface};
+
+use crate::config::NotificationsConfig;
+use crate::events::{EventHub, MediaCommand, MediaEvent};
+use crate::media_manager::TrackInfo;
+use crate::templates::Template;
+
+/// Toasts of unpackaged apps need a registered App User Model ID, PowerShell's is always there
+const DEFAULT_APP_ID: &str =
+    "{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\\WindowsPowerShell\\v1.0\\powershell.exe";
+/// Every toast replaces the previous one instead of piling up in the action center
+const TOAST_TAG: &str = "now-playing";
+const TOAST_GROUP: &str = "media-controller";
+
+const TOGGLE_ACTION: &str = "toggle_play_pause";
+const NEXT_ACTION: &str = "next_track";
+
+/// Shows a toast for every new track
+struct Notifier {
+    notifier: ToastNotifier,
+    hub: EventHub,
+    /// Templates of the two lines, the title and artist without
+    title: Option<Template>,
+    body: Option<Template>,
+    /// Kept alive so its buttons still reach the activation handler
+    current: Mutex<Option<ToastNotification>>,
+    /// Artwork file of the current toast, removed once it is replaced
+    artwork: Mutex<Option<PathBuf>>,
+}
+
+/// Mirror track changes as native toast notifications with play/pause and next buttons
+pub async fn run(config: NotificationsConfig, hub: EventHub) -> Result<()> {
+    let app_id = config.app_id.as_deref().unwrap_or(DEFAULT_APP_ID);
+    let notifier = ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(app_id))?;
+    let notifier = Arc::new(Notifier {
+        notifier,
+        hub: hub.clone(),
+        title: Template::optional(config.title.as_deref())?,
+        body: Template::optional(config.body.as_deref())?,
+        current: Mutex::new(None),
+        artwork: Mutex::new(None),
+    });
+    tracing::info!("Showing track changes as notifications");
+
+    let mut receiver = hub.subscribe();
+    let mut shown = None;
+    loop {
+        let track = match receiver.recv().await {
+            Ok(MediaEvent::TrackInfo(Some(track))) => track,
+            Ok(_) => continue,
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("Notifications lagged behind by {} events", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+        // Track info is sent again when metadata or artwork arrive for the same track
+        let key = (
+            track.session.session_id.clone(),
+            track.title.clone(),
+            track.artist.clone(),
+        );
+        if track.stale || shown.as_ref() == Some(&key) {
+            continue;
+        }
+        shown = Some(key);
+
+        let notifier = Arc::clone(&notifier);
+        let result = tokio::task::spawn_blocking(move || notifier.show(&track)).await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!("Failed to show notification: {:#}", e),
+            Err(e) => tracing::error!("Notification task failed: {}", e),
+        }
+    }
+}
+
+impl Notifier {
+    fn show(&self, track: &TrackInfo) -> Result<()> {
+        let title = match &self.title {
+            Some(template) => template.render(track)?,
+            None => track.title.clone(),
+        };
+        let body = match &self.body {
+            Some(template) => template.render(track)?,
+            None => track.artist.clone(),
+        };
+        let artwork = self.save_artwork(track);
+        let document = XmlDocument::new()?;
+        let xml = toast_xml(track, &title, &body, artwork.as_ref());
+        document.LoadXml(&HSTRING::from(xml))?;
+
+        let toast = ToastNotification::CreateToastNotification(&document)?;
+        toast.SetTag(&HSTRING::from(TOAST_TAG))?;
+        toast.SetGroup(&HSTRING::from(TOAST_GROUP))?;
+        let hub = self.hub.clone();
+        toast.Activated(&TypedEventHandler::new(move |_, args| {
+            if let Some(args) = args.as_ref() {
+                let action = args.cast::<ToastActivatedEventArgs>()?.Arguments()?;
+                activate(&hub, &action.to_string());
+            }
+            windows::core::Result::Ok(())
+        }))?;
+        self.notifier.Show(&toast)?;
+
+        if let Ok(mut current) = self.current.lock() {
+            *current = Some(toast);
+        }
+        if let Ok(mut saved) = self.artwork.lock() {
+            let previous = std::mem::replace(&mut *saved, artwork);
+            // Tracks of the same album share the file
+            if let Some(path) = previous.filter(|path| Some(path) != saved.as_ref()) {
+                std::fs::remove_file(path).ok();
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the artwork to a file, toasts of desktop apps can only show local images
+    fn save_artwork(&self, track: &TrackInfo) -> Option<PathBuf> {
+        let bytes = track.thumbnail.as_deref().and_then(decode_base64_image)?;
+        // Named by content, the notification platform caches images by path
+        let extension = image_content_type(&bytes).trim_start_matches("image/");
+        let path = std::env::temp_dir().join(format!(
+            "media-controller-{}.{}",
+            content_hash(&bytes),
+            extension
+        ));
+        match std::fs::write(&path, bytes) {
+            Ok(()) => Some(path),
+            Err(e) => {
+                tracing::warn!("Failed to save notification artwork: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Run the command of a toast button
+fn activate(hub: &EventHub, action: &str) {
+    let command = match action {
+        TOGGLE_ACTION => MediaCommand::TogglePlayPause(None),
+        NEXT_ACTION => MediaCommand::NextTrack,
+        // A click on the toast itself
+        _ => return,
+    };
+    if let Err(e) = hub.execute(command) {
+        tracing::error!("Notification {} failed: {}", action, e);
+    }
+}
+
+fn toast_xml(track: &TrackInfo, title: &str, body: &str, artwork: Option<&PathBuf>) -> String {
+    let image = artwork
+        .map(|path| {
+            format!(
+                r#"<image placement="appLogoOverride" src="{}"/>"#,
+                escape_xml(&path.display().to_string())
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        r#"<toast activationType="foreground">
+  <visual>
+    <binding template="ToastGeneric">
+      <text>{title}</text>
+      <text>{body}</text>
+      <text placement="attribution">{app}</text>
+      {image}
+    </binding>
+  </visual>
+  <audio silent="true"/>
+  <actions>
+    <action content="Play/Pause" arguments="{TOGGLE_ACTION}" activationType="foreground"/>
+    <action content="Next" arguments="{NEXT_ACTION}" activationType="foreground"/>
+  </actions>
+</toast>"#,
+        title = escape_xml(title),
+        body = escape_xml(body),
+        app = escape_xml(&track.session.app_name),
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
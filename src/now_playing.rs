@@ -0,0 +1,160 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use image::{DynamicImage, ImageFormat};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::NowPlayingConfig;
+use crate::events::{EventHub, MediaEvent};
+use crate::media_manager::TrackInfo;
+use crate::templates::Template;
+
+/// Keep now-playing files up to date for OBS text and image sources and scripts
+pub async fn run(config: NowPlayingConfig, hub: EventHub) -> Result<()> {
+    tracing::info!("Writing the current track to files");
+    let mut writer = Writer {
+        template: Template::new(&config.template)?,
+        config,
+        text: None,
+        json: None,
+        track: None,
+    };
+
+    let mut receiver = hub.subscribe();
+    // The current track first, it may have changed long before the server started
+    let reader = hub.clone();
+    let events = tokio::task::spawn_blocking(move || reader.current_state())
+        .await
+        .unwrap_or_default();
+    for event in events {
+        writer.handle(&hub, &event).await;
+    }
+    loop {
+        match receiver.recv().await {
+            Ok(MediaEvent::ServerShutdown) | Err(RecvError::Closed) => return Ok(()),
+            Ok(event) => writer.handle(&hub, &event).await,
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("Now-playing files lagged behind by {} events", skipped);
+            }
+        }
+    }
+}
+
+struct Writer {
+    config: NowPlayingConfig,
+    template: Template,
+    /// Last written contents, track info is sent again for every metadata update
+    text: Option<String>,
+    json: Option<String>,
+    /// Source app, title and artist of the track the artwork was written for
+    track: Option<(String, String, String)>,
+}
+
+impl Writer {
+    async fn handle(&mut self, hub: &EventHub, event: &MediaEvent) {
+        let result = match event {
+            MediaEvent::TrackInfo(track) => self.write_track(hub, track.as_ref()).await,
+            MediaEvent::ThumbnailUpdated(_) => self.write_artwork(hub, true).await,
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to write the now-playing files: {:#}", e);
+        }
+    }
+
+    async fn write_track(&mut self, hub: &EventHub, track: Option<&TrackInfo>) -> Result<()> {
+        // Templates see the same fields as scripts reading the JSON file
+        let mut value = serde_json::to_value(track)?;
+        if let Some(track) = value.as_object_mut() {
+            // Scripts can read the artwork file instead
+            track.remove("thumbnail");
+            track.remove("thumbnails");
+        }
+
+        if let Some(path) = &self.config.text {
+            let text = match track {
+                Some(_) => self.template.render(&value)?,
+                None => String::new(),
+            };
+            if self.text.as_ref() != Some(&text) {
+                replace(path, text.as_bytes())?;
+                self.text = Some(text);
+            }
+        }
+
+        if let Some(path) = &self.config.json {
+            let json = serde_json::to_string_pretty(&value)?;
+            if self.json.as_ref() != Some(&json) {
+                replace(path, json.as_bytes())?;
+                self.json = Some(json);
+            }
+        }
+
+        let key = track.map(|track| {
+            (
+                track.session.source_app_id.clone(),
+                track.title.clone(),
+                track.artist.clone(),
+            )
+        });
+        let changed = self.track != key;
+        self.track = key;
+        self.write_artwork(hub, changed).await
+    }
+
+    /// Write the artwork of the current track, or remove the file without one
+    ///
+    /// # Arguments
+    /// * `changed` - The track or its artwork changed since the last write
+    async fn write_artwork(&mut self, hub: &EventHub, changed: bool) -> Result<()> {
+        let Some(path) = self.config.artwork.clone() else {
+            return Ok(());
+        };
+        if !changed {
+            return Ok(());
+        }
+        if self.track.is_none() {
+            return remove(&path);
+        }
+
+        let hub = hub.clone();
+        let bytes =
+            tokio::task::spawn_blocking(move || hub.artwork().ok().or_else(|| hub.placeholder()))
+                .await?;
+        match bytes {
+            Some(bytes) => tokio::task::spawn_blocking(move || write_image(&path, &bytes)).await?,
+            None => remove(&path),
+        }
+    }
+}
+
+/// Convert the artwork to the format of the file extension, e.g. JPEG for `cover.jpg`
+fn write_image(path: &Path, bytes: &[u8]) -> Result<()> {
+    let format = ImageFormat::from_path(path)
+        .with_context(|| format!("No image format for {}", path.display()))?;
+    // JPEG has no alpha channel
+    let image = DynamicImage::ImageRgb8(image::load_from_memory(bytes)?.to_rgb8());
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut encoded, format)?;
+    replace(path, encoded.get_ref())
+}
+
+/// Write to a temporary file next to the target and rename it over the target, so
+/// readers never see a half-written file
+fn replace(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut temporary = PathBuf::from(path);
+    temporary.as_mut_os_string().push(".tmp");
+    std::fs::write(&temporary, contents)
+        .with_context(|| format!("Failed to write {}", temporary.display()))?;
+    std::fs::rename(&temporary, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+fn remove(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+#[cfg(windows)]
+use tokio::sync::mpsc::{self, UnboundedSender};
+#[cfg(windows)]
+use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
+#[cfg(windows)]
+use windows::Win32::Foundation::PROPERTYKEY;
+#[cfg(windows)]
+use windows::Win32::Media::Audio::{
+    DEVICE_STATE, DEVICE_STATE_ACTIVE, EDataFlow, ERole, IMMDevice, IMMDeviceEnumerator,
+    IMMNotificationClient, IMMNotificationClient_Impl, MMDeviceEnumerator, eConsole, eRender,
+};
+#[cfg(windows)]
+use windows::Win32::System::Com::{
+    CLSCTX_ALL, COINIT_MULTITHREADED, CoCreateInstance, CoInitializeEx, CoTaskMemFree, STGM_READ,
+};
+#[cfg(windows)]
+use windows::core::{HSTRING, PCWSTR, implement};
+
+use crate::config::OutputDevicesConfig;
+use crate::events::EventHub;
+#[cfg(windows)]
+use crate::events::MediaCommand;
+
+/// Payload of the `output_device_changed` event
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct OutputDeviceChange {
+    /// Friendly name of the new default output, `None` when there is none left
+    pub device: Option<String>,
+    /// Friendly name of the default output before
+    pub previous: Option<String>,
+    /// The previous output is gone, e.g. headphones were turned off
+    pub disconnected: bool,
+    /// Playback was paused because the previous output is gone
+    pub paused: bool,
+}
+
+/// A render endpoint as far as the watcher cares
+#[cfg(windows)]
+#[derive(Debug, Clone)]
+struct Endpoint {
+    id: String,
+    name: String,
+}
+
+/// Receives the endpoint changes of Windows on a COM thread
+#[cfg(windows)]
+#[implement(IMMNotificationClient)]
+struct EndpointWatcher {
+    /// Id of the new default output, `None` without one
+    changes: UnboundedSender<Option<String>>,
+}
+
+#[cfg(windows)]
+impl IMMNotificationClient_Impl for EndpointWatcher_Impl {
+    fn OnDeviceStateChanged(&self, _: &PCWSTR, _: DEVICE_STATE) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDeviceAdded(&self, _: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDeviceRemoved(&self, _: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDefaultDeviceChanged(
+        &self,
+        flow: EDataFlow,
+        role: ERole,
+        id: &PCWSTR,
+    ) -> windows::core::Result<()> {
+        // Every role reports its own change, the console one is what apps play to
+        if flow == eRender && role == eConsole {
+            let id = if id.is_null() {
+                None
+            } else {
+                unsafe { id.to_string() }.ok()
+            };
+            self.changes.send(id).ok();
+        }
+        Ok(())
+    }
+
+    fn OnPropertyValueChanged(&self, _: &PCWSTR, _: &PROPERTYKEY) -> windows::core::Result<()> {
+        Ok(())
+    }
+}
+
+/// Follow the default output device, telling clients about every change and pausing
+/// when a configured device disappears
+#[cfg(windows)]
+pub async fn run(config: OutputDevicesConfig, hub: EventHub) -> Result<()> {
+    let (sender, mut changes) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        if let Err(e) = watch_endpoints(sender) {
+            tracing::error!("Failed to watch the output devices: {}", e);
+        }
+    });
+
+    let mut current = tokio::task::spawn_blocking(|| default_endpoint().ok())
+        .await
+        .unwrap_or_default();
+    tracing::info!(
+        "Watching the output devices, default is {}",
+        current
+            .as_ref()
+            .map_or("none", |endpoint| endpoint.name.as_str())
+    );
+
+    while let Some(id) = changes.recv().await {
+        let previous = current.take();
+        let (config, hub) = (config.clone(), hub.clone());
+        let result = tokio::task::spawn_blocking(move || {
+            let device = id.and_then(|id| endpoint(&id).ok());
+            let change = device_changed(&config, &hub, previous.as_ref(), device.as_ref());
+            hub.publish_output_device(change);
+            device
+        })
+        .await;
+        match result {
+            Ok(device) => current = device,
+            Err(e) => tracing::error!("Output device task failed: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Only the output devices of Windows are followed
+#[cfg(not(windows))]
+pub async fn run(_config: OutputDevicesConfig, _hub: EventHub) -> Result<()> {
+    anyhow::bail!("Output devices are only watched on Windows")
+}
+
+/// Pause when the previous default is gone and configured to pause
+#[cfg(windows)]
+fn device_changed(
+    config: &OutputDevicesConfig,
+    hub: &EventHub,
+    previous: Option<&Endpoint>,
+    device: Option<&Endpoint>,
+) -> OutputDeviceChange {
+    // Switching outputs by hand leaves the old one active
+    let disconnected = previous.is_some_and(|previous| !is_active(&previous.id));
+    let pause = disconnected && previous.is_some_and(|previous| config.pauses_for(&previous.name));
+
+    let mut paused = false;
+    if pause {
+        let playing = hub.media().call(|manager| manager.is_playing());
+        if matches!(playing, Ok(Ok(true))) {
+            match hub.execute(MediaCommand::Pause) {
+                Ok(_) => paused = true,
+                Err(e) => tracing::error!("Failed to pause for the lost output: {}", e),
+            }
+        }
+    }
+    let change = OutputDeviceChange {
+        device: device.map(|device| device.name.clone()),
+        previous: previous.map(|previous| previous.name.clone()),
+        disconnected,
+        paused,
+    };
+    tracing::info!(
+        "Default output changed from {:?} to {:?}, paused: {}",
+        change.previous,
+        change.device,
+        change.paused
+    );
+    change
+}
+
+/// Register for endpoint changes and keep the registration alive, blocking for good
+#[cfg(windows)]
+fn watch_endpoints(changes: UnboundedSender<Option<String>>) -> Result<()> {
+    let enumerator = enumerator()?;
+    let client: IMMNotificationClient = EndpointWatcher { changes }.into();
+    unsafe { enumerator.RegisterEndpointNotificationCallback(&client)? };
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(windows)]
+fn enumerator() -> Result<IMMDeviceEnumerator> {
+    unsafe {
+        // Fails harmlessly when the thread is already initialized
+        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+        Ok(CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?)
+    }
+}
+
+#[cfg(windows)]
+fn default_endpoint() -> Result<Endpoint> {
+    let device = unsafe { enumerator()?.GetDefaultAudioEndpoint(eRender, eConsole)? };
+    let id = unsafe {
+        let id = device.GetId()?;
+        let string = id.to_string();
+        CoTaskMemFree(Some(id.0 as _));
+        string?
+    };
+    Ok(Endpoint {
+        name: friendly_name(&device)?,
+        id,
+    })
+}
+
+#[cfg(windows)]
+fn endpoint(id: &str) -> Result<Endpoint> {
+    let device = unsafe { enumerator()?.GetDevice(&HSTRING::from(id))? };
+    Ok(Endpoint {
+        id: id.to_string(),
+        name: friendly_name(&device)?,
+    })
+}
+
+/// Whether a device is still there and enabled
+#[cfg(windows)]
+fn is_active(id: &str) -> bool {
+    device_state(id).is_ok_and(|state| state == DEVICE_STATE_ACTIVE)
+}
+
+#[cfg(windows)]
+fn device_state(id: &str) -> Result<DEVICE_STATE> {
+    Ok(unsafe { enumerator()?.GetDevice(&HSTRING::from(id))?.GetState()? })
+}
+
+/// Name shown in the sound settings, e.g. `Headphones (WH-1000XM4)`
+#[cfg(windows)]
+fn friendly_name(device: &IMMDevice) -> Result<String> {
+    unsafe {
+        let store = device.OpenPropertyStore(STGM_READ)?;
+        Ok(store.GetValue(&PKEY_Device_FriendlyName)?.to_string())
+    }
+}
@@ -0,0 +1,46 @@
+use axum::{Router, extract::Query, response::Html, routing::get};
+use serde::Deserialize;
+
+const OVERLAY_HTML: &str = include_str!("overlay.html");
+
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum OverlayTheme {
+    #[default]
+    Dark,
+    Light,
+}
+
+#[derive(Debug, Deserialize)]
+struct OverlayParams {
+    #[serde(default)]
+    theme: OverlayTheme,
+    /// Fixed accent color as `rrggbb` hex, overrides the color extracted from the artwork
+    accent: Option<String>,
+}
+
+/// Routes for the OBS/browser-source now-playing overlay, fed by `/api/events`
+pub fn router() -> Router {
+    Router::new().route("/overlay", get(overlay_handler))
+}
+
+async fn overlay_handler(Query(params): Query<OverlayParams>) -> Html<String> {
+    let (theme, background, foreground) = match params.theme {
+        OverlayTheme::Dark => ("dark", "rgba(0, 0, 0, 0.6)", "#ffffff"),
+        OverlayTheme::Light => ("light", "rgba(255, 255, 255, 0.8)", "#111111"),
+    };
+    // Only accept plain hex so the value can be inlined into the page safely
+    let accent = params
+        .accent
+        .filter(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|hex| format!("\"#{}\"", hex))
+        .unwrap_or_else(|| String::from("null"));
+
+    let html = OVERLAY_HTML
+        .replace("{{THEME}}", theme)
+        .replace("{{BACKGROUND}}", background)
+        .replace("{{FOREGROUND}}", foreground)
+        .replace("{{ACCENT}}", &accent);
+
+    Html(html)
+}
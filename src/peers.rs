@@ -0,0 +1,261 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
+
+use futures_util::FutureExt;
+use futures_util::future::join_all;
+use rust_socketio::asynchronous::{Client, ClientBuilder};
+use rust_socketio::{Event, Payload};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::runtime::Handle;
+
+use crate::config::PeerConfig;
+use crate::error::{MediaError, MediaResult};
+use crate::events::EventHub;
+
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Events of a peer that are relayed to local clients, the state a UI needs to
+/// show what the other machine plays
+const RELAYED_EVENTS: [&str; 5] = [
+    "state_snapshot",
+    "session_state",
+    "track_info",
+    "track_controls",
+    "track_timeline",
+];
+
+/// Target of `peer_command` running it on this server
+pub const LOCAL_PEER: &str = "local";
+/// Target of `peer_command` running it on this server and every connected peer
+pub const ALL_PEERS: &str = "all";
+
+/// Another server, as listed by the `peers` event
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct PeerStatus {
+    pub name: String,
+    pub url: String,
+    pub connected: bool,
+}
+
+/// Payload of the `peer_event` event, an event of a peer namespaced by its name
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct PeerEvent {
+    pub peer: String,
+    /// Event code on the peer, e.g. `track_info`
+    pub event: String,
+    pub data: Value,
+}
+
+/// Payload of the `peer_command` function
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PeerCommand {
+    /// Name of a peer, `local` for this server or `all` for every machine
+    pub peer: String,
+    /// Function code, e.g. `pause`
+    pub command: String,
+    /// Payload of the function, if it takes one
+    #[serde(default)]
+    pub data: Option<Value>,
+}
+
+struct Peer {
+    name: String,
+    url: String,
+    /// Set once the first connection succeeded, reconnects are left to the client
+    client: Mutex<Option<Client>>,
+    connected: AtomicBool,
+}
+
+/// Other media-controller servers this one federates with.
+///
+/// Each peer is connected to as a Socket.IO client. Its state events come in as
+/// `peer_event`, and `peer_command` runs functions on it.
+pub struct PeerRegistry {
+    peers: Vec<Arc<Peer>>,
+    /// Commands arrive on blocking threads, the clients live on the runtime
+    runtime: Handle,
+}
+
+impl PeerRegistry {
+    pub fn new(config: &[PeerConfig]) -> anyhow::Result<Self> {
+        let mut peers: Vec<Arc<Peer>> = Vec::new();
+        for peer in config {
+            let name = peer.name();
+            if name == LOCAL_PEER || name == ALL_PEERS {
+                anyhow::bail!("Peer name {} is reserved", name);
+            }
+            if peers.iter().any(|known| known.name == name) {
+                anyhow::bail!("Peer name {} is used twice", name);
+            }
+            peers.push(Arc::new(Peer {
+                name,
+                url: peer.url.clone(),
+                client: Mutex::new(None),
+                connected: AtomicBool::new(false),
+            }));
+        }
+        Ok(Self {
+            peers,
+            runtime: Handle::current(),
+        })
+    }
+
+    pub fn status(&self) -> Vec<PeerStatus> {
+        self.peers
+            .iter()
+            .map(|peer| PeerStatus {
+                name: peer.name.clone(),
+                url: peer.url.clone(),
+                connected: peer.connected.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Run a function on one peer or on every connected one, blocking until each answered
+    ///
+    /// # Arguments
+    /// * `target` - Name of a peer, or `all`
+    /// * `command` - Function code
+    /// * `data` - Payload of the function
+    pub fn send(&self, target: &str, command: &str, data: Option<Value>) -> MediaResult<()> {
+        let peers: Vec<Arc<Peer>> = match target {
+            ALL_PEERS => self
+                .peers
+                .iter()
+                .filter(|peer| peer.connected.load(Ordering::Relaxed))
+                .cloned()
+                .collect(),
+            name => {
+                let peer = self
+                    .peers
+                    .iter()
+                    .find(|peer| peer.name == name)
+                    .ok_or_else(|| MediaError::invalid_input(format!("No peer named {}", name)))?;
+                vec![Arc::clone(peer)]
+            }
+        };
+
+        let command = command.to_string();
+        let data = data.unwrap_or(Value::Null);
+        let (sender, receiver) = mpsc::channel();
+        self.runtime.spawn(async move {
+            let sends = peers.iter().map(|peer| peer.send(&command, data.clone()));
+            sender.send(join_all(sends).await).ok();
+        });
+        let results = receiver.recv().map_err(MediaError::internal)?;
+
+        let failures: Vec<String> = results
+            .into_iter()
+            .filter_map(|result| result.err())
+            .map(|e| e.to_string())
+            .collect();
+        if !failures.is_empty() {
+            return Err(MediaError::internal(failures.join(", ")));
+        }
+        Ok(())
+    }
+}
+
+impl Peer {
+    async fn send(&self, command: &str, data: Value) -> MediaResult<()> {
+        let client = self.client.lock().ok().and_then(|client| client.clone());
+        let client = client.ok_or_else(|| self.error("not connected"))?;
+
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let sender = Arc::new(Mutex::new(Some(sender)));
+        let on_ack = move |payload: Payload, _: Client| {
+            if let Some(sender) = sender.lock().ok().and_then(|mut sender| sender.take()) {
+                sender.send(first_value(payload)).ok();
+            }
+            async {}.boxed()
+        };
+        client
+            .emit_with_ack(command, data, ACK_TIMEOUT, on_ack)
+            .await
+            .map_err(|e| self.error(e))?;
+
+        let ack = tokio::time::timeout(ACK_TIMEOUT, receiver)
+            .await
+            .map_err(|_| self.error("no answer"))?
+            .map_err(|_| self.error("no answer"))?;
+        if ack["ok"] == true {
+            return Ok(());
+        }
+        Err(self.error(ack["error"].as_str().unwrap_or("failed")))
+    }
+
+    fn error(&self, detail: impl std::fmt::Display) -> MediaError {
+        MediaError::internal(format!("{}: {}", self.name, detail))
+    }
+}
+
+/// Connect to every peer and relay their state events, retrying peers that are down
+pub async fn run(peers: Arc<PeerRegistry>, hub: EventHub) {
+    let connects = peers
+        .peers
+        .iter()
+        .map(|peer| connect(Arc::clone(peer), Arc::clone(&peers), hub.clone()));
+    join_all(connects).await;
+}
+
+async fn connect(peer: Arc<Peer>, registry: Arc<PeerRegistry>, hub: EventHub) {
+    loop {
+        match build_client(&peer, &registry, &hub).connect().await {
+            Ok(client) => {
+                tracing::info!("Connected to peer {} at {}", peer.name, peer.url);
+                if let Ok(mut current) = peer.client.lock() {
+                    *current = Some(client);
+                }
+                return;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to connect to peer {}: {}", peer.name, e);
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+fn build_client(peer: &Arc<Peer>, registry: &Arc<PeerRegistry>, hub: &EventHub) -> ClientBuilder {
+    let connection_changed = |connected: bool| {
+        let (peer, registry, hub) = (Arc::clone(peer), Arc::clone(registry), hub.clone());
+        move |_: Payload, _: Client| {
+            peer.connected.store(connected, Ordering::Relaxed);
+            hub.publish_peers(registry.status());
+            async {}.boxed()
+        }
+    };
+
+    let relay = {
+        let (name, hub) = (peer.name.clone(), hub.clone());
+        move |event: Event, payload: Payload, _: Client| {
+            let event = String::from(event);
+            if RELAYED_EVENTS.contains(&event.as_str()) {
+                hub.publish_peer_event(PeerEvent {
+                    peer: name.clone(),
+                    event,
+                    data: first_value(payload),
+                });
+            }
+            async {}.boxed()
+        }
+    };
+
+    ClientBuilder::new(peer.url.as_str())
+        .reconnect(true)
+        .reconnect_on_disconnect(true)
+        .on(Event::Connect, connection_changed(true))
+        .on(Event::Close, connection_changed(false))
+        .on_any(relay)
+}
+
+fn first_value(payload: Payload) -> Value {
+    match payload {
+        Payload::Text(values) => values.into_iter().next().unwrap_or_default(),
+        _ => Value::Null,
+    }
+}
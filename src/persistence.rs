@@ -0,0 +1,382 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::events::MediaEvent;
+use crate::media_manager::{SessionStatus, TrackControls, TrackInfo};
+use crate::position_alarms::{PositionAlarm, PositionAlarmRequest};
+use crate::utils;
+
+const STATE_FILE: &str = "last_state.json";
+/// Played tracks kept in the history, oldest are dropped first
+const HISTORY_LENGTH: usize = 5000;
+/// Played tracks listed by `/api/history`, the export has all of them
+const RECENT_HISTORY: usize = 50;
+/// Bookmarks kept, oldest are dropped first
+const BOOKMARK_LIMIT: usize = 50;
+/// Position alarms kept, oldest are dropped first
+const POSITION_ALARM_LIMIT: usize = 50;
+
+/// A track that played, as listed by `/api/history` and its export
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct HistoryEntry {
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    pub app_name: String,
+    /// Unix timestamp in milliseconds
+    pub played_at: u64,
+}
+
+/// A saved position in a track, as listed by the `bookmarks` event
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Bookmark {
+    pub source_app_id: String,
+    pub app_name: String,
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    /// Position in milliseconds
+    pub position: u64,
+    /// Unix timestamp in milliseconds
+    pub saved_at: u64,
+}
+
+impl Bookmark {
+    pub fn new(track: &TrackInfo, position: u64) -> Self {
+        Self {
+            source_app_id: track.session.source_app_id.clone(),
+            app_name: track.session.app_name.clone(),
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            album: track.album.clone(),
+            position,
+            saved_at: utils::unix_millis(),
+        }
+    }
+
+    /// Whether the bookmark was saved in this track
+    pub fn is_for(&self, track: &TrackInfo) -> bool {
+        self.track()
+            == (
+                track.session.source_app_id.as_str(),
+                track.title.as_str(),
+                track.artist.as_str(),
+            )
+    }
+
+    /// What tells tracks apart, apps don't report a track id
+    fn track(&self) -> (&str, &str, &str) {
+        (&self.source_app_id, &self.title, &self.artist)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct LastState {
+    track_info: Option<TrackInfo>,
+    track_controls: Option<TrackControls>,
+    /// Newest first
+    history: Vec<HistoryEntry>,
+    /// Newest first, one per track
+    #[serde(default)]
+    bookmarks: Vec<Bookmark>,
+    /// Oldest first
+    #[serde(default)]
+    position_alarms: Vec<PositionAlarm>,
+}
+
+#[derive(Default)]
+struct Inner {
+    state: LastState,
+    /// Changed since the file was last written
+    dirty: bool,
+    /// A session was active since startup, the restored state is outdated for good
+    live: bool,
+}
+
+/// Last known track and controls, kept on disk so clients of a restarted server
+/// see something until a session shows up
+#[derive(Default)]
+pub struct StateStore {
+    inner: Mutex<Inner>,
+}
+
+impl StateStore {
+    /// Restore the state written by the previous run, if any
+    pub fn load() -> Self {
+        let state = std::fs::read(state_path())
+            .ok()
+            .and_then(|contents| serde_json::from_slice(&contents).ok())
+            .unwrap_or_default();
+        Self {
+            inner: Mutex::new(Inner {
+                state,
+                ..Inner::default()
+            }),
+        }
+    }
+
+    /// Remember a broadcast event, writing to disk when a new track starts
+    pub fn record(&self, event: &MediaEvent) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        match event {
+            MediaEvent::SessionState(state) if state.state == SessionStatus::Active => {
+                inner.live = true;
+            }
+            MediaEvent::TrackInfo(Some(track)) => {
+                let is_new = inner.state.history.first().is_none_or(|last| {
+                    last.title != track.title
+                        || last.artist != track.artist
+                        || last.app_name != track.session.app_name
+                });
+                if is_new {
+                    inner.state.history.insert(
+                        0,
+                        HistoryEntry {
+                            title: track.title.clone(),
+                            artist: track.artist.clone(),
+                            album: track.album.clone(),
+                            app_name: track.session.app_name.clone(),
+                            played_at: utils::unix_millis(),
+                        },
+                    );
+                    inner.state.history.truncate(HISTORY_LENGTH);
+                }
+                inner.state.track_info = Some(track.clone());
+                inner.dirty = true;
+                // Track changes are rare, writing now keeps the history on a crash
+                if is_new {
+                    write(&mut inner);
+                }
+            }
+            MediaEvent::TrackControls(controls) => {
+                inner.state.track_controls = Some(controls.clone());
+                inner.dirty = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// A live session was found, the restored state is never served again
+    pub fn mark_live(&self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.live = true;
+        }
+    }
+
+    /// The restored track and controls flagged as stale, empty once a session was live
+    pub fn stale_events(&self) -> Vec<MediaEvent> {
+        let Ok(inner) = self.inner.lock() else {
+            return Vec::new();
+        };
+        if inner.live {
+            return Vec::new();
+        }
+
+        let mut events = Vec::new();
+        if let Some(track) = &inner.state.track_info {
+            let mut track = track.clone();
+            track.stale = true;
+            // The artwork route serves the live session's artwork
+            track.thumbnails = None;
+            events.push(MediaEvent::TrackInfo(Some(track)));
+        }
+        if let Some(controls) = &inner.state.track_controls {
+            let mut controls = controls.clone();
+            controls.stale = true;
+            events.push(MediaEvent::TrackControls(controls));
+        }
+        events
+    }
+
+    /// Recently played tracks, newest first
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.inner
+            .lock()
+            .map(|inner| {
+                inner
+                    .state
+                    .history
+                    .iter()
+                    .take(RECENT_HISTORY)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Every played track kept, newest first
+    pub fn full_history(&self) -> Vec<HistoryEntry> {
+        self.inner
+            .lock()
+            .map(|inner| inner.state.history.clone())
+            .unwrap_or_default()
+    }
+
+    /// Merge played tracks into the history, skipping the ones it already has
+    ///
+    /// # Returns
+    /// * `usize` - How many tracks were added
+    pub fn import_history(&self, entries: Vec<HistoryEntry>) -> usize {
+        let Ok(mut inner) = self.inner.lock() else {
+            return 0;
+        };
+        let history = &mut inner.state.history;
+        let before = history.len();
+        history.extend(entries);
+        history.sort_by(|a, b| b.played_at.cmp(&a.played_at));
+        let mut seen = HashSet::new();
+        history.retain(|entry| {
+            seen.insert((entry.played_at, entry.title.clone(), entry.artist.clone()))
+        });
+        let added = history.len().saturating_sub(before);
+        history.truncate(HISTORY_LENGTH);
+        inner.dirty = true;
+        added
+    }
+
+    /// Save a position, replacing an older bookmark of the same track
+    ///
+    /// # Returns
+    /// * `Vec<Bookmark>` - Every bookmark, newest first
+    pub fn add_bookmark(&self, bookmark: Bookmark) -> Vec<Bookmark> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Vec::new();
+        };
+        let bookmarks = &mut inner.state.bookmarks;
+        bookmarks.retain(|saved| saved.track() != bookmark.track());
+        bookmarks.insert(0, bookmark);
+        bookmarks.truncate(BOOKMARK_LIMIT);
+        let bookmarks = bookmarks.clone();
+        // Bookmarks are saved on purpose, a crash must not lose them
+        inner.dirty = true;
+        write(&mut inner);
+        bookmarks
+    }
+
+    /// Saved positions, newest first
+    pub fn bookmarks(&self) -> Vec<Bookmark> {
+        self.inner
+            .lock()
+            .map(|inner| inner.state.bookmarks.clone())
+            .unwrap_or_default()
+    }
+
+    /// The bookmark saved in a track, if any
+    pub fn bookmark_for(&self, track: &TrackInfo) -> Option<Bookmark> {
+        let inner = self.inner.lock().ok()?;
+        inner
+            .state
+            .bookmarks
+            .iter()
+            .find(|bookmark| bookmark.is_for(track))
+            .cloned()
+    }
+
+    /// Set an alarm at a position of a track
+    ///
+    /// # Returns
+    /// * `Vec<PositionAlarm>` - Every alarm, oldest first
+    pub fn add_position_alarm(
+        &self,
+        track: &TrackInfo,
+        request: PositionAlarmRequest,
+    ) -> Vec<PositionAlarm> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Vec::new();
+        };
+        let alarms = &mut inner.state.position_alarms;
+        let id = alarms.iter().map(|alarm| alarm.id).max().unwrap_or(0) + 1;
+        alarms.push(PositionAlarm::new(id, track, request));
+        let excess = alarms.len().saturating_sub(POSITION_ALARM_LIMIT);
+        alarms.drain(..excess);
+        let alarms = alarms.clone();
+        inner.dirty = true;
+        write(&mut inner);
+        alarms
+    }
+
+    /// Remove an alarm before it went off
+    ///
+    /// # Returns
+    /// * `Option<Vec<PositionAlarm>>` - Every remaining alarm, `None` when there was no such alarm
+    pub fn remove_position_alarm(&self, id: u64) -> Option<Vec<PositionAlarm>> {
+        let mut inner = self.inner.lock().ok()?;
+        let alarms = &mut inner.state.position_alarms;
+        let before = alarms.len();
+        alarms.retain(|alarm| alarm.id != id);
+        if alarms.len() == before {
+            return None;
+        }
+        let alarms = alarms.clone();
+        inner.dirty = true;
+        write(&mut inner);
+        Some(alarms)
+    }
+
+    /// Alarms that haven't gone off yet, oldest first
+    pub fn position_alarms(&self) -> Vec<PositionAlarm> {
+        self.inner
+            .lock()
+            .map(|inner| inner.state.position_alarms.clone())
+            .unwrap_or_default()
+    }
+
+    /// Remove and return the alarms of a track within a range of positions, as
+    /// they go off only once
+    ///
+    /// # Arguments
+    /// * `track_id` - `track_id` of the track
+    /// * `from` - Position the range starts after, in milliseconds
+    /// * `to` - Last position of the range
+    pub fn take_position_alarms(&self, track_id: &str, from: u64, to: u64) -> Vec<PositionAlarm> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Vec::new();
+        };
+        let (crossed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut inner.state.position_alarms)
+            .into_iter()
+            .partition(|alarm| {
+                alarm.is_for(track_id) && alarm.position > from && alarm.position <= to
+            });
+        inner.state.position_alarms = kept;
+        if !crossed.is_empty() {
+            inner.dirty = true;
+            write(&mut inner);
+        }
+        crossed
+    }
+
+    /// Write pending changes to disk
+    pub fn flush(&self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            write(&mut inner);
+        }
+    }
+}
+
+fn state_path() -> PathBuf {
+    utils::data_dir().join(STATE_FILE)
+}
+
+fn write(inner: &mut Inner) {
+    if !inner.dirty {
+        return;
+    }
+    let path = state_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).ok();
+    }
+    let result = serde_json::to_vec(&inner.state)
+        .map_err(std::io::Error::other)
+        .and_then(|json| std::fs::write(&path, json));
+    match result {
+        Ok(()) => inner.dirty = false,
+        Err(e) => tracing::error!("Failed to store last state at {}: {}", path.display(), e),
+    }
+}
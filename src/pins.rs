@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::time::MissedTickBehavior;
+
+use crate::events::{EventHub, MediaEvent};
+
+/// Only the current session is watched, a pinned one is read this often
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// A pinned session changes a few times per read at most
+const CHANNEL_CAPACITY: usize = 16;
+
+/// The sessions sockets are pinned to, each read by a single task however many
+/// sockets follow it
+#[derive(Clone, Default)]
+pub struct PinnedSessions {
+    /// Changes of each followed app's session, by lowercase app id
+    watchers: Arc<Mutex<HashMap<String, broadcast::Sender<MediaEvent>>>>,
+}
+
+impl PinnedSessions {
+    /// Changes of an app's session, its watcher starts with the first follower and
+    /// stops once the last one is gone
+    ///
+    /// # Arguments
+    /// * `hub` - Source of the session's state
+    /// * `app_id` - Source app id, compared case-insensitively
+    ///
+    /// # Returns
+    /// * `broadcast::Receiver<MediaEvent>` - The state events that changed since the
+    ///   last read, new followers read the whole state themselves
+    pub fn follow(&self, hub: &EventHub, app_id: &str) -> broadcast::Receiver<MediaEvent> {
+        let key = app_id.to_lowercase();
+        let Ok(mut watchers) = self.watchers.lock() else {
+            return broadcast::channel(1).1;
+        };
+        if let Some(sender) = watchers.get(&key) {
+            return sender.subscribe();
+        }
+
+        let (sender, receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        watchers.insert(key.clone(), sender.clone());
+        tokio::spawn(watch(hub.clone(), self.clone(), key, sender));
+        receiver
+    }
+
+    /// Forget the watcher of an app once nobody follows it
+    ///
+    /// # Returns
+    /// * `bool` - Whether the watcher was forgotten and should stop
+    fn release(&self, key: &str, sender: &broadcast::Sender<MediaEvent>) -> bool {
+        let Ok(mut watchers) = self.watchers.lock() else {
+            return true;
+        };
+        // Checked under the lock, so no socket starts following a stopping watcher
+        if sender.receiver_count() > 0 {
+            return false;
+        }
+        watchers.remove(key);
+        true
+    }
+}
+
+/// Read an app's session every interval and send the state events that changed.
+///
+/// The artwork is the slow part of a read, so it is only read again when the rest of
+/// the track changed.
+async fn watch(
+    hub: EventHub,
+    pins: PinnedSessions,
+    key: String,
+    sender: broadcast::Sender<MediaEvent>,
+) {
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut last_read = HashMap::new();
+    loop {
+        ticker.tick().await;
+        if pins.release(&key, &sender) {
+            return;
+        }
+
+        for event in read(&hub, &key, false).await {
+            let payload = event.payload();
+            if last_read.get(event.name()) == Some(&payload) {
+                continue;
+            }
+            last_read.insert(event.name(), payload);
+
+            let event = match event {
+                MediaEvent::TrackInfo(Some(_)) => {
+                    let track = read(&hub, &key, true)
+                        .await
+                        .into_iter()
+                        .find(|event| matches!(event, MediaEvent::TrackInfo(_)));
+                    match track {
+                        Some(track) => track,
+                        None => continue,
+                    }
+                }
+                event => event,
+            };
+            // Only fails without followers, the next tick stops the watcher
+            let _ = sender.send(event);
+        }
+    }
+}
+
+async fn read(hub: &EventHub, app_id: &str, artwork: bool) -> Vec<MediaEvent> {
+    let (hub, app_id) = (hub.clone(), app_id.to_string());
+    tokio::task::spawn_blocking(move || hub.pinned_state(&app_id, artwork))
+        .await
+        .unwrap_or_default()
+}
@@ -0,0 +1,216 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use rhai::{AST, CallFnOptions, Dynamic, Engine, Map, Scope};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::cron::LocalTime;
+use crate::events::{EventHub, MediaCommand, MediaEvent, PluginEvent, SeekPosition};
+
+/// Script extension, other files in the directory are ignored
+const EXTENSION: &str = "rhai";
+/// Stops runaway scripts, an infinite loop would otherwise block every plugin
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A compiled script with the state of its top-level variables
+struct Plugin {
+    name: String,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+}
+
+impl Plugin {
+    fn load(path: &Path, hub: &EventHub) -> Result<Self> {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .context("Plugin path has no file name")?;
+        let engine = engine(&name, hub);
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| anyhow::anyhow!("Failed to compile {}: {}", path.display(), e))?;
+
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", path.display(), e))?;
+
+        Ok(Self {
+            name,
+            engine,
+            ast,
+            scope,
+        })
+    }
+
+    /// Call a callback if the script defines it
+    fn call(&mut self, callback: &str, payload: &serde_json::Value) {
+        let defined = self
+            .ast
+            .iter_functions()
+            .any(|function| function.name == callback && function.params.len() == 1);
+        if !defined {
+            return;
+        }
+
+        let argument = match rhai::serde::to_dynamic(payload) {
+            Ok(argument) => argument,
+            Err(e) => {
+                tracing::error!("Failed to pass {} to plugin {}: {}", callback, self.name, e);
+                return;
+            }
+        };
+        // The top-level statements already ran when the plugin was loaded
+        let options = CallFnOptions::new().eval_ast(false);
+        if let Err(e) = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            callback,
+            (argument,),
+        ) {
+            tracing::error!("Plugin {} failed in {}: {}", self.name, callback, e);
+        }
+    }
+}
+
+/// Load every script in the plugins directory and feed them media events
+///
+/// # Arguments
+/// * `directory` - Directory holding the `.rhai` scripts
+/// * `hub` - Hub whose events are passed to the scripts and which runs their commands
+pub fn start(directory: PathBuf, hub: EventHub) -> Result<()> {
+    let entries = std::fs::read_dir(&directory)
+        .with_context(|| format!("Failed to read plugins directory {}", directory.display()))?;
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == EXTENSION)
+        })
+        .collect();
+    paths.sort();
+
+    // A broken script shouldn't keep the others from loading
+    let mut plugins = Vec::new();
+    for path in paths {
+        match Plugin::load(&path, &hub) {
+            Ok(plugin) => {
+                tracing::info!("Loaded plugin {}", plugin.name);
+                plugins.push(plugin);
+            }
+            Err(e) => tracing::error!("{:#}", e),
+        }
+    }
+    if plugins.is_empty() {
+        tracing::info!("No plugins in {}", directory.display());
+        return Ok(());
+    }
+
+    let mut receiver = hub.subscribe();
+    // Scripts and the commands they run block, so they get a thread of their own.
+    // A plain thread, the runtime would wait for a blocking task on shutdown
+    std::thread::spawn(move || {
+        loop {
+            let event = match receiver.blocking_recv() {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Plugins lagged behind by {} events", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            let callback = match &event {
+                MediaEvent::TrackInfo(_) => "track_changed",
+                MediaEvent::TrackControls(_) => "controls_changed",
+                MediaEvent::SessionState(_) => "session_changed",
+                _ => continue,
+            };
+            let mut payload = event.payload();
+            if let Some(track) = payload.as_object_mut() {
+                track.remove("thumbnail");
+            }
+            for plugin in &mut plugins {
+                plugin.call(callback, &payload);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// An engine with the control functions, bound to the plugin that uses it
+fn engine(plugin: &str, hub: &EventHub) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let name = plugin.to_string();
+    engine.on_print(move |text| tracing::info!("[{}] {}", name, text));
+    let name = plugin.to_string();
+    engine.on_debug(move |text, _, _| tracing::debug!("[{}] {}", name, text));
+
+    let commands: [(&str, fn() -> MediaCommand); 7] = [
+        ("toggle_play_pause", || MediaCommand::TogglePlayPause(None)),
+        ("play", || MediaCommand::Play),
+        ("pause", || MediaCommand::Pause),
+        ("stop", || MediaCommand::Stop),
+        ("next_track", || MediaCommand::NextTrack),
+        ("previous_track", || MediaCommand::PreviousTrack),
+        ("toggle_shuffle", || MediaCommand::ToggleShuffle),
+    ];
+    for (function, command) in commands {
+        let hub = hub.clone();
+        let name = plugin.to_string();
+        engine.register_fn(function, move || execute(&hub, &name, command()));
+    }
+
+    let hub_for_seek = hub.clone();
+    let name = plugin.to_string();
+    engine.register_fn("seek", move |position: i64| {
+        let position = position.max(0) as u64;
+        execute(
+            &hub_for_seek,
+            &name,
+            MediaCommand::Seek(SeekPosition { position }),
+        )
+    });
+
+    let hub_for_emit = hub.clone();
+    let name = plugin.to_string();
+    engine.register_fn("emit", move |event: &str, data: Dynamic| {
+        let data = rhai::serde::from_dynamic(&data).unwrap_or_default();
+        hub_for_emit.publish_plugin_event(PluginEvent {
+            plugin: name.clone(),
+            name: event.to_string(),
+            data,
+        });
+    });
+
+    engine.register_fn("now", || {
+        let time = LocalTime::now();
+        let mut now = Map::new();
+        now.insert("hour".into(), (time.hour as i64).into());
+        now.insert("minute".into(), (time.minute as i64).into());
+        now.insert("weekday".into(), (time.weekday as i64).into());
+        now
+    });
+
+    engine
+}
+
+/// Run a command for a script
+///
+/// # Returns
+/// * `bool` - Whether it succeeded, failures are logged instead of aborting the script
+fn execute(hub: &EventHub, plugin: &str, command: MediaCommand) -> bool {
+    let name = command.name();
+    match hub.execute(command) {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::warn!("Plugin {} failed to run {}: {}", plugin, name, e);
+            false
+        }
+    }
+}
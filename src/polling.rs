@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use crate::events::EventHub;
+
+/// Wait between checks while the current app isn't polled
+const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Shortest poll interval, reading a session takes several WinRT calls
+const MIN_INTERVAL: Duration = Duration::from_millis(500);
+/// Poll interval of apps without one of their own in `--mode polling`
+pub const MODE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Read the current session on the interval of its app's profile, on top of its
+/// change events, for players that don't fire them reliably
+///
+/// # Arguments
+/// * `hub` - Publishes what changed
+/// * `interval` - Poll interval of every other app, when there are no change events
+pub async fn run(hub: EventHub, interval: Option<Duration>) {
+    match interval {
+        Some(interval) => tracing::info!("Polling the current session every {:?}", interval),
+        None => tracing::info!("Polling the sessions of apps with a poll interval"),
+    }
+    loop {
+        let reader = hub.clone();
+        let poll = move || reader.poll_current_session(interval);
+        let wait = match tokio::task::spawn_blocking(poll).await {
+            Ok(Ok(Some(interval))) => interval.max(MIN_INTERVAL),
+            Ok(Ok(None)) => IDLE_INTERVAL,
+            Ok(Err(e)) => {
+                tracing::debug!("Failed to poll the media session: {}", e);
+                IDLE_INTERVAL
+            }
+            Err(e) => {
+                tracing::error!("Session poll task failed: {}", e);
+                IDLE_INTERVAL
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::time::MissedTickBehavior;
+
+use crate::events::EventHub;
+use crate::media_manager::TrackInfo;
+use crate::utils;
+
+/// Interval between reads of the position, alarms go off up to this late
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Positions advancing further between two checks were seeked to, not played through
+const MAX_STEP_MS: u64 = 5_000;
+
+/// Payload of `add_position_alarm`
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PositionAlarmRequest {
+    /// Position in the current track in milliseconds
+    pub position: u64,
+    /// Pause playback when the alarm goes off
+    #[serde(default)]
+    pub pause: bool,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PositionAlarmTarget {
+    pub id: u64,
+}
+
+/// A position in a track to be told about, stored across restarts
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct PositionAlarm {
+    pub id: u64,
+    /// `track_id` of the track the alarm was set in, alarms of older versions have none
+    #[serde(default)]
+    pub track_id: String,
+    pub source_app_id: String,
+    pub app_name: String,
+    pub title: String,
+    pub artist: String,
+    /// Position in milliseconds
+    pub position: u64,
+    pub pause: bool,
+    pub label: Option<String>,
+    /// Unix timestamp in milliseconds
+    pub created_at: u64,
+}
+
+impl PositionAlarm {
+    pub fn new(id: u64, track: &TrackInfo, request: PositionAlarmRequest) -> Self {
+        Self {
+            id,
+            track_id: track.track_id.clone(),
+            source_app_id: track.session.source_app_id.clone(),
+            app_name: track.session.app_name.clone(),
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            position: request.position,
+            pause: request.pause,
+            label: request.label,
+            created_at: utils::unix_millis(),
+        }
+    }
+
+    /// Whether the alarm was set in a track
+    ///
+    /// # Arguments
+    /// * `track_id` - `track_id` of the track, e.g. of its timeline
+    pub fn is_for(&self, track_id: &str) -> bool {
+        !self.track_id.is_empty() && self.track_id == track_id
+    }
+}
+
+/// Remembers the position of the last check, to tell which alarm positions were played through
+#[derive(Default)]
+pub struct AlarmWatch {
+    /// `track_id` of the track and its position
+    last: Mutex<Option<(String, u64)>>,
+}
+
+impl AlarmWatch {
+    /// Move on to the current position
+    ///
+    /// # Arguments
+    /// * `track_id` - `track_id` of the current track
+    /// * `position` - Its position in milliseconds
+    ///
+    /// # Returns
+    /// * `Option<(u64, u64)>` - Positions played through since the last check, start
+    ///   excluded, `None` on a new track, a seek or while paused
+    pub fn advance(&self, track_id: &str, position: u64) -> Option<(u64, u64)> {
+        let mut last = self.last.lock().ok()?;
+        let previous = last.replace((track_id.to_string(), position))?;
+        if previous.0 != track_id || position <= previous.1 || position - previous.1 > MAX_STEP_MS {
+            return None;
+        }
+        Some((previous.1, position))
+    }
+}
+
+/// Go off when the current track plays through the position of an alarm
+pub async fn run(hub: EventHub) {
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    // Counts as a listener while an alarm waits, so idling keeps following the session
+    let mut listener = None;
+    loop {
+        ticker.tick().await;
+        match (hub.has_position_alarms(), &listener) {
+            (true, None) => listener = Some(hub.clients().listen()),
+            (false, Some(_)) => listener = None,
+            _ => {}
+        }
+        let hub = hub.clone();
+        match tokio::task::spawn_blocking(move || hub.check_position_alarms()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::debug!("Failed to check position alarms: {}", e),
+            Err(e) => tracing::error!("Position alarm task failed: {}", e),
+        }
+    }
+}
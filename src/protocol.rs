@@ -0,0 +1,320 @@
+use media_controller_core::utils::decode_base64_image;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{MediaError, MediaResult};
+use crate::events::{Capabilities, MediaEvent};
+
+/// Bumped whenever an event or function changes incompatibly
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Optional event groups a client can opt into with `hello`
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    StateSnapshot,
+    Lyrics,
+    Queue,
+    Spotify,
+    SleepTimer,
+    Schedules,
+    Bookmarks,
+    PositionAlarms,
+    QuietHours,
+    Outputs,
+    Peers,
+    /// Every session in aggregate mode
+    Sessions,
+}
+
+const FEATURES: [Feature; 12] = [
+    Feature::StateSnapshot,
+    Feature::Lyrics,
+    Feature::Queue,
+    Feature::Spotify,
+    Feature::SleepTimer,
+    Feature::Schedules,
+    Feature::Bookmarks,
+    Feature::PositionAlarms,
+    Feature::QuietHours,
+    Feature::Outputs,
+    Feature::Peers,
+    Feature::Sessions,
+];
+
+impl Feature {
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::StateSnapshot => "state_snapshot",
+            Feature::Lyrics => "lyrics",
+            Feature::Queue => "queue",
+            Feature::Spotify => "spotify",
+            Feature::SleepTimer => "sleep_timer",
+            Feature::Schedules => "schedules",
+            Feature::Bookmarks => "bookmarks",
+            Feature::PositionAlarms => "position_alarms",
+            Feature::QuietHours => "quiet_hours",
+            Feature::Outputs => "outputs",
+            Feature::Peers => "peers",
+            Feature::Sessions => "sessions",
+        }
+    }
+
+    /// Features this server can serve with its config
+    pub fn supported(capabilities: &Capabilities) -> Vec<Feature> {
+        FEATURES
+            .into_iter()
+            .filter(|feature| match feature {
+                Feature::Lyrics => capabilities.lyrics,
+                Feature::Spotify => capabilities.spotify,
+                Feature::Outputs => capabilities.outputs,
+                Feature::Peers => capabilities.peers,
+                Feature::Sessions => capabilities.sessions,
+                Feature::QuietHours => capabilities.quiet_hours,
+                _ => true,
+            })
+            .collect()
+    }
+
+    /// The feature an event belongs to, `None` for events every client gets
+    fn for_event(event: &MediaEvent) -> Option<Feature> {
+        match event {
+            MediaEvent::StateSnapshot(_) => Some(Feature::StateSnapshot),
+            MediaEvent::Lyrics(_) => Some(Feature::Lyrics),
+            MediaEvent::Queue(_) => Some(Feature::Queue),
+            MediaEvent::SpotifyDevices(_) => Some(Feature::Spotify),
+            MediaEvent::SleepTimerStatus(_) => Some(Feature::SleepTimer),
+            MediaEvent::Schedules(_) => Some(Feature::Schedules),
+            MediaEvent::Bookmarks(_) => Some(Feature::Bookmarks),
+            MediaEvent::PositionAlarms(_) => Some(Feature::PositionAlarms),
+            MediaEvent::QuietHoursChanged(_) => Some(Feature::QuietHours),
+            MediaEvent::Outputs(_) => Some(Feature::Outputs),
+            MediaEvent::Peers(_) | MediaEvent::PeerEvent(_) => Some(Feature::Peers),
+            MediaEvent::Sessions(_) => Some(Feature::Sessions),
+            _ => None,
+        }
+    }
+}
+
+/// Wire format of the events sent to a client
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Json,
+    /// MessagePack, with artwork as raw bytes instead of base64 data URLs
+    Msgpack,
+}
+
+impl Encoding {
+    /// Encoding asked for with `?encoding=msgpack` in the connection URL
+    pub fn from_query(query: Option<&str>) -> Self {
+        let msgpack =
+            query.is_some_and(|query| query.split('&').any(|pair| pair == "encoding=msgpack"));
+        if msgpack {
+            Encoding::Msgpack
+        } else {
+            Encoding::Json
+        }
+    }
+}
+
+/// MessagePack payload of an event, sent as a Socket.IO binary attachment
+pub fn msgpack_payload(event: &MediaEvent) -> Vec<u8> {
+    encode_msgpack(&msgpack_value(event))
+}
+
+/// MessagePack `{ event, data }` envelope of an event, sent as a binary WebSocket message
+pub fn msgpack_envelope(event: &MediaEvent) -> Vec<u8> {
+    encode_msgpack(&rmpv::Value::Map(vec![
+        ("event".into(), event.name().into()),
+        ("data".into(), msgpack_value(event)),
+    ]))
+}
+
+fn msgpack_value(event: &MediaEvent) -> rmpv::Value {
+    let mut value = rmpv::ext::to_value(event.payload()).unwrap_or(rmpv::Value::Nil);
+    attach_thumbnails(&mut value);
+    value
+}
+
+/// Replace every `thumbnail` data URL with the image bytes, a third smaller than base64
+fn attach_thumbnails(value: &mut rmpv::Value) {
+    match value {
+        rmpv::Value::Map(entries) => {
+            for (key, field) in entries {
+                let image = match (key.as_str(), field.as_str()) {
+                    (Some("thumbnail"), Some(data_url)) => decode_base64_image(data_url),
+                    _ => None,
+                };
+                match image {
+                    Some(image) => *field = rmpv::Value::Binary(image),
+                    None => attach_thumbnails(field),
+                }
+            }
+        }
+        rmpv::Value::Array(values) => values.iter_mut().for_each(attach_thumbnails),
+        _ => {}
+    }
+}
+
+fn encode_msgpack(value: &rmpv::Value) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    // Writing to a Vec can't fail
+    rmpv::encode::write_value(&mut bytes, value).ok();
+    bytes
+}
+
+/// Broadcasts a client can pick with `subscribe`, each is a room of the socket
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    TrackInfo,
+    Timeline,
+    Controls,
+    /// Snapcast output volumes
+    Volume,
+}
+
+impl Topic {
+    /// Room of the sockets subscribed to the topic
+    pub fn room(self) -> &'static str {
+        match self {
+            Topic::TrackInfo => "topic:track_info",
+            Topic::Timeline => "topic:timeline",
+            Topic::Controls => "topic:controls",
+            Topic::Volume => "topic:volume",
+        }
+    }
+
+    /// The topic an event belongs to, `None` for events that aren't subscribed to
+    pub fn for_event(event: &MediaEvent) -> Option<Topic> {
+        match event {
+            MediaEvent::TrackInfo(_) => Some(Topic::TrackInfo),
+            MediaEvent::TrackTimeline(_) => Some(Topic::Timeline),
+            MediaEvent::TrackControls(_) => Some(Topic::Controls),
+            MediaEvent::Outputs(_) => Some(Topic::Volume),
+            _ => None,
+        }
+    }
+
+    /// Whether a socket in these rooms should receive an event. Sockets that never
+    /// subscribed get every topic.
+    ///
+    /// # Arguments
+    /// * `rooms` - Rooms the socket is in
+    /// * `event` - Broadcast event
+    pub fn allows(rooms: &[&str], event: &MediaEvent) -> bool {
+        let Some(topic) = Topic::for_event(event) else {
+            return true;
+        };
+        let subscribed = TOPICS.iter().any(|topic| rooms.contains(&topic.room()));
+        !subscribed || rooms.contains(&topic.room())
+    }
+}
+
+const TOPICS: [Topic; 4] = [
+    Topic::TrackInfo,
+    Topic::Timeline,
+    Topic::Controls,
+    Topic::Volume,
+];
+
+/// Payload of the `subscribe` and `unsubscribe` functions
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Subscription {
+    pub topics: Vec<Topic>,
+}
+
+/// Payload of the `hello` function
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ClientHello {
+    pub protocol_version: u32,
+    /// Feature names, unknown ones are ignored so newer clients can connect
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Pairing token of a device listed under `[automation.devices]`
+    #[serde(default)]
+    pub device_token: Option<String>,
+    /// Encoding of every event from the answer to this `hello` on
+    #[serde(default)]
+    pub encoding: Encoding,
+}
+
+/// Payload of the `hello` event answering a client's `hello`
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct ServerHello {
+    pub protocol_version: u32,
+    /// Features both sides support, the only optional events sent from now on
+    pub features: Vec<Feature>,
+    pub capabilities: Capabilities,
+    /// Name of the paired device, when the client sent a known pairing token
+    pub device: Option<String>,
+    pub encoding: Encoding,
+}
+
+/// Payload of the `server_ping` heartbeat, acknowledged by clients
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ServerPing {
+    /// Unix timestamp in milliseconds
+    pub timestamp: u64,
+}
+
+/// Optional events a client receives, every event until it says hello
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    features: Option<Vec<Feature>>,
+}
+
+impl EventFilter {
+    /// Agree on the features of a client
+    ///
+    /// # Arguments
+    /// * `hello` - What the client asked for
+    /// * `capabilities` - What the server is configured for
+    ///
+    /// # Returns
+    /// * `MediaResult<(Self, ServerHello)>` - The filter for the client's events and the answer to send
+    pub fn negotiate(
+        hello: &ClientHello,
+        capabilities: Capabilities,
+    ) -> MediaResult<(Self, ServerHello)> {
+        if hello.protocol_version == 0 {
+            return Err(MediaError::invalid_input("Protocol versions start at 1"));
+        }
+        if hello.protocol_version > PROTOCOL_VERSION {
+            tracing::debug!(
+                "Client speaks protocol {}, answering with {}",
+                hello.protocol_version,
+                PROTOCOL_VERSION
+            );
+        }
+
+        let features: Vec<Feature> = Feature::supported(&capabilities)
+            .into_iter()
+            .filter(|feature| hello.features.iter().any(|name| name == feature.name()))
+            .collect();
+
+        let reply = ServerHello {
+            protocol_version: PROTOCOL_VERSION,
+            features: features.clone(),
+            capabilities,
+            device: None,
+            encoding: hello.encoding,
+        };
+        Ok((
+            Self {
+                features: Some(features),
+            },
+            reply,
+        ))
+    }
+
+    /// Whether a broadcast event should reach the client
+    pub fn allows(&self, event: &MediaEvent) -> bool {
+        match (&self.features, Feature::for_event(event)) {
+            (Some(features), Some(feature)) => features.contains(&feature),
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use rumqttc::{AsyncClient, Event, Packet, QoS};
+use serde_json::{Value, json};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use windows::Foundation::{TimeSpan, TypedEventHandler};
+use windows::Media::Playback::MediaPlayer;
+use windows::Media::{
+    MediaPlaybackAutoRepeatMode, MediaPlaybackStatus, MediaPlaybackType,
+    SystemMediaTransportControls, SystemMediaTransportControlsButton,
+    SystemMediaTransportControlsTimelineProperties,
+};
+use windows::Storage::Streams::{
+    DataWriter, InMemoryRandomAccessStream, RandomAccessStreamReference,
+};
+use windows::core::HSTRING;
+
+use crate::config::{MqttConfig, PublisherConfig};
+use crate::events::{EventHub, MediaCommand, MediaEvent, SeekPosition};
+use crate::media_manager::{AutoRepeatMode, TrackControls, TrackInfo, TrackTimeline};
+use crate::mqtt;
+use crate::peers::PeerCommand;
+use media_controller_core::utils::decode_base64_image;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Topics of the mirrored server below its prefix, with the event each one carries
+const MQTT_TOPICS: [(&str, &str); 3] = [
+    ("track", "track_info"),
+    ("state", "track_controls"),
+    ("position", "track_timeline"),
+];
+
+/// Where the mirrored state comes from and where button presses go
+enum Source {
+    /// A peer of `[[peers]]`, relayed as `peer_event`
+    Peer(String),
+    /// A server publishing to the broker of `[mqtt]` under a prefix
+    Mqtt { client: AsyncClient, prefix: String },
+}
+
+/// Mirror the media of another machine in this PC's own media flyout, hardware
+/// media keys and the flyout's buttons control the other machine
+pub async fn run(config: PublisherConfig, mqtt: Option<MqttConfig>, hub: EventHub) -> Result<()> {
+    let (button_sender, mut buttons) = mpsc::unbounded_channel();
+    let session = tokio::task::spawn_blocking(move || MirrorSession::new(button_sender))
+        .await?
+        .context("Failed to register a media session")?;
+    let session = Arc::new(session);
+
+    let (event_sender, mut events) = mpsc::unbounded_channel();
+    let source = match (config.peer, config.mqtt_prefix) {
+        (Some(peer), None) => {
+            tokio::spawn(follow_peer(hub.clone(), peer.clone(), event_sender));
+            tracing::info!("Mirroring peer {} in the media flyout", peer);
+            Source::Peer(peer)
+        }
+        (None, Some(prefix)) => {
+            let mqtt = mqtt.context("Mirroring over MQTT needs the [mqtt] broker")?;
+            let prefix = prefix.trim_end_matches('/').to_string();
+            let client = follow_mqtt(&mqtt, &prefix, event_sender)?;
+            tracing::info!("Mirroring MQTT prefix {} in the media flyout", prefix);
+            Source::Mqtt { client, prefix }
+        }
+        _ => bail!("The publisher needs either a peer or an mqtt_prefix"),
+    };
+
+    loop {
+        tokio::select! {
+            Some((event, data)) = events.recv() => {
+                let session = Arc::clone(&session);
+                let result = tokio::task::spawn_blocking(move || session.apply(&event, data)).await;
+                match result {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => tracing::warn!("Failed to update the mirrored session: {}", e),
+                    Err(e) => tracing::error!("Mirrored session task failed: {}", e),
+                }
+            }
+            Some(command) = buttons.recv() => source.send(&hub, command).await,
+            else => return Ok(()),
+        }
+    }
+}
+
+impl Source {
+    async fn send(&self, hub: &EventHub, command: MediaCommand) {
+        let name = command.name();
+        let result = match self {
+            Source::Peer(peer) => {
+                let data = match &command {
+                    MediaCommand::Seek(seek) => Some(json!({ "position": seek.position })),
+                    _ => None,
+                };
+                let command = MediaCommand::PeerCommand(PeerCommand {
+                    peer: peer.clone(),
+                    command: name.to_string(),
+                    data,
+                });
+                let hub = hub.clone();
+                tokio::task::spawn_blocking(move || hub.execute(command).map(|_| ()))
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .and_then(|result| result.map_err(anyhow::Error::from))
+            }
+            Source::Mqtt { client, prefix } => {
+                let Some((topic, payload)) = mqtt_command(&command) else {
+                    return;
+                };
+                client
+                    .publish(
+                        format!("{}/cmd/{}", prefix, topic),
+                        QoS::AtLeastOnce,
+                        false,
+                        payload,
+                    )
+                    .await
+                    .map_err(anyhow::Error::from)
+            }
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to forward {} to the mirrored machine: {}", name, e);
+        }
+    }
+}
+
+/// Topic and payload of a command on the `<prefix>/cmd/<command>` topics
+fn mqtt_command(command: &MediaCommand) -> Option<(&'static str, String)> {
+    let topic = match command {
+        MediaCommand::Play => "play",
+        MediaCommand::Pause => "pause",
+        MediaCommand::TogglePlayPause(_) => "play_pause",
+        MediaCommand::Stop => "stop",
+        MediaCommand::NextTrack => "next",
+        MediaCommand::PreviousTrack => "previous",
+        MediaCommand::FastForward => "fast_forward",
+        MediaCommand::Rewind => "rewind",
+        MediaCommand::Seek(seek) => return Some(("seek", seek.position.to_string())),
+        _ => return None,
+    };
+    Some((topic, String::new()))
+}
+
+/// Pass on the state events of a peer
+async fn follow_peer(hub: EventHub, peer: String, events: UnboundedSender<(String, Value)>) {
+    let mut receiver = hub.subscribe();
+    loop {
+        match receiver.recv().await {
+            Ok(MediaEvent::PeerEvent(event)) if event.peer == peer => {
+                if events.send((event.event, event.data)).is_err() {
+                    return;
+                }
+            }
+            Ok(MediaEvent::ServerShutdown) | Err(RecvError::Closed) => return,
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("Mirrored session lagged behind by {} events", skipped);
+            }
+        }
+    }
+}
+
+/// Subscribe to the retained state topics of a server and pass on their payloads
+fn follow_mqtt(
+    config: &MqttConfig,
+    prefix: &str,
+    events: UnboundedSender<(String, Value)>,
+) -> Result<AsyncClient> {
+    let client_id = format!("{}-publisher", config.client_id);
+    let options = mqtt::connect_options(config, &client_id)?;
+    let (client, mut eventloop) = AsyncClient::new(options, 32);
+
+    let (subscriber, prefix) = (client.clone(), prefix.to_string());
+    tokio::spawn(async move {
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    for (topic, _) in MQTT_TOPICS {
+                        let topic = format!("{}/{}", prefix, topic);
+                        if let Err(e) = subscriber.subscribe(topic, QoS::AtLeastOnce).await {
+                            tracing::error!("Failed to subscribe to the mirrored state: {}", e);
+                        }
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let name = publish.topic.strip_prefix(&prefix);
+                    let event = MQTT_TOPICS
+                        .iter()
+                        .find(|(topic, _)| name == Some(&format!("/{}", topic)[..]));
+                    let data = serde_json::from_slice(&publish.payload).unwrap_or_default();
+                    if let Some((_, event)) = event {
+                        if events.send((event.to_string(), data)).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("MQTT connection of the mirrored session failed: {}", e);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    });
+    Ok(client)
+}
+
+/// A media session of this process, shown in the flyout like any player's
+struct MirrorSession {
+    /// Owns the session, which ends when it is dropped
+    _player: MediaPlayer,
+    controls: SystemMediaTransportControls,
+}
+
+impl MirrorSession {
+    /// # Arguments
+    /// * `buttons` - Receives the commands of the flyout buttons and media keys
+    fn new(buttons: UnboundedSender<MediaCommand>) -> Result<Self> {
+        let player = MediaPlayer::new()?;
+        // The player plays nothing, the buttons are handled below instead
+        player.CommandManager()?.SetIsEnabled(false)?;
+        let controls = player.SystemMediaTransportControls()?;
+        controls.SetIsEnabled(true)?;
+        controls.SetPlaybackStatus(MediaPlaybackStatus::Closed)?;
+
+        let sender = buttons.clone();
+        controls.ButtonPressed(&TypedEventHandler::new(move |_, args| {
+            if let Some(args) = args.as_ref() {
+                let command = match args.Button()? {
+                    SystemMediaTransportControlsButton::Play => MediaCommand::Play,
+                    SystemMediaTransportControlsButton::Pause => MediaCommand::Pause,
+                    SystemMediaTransportControlsButton::Stop => MediaCommand::Stop,
+                    SystemMediaTransportControlsButton::Next => MediaCommand::NextTrack,
+                    SystemMediaTransportControlsButton::Previous => MediaCommand::PreviousTrack,
+                    SystemMediaTransportControlsButton::FastForward => MediaCommand::FastForward,
+                    SystemMediaTransportControlsButton::Rewind => MediaCommand::Rewind,
+                    _ => return windows::core::Result::Ok(()),
+                };
+                sender.send(command).ok();
+            }
+            windows::core::Result::Ok(())
+        }))?;
+        controls.PlaybackPositionChangeRequested(&TypedEventHandler::new(move |_, args| {
+            if let Some(args) = args.as_ref() {
+                let position: Duration = args.RequestedPlaybackPosition()?.into();
+                buttons
+                    .send(MediaCommand::Seek(SeekPosition {
+                        position: position.as_millis() as u64,
+                    }))
+                    .ok();
+            }
+            windows::core::Result::Ok(())
+        }))?;
+
+        Ok(Self {
+            _player: player,
+            controls,
+        })
+    }
+
+    /// Show a state event of the mirrored machine
+    fn apply(&self, event: &str, data: Value) -> Result<()> {
+        match event {
+            "state_snapshot" => {
+                for event in ["track_info", "track_controls", "track_timeline"] {
+                    self.apply(event, data[event].clone())?;
+                }
+            }
+            // An empty object on MQTT when there is no track
+            "track_info" => self.show_track(serde_json::from_value(data).ok().flatten())?,
+            "track_controls" => {
+                if let Ok(controls) = serde_json::from_value(data) {
+                    self.show_controls(&controls)?;
+                }
+            }
+            "track_timeline" => {
+                if let Ok(timeline) = serde_json::from_value(data) {
+                    self.show_timeline(&timeline)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn show_track(&self, track: Option<TrackInfo>) -> Result<()> {
+        let updater = self.controls.DisplayUpdater()?;
+        updater.ClearAll()?;
+        let Some(track) = track else {
+            self.controls
+                .SetPlaybackStatus(MediaPlaybackStatus::Closed)?;
+            return Ok(updater.Update()?);
+        };
+
+        updater.SetType(MediaPlaybackType::Music)?;
+        let music = updater.MusicProperties()?;
+        music.SetTitle(&HSTRING::from(&track.title))?;
+        music.SetArtist(&HSTRING::from(&track.artist))?;
+        if let Some(album) = &track.album {
+            music.SetAlbumTitle(&HSTRING::from(album))?;
+        }
+        if let Some(album_artist) = &track.album_artist {
+            music.SetAlbumArtist(&HSTRING::from(album_artist))?;
+        }
+        // The artwork only comes along with peers, MQTT leaves it out
+        let artwork = track.thumbnail.as_deref().and_then(decode_base64_image);
+        if let Some(bytes) = artwork {
+            updater.SetThumbnail(&artwork_reference(&bytes)?)?;
+        }
+        updater.Update()?;
+        Ok(())
+    }
+
+    fn show_controls(&self, controls: &TrackControls) -> Result<()> {
+        let status = if controls.playing() {
+            MediaPlaybackStatus::Playing
+        } else {
+            MediaPlaybackStatus::Paused
+        };
+        self.controls.SetPlaybackStatus(status)?;
+        self.controls
+            .SetIsPlayEnabled(controls.play_pause_enabled())?;
+        self.controls
+            .SetIsPauseEnabled(controls.play_pause_enabled())?;
+        self.controls.SetIsStopEnabled(true)?;
+        self.controls.SetIsNextEnabled(controls.next_enabled())?;
+        self.controls
+            .SetIsPreviousEnabled(controls.prev_enabled())?;
+        self.controls.SetShuffleEnabled(controls.shuffle())?;
+        self.controls
+            .SetAutoRepeatMode(match controls.auto_repeat_mode() {
+                AutoRepeatMode::None => MediaPlaybackAutoRepeatMode::None,
+                AutoRepeatMode::Track => MediaPlaybackAutoRepeatMode::Track,
+                AutoRepeatMode::List => MediaPlaybackAutoRepeatMode::List,
+            })?;
+        Ok(())
+    }
+
+    fn show_timeline(&self, timeline: &TrackTimeline) -> Result<()> {
+        let properties = SystemMediaTransportControlsTimelineProperties::new()?;
+        properties.SetStartTime(time_span(timeline.start_time()))?;
+        properties.SetEndTime(time_span(timeline.end_time()))?;
+        properties.SetMinSeekTime(time_span(timeline.min_seek_time()))?;
+        properties.SetMaxSeekTime(time_span(if timeline.seek_enabled() {
+            timeline.max_seek_time()
+        } else {
+            timeline.min_seek_time()
+        }))?;
+        properties.SetPosition(time_span(timeline.progress()))?;
+        self.controls.UpdateTimelineProperties(&properties)?;
+        Ok(())
+    }
+}
+
+/// Artwork as the stream reference the flyout reads it from
+fn artwork_reference(bytes: &[u8]) -> Result<RandomAccessStreamReference> {
+    let stream = InMemoryRandomAccessStream::new()?;
+    let writer = DataWriter::CreateDataWriter(&stream)?;
+    writer.WriteBytes(bytes)?;
+    writer.StoreAsync()?.get()?;
+    // The stream stays open once the writer lets go of it
+    writer.DetachStream()?;
+    stream.Seek(0)?;
+    Ok(RandomAccessStreamReference::CreateFromStream(&stream)?)
+}
+
+fn time_span(millis: u64) -> TimeSpan {
+    Duration::from_millis(millis).into()
+}
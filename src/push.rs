@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::{PushConfig, PushService};
+use crate::events::{EventHub, MediaEvent};
+use crate::templates::{self, Template};
+use crate::utils;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Leeway for the timer firing a little before its `ends_at`
+const TIMER_SLACK_MS: u64 = 2000;
+
+/// A notification for the phone, also the context of the title and message templates
+#[derive(Serialize)]
+struct Push {
+    /// `track_changed`, `sleep_timer` or `position_alarm`
+    event: &'static str,
+    title: String,
+    message: String,
+    /// The `track_info` payload without the artwork, `None` for the sleep timer
+    track: Option<serde_json::Value>,
+    /// The `position_alarm` payload, only for alarms
+    alarm: Option<serde_json::Value>,
+}
+
+/// A push target with its compiled templates
+struct Target {
+    config: PushConfig,
+    title: Option<Template>,
+    message: Option<Template>,
+}
+
+/// Push track changes, sleep timer pauses and position alarms to ntfy or Gotify
+pub async fn run(targets: Vec<PushConfig>, hub: EventHub) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(concat!("media-controller/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let mut compiled = Vec::new();
+    for config in targets {
+        compiled.push(Target {
+            title: Template::optional(config.title.as_deref())?,
+            message: Template::optional(config.message.as_deref())?,
+            config,
+        });
+    }
+    let targets = compiled;
+    tracing::info!("Pushing notifications to {} target(s)", targets.len());
+
+    let mut receiver = hub.subscribe();
+    let mut shown = None;
+    // End of the running sleep timer, to tell it running out from it being cancelled
+    let mut timer_ends_at = None;
+    loop {
+        let push = match receiver.recv().await {
+            Ok(MediaEvent::TrackInfo(Some(track))) => {
+                // Track info is sent again when metadata or artwork arrive for the same track
+                let key = (
+                    track.session.session_id.clone(),
+                    track.title.clone(),
+                    track.artist.clone(),
+                );
+                if track.stale || shown.as_ref() == Some(&key) {
+                    continue;
+                }
+                shown = Some(key);
+                let message = match &track.album {
+                    Some(album) if !album.is_empty() => format!("{} · {}", track.artist, album),
+                    _ => track.artist.clone(),
+                };
+                let mut payload = serde_json::to_value(&track).unwrap_or_default();
+                if let Some(track) = payload.as_object_mut() {
+                    track.remove("thumbnail");
+                    track.remove("thumbnails");
+                }
+                Push {
+                    event: "track_changed",
+                    title: track.title,
+                    message,
+                    track: Some(payload),
+                    alarm: None,
+                }
+            }
+            Ok(MediaEvent::SleepTimerStatus(status)) => {
+                if status.active {
+                    timer_ends_at = status.ends_at;
+                    continue;
+                }
+                match timer_ends_at.take() {
+                    Some(ends_at) if utils::unix_millis() + TIMER_SLACK_MS >= ends_at => Push {
+                        event: "sleep_timer",
+                        title: String::from("Sleep timer"),
+                        message: String::from("Playback was paused"),
+                        track: None,
+                        alarm: None,
+                    },
+                    _ => continue,
+                }
+            }
+            Ok(MediaEvent::PositionAlarm(alarm)) => Push {
+                event: "position_alarm",
+                title: alarm
+                    .label
+                    .clone()
+                    .unwrap_or_else(|| String::from("Position alarm")),
+                message: format!(
+                    "{} reached {}",
+                    alarm.title,
+                    templates::duration(alarm.position)
+                ),
+                track: None,
+                alarm: serde_json::to_value(&alarm).ok(),
+            },
+            Ok(MediaEvent::ServerShutdown) => return Ok(()),
+            Ok(_) => continue,
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("Push notifications lagged behind by {} events", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+
+        for target in &targets {
+            let events = &target.config.events;
+            if !events.is_empty() && !events.iter().any(|event| event == push.event) {
+                continue;
+            }
+            if let Err(e) = send(&client, target, &push).await {
+                tracing::error!(
+                    "Failed to push {} to {}: {:#}",
+                    push.event,
+                    target.config.url,
+                    e
+                );
+            }
+        }
+    }
+}
+
+async fn send(client: &reqwest::Client, target: &Target, push: &Push) -> Result<()> {
+    let title = match &target.title {
+        Some(template) => template.render(push)?,
+        None => push.title.clone(),
+    };
+    let message = match &target.message {
+        Some(template) => template.render(push)?,
+        None => push.message.clone(),
+    };
+    let target = &target.config;
+    let request = match target.service {
+        // The topic URL takes the message as the body and the rest as parameters,
+        // headers would only take ASCII titles
+        PushService::Ntfy => {
+            let mut request = client
+                .post(&target.url)
+                .query(&[
+                    ("title", title.as_str()),
+                    ("tags", "musical_note"),
+                    ("priority", &target.priority.to_string()),
+                ])
+                .body(message);
+            if let Some(token) = &target.token {
+                request = request.bearer_auth(token);
+            }
+            request
+        }
+        PushService::Gotify => {
+            let mut request = client
+                .post(format!("{}/message", target.url.trim_end_matches('/')))
+                .json(&json!({
+                    "title": title,
+                    "message": message,
+                    "priority": target.priority,
+                }));
+            if let Some(token) = &target.token {
+                request = request.header("X-Gotify-Key", token);
+            }
+            request
+        }
+    };
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
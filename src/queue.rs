@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::error::{MediaError, MediaResult};
+use crate::spotify::{self, SpotifyClient};
+
+/// An entry of an app's play queue
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct QueueItem {
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    /// Duration in milliseconds
+    pub duration: u64,
+    /// URL of the artwork
+    pub artwork: Option<String>,
+}
+
+/// What's playing and what's up next, as reported by the app itself
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct Queue {
+    /// Provider the queue came from, e.g. `spotify`
+    pub source: String,
+    pub current: Option<QueueItem>,
+    pub upcoming: Vec<QueueItem>,
+}
+
+/// A source of queue information for apps that expose more than GSMTC does
+pub trait QueueProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Whether this provider can describe the queue of the given source app
+    fn supports(&self, source_app_id: &str) -> bool;
+
+    fn queue(&self) -> Result<Queue>;
+}
+
+/// Picks the provider matching the current session's app
+pub struct QueueService {
+    providers: Vec<Box<dyn QueueProvider>>,
+}
+
+impl QueueService {
+    pub fn new(spotify: Option<Arc<SpotifyClient>>) -> Self {
+        let mut providers: Vec<Box<dyn QueueProvider>> = Vec::new();
+        if let Some(client) = spotify {
+            providers.push(Box::new(SpotifyQueueProvider { client }));
+        }
+        Self { providers }
+    }
+
+    /// Queue of the app owning the current session
+    ///
+    /// # Arguments
+    /// * `source_app_id` - App User Model ID of the current session
+    pub fn queue(&self, source_app_id: &str) -> MediaResult<Queue> {
+        let provider = self
+            .providers
+            .iter()
+            .find(|provider| provider.supports(source_app_id))
+            .ok_or_else(|| MediaError::unsupported("queue"))?;
+
+        provider.queue().map_err(|e| {
+            MediaError::internal(format!("{} queue lookup failed: {:#}", provider.name(), e))
+        })
+    }
+}
+
+/// Queue of the Spotify desktop app, through the Web API
+struct SpotifyQueueProvider {
+    client: Arc<SpotifyClient>,
+}
+
+impl QueueProvider for SpotifyQueueProvider {
+    fn name(&self) -> &'static str {
+        "spotify"
+    }
+
+    fn supports(&self, source_app_id: &str) -> bool {
+        spotify::is_spotify_app(source_app_id)
+    }
+
+    fn queue(&self) -> Result<Queue> {
+        self.client.queue()
+    }
+}
@@ -0,0 +1,180 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::time::MissedTickBehavior;
+
+use crate::config::QuietHoursConfig;
+use crate::cron::LocalTime;
+use crate::error::{MediaError, MediaResult};
+use crate::events::EventHub;
+
+/// Interval between checks of the schedule and the volume, the volume can be
+/// raised with the keyboard at any time
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Payload of the `quiet_hours_changed` event
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct QuietHoursStatus {
+    pub active: bool,
+    /// Whether `quiet_hours_override` went against the schedule
+    pub overridden: bool,
+    /// Highest system volume in percent while active
+    pub max_volume: u8,
+    /// Whether starting playback is refused while active
+    pub refuse_play: bool,
+    /// Local time they start, e.g. `22:00`
+    pub start: String,
+    /// Local time they end, e.g. `07:00`
+    pub end: String,
+}
+
+/// Payload of `quiet_hours_override`
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct QuietHoursOverride {
+    /// `override_token` of the quiet hours config
+    pub token: String,
+    /// Turn quiet hours on or off until they next start or end, `None` follows
+    /// the schedule again
+    pub active: Option<bool>,
+}
+
+/// Caps the volume and refuses playback during the configured hours
+pub struct QuietHours {
+    config: QuietHoursConfig,
+    /// Minutes after midnight
+    start: u32,
+    end: u32,
+    state: Mutex<QuietState>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct QuietState {
+    /// Whether the schedule was active at the last check
+    scheduled: bool,
+    overridden: Option<bool>,
+}
+
+impl QuietState {
+    fn active(self) -> bool {
+        self.overridden.unwrap_or(self.scheduled)
+    }
+}
+
+impl QuietHours {
+    pub fn new(config: &QuietHoursConfig) -> Result<Self> {
+        let start = parse_time(&config.start).context("Invalid quiet hours start")?;
+        let end = parse_time(&config.end).context("Invalid quiet hours end")?;
+        if config.max_volume > 100 {
+            bail!("Quiet hours volume must be a percentage");
+        }
+        Ok(Self {
+            config: config.clone(),
+            start,
+            end,
+            state: Mutex::new(QuietState {
+                scheduled: in_window(start, end, LocalTime::now()),
+                overridden: None,
+            }),
+        })
+    }
+
+    pub fn status(&self) -> QuietHoursStatus {
+        let state = self.state();
+        QuietHoursStatus {
+            active: state.active(),
+            overridden: state
+                .overridden
+                .is_some_and(|active| active != state.scheduled),
+            max_volume: self.config.max_volume,
+            refuse_play: self.config.refuse_play,
+            start: self.config.start.clone(),
+            end: self.config.end.clone(),
+        }
+    }
+
+    /// Highest system volume as a scalar, `None` outside quiet hours
+    pub fn volume_cap(&self) -> Option<f32> {
+        self.state()
+            .active()
+            .then(|| f32::from(self.config.max_volume) / 100.0)
+    }
+
+    pub fn refuses_play(&self) -> bool {
+        self.config.refuse_play && self.state().active()
+    }
+
+    /// Follow the schedule, an override lasts until quiet hours next start or end
+    ///
+    /// # Returns
+    /// * `Option<QuietHoursStatus>` - The new status when they started or ended
+    pub fn update(&self, now: LocalTime) -> Option<QuietHoursStatus> {
+        let scheduled = in_window(self.start, self.end, now);
+        {
+            let mut state = self.state.lock().ok()?;
+            if state.scheduled == scheduled {
+                return None;
+            }
+            state.scheduled = scheduled;
+            state.overridden = None;
+        }
+        Some(self.status())
+    }
+
+    /// Turn quiet hours on or off against the schedule
+    pub fn set_override(&self, request: &QuietHoursOverride) -> MediaResult<QuietHoursStatus> {
+        let Some(token) = &self.config.override_token else {
+            return Err(MediaError::unsupported("quiet_hours_override"));
+        };
+        if request.token != *token {
+            tracing::warn!("Rejected a quiet hours override with a wrong token");
+            return Err(MediaError::invalid_input("Wrong quiet hours token"));
+        }
+        self.state.lock().map_err(MediaError::internal)?.overridden = request.active;
+        Ok(self.status())
+    }
+
+    fn state(&self) -> QuietState {
+        self.state.lock().map(|state| *state).unwrap_or_default()
+    }
+}
+
+/// Start and end quiet hours on schedule and keep the volume below the cap
+pub async fn run(hub: EventHub) {
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        let hub = hub.clone();
+        match tokio::task::spawn_blocking(move || hub.check_quiet_hours()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to cap the volume during quiet hours: {}", e),
+            Err(e) => tracing::error!("Quiet hours task failed: {}", e),
+        }
+    }
+}
+
+/// Minutes after midnight of a `HH:MM` time
+fn parse_time(time: &str) -> Result<u32> {
+    let (hour, minute) = time
+        .split_once(':')
+        .with_context(|| format!("Expected HH:MM, got {}", time))?;
+    let hour: u32 = hour.trim().parse().context("Invalid hour")?;
+    let minute: u32 = minute.trim().parse().context("Invalid minute")?;
+    if hour > 23 || minute > 59 {
+        bail!("{} is not a time of day", time);
+    }
+    Ok(hour * 60 + minute)
+}
+
+/// Whether a time is between the start and the end, which may be past midnight
+fn in_window(start: u32, end: u32, now: LocalTime) -> bool {
+    let minute = now.hour * 60 + now.minute;
+    if start <= end {
+        (start..end).contains(&minute)
+    } else {
+        minute >= start || minute < end
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Commands a client may burst before being throttled
+const BURST_CAPACITY: f64 = 20.0;
+/// Commands per second a client regains after a burst
+const REFILL_PER_SECOND: f64 = 10.0;
+/// Window in which rapid seeks are collapsed into the latest position
+pub const SEEK_COALESCE_WINDOW: Duration = Duration::from_millis(100);
+
+/// Token bucket limiting how fast a single client can issue commands
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: f64, refill_per_second: f64) -> Self {
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_second,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take a token if one is available
+    ///
+    /// # Returns
+    /// * `bool` - `false` when the client is over its rate limit
+    pub fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Default for TokenBucket {
+    fn default() -> Self {
+        Self::new(BURST_CAPACITY, REFILL_PER_SECOND)
+    }
+}
+
+/// Per-client command limiter, cheap to clone and share between handlers
+#[derive(Debug, Clone, Default)]
+pub struct CommandLimiter {
+    bucket: Arc<Mutex<TokenBucket>>,
+    pending_seek: Arc<Mutex<Option<u64>>>,
+}
+
+impl CommandLimiter {
+    pub fn allow(&self) -> bool {
+        match self.bucket.lock() {
+            Ok(mut bucket) => bucket.try_acquire(),
+            Err(_) => true,
+        }
+    }
+
+    /// Record a seek request, replacing any seek still waiting in the current window
+    ///
+    /// # Returns
+    /// * `bool` - `true` if this seek opened a new window and the caller should
+    ///   apply the latest position once it closes
+    pub fn queue_seek(&self, position: u64) -> bool {
+        match self.pending_seek.lock() {
+            Ok(mut pending) => pending.replace(position).is_none(),
+            Err(_) => true,
+        }
+    }
+
+    /// Close the current seek window, returning the latest requested position
+    pub fn take_seek(&self) -> Option<u64> {
+        self.pending_seek
+            .lock()
+            .ok()
+            .and_then(|mut pending| pending.take())
+    }
+}
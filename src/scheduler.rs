@@ -0,0 +1,316 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+use tokio::task::AbortHandle;
+use utoipa::ToSchema;
+
+use crate::cron::{CronExpr, LocalTime};
+use crate::error::{MediaError, MediaResult};
+use crate::utils;
+
+/// Longest accepted sleep timer, anything above is most likely a unit mistake
+const MAX_SLEEP_TIMER: Duration = Duration::from_secs(24 * 60 * 60);
+const SCHEDULES_FILE: &str = "schedules.json";
+
+/// Payload of `set_sleep_timer`, either `minutes` or `at` must be set
+#[derive(Debug, Deserialize, JsonSchema, ToSchema)]
+pub struct SleepTimerRequest {
+    /// Minutes from now
+    pub minutes: Option<u64>,
+    /// Absolute time as a Unix timestamp in milliseconds
+    pub at: Option<u64>,
+    /// Fade the system volume out over this many seconds before pausing
+    pub fade_seconds: Option<u64>,
+}
+
+impl SleepTimerRequest {
+    /// Time left until the timer fires
+    fn delay(&self) -> MediaResult<Duration> {
+        let delay = match (self.minutes, self.at) {
+            (Some(minutes), None) => Duration::from_secs(minutes.saturating_mul(60)),
+            (None, Some(at)) => Duration::from_millis(at.saturating_sub(utils::unix_millis())),
+            _ => {
+                return Err(MediaError::invalid_input(
+                    "Sleep timer needs either minutes or at",
+                ));
+            }
+        };
+
+        if delay > MAX_SLEEP_TIMER {
+            return Err(MediaError::invalid_input(
+                "Sleep timer is longer than a day",
+            ));
+        }
+        Ok(delay)
+    }
+}
+
+/// Payload of the `sleep_timer_status` event
+#[derive(Debug, Serialize, Clone, Default, JsonSchema, ToSchema)]
+pub struct SleepTimerStatus {
+    pub active: bool,
+    /// Unix timestamp in milliseconds
+    pub ends_at: Option<u64>,
+    pub fade_seconds: Option<u64>,
+}
+
+/// What a schedule does when it fires
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduledAction {
+    Play,
+    Pause,
+    SetVolume {
+        /// Scalar between 0 and 1
+        level: f32,
+    },
+}
+
+/// Payload of `add_schedule`
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScheduleRequest {
+    /// Cron expression in local time, e.g. `0 7 * * 1-5`
+    pub cron: String,
+    pub action: ScheduledAction,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScheduleTarget {
+    pub id: u64,
+}
+
+/// A recurring action, stored across restarts
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Schedule {
+    pub id: u64,
+    pub cron: String,
+    pub action: ScheduledAction,
+    pub label: Option<String>,
+}
+
+struct SleepTimer {
+    id: u64,
+    status: SleepTimerStatus,
+    task: AbortHandle,
+}
+
+/// Runs timed actions on the async runtime
+#[derive(Clone)]
+pub struct Scheduler {
+    runtime: Handle,
+    sleep_timer: Arc<Mutex<Option<SleepTimer>>>,
+    schedules: Arc<Mutex<Vec<Schedule>>>,
+}
+
+impl Scheduler {
+    /// # Arguments
+    /// * `runtime` - Runtime timers are spawned on, commands run on blocking threads without one
+    pub fn new(runtime: Handle) -> Self {
+        Self {
+            runtime,
+            sleep_timer: Arc::new(Mutex::new(None)),
+            schedules: Arc::new(Mutex::new(read_schedules())),
+        }
+    }
+
+    pub fn sleep_timer_status(&self) -> SleepTimerStatus {
+        self.sleep_timer
+            .lock()
+            .ok()
+            .and_then(|timer| timer.as_ref().map(|timer| timer.status.clone()))
+            .unwrap_or_default()
+    }
+
+    /// Start the sleep timer, replacing a running one
+    ///
+    /// # Arguments
+    /// * `request` - When to fire and how long to fade
+    /// * `on_fire` - Blocking action to run when the timer fires
+    pub fn set_sleep_timer<F>(
+        &self,
+        request: &SleepTimerRequest,
+        on_fire: F,
+    ) -> MediaResult<SleepTimerStatus>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let delay = request.delay()?;
+        let mut timer = self.sleep_timer.lock().map_err(MediaError::internal)?;
+
+        let id = timer.as_ref().map_or(0, |timer| timer.id + 1);
+        if let Some(previous) = timer.take() {
+            previous.task.abort();
+        }
+
+        let sleep_timer = Arc::clone(&self.sleep_timer);
+        let task = self.runtime.spawn(async move {
+            tokio::time::sleep(delay).await;
+            tokio::task::spawn_blocking(on_fire).await.ok();
+
+            // Only clear the timer if it wasn't replaced in the meantime
+            let Ok(mut timer) = sleep_timer.lock() else {
+                return;
+            };
+            if timer.as_ref().is_some_and(|timer| timer.id == id) {
+                *timer = None;
+            }
+        });
+
+        let status = SleepTimerStatus {
+            active: true,
+            ends_at: Some(utils::unix_millis() + delay.as_millis() as u64),
+            fade_seconds: request.fade_seconds,
+        };
+        *timer = Some(SleepTimer {
+            id,
+            status: status.clone(),
+            task: task.abort_handle(),
+        });
+        Ok(status)
+    }
+
+    /// Cancel the sleep timer if one is running
+    pub fn cancel_sleep_timer(&self) -> MediaResult<SleepTimerStatus> {
+        let mut timer = self.sleep_timer.lock().map_err(MediaError::internal)?;
+        if let Some(timer) = timer.take() {
+            timer.task.abort();
+        }
+        Ok(SleepTimerStatus::default())
+    }
+}
+
+impl Scheduler {
+    pub fn schedules(&self) -> MediaResult<Vec<Schedule>> {
+        Ok(self.schedules.lock().map_err(MediaError::internal)?.clone())
+    }
+
+    /// Add a recurring action and persist it
+    ///
+    /// # Returns
+    /// * `MediaResult<Vec<Schedule>>` - Every schedule, including the new one
+    pub fn add_schedule(&self, request: ScheduleRequest) -> MediaResult<Vec<Schedule>> {
+        CronExpr::parse(&request.cron)
+            .map_err(|e| MediaError::invalid_input(format!("Invalid cron expression: {:#}", e)))?;
+        match request.action {
+            ScheduledAction::SetVolume { level } if !(0.0..=1.0).contains(&level) => {
+                return Err(MediaError::invalid_input(format!(
+                    "Volume must be between 0 and 1, got {}",
+                    level
+                )));
+            }
+            _ => {}
+        }
+
+        let mut schedules = self.schedules.lock().map_err(MediaError::internal)?;
+        let id = schedules
+            .iter()
+            .map(|schedule| schedule.id + 1)
+            .max()
+            .unwrap_or(0);
+        schedules.push(Schedule {
+            id,
+            cron: request.cron,
+            action: request.action,
+            label: request.label,
+        });
+        write_schedules(&schedules);
+        Ok(schedules.clone())
+    }
+
+    /// Remove a schedule and persist the change
+    ///
+    /// # Returns
+    /// * `MediaResult<Vec<Schedule>>` - The remaining schedules
+    pub fn remove_schedule(&self, id: u64) -> MediaResult<Vec<Schedule>> {
+        let mut schedules = self.schedules.lock().map_err(MediaError::internal)?;
+        let count = schedules.len();
+        schedules.retain(|schedule| schedule.id != id);
+        if schedules.len() == count {
+            return Err(MediaError::invalid_input(format!(
+                "No schedule with id {}",
+                id
+            )));
+        }
+        write_schedules(&schedules);
+        Ok(schedules.clone())
+    }
+
+    /// Check the schedules at the start of every minute
+    ///
+    /// # Arguments
+    /// * `on_due` - Blocking action runner, called once per due schedule
+    pub fn start_schedules<F>(&self, on_due: F)
+    where
+        F: Fn(ScheduledAction) + Send + Sync + 'static,
+    {
+        let schedules = Arc::clone(&self.schedules);
+        let on_due = Arc::new(on_due);
+        self.runtime.spawn(async move {
+            let mut last_checked = None;
+            loop {
+                let into_minute = utils::unix_millis() % 60_000;
+                tokio::time::sleep(Duration::from_millis(60_000 - into_minute)).await;
+
+                // Clock adjustments can wake us twice in the same minute
+                let now = LocalTime::now();
+                if last_checked == Some(now) {
+                    continue;
+                }
+                last_checked = Some(now);
+
+                let due: Vec<Schedule> = match schedules.lock() {
+                    Ok(schedules) => schedules
+                        .iter()
+                        .filter(|schedule| is_due(schedule, &now))
+                        .cloned()
+                        .collect(),
+                    Err(_) => continue,
+                };
+                for schedule in due {
+                    tracing::info!("Running schedule {} ({})", schedule.id, schedule.cron);
+                    let on_due = Arc::clone(&on_due);
+                    tokio::task::spawn_blocking(move || on_due(schedule.action));
+                }
+            }
+        });
+    }
+}
+
+fn is_due(schedule: &Schedule, now: &LocalTime) -> bool {
+    match CronExpr::parse(&schedule.cron) {
+        Ok(cron) => cron.matches(now),
+        Err(e) => {
+            tracing::warn!("Skipping schedule {}: {:#}", schedule.id, e);
+            false
+        }
+    }
+}
+
+fn schedules_path() -> PathBuf {
+    utils::data_dir().join(SCHEDULES_FILE)
+}
+
+fn read_schedules() -> Vec<Schedule> {
+    std::fs::read(schedules_path())
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn write_schedules(schedules: &[Schedule]) {
+    let path = schedules_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).ok();
+    }
+    let result = serde_json::to_vec_pretty(schedules)
+        .map_err(std::io::Error::other)
+        .and_then(|json| std::fs::write(&path, json));
+    if let Err(e) = result {
+        tracing::error!("Failed to store schedules at {}: {}", path.display(), e);
+    }
+}
@@ -0,0 +1,41 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use schemars::r#gen::SchemaSettings;
+
+use crate::events::{CommandAck, MediaCommand, MediaEvent};
+use crate::protocol::{PROTOCOL_VERSION, ServerPing};
+
+/// Write the JSON Schema of the Socket.IO and WebSocket protocol
+///
+/// Events and functions are described as `{ event, data }` envelopes, the form
+/// the plain WebSocket uses, so the event codes are part of the schema. Every
+/// payload type is listed under `definitions`.
+///
+/// # Arguments
+/// * `output` - File to write, stdout when `None`
+pub fn generate(output: Option<&Path>) -> Result<()> {
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let events = generator.subschema_for::<MediaEvent>();
+    let functions = generator.subschema_for::<MediaCommand>();
+    generator.subschema_for::<CommandAck>();
+    generator.subschema_for::<ServerPing>();
+
+    let schema = serde_json::json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": format!("media-controller protocol v{}", PROTOCOL_VERSION),
+        "properties": {
+            "events": events,
+            "functions": functions,
+        },
+        "definitions": generator.definitions(),
+    });
+    let json = serde_json::to_string_pretty(&schema)?;
+
+    match output {
+        Some(path) => std::fs::write(path, json)
+            .with_context(|| format!("Failed to write {}", path.display()))?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::ScrubbingConfig;
+use crate::error::{MediaError, MediaResult};
+use crate::events::EventHub;
+use crate::volume::Volume;
+
+/// A drag without previews for this long was abandoned, e.g. the client went away mid-drag
+pub const SCRUB_TIMEOUT: Duration = Duration::from_secs(3);
+/// Wait between the committing seek and unmuting, so the jump itself stays silent
+pub const UNMUTE_DELAY: Duration = Duration::from_millis(250);
+
+/// Where a socket is in dragging the progress slider, kept in its extensions
+#[derive(Clone, Default)]
+pub struct Scrub {
+    state: Arc<Mutex<ScrubState>>,
+}
+
+#[derive(Default)]
+enum ScrubState {
+    #[default]
+    Idle,
+    Scrubbing {
+        /// App that was muted for the drag, with its level before
+        muted: Option<(String, f32)>,
+        last_preview: Instant,
+    },
+}
+
+impl Scrub {
+    /// Take a preview position, the first one starts the drag and mutes the app when configured
+    ///
+    /// # Arguments
+    /// * `app_id` - App the socket is pinned to, the current session's otherwise
+    ///
+    /// # Returns
+    /// * `MediaResult<bool>` - Whether this preview started the drag
+    pub fn preview(
+        &self,
+        hub: &EventHub,
+        config: &ScrubbingConfig,
+        app_id: Option<String>,
+    ) -> MediaResult<bool> {
+        let mut state = self.state.lock().map_err(MediaError::internal)?;
+        if let ScrubState::Scrubbing { last_preview, .. } = &mut *state {
+            *last_preview = Instant::now();
+            return Ok(false);
+        }
+
+        let muted = if config.mute {
+            let app_id = match app_id {
+                Some(app_id) => app_id,
+                None => hub.media().call(|manager| manager.current_app_id())??,
+            };
+            match mute(&app_id) {
+                Ok(level) => Some((app_id, level)),
+                // Scrubbing still works, just not silently
+                Err(e) => {
+                    tracing::warn!("Failed to mute {} while scrubbing: {}", app_id, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        *state = ScrubState::Scrubbing {
+            muted,
+            last_preview: Instant::now(),
+        };
+        Ok(true)
+    }
+
+    pub fn is_scrubbing(&self) -> bool {
+        self.state
+            .lock()
+            .is_ok_and(|state| matches!(*state, ScrubState::Scrubbing { .. }))
+    }
+
+    /// Time since the last preview, `None` when not scrubbing
+    pub fn idle_for(&self) -> Option<Duration> {
+        match *self.state.lock().ok()? {
+            ScrubState::Scrubbing { last_preview, .. } => Some(last_preview.elapsed()),
+            ScrubState::Idle => None,
+        }
+    }
+
+    /// End the drag, restoring the volume of the app when it was muted
+    pub fn finish(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let ScrubState::Scrubbing { muted, .. } = std::mem::take(&mut *state) else {
+            return;
+        };
+        if let Some((app_id, level)) = muted {
+            if let Err(e) = Volume::app(&app_id).and_then(|volume| volume.set_level(level)) {
+                tracing::error!("Failed to unmute {} after scrubbing: {}", app_id, e);
+            }
+        }
+    }
+}
+
+/// Silence an app
+///
+/// # Returns
+/// * `MediaResult<f32>` - The level it had before
+fn mute(app_id: &str) -> MediaResult<f32> {
+    let volume = Volume::app(app_id)?;
+    let level = volume.level()?;
+    volume.set_level(0.0)?;
+    Ok(level)
+}
+
+/// End a drag once its client stops sending previews without committing a seek
+pub async fn end_abandoned(scrub: Scrub) {
+    loop {
+        tokio::time::sleep(SCRUB_TIMEOUT).await;
+        match scrub.idle_for() {
+            Some(idle) if idle >= SCRUB_TIMEOUT => break,
+            Some(_) => continue,
+            None => return,
+        }
+    }
+    tracing::debug!("Scrubbing was abandoned, restoring the volume");
+    tokio::task::spawn_blocking(move || scrub.finish())
+        .await
+        .ok();
+}
@@ -0,0 +1,161 @@
+use std::sync::Mutex;
+#[cfg(windows)]
+use std::sync::OnceLock;
+
+use anyhow::{Result, bail};
+use schemars::JsonSchema;
+use serde::Serialize;
+#[cfg(windows)]
+use tokio::sync::mpsc::{self, UnboundedSender};
+#[cfg(windows)]
+use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+#[cfg(windows)]
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+#[cfg(windows)]
+use windows::Win32::System::RemoteDesktop::{
+    NOTIFY_FOR_THIS_SESSION, WTSRegisterSessionNotification,
+};
+#[cfg(windows)]
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, HWND_MESSAGE, MSG,
+    RegisterClassW, WINDOW_EX_STYLE, WINDOW_STYLE, WM_WTSSESSION_CHANGE, WNDCLASSW,
+    WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+};
+#[cfg(windows)]
+use windows::core::w;
+
+use crate::config::LockConfig;
+use crate::events::EventHub;
+
+/// The window procedure has no state of its own to reach the watcher through
+#[cfg(windows)]
+static CHANGES: OnceLock<UnboundedSender<bool>> = OnceLock::new();
+
+/// Payload of the `lock_changed` event
+#[derive(Debug, Serialize, Clone, Copy, Default, JsonSchema)]
+pub struct LockStatus {
+    /// The workstation is locked
+    pub locked: bool,
+    /// Playback was paused because it locked, and is resumed on unlock when configured
+    pub paused_by_lock: bool,
+}
+
+/// Pauses playback while the workstation is locked
+pub struct SessionLock {
+    config: LockConfig,
+    status: Mutex<LockStatus>,
+}
+
+impl SessionLock {
+    pub fn new(config: &LockConfig) -> Self {
+        Self {
+            config: config.clone(),
+            status: Mutex::new(LockStatus::default()),
+        }
+    }
+
+    pub fn config(&self) -> &LockConfig {
+        &self.config
+    }
+
+    pub fn status(&self) -> LockStatus {
+        self.status.lock().map(|status| *status).unwrap_or_default()
+    }
+
+    pub fn set_status(&self, status: LockStatus) {
+        if let Ok(mut current) = self.status.lock() {
+            *current = status;
+        }
+    }
+}
+
+/// Follow the lock and unlock of the Windows session this server runs in
+#[cfg(windows)]
+pub async fn run(hub: EventHub) -> Result<()> {
+    let (sender, mut changes) = mpsc::unbounded_channel();
+    if CHANGES.set(sender).is_err() {
+        bail!("The session lock is already watched");
+    }
+    std::thread::spawn(|| {
+        if let Err(e) = watch_session() {
+            tracing::error!("Failed to watch the session lock: {}", e);
+        }
+    });
+    tracing::info!("Watching the session lock");
+
+    while let Some(locked) = changes.recv().await {
+        let hub = hub.clone();
+        match tokio::task::spawn_blocking(move || hub.set_locked(locked)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to follow the session lock: {}", e),
+            Err(e) => tracing::error!("Session lock task failed: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Only Windows sessions are followed
+#[cfg(not(windows))]
+pub async fn run(_hub: EventHub) -> Result<()> {
+    bail!("The session lock is only watched on Windows")
+}
+
+/// Receive session changes in a message-only window, blocking for good
+#[cfg(windows)]
+fn watch_session() -> Result<()> {
+    let class = w!("MediaControllerSessionLock");
+    unsafe {
+        let instance = GetModuleHandleW(None)?;
+        let window_class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance.into(),
+            lpszClassName: class,
+            ..Default::default()
+        };
+        if RegisterClassW(&window_class) == 0 {
+            return Err(windows::core::Error::from_win32().into());
+        }
+        let window = CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            class,
+            w!(""),
+            WINDOW_STYLE::default(),
+            0,
+            0,
+            0,
+            0,
+            Some(HWND_MESSAGE),
+            None,
+            Some(instance.into()),
+            None,
+        )?;
+        WTSRegisterSessionNotification(window, NOTIFY_FOR_THIS_SESSION)?;
+
+        let mut message = MSG::default();
+        while GetMessageW(&mut message, None, 0, 0).as_bool() {
+            DispatchMessageW(&message);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if message == WM_WTSSESSION_CHANGE {
+        let locked = match wparam.0 as u32 {
+            WTS_SESSION_LOCK => Some(true),
+            WTS_SESSION_UNLOCK => Some(false),
+            _ => None,
+        };
+        if let (Some(locked), Some(changes)) = (locked, CHANGES.get()) {
+            changes.send(locked).ok();
+        }
+        return LRESULT(0);
+    }
+    unsafe { DefWindowProcW(window, message, wparam, lparam) }
+}
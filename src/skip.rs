@@ -0,0 +1,119 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::config::SkipRule;
+use crate::media_manager::{SessionInfo, TrackInfo};
+use crate::utils;
+
+/// Apps fire several track changes per track, the same track is only skipped once in this window
+const SKIP_DEBOUNCE: Duration = Duration::from_secs(5);
+
+/// Payload of the `track_skipped` event
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct TrackSkipped {
+    #[serde(flatten)]
+    pub session: SessionInfo,
+    pub title: String,
+    pub artist: String,
+    /// Index of the matching rule in the config file
+    pub rule: usize,
+}
+
+struct CompiledRule {
+    title: Option<Regex>,
+    artist: Option<Regex>,
+    app: Option<Regex>,
+}
+
+impl CompiledRule {
+    fn matches(&self, track: &TrackInfo) -> bool {
+        let matches = |pattern: &Option<Regex>, values: &[&str]| {
+            pattern
+                .as_ref()
+                .is_none_or(|pattern| values.iter().any(|value| pattern.is_match(value)))
+        };
+        matches(&self.title, &[&track.title])
+            && matches(&self.artist, &[&track.artist])
+            && matches(
+                &self.app,
+                &[&track.session.app_name, &track.session.source_app_id],
+            )
+    }
+}
+
+/// Tracks that are skipped as soon as they start playing
+pub struct SkipList {
+    rules: Vec<CompiledRule>,
+    /// Key and time of the last skipped track
+    last_skipped: Mutex<Option<(String, Instant)>>,
+}
+
+impl SkipList {
+    /// Compile the skip rules of the config file
+    ///
+    /// # Arguments
+    /// * `rules` - Rules with regex patterns, a track must match every pattern a rule sets
+    pub fn new(rules: &[SkipRule]) -> Result<Self> {
+        let compile = |index: usize, pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .with_context(|| format!("Invalid pattern in skip rule {}", index))
+        };
+
+        let mut compiled = Vec::with_capacity(rules.len());
+        for (index, rule) in rules.iter().enumerate() {
+            if rule.title.is_none() && rule.artist.is_none() && rule.app.is_none() {
+                bail!(
+                    "Skip rule {} has no pattern and would skip every track",
+                    index
+                );
+            }
+            compiled.push(CompiledRule {
+                title: compile(index, &rule.title)?,
+                artist: compile(index, &rule.artist)?,
+                app: compile(index, &rule.app)?,
+            });
+        }
+
+        Ok(Self {
+            rules: compiled,
+            last_skipped: Mutex::new(None),
+        })
+    }
+
+    /// Check a new track against the rules
+    ///
+    /// # Returns
+    /// * `Option<TrackSkipped>` - The event to broadcast when the track should be skipped
+    pub fn check(&self, track: &TrackInfo) -> Option<TrackSkipped> {
+        let rule = self.rules.iter().position(|rule| rule.matches(track))?;
+
+        let key = format!(
+            "{}:{}",
+            track.session.session_id,
+            utils::track_key(&track.artist, &track.title)
+        );
+        let mut last_skipped = self.last_skipped.lock().ok()?;
+        let repeated = last_skipped
+            .as_ref()
+            .is_some_and(|(last_key, at)| *last_key == key && at.elapsed() < SKIP_DEBOUNCE);
+        if repeated {
+            return None;
+        }
+        *last_skipped = Some((key, Instant::now()));
+
+        Some(TrackSkipped {
+            session: track.session.clone(),
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            rule,
+        })
+    }
+}
@@ -0,0 +1,233 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::config::SnapcastConfig;
+use crate::events::EventHub;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Snapcast only notifies over raw TCP, so the status is polled
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// A Snapcast group, clients in it play the same stream in sync
+#[derive(Debug, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct OutputGroup {
+    pub id: String,
+    pub name: String,
+    /// Stream the group plays, e.g. `default`
+    pub stream: String,
+    pub muted: bool,
+    pub outputs: Vec<Output>,
+}
+
+/// A Snapcast client, one speaker or room
+#[derive(Debug, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct Output {
+    pub id: String,
+    /// Name set in Snapcast, the host name otherwise
+    pub name: String,
+    pub connected: bool,
+    /// Between 0 and 1
+    pub volume: f32,
+    pub muted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Status {
+    server: ServerStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerStatus {
+    groups: Vec<GroupStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupStatus {
+    id: String,
+    name: String,
+    stream_id: String,
+    muted: bool,
+    clients: Vec<ClientStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientStatus {
+    id: String,
+    connected: bool,
+    host: HostStatus,
+    config: ClientConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct HostStatus {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientConfig {
+    name: String,
+    volume: Volume,
+}
+
+#[derive(Debug, Deserialize)]
+struct Volume {
+    percent: u8,
+    muted: bool,
+}
+
+impl From<GroupStatus> for OutputGroup {
+    fn from(group: GroupStatus) -> Self {
+        Self {
+            id: group.id,
+            name: group.name,
+            stream: group.stream_id,
+            muted: group.muted,
+            outputs: group
+                .clients
+                .into_iter()
+                .map(|client| Output {
+                    id: client.id,
+                    name: if client.config.name.is_empty() {
+                        client.host.name
+                    } else {
+                        client.config.name
+                    },
+                    connected: client.connected,
+                    volume: f32::from(client.config.volume.percent) / 100.0,
+                    muted: client.config.volume.muted,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// JSON-RPC client of a Snapcast server, for multi-room outputs
+pub struct SnapcastClient {
+    http: reqwest::blocking::Client,
+    /// `jsonrpc` endpoint of the HTTP control API
+    endpoint: reqwest::Url,
+    next_id: AtomicU64,
+}
+
+impl SnapcastClient {
+    pub fn new(config: &SnapcastConfig) -> Result<Self> {
+        let http = reqwest::blocking::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        let endpoint = reqwest::Url::parse(&config.url)
+            .and_then(|url| url.join("/jsonrpc"))
+            .with_context(|| format!("Invalid Snapcast URL {}", config.url))?;
+        Ok(Self {
+            http,
+            endpoint,
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Every group with its outputs
+    pub fn outputs(&self) -> Result<Vec<OutputGroup>> {
+        let status: Status = serde_json::from_value(self.call("Server.GetStatus", json!({}))?)?;
+        Ok(status.server.groups.into_iter().map(Into::into).collect())
+    }
+
+    /// Set the volume of an output, or of every output in a group
+    ///
+    /// # Arguments
+    /// * `id` - Id of an output or a group
+    /// * `level` - Between 0 and 1
+    pub fn set_volume(&self, id: &str, level: f32) -> Result<()> {
+        let percent = (level.clamp(0.0, 1.0) * 100.0).round() as u8;
+        for output in self.resolve(id)? {
+            self.call(
+                "Client.SetVolume",
+                json!({ "id": output.id, "volume": { "percent": percent, "muted": output.muted } }),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Mute or unmute an output or a whole group
+    ///
+    /// # Arguments
+    /// * `id` - Id of an output or a group
+    pub fn set_mute(&self, id: &str, muted: bool) -> Result<()> {
+        let groups = self.outputs()?;
+        if groups.iter().any(|group| group.id == id) {
+            self.call("Group.SetMute", json!({ "id": id, "mute": muted }))?;
+            return Ok(());
+        }
+        let output = find_output(&groups, id)?;
+        let percent = (output.volume * 100.0).round() as u8;
+        self.call(
+            "Client.SetVolume",
+            json!({ "id": id, "volume": { "percent": percent, "muted": muted } }),
+        )?;
+        Ok(())
+    }
+
+    /// The outputs an id stands for, all of a group's or a single one
+    fn resolve(&self, id: &str) -> Result<Vec<Output>> {
+        let groups = self.outputs()?;
+        if let Some(group) = groups.iter().find(|group| group.id == id) {
+            return Ok(group.outputs.clone());
+        }
+        Ok(vec![find_output(&groups, id)?.clone()])
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = json!({ "id": id, "jsonrpc": "2.0", "method": method, "params": params });
+        let mut response: Value = self
+            .http
+            .post(self.endpoint.clone())
+            .json(&request)
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        if let Some(error) = response.get("error") {
+            let message = error["message"].as_str().unwrap_or("unknown error");
+            bail!("Snapcast {} failed: {}", method, message);
+        }
+        Ok(response["result"].take())
+    }
+}
+
+fn find_output<'a>(groups: &'a [OutputGroup], id: &str) -> Result<&'a Output> {
+    groups
+        .iter()
+        .flat_map(|group| &group.outputs)
+        .find(|output| output.id == id)
+        .with_context(|| format!("No Snapcast group or output with id {}", id))
+}
+
+/// Broadcast the outputs whenever they change, e.g. volumes set from another Snapcast app
+pub async fn run(snapcast: Arc<SnapcastClient>, hub: EventHub) {
+    let mut last = None;
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let client = Arc::clone(&snapcast);
+        let outputs = match tokio::task::spawn_blocking(move || client.outputs()).await {
+            Ok(Ok(outputs)) => outputs,
+            Ok(Err(e)) => {
+                tracing::debug!("Failed to get Snapcast status: {:#}", e);
+                continue;
+            }
+            Err(e) => {
+                tracing::error!("Snapcast status task failed: {}", e);
+                continue;
+            }
+        };
+        if last.as_ref() != Some(&outputs) {
+            hub.publish_outputs(outputs.clone());
+            last = Some(outputs);
+        }
+    }
+}
@@ -1,369 +1,942 @@
+use std::net::SocketAddr;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
-
-use anyhow::Result;
-use serde::Deserialize;
-use socketioxide::extract::{Data, SocketRef};
-
-use crate::media_manager::{AutoRepeatMode, MediaManager};
+use std::time::{Duration, Instant};
+
+use axum::extract::ConnectInfo;
+
+use socketioxide::AckError;
+use socketioxide::extract::{AckSender, Data, SocketRef, State, TryData};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::AbortHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::events::{
+    ClientTarget, CommandAck, CommandError, EventHub, Fade, MediaCommand, MediaEvent, OutputMute,
+    OutputVolume, PlaylistTarget, QueueTarget, SeekPosition, SessionCommand, SessionTarget,
+    TransferTarget, VolumeLevel,
+};
+use crate::media_manager::AutoRepeatMode;
+use crate::peers::PeerCommand;
+use crate::position_alarms::{PositionAlarmRequest, PositionAlarmTarget};
+use crate::protocol::{
+    ClientHello, Encoding, EventFilter, ServerPing, Subscription, Topic, msgpack_payload,
+};
+use crate::quiet_hours::QuietHoursOverride;
+use crate::rate_limit::{CommandLimiter, SEEK_COALESCE_WINDOW};
+use crate::scheduler::{ScheduleRequest, ScheduleTarget, SleepTimerRequest};
+use crate::scrub::{self, Scrub, UNMUTE_DELAY};
+use crate::timings;
+use crate::utils;
 
 const GET_MEDIA_DETAILS: &str = "get_media_details";
 const TOGGLE_PLAY_PAUSE: &str = "toggle_play_pause";
+const PLAY: &str = "play";
+const PAUSE: &str = "pause";
+const STOP: &str = "stop";
+const FAST_FORWARD: &str = "fast_forward";
+const REWIND: &str = "rewind";
 const NEXT_TRACK: &str = "next_track";
 const PREVIOUS_TRACK: &str = "previous_track";
 const SEEK: &str = "seek";
+const SEEK_PREVIEW: &str = "seek_preview";
 const SET_REPEAT_MODE: &str = "set_repeat_mode";
 const TOGGLE_SHUFFLE: &str = "toggle_shuffle";
+const GET_LYRICS: &str = "get_lyrics";
+const GET_QUEUE: &str = "get_queue";
+const SPOTIFY_SAVE_TRACK: &str = "spotify_save_track";
+const SPOTIFY_ADD_TO_PLAYLIST: &str = "spotify_add_to_playlist";
+const SPOTIFY_ADD_TO_QUEUE: &str = "spotify_add_to_queue";
+const SPOTIFY_GET_DEVICES: &str = "spotify_get_devices";
+const SPOTIFY_TRANSFER_PLAYBACK: &str = "spotify_transfer_playback";
+const GET_OUTPUTS: &str = "get_outputs";
+const SET_OUTPUT_VOLUME: &str = "set_output_volume";
+const SET_OUTPUT_MUTE: &str = "set_output_mute";
+const SET_SLEEP_TIMER: &str = "set_sleep_timer";
+const CANCEL_SLEEP_TIMER: &str = "cancel_sleep_timer";
+const CANCEL_INACTIVITY_PAUSE: &str = "cancel_inactivity_pause";
+const ADD_SCHEDULE: &str = "add_schedule";
+const LIST_SCHEDULES: &str = "list_schedules";
+const REMOVE_SCHEDULE: &str = "remove_schedule";
+const BOOKMARK_POSITION: &str = "bookmark_position";
+const RESUME_BOOKMARK: &str = "resume_bookmark";
+const LIST_BOOKMARKS: &str = "list_bookmarks";
+const ADD_POSITION_ALARM: &str = "add_position_alarm";
+const LIST_POSITION_ALARMS: &str = "list_position_alarms";
+const REMOVE_POSITION_ALARM: &str = "remove_position_alarm";
+const QUIET_HOURS_OVERRIDE: &str = "quiet_hours_override";
+const ADMIN_KICK: &str = "admin_kick";
+const FULL_STATE: &str = "full_state";
+const GET_STATE_SNAPSHOT: &str = "get_state_snapshot";
+const HELLO: &str = "hello";
+const SUBSCRIBE: &str = "subscribe";
+const UNSUBSCRIBE: &str = "unsubscribe";
+const PIN_SESSION: &str = "pin_session";
+const UNPIN_SESSION: &str = "unpin_session";
+const SET_VOLUME: &str = "set_volume";
+const LIST_PEERS: &str = "list_peers";
+const PEER_COMMAND: &str = "peer_command";
+const SESSION_COMMAND: &str = "session_command";
+const SERVER_PING: &str = "server_ping";
+
+/// The app a socket stays on, kept in its extensions
+#[derive(Clone)]
+struct Pin {
+    app_id: String,
+    /// Task sending the state of the app's session
+    poll: AbortHandle,
+}
 
-const TRACK_INFO: &str = "track_info";
-const TRACK_CONTROLS: &str = "track_controls";
-const TRACK_TIMELINE: &str = "track_timeline";
+pub fn on_connect(socket: SocketRef, State(hub): State<EventHub>) {
+    tracing::info!("socket connected: {}", socket.id);
+    socket.extensions.insert(CommandLimiter::default());
+    socket.extensions.insert(Scrub::default());
+    socket
+        .extensions
+        .insert(Encoding::from_query(socket.req_parts().uri.query()));
+
+    // FORWARD HUB EVENTS TO THIS SOCKET
+    let forward_task = tokio::spawn(forward_events(hub.clone(), socket.clone()));
+    let forward_abort = forward_task.abort_handle();
+
+    // DISCONNECT WHEN KICKED BY AN ADMIN
+    let address = socket
+        .req_parts()
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0);
+    let kick = hub
+        .clients()
+        .register(socket.id.to_string(), "socket.io", address);
+    let kicked_socket = socket.clone();
+    let kick_task = tokio::spawn(async move {
+        kick.notified().await;
+        kicked_socket.disconnect().ok();
+    });
+    let kick_abort = kick_task.abort_handle();
+
+    // PING THE CLIENT TO MEASURE LATENCY AND DROP ZOMBIES
+    let heartbeat_task = tokio::spawn(heartbeat(hub.clone(), socket.clone()));
+    let heartbeat_abort = heartbeat_task.abort_handle();
+
+    socket.on(
+        GET_MEDIA_DETAILS,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            tracing::info!("Getting media details");
+            run_command(hub, socket, ack, MediaCommand::GetMediaDetails);
+        },
+    );
 
-#[derive(Debug, Deserialize)]
-pub struct SeekPosition {
-    /// Position in milliseconds
-    pub position: u64,
-}
+    // HANDLE PLAY/PAUSE TOGGLE
+    socket.on(
+        TOGGLE_PLAY_PAUSE,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         TryData(data): TryData<Fade>| {
+            // The fade payload is optional, a bare toggle sends nothing
+            run_command(hub, socket, ack, MediaCommand::TogglePlayPause(data.ok()));
+        },
+    );
 
-struct HandlerSession {
-    media_manager: Arc<Mutex<MediaManager>>,
-    track_changed_token: Option<i64>,
-    track_controls_token: Option<i64>,
-    track_timeline_token: Option<i64>,
-}
+    // HANDLE EXPLICIT TRANSPORT COMMANDS
+    socket.on(
+        PLAY,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::Play);
+        },
+    );
 
-impl HandlerSession {
-    fn new(media_manager: MediaManager) -> Self {
-        Self {
-            media_manager: Arc::new(Mutex::new(media_manager)),
-            track_changed_token: None,
-            track_controls_token: None,
-            track_timeline_token: None,
-        }
-    }
+    socket.on(
+        PAUSE,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::Pause);
+        },
+    );
 
-    fn restart_listeners(&mut self, socket: SocketRef) {
-        tracing::info!("Restarting listeners");
-        self.cleanup();
-        self.setup_listeners(socket);
-    }
+    socket.on(
+        STOP,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::Stop);
+        },
+    );
 
-    fn emit_intial_data(&self, socket: SocketRef) {
-        let mm = &self.media_manager;
-        emit_track_info(&mm, &socket).ok();
-        emit_track_controls(&mm, &socket).ok();
-        emit_track_timeline(&mm, &socket).ok();
-    }
+    socket.on(
+        FAST_FORWARD,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::FastForward);
+        },
+    );
 
-    fn setup_listeners(&mut self, socket: SocketRef) {
-        if let Ok(token) =
-            on_track_controls_changed(Arc::clone(&self.media_manager), socket.clone())
-        {
-            self.track_controls_token = Some(token);
-        }
+    socket.on(
+        REWIND,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::Rewind);
+        },
+    );
 
-        if let Ok(token) =
-            on_track_timeline_changed(Arc::clone(&self.media_manager), socket.clone())
-        {
-            self.track_timeline_token = Some(token);
-        }
+    // HANDLE NEXT TRACK
+    socket.on(
+        NEXT_TRACK,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::NextTrack);
+        },
+    );
 
-        if let Ok(token) = on_track_changed(Arc::clone(&self.media_manager), socket.clone()) {
-            self.track_changed_token = Some(token);
-        }
-    }
+    // HANDLE PREVIOUS TRACK
+    socket.on(
+        PREVIOUS_TRACK,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::PreviousTrack);
+        },
+    );
 
-    fn cleanup(&mut self) {
-        tracing::info!("Cleaning up handler session");
-        if let Ok(manager) = self.media_manager.lock() {
-            if let Some(token) = self.track_changed_token.take() {
-                if let Err(e) = manager.remove_track_changed_handler(token) {
-                    tracing::error!("Failed to unregister track changed callback: {}", e);
+    // HANDLE REPEAT MODE
+    socket.on(
+        SET_REPEAT_MODE,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender, data: Data<String>| {
+            tracing::info!("Setting auto repeat mode: {:?}", data.0);
+            match AutoRepeatMode::from_str(&data) {
+                Ok(mode) => run_command(hub, socket, ack, MediaCommand::SetRepeatMode(mode)),
+                Err(e) => {
+                    tracing::error!("Invalid auto repeat mode: {}", data.0);
+                    emit_event(
+                        &socket,
+                        &MediaEvent::Error(CommandError::new(SET_REPEAT_MODE, e.clone())),
+                    );
+                    send_ack(ack, CommandAck::error(e));
                 }
             }
+        },
+    );
 
-            if let Some(token) = self.track_controls_token.take() {
-                if let Err(e) = manager.remove_track_controls_changed_handler(token) {
-                    tracing::error!("Failed to unregister track controls callback: {}", e);
-                }
-            }
+    // TOGGLE SHUFFLE
+    socket.on(
+        TOGGLE_SHUFFLE,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::ToggleShuffle);
+        },
+    );
 
-            if let Some(token) = self.track_timeline_token.take() {
-                if let Err(e) = manager.remove_track_timeline_changed_handler(token) {
-                    tracing::error!("Failed to unregister track timeline callback: {}", e);
-                }
-            }
-        } else {
-            tracing::error!("Failed to lock media manager for cleanup");
-        }
-    }
-}
+    // GET LYRICS OF THE CURRENT TRACK
+    socket.on(
+        GET_LYRICS,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::GetLyrics);
+        },
+    );
 
-pub fn on_connect(socket: SocketRef) {
-    tracing::info!("socket connected: {}", socket.id);
+    // GET THE PLAY QUEUE OF APPS THAT EXPOSE IT
+    socket.on(
+        GET_QUEUE,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::GetQueue);
+        },
+    );
 
-    let mut session = HandlerSession::new(MediaManager::new().unwrap());
-    let media_manager = Arc::clone(&session.media_manager);
+    // SPOTIFY WEB API CONTROLS
+    socket.on(
+        SPOTIFY_SAVE_TRACK,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::SpotifySaveTrack);
+        },
+    );
 
-    session.emit_intial_data(socket.clone());
-    session.setup_listeners(socket.clone());
+    socket.on(
+        SPOTIFY_ADD_TO_PLAYLIST,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<PlaylistTarget>| {
+            run_command(hub, socket, ack, MediaCommand::SpotifyAddToPlaylist(data));
+        },
+    );
 
-    let mm_details = Arc::clone(&media_manager);
-    socket.on(GET_MEDIA_DETAILS, move |socket: SocketRef| {
-        tracing::info!("Getting media details");
-        let media_manager = Arc::clone(&mm_details);
-        let socket = socket.clone();
-        if let Err(e) = emit_track_info(&media_manager, &socket) {
-            tracing::error!("Failed to get media details: {}", e);
-        }
-    });
+    socket.on(
+        SPOTIFY_ADD_TO_QUEUE,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<QueueTarget>| {
+            run_command(hub, socket, ack, MediaCommand::SpotifyAddToQueue(data));
+        },
+    );
 
-    // HANDLE PLAY/PAUSE TOGGLE
-    let mm_play_pause = Arc::clone(&media_manager);
-    socket.on(TOGGLE_PLAY_PAUSE, move |_: SocketRef| {
-        let media_manager = Arc::clone(&mm_play_pause);
-        if let Ok(manager) = media_manager.lock() {
-            if let Err(e) = manager.toggle_play() {
-                tracing::error!("Failed to toggle play/pause: {}", e);
-            }
-        }
-    });
+    socket.on(
+        SPOTIFY_GET_DEVICES,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::SpotifyGetDevices);
+        },
+    );
 
-    // HANDLE NEXT TRACK
-    let mm_next = Arc::clone(&media_manager);
-    socket.on(NEXT_TRACK, move |_: SocketRef| {
-        if let Ok(manager) = mm_next.lock() {
-            if let Err(e) = manager.next_track() {
-                tracing::error!("Failed to skip to next track: {}", e);
-            }
-        }
-    });
+    socket.on(
+        SPOTIFY_TRANSFER_PLAYBACK,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<TransferTarget>| {
+            run_command(
+                hub,
+                socket,
+                ack,
+                MediaCommand::SpotifyTransferPlayback(data),
+            );
+        },
+    );
 
-    // HANDLE PREVIOUS TRACK
-    let mm_prev = Arc::clone(&media_manager);
-    socket.on(PREVIOUS_TRACK, move |_: SocketRef| {
-        if let Ok(manager) = mm_prev.lock() {
-            if let Err(e) = manager.previous_track() {
-                tracing::error!("Failed to go to previous track: {}", e);
+    // SYSTEM VOLUME
+    socket.on(
+        SET_VOLUME,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<VolumeLevel>| {
+            run_command(hub, socket, ack, MediaCommand::SetVolume(data));
+        },
+    );
+
+    // SNAPCAST OUTPUTS
+    socket.on(
+        GET_OUTPUTS,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::GetOutputs);
+        },
+    );
+
+    socket.on(
+        SET_OUTPUT_VOLUME,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<OutputVolume>| {
+            run_command(hub, socket, ack, MediaCommand::SetOutputVolume(data));
+        },
+    );
+
+    socket.on(
+        SET_OUTPUT_MUTE,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<OutputMute>| {
+            run_command(hub, socket, ack, MediaCommand::SetOutputMute(data));
+        },
+    );
+
+    // SLEEP TIMER
+    socket.on(
+        SET_SLEEP_TIMER,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<SleepTimerRequest>| {
+            run_command(hub, socket, ack, MediaCommand::SetSleepTimer(data));
+        },
+    );
+
+    socket.on(
+        CANCEL_SLEEP_TIMER,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::CancelSleepTimer);
+        },
+    );
+
+    // INACTIVITY
+    socket.on(
+        CANCEL_INACTIVITY_PAUSE,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::CancelInactivityPause);
+        },
+    );
+
+    // SCHEDULES
+    socket.on(
+        ADD_SCHEDULE,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<ScheduleRequest>| {
+            run_command(hub, socket, ack, MediaCommand::AddSchedule(data));
+        },
+    );
+
+    socket.on(
+        LIST_SCHEDULES,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::ListSchedules);
+        },
+    );
+
+    socket.on(
+        REMOVE_SCHEDULE,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<ScheduleTarget>| {
+            run_command(hub, socket, ack, MediaCommand::RemoveSchedule(data));
+        },
+    );
+
+    // BOOKMARKS OF TRACK POSITIONS
+    socket.on(
+        BOOKMARK_POSITION,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::BookmarkPosition);
+        },
+    );
+
+    socket.on(
+        RESUME_BOOKMARK,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::ResumeBookmark);
+        },
+    );
+
+    socket.on(
+        LIST_BOOKMARKS,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::ListBookmarks);
+        },
+    );
+
+    // ALARMS AT POSITIONS OF THE CURRENT TRACK
+    socket.on(
+        ADD_POSITION_ALARM,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<PositionAlarmRequest>| {
+            run_command(hub, socket, ack, MediaCommand::AddPositionAlarm(data));
+        },
+    );
+
+    socket.on(
+        LIST_POSITION_ALARMS,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::ListPositionAlarms);
+        },
+    );
+
+    socket.on(
+        REMOVE_POSITION_ALARM,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<PositionAlarmTarget>| {
+            run_command(hub, socket, ack, MediaCommand::RemovePositionAlarm(data));
+        },
+    );
+
+    // QUIET HOURS
+    socket.on(
+        QUIET_HOURS_OVERRIDE,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<QuietHoursOverride>| {
+            run_command(hub, socket, ack, MediaCommand::QuietHoursOverride(data));
+        },
+    );
+
+    // CONTROL OTHER SERVERS
+    socket.on(
+        LIST_PEERS,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::ListPeers);
+        },
+    );
+
+    socket.on(
+        PEER_COMMAND,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<PeerCommand>| {
+            run_command(hub, socket, ack, MediaCommand::PeerCommand(data));
+        },
+    );
+
+    // CONTROL ONE SESSION OF AGGREGATE MODE
+    socket.on(
+        SESSION_COMMAND,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<SessionCommand>| {
+            run_command(hub, socket, ack, MediaCommand::SessionCommand(data));
+        },
+    );
+
+    // HANDLE SEEK
+    socket.on(
+        SEEK,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<SeekPosition>| {
+            coalesce_seek(hub, socket.clone(), ack, data.position);
+
+            // The release of a drag, unmute once the final seek went through
+            let Some(scrub) = socket.extensions.get::<Scrub>() else {
+                return;
+            };
+            if scrub.is_scrubbing() {
+                tokio::spawn(async move {
+                    tokio::time::sleep(SEEK_COALESCE_WINDOW + UNMUTE_DELAY).await;
+                    // Unless the slider was grabbed again
+                    if scrub.idle_for().is_some_and(|idle| idle >= UNMUTE_DELAY) {
+                        tokio::task::spawn_blocking(move || scrub.finish());
+                    }
+                });
             }
-        }
-    });
+        },
+    );
 
-    // HANDLE REPEAT MODE
-    let mm_set_repeat_mode = Arc::clone(&media_manager);
-    socket.on(SET_REPEAT_MODE, move |_: SocketRef, data: Data<String>| {
-        tracing::info!("Setting auto repeat mode: {:?}", data.0);
-        if let Ok(mode) = AutoRepeatMode::from_str(&data) {
-            if let Ok(manager) = mm_set_repeat_mode.lock() {
-                if let Err(e) = manager.set_auto_repeat_mode(mode) {
-                    tracing::error!("Failed to set auto repeat mode: {}", e);
+    // PREVIEW A SEEK WHILE THE PROGRESS SLIDER IS DRAGGED
+    socket.on(
+        SEEK_PREVIEW,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<SeekPosition>| {
+            let config = hub.config().scrubbing.clone();
+            // Otherwise only the final seek on release moves the playback
+            if !config.live {
+                send_ack(ack, CommandAck::ok());
+                return;
+            }
+            let Some(scrub) = socket.extensions.get::<Scrub>() else {
+                send_ack(ack, CommandAck::ok());
+                return;
+            };
+
+            // Queued right away, so a late preview can't override the seek of the release
+            let app_id = socket.extensions.get::<Pin>().map(|pin| pin.app_id);
+            coalesce_seek(hub.clone(), socket, ack, data.position);
+
+            tokio::spawn(async move {
+                let preview_scrub = scrub.clone();
+                let started = tokio::task::spawn_blocking(move || {
+                    preview_scrub.preview(&hub, &config, app_id)
+                })
+                .await;
+                match started {
+                    Ok(Ok(true)) => {
+                        tokio::spawn(scrub::end_abandoned(scrub));
+                    }
+                    Ok(Ok(false)) => {}
+                    Ok(Err(e)) => tracing::warn!("Failed to start scrubbing: {}", e),
+                    Err(e) => tracing::error!("Scrubbing task failed: {}", e),
                 }
+            });
+        },
+    );
+
+    // RESEND THE WHOLE STATE, BROADCASTS SKIP UNCHANGED EVENTS
+    socket.on(
+        FULL_STATE,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            if rate_limited(&socket) {
+                send_ack(ack, CommandAck::error("Rate limited, slow down"));
+                return;
             }
-        } else {
-            tracing::error!("Invalid auto repeat mode: {}", data.0);
-        }
-    });
+            hub.clients()
+                .record_command(&socket.id.to_string(), MediaCommand::FullState.name());
+
+            tokio::task::spawn_blocking(move || {
+                let events = match socket.extensions.get::<Pin>() {
+                    Some(pin) => hub.pinned_state(&pin.app_id, true),
+                    None => hub.current_state(),
+                };
+                for event in events {
+                    emit_event(&socket, &event);
+                }
+                send_ack(ack, CommandAck::ok());
+            });
+        },
+    );
 
-    // TOGGLE SHUFFLE
-    let mm_toggle_shuffle = Arc::clone(&media_manager);
-    socket.on(TOGGLE_SHUFFLE, move |_: SocketRef| {
-        let mm = Arc::clone(&mm_toggle_shuffle);
-        tokio::spawn(async move {
-            if let Ok(manager) = mm.lock() {
-                if let Err(e) = manager.toggle_shuffle() {
-                    tracing::error!("Failed to toggle shuffle: {}", e);
+    // AGREE ON THE OPTIONAL EVENTS THIS CLIENT RECEIVES
+    socket.on(
+        HELLO,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(hello): Data<ClientHello>| {
+            hub.clients().record_command(&socket.id.to_string(), HELLO);
+            match hub.hello(&socket.id.to_string(), &hello) {
+                Ok((filter, reply)) => {
+                    socket.extensions.insert(filter);
+                    socket.extensions.insert(reply.encoding);
+                    emit_event(&socket, &MediaEvent::Hello(reply));
+                    send_ack(ack, CommandAck::ok());
+                }
+                Err(e) => {
+                    emit_event(
+                        &socket,
+                        &MediaEvent::Error(CommandError::new(HELLO, e.clone())),
+                    );
+                    send_ack(ack, CommandAck::error(e));
                 }
             }
-        });
-    });
+        },
+    );
 
-    // HANDLE SEEK
-    let mm_seek = Arc::clone(&media_manager);
-    socket.on(SEEK, move |_socket: SocketRef, data: Data<SeekPosition>| {
-        let mm = Arc::clone(&mm_seek);
-        let position = data.position;
-        tokio::spawn(async move {
-            if let Ok(manager) = mm.lock() {
-                if let Err(e) = manager.seek_to(position) {
-                    tracing::error!("Failed to seek to position {}: {}", position, e);
-                }
+    // PICK THE TRACK INFO, TIMELINE, CONTROLS AND VOLUME BROADCASTS THIS CLIENT RECEIVES
+    socket.on(
+        SUBSCRIBE,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(subscription): Data<Subscription>| {
+            hub.clients()
+                .record_command(&socket.id.to_string(), SUBSCRIBE);
+            for topic in subscription.topics {
+                socket.join(topic.room());
             }
-        });
-    });
+            send_ack(ack, CommandAck::ok());
+        },
+    );
 
-    // SET UP EVENT LISTENERS AND STORE THEIR TOKENS IN THE SESSION
-
-    let session_arc = Arc::new(Mutex::new(session));
-    let socket_id = socket.id.clone();
-
-    let session_for_change = Arc::clone(&session_arc);
-    let socket_clone = socket.clone();
-    let callback = move || {
-        let session = Arc::clone(&session_for_change);
-        let socket = socket_clone.clone();
-        std::thread::spawn(move || {
-            if let Ok(mut session) = session.lock() {
-                session.restart_listeners(socket.clone());
-                session.emit_intial_data(socket);
-            } else {
-                tracing::error!("Failed to lock session for restart on change");
+    socket.on(
+        UNSUBSCRIBE,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(subscription): Data<Subscription>| {
+            hub.clients()
+                .record_command(&socket.id.to_string(), UNSUBSCRIBE);
+            for topic in subscription.topics {
+                socket.leave(topic.room());
             }
-        });
-    };
+            send_ack(ack, CommandAck::ok());
+        },
+    );
 
-    let session_change_token = if let Ok(manager) = media_manager.lock() {
-        match manager.session_changed(callback) {
-            Ok(token) => Some(token),
-            Err(e) => {
-                tracing::error!("Failed to register session change callback: {}", e);
-                None
+    // STAY ON ONE APP'S SESSION WHEN ANOTHER ONE BECOMES CURRENT
+    socket.on(
+        PIN_SESSION,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(target): Data<SessionTarget>| {
+            hub.clients()
+                .record_command(&socket.id.to_string(), PIN_SESSION);
+            tracing::info!("Pinning socket {} to {}", socket.id, target.app_id);
+            unpin(&socket);
+            let poll = tokio::spawn(follow_pin(hub, socket.clone(), target.app_id.clone()));
+            socket.extensions.insert(Pin {
+                app_id: target.app_id,
+                poll: poll.abort_handle(),
+            });
+            send_ack(ack, CommandAck::ok());
+        },
+    );
+
+    socket.on(
+        UNPIN_SESSION,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            hub.clients()
+                .record_command(&socket.id.to_string(), UNPIN_SESSION);
+            if !unpin(&socket) {
+                send_ack(ack, CommandAck::ok());
+                return;
             }
+            // Back to the current session, whatever it is now
+            tokio::task::spawn_blocking(move || {
+                for event in hub.current_state() {
+                    emit_event(&socket, &event);
+                }
+                send_ack(ack, CommandAck::ok());
+            });
+        },
+    );
+
+    socket.on(
+        GET_STATE_SNAPSHOT,
+        |socket: SocketRef, State(hub): State<EventHub>, ack: AckSender| {
+            run_command(hub, socket, ack, MediaCommand::GetStateSnapshot);
+        },
+    );
+
+    socket.on(
+        ADMIN_KICK,
+        |socket: SocketRef,
+         State(hub): State<EventHub>,
+         ack: AckSender,
+         Data(data): Data<ClientTarget>| {
+            run_command(hub, socket, ack, MediaCommand::AdminKick(data));
+        },
+    );
+
+    socket.on_disconnect(move |socket: SocketRef, State(hub): State<EventHub>| {
+        tracing::info!("socket disconnected: {}", socket.id);
+        hub.clients().unregister(&socket.id.to_string());
+        unpin(&socket);
+        // A client gone mid-drag would leave the app muted
+        if let Some(scrub) = socket.extensions.get::<Scrub>() {
+            tokio::task::spawn_blocking(move || scrub.finish());
         }
-    } else {
-        tracing::error!("Failed to lock media manager for session change callback");
-        None
-    };
+        forward_abort.abort();
+        kick_abort.abort();
+        heartbeat_abort.abort();
+    });
+}
 
-    let session_for_dc = Arc::clone(&session_arc);
-    let disconnect_handler = move || {
-        tracing::info!("socket disconnected: {}", socket_id);
+/// Execute a command off the async runtime, replying to the sender when the
+/// command produces a response and acknowledging the outcome.
+fn run_command(hub: EventHub, socket: SocketRef, ack: AckSender, command: MediaCommand) {
+    if rate_limited(&socket) {
+        tracing::warn!("Rate limited socket {}: {:?}", socket.id, command);
+        send_ack(ack, CommandAck::error("Rate limited, slow down"));
+        return;
+    }
 
-        if let Ok(mut session) = session_for_dc.lock() {
-            session.cleanup();
-            if let Ok(manager) = session.media_manager.lock() {
-                if let Some(token) = session_change_token {
-                    manager.remove_session_changed_handler(token).ok();
+    hub.clients()
+        .record_command(&socket.id.to_string(), command.name());
+
+    let pin = socket.extensions.get::<Pin>();
+    let span = timings::command_span(&socket.id.to_string(), command.name());
+    tokio::task::spawn_blocking(move || {
+        let _span = span.entered();
+        let name = command.name();
+        let result = match pin {
+            Some(pin) => hub.execute_pinned(&pin.app_id, command),
+            None => hub.execute(command),
+        };
+        match result {
+            Ok(response) => {
+                if let Some(event) = response {
+                    emit_event(&socket, &event);
                 }
+                send_ack(ack, CommandAck::ok());
+            }
+            Err(e) => {
+                tracing::error!("Failed to execute {}: {}", name, e);
+                emit_event(
+                    &socket,
+                    &MediaEvent::Error(CommandError::new(name, e.clone())),
+                );
+                send_ack(ack, CommandAck::error(e));
             }
-        } else {
-            tracing::error!("Failed to lock session for cleanup on disconnect");
         }
-    };
-
-    socket.on_disconnect(disconnect_handler);
+    });
 }
 
-fn on_track_changed(media_manager: Arc<Mutex<MediaManager>>, socket: SocketRef) -> Result<i64> {
-    let mm_handler = Arc::clone(&media_manager);
-    let socket_clone = socket.clone();
+/// Seek once the coalescing window of the socket closed, to the latest position
+/// queued within it, so dragging a slider doesn't flood the media session
+fn coalesce_seek(hub: EventHub, socket: SocketRef, ack: AckSender, position: u64) {
+    let Some(limiter) = socket.extensions.get::<CommandLimiter>() else {
+        run_command(
+            hub,
+            socket,
+            ack,
+            MediaCommand::Seek(SeekPosition { position }),
+        );
+        return;
+    };
 
-    let callback = move || {
-        let mm = Arc::clone(&mm_handler);
-        let socket = socket_clone.clone();
-        tracing::info!("Track changed");
+    // Only the latest position within a window reaches the media session
+    if !limiter.queue_seek(position) {
+        send_ack(ack, CommandAck::ok());
+        return;
+    }
 
-        if let Err(e) = emit_track_info(&mm, &socket) {
-            tracing::error!("Failed to get track info: {}", e);
+    tokio::spawn(async move {
+        tokio::time::sleep(SEEK_COALESCE_WINDOW).await;
+        if let Some(position) = limiter.take_seek() {
+            run_command(
+                hub,
+                socket,
+                ack,
+                MediaCommand::Seek(SeekPosition { position }),
+            );
         }
-    };
-
-    let token = match media_manager.lock() {
-        Ok(manager) => manager.track_changed(callback),
-        Err(e) => Err(anyhow::anyhow!("Failed to lock media manager: {}", e)),
-    }?;
+    });
+}
 
-    tracing::info!("Registered track change callback with token: {}", token);
-    Ok(token)
+fn rate_limited(socket: &SocketRef) -> bool {
+    socket
+        .extensions
+        .get::<CommandLimiter>()
+        .is_some_and(|limiter| !limiter.allow())
 }
 
-fn on_track_controls_changed(
-    media_manager: Arc<Mutex<MediaManager>>,
-    socket: SocketRef,
-) -> Result<i64> {
-    let mm_handler = Arc::clone(&media_manager);
-    let socket_clone = socket.clone();
-
-    let callback = move || {
-        let mm = Arc::clone(&mm_handler);
-        let socket = socket_clone.clone();
-        tracing::info!("Track Controls changed");
-
-        // std::thread::spawn(move || {
-        if let Err(e) = emit_track_controls(&mm, &socket) {
-            tracing::error!("Failed to get track controls info: {}", e);
-        }
-        // });
+fn emit_event(socket: &SocketRef, event: &MediaEvent) {
+    let result = match socket.extensions.get::<Encoding>() {
+        Some(Encoding::Msgpack) => socket.emit(
+            event.name(),
+            &serde_bytes::ByteBuf::from(msgpack_payload(event)),
+        ),
+        _ => socket.emit(event.name(), &event.payload()),
     };
+    if let Err(e) = result {
+        tracing::error!("Failed to emit {}: {}", event.name(), e);
+    }
+}
 
-    let token = match media_manager.lock() {
-        Ok(manager) => manager.track_controls_changed(callback),
-        Err(e) => Err(anyhow::anyhow!("Failed to lock media manager: {}", e)),
-    }?;
+/// Whether the socket's hello filter and topic rooms let a broadcast through
+fn receives(socket: &SocketRef, event: &MediaEvent) -> bool {
+    let allowed = socket
+        .extensions
+        .get::<EventFilter>()
+        .is_none_or(|filter| filter.allows(event));
+    allowed && subscribed(socket, event)
+}
 
-    tracing::info!(
-        "Registered Playback Info change callback with token: {}",
-        token
-    );
-    Ok(token)
+/// Whether the socket's topic rooms let a broadcast through
+fn subscribed(socket: &SocketRef, event: &MediaEvent) -> bool {
+    if Topic::for_event(event).is_none() {
+        return true;
+    }
+    let rooms = socket.rooms();
+    let rooms: Vec<&str> = rooms.iter().map(|room| room.as_ref()).collect();
+    Topic::allows(&rooms, event)
 }
 
-fn on_track_timeline_changed(
-    media_manager: Arc<Mutex<MediaManager>>,
-    socket: SocketRef,
-) -> Result<i64> {
-    let mm_handler = Arc::clone(&media_manager);
-    let socket_clone = socket.clone();
-
-    let callback = move || {
-        // let mm = Arc::clone(&mm_handler);
-        // let socket = socket_clone.clone();
-        tracing::info!("Track timeline changed");
-
-        // std::thread::spawn(move || {
-        if let Err(e) = emit_track_timeline(&mm_handler, &socket_clone) {
-            tracing::error!("Failed to get track timeline info: {}", e);
-        }
-        // });
-    };
+fn send_ack(ack: AckSender, result: CommandAck) {
+    // Clients that didn't ask for an acknowledgement make this a no-op
+    if let Err(e) = ack.send(&result) {
+        tracing::debug!("Failed to send command ack: {}", e);
+    }
+}
 
-    let token = match media_manager.lock() {
-        Ok(manager) => manager.track_timeline_changed(callback),
-        Err(e) => Err(anyhow::anyhow!("Failed to lock media manager: {}", e)),
-    }?;
+async fn forward_events(hub: EventHub, socket: SocketRef) {
+    let mut receiver = hub.subscribe();
 
-    tracing::info!(
-        "Registered Track Timeline change callback with token: {}",
-        token
-    );
-    Ok(token)
-}
+    let initial = tokio::task::spawn_blocking(move || hub.initial_events())
+        .await
+        .unwrap_or_default();
+
+    for event in initial {
+        emit_event(&socket, &event);
+    }
 
-fn emit_track_info(media_manager: &Arc<Mutex<MediaManager>>, socket: &SocketRef) -> Result<()> {
-    if let Ok(manager) = media_manager.lock() {
-        if let Ok(track) = manager.track_info() {
-            drop(manager);
-            if let Err(e) = socket.emit(TRACK_INFO, &track) {
-                tracing::error!("Failed to emit track info: {}", e);
+    loop {
+        match receiver.recv().await {
+            Ok(event) => {
+                if receives(&socket, &event) && !hidden_by_pin(&socket, &event) {
+                    emit_event(&socket, &event);
+                }
+                if matches!(event, MediaEvent::ServerShutdown) {
+                    socket.disconnect().ok();
+                    break;
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("Socket {} lagged behind by {} events", socket.id, skipped);
             }
-            return Ok(());
+            Err(RecvError::Closed) => break,
         }
     }
-    anyhow::bail!("Failed to get track info");
 }
 
-fn emit_track_controls(media_manager: &Arc<Mutex<MediaManager>>, socket: &SocketRef) -> Result<()> {
-    if let Ok(manager) = media_manager.lock() {
-        if let Ok(controls) = manager.track_controls() {
-            drop(manager);
-            if let Err(e) = socket.emit(TRACK_CONTROLS, &controls) {
-                tracing::error!("Failed to emit track controls: {}", e);
-            }
-            return Ok(());
+/// Whether the socket is pinned to another app than the one a state event is about
+fn hidden_by_pin(socket: &SocketRef, event: &MediaEvent) -> bool {
+    let Some(pin) = socket.extensions.get::<Pin>() else {
+        return false;
+    };
+    event.is_state()
+        && !event
+            .source_app_id()
+            .is_some_and(|app_id| app_id.eq_ignore_ascii_case(&pin.app_id))
+}
+
+/// Stop following a pinned app
+///
+/// # Returns
+/// * `bool` - Whether the socket was pinned
+fn unpin(socket: &SocketRef) -> bool {
+    match socket.extensions.remove::<Pin>() {
+        Some(pin) => {
+            pin.poll.abort();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Send the state of a pinned app's session, then whatever changed.
+///
+/// The pin is kept by app id, so the state comes back when the app restarts
+/// and opens a new session.
+async fn follow_pin(hub: EventHub, socket: SocketRef, app_id: String) {
+    // Followed first, so nothing changes unseen after the state was read
+    let mut changes = hub.follow_pinned(&app_id);
+    let state = tokio::task::spawn_blocking(move || hub.pinned_state(&app_id, true))
+        .await
+        .unwrap_or_default();
+    for event in state {
+        if receives(&socket, &event) {
+            emit_event(&socket, &event);
         }
     }
 
-    anyhow::bail!("Failed to get track controls")
+    loop {
+        match changes.recv().await {
+            Ok(event) => {
+                if receives(&socket, &event) {
+                    emit_event(&socket, &event);
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!(
+                    "Pinned socket {} lagged behind by {} events",
+                    socket.id,
+                    skipped
+                );
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
 }
 
-fn emit_track_timeline(media_manager: &Arc<Mutex<MediaManager>>, socket: &SocketRef) -> Result<()> {
-    if let Ok(manager) = media_manager.lock() {
-        if let Ok(controls) = manager.track_timeline() {
-            drop(manager);
-            if let Err(e) = socket.emit(TRACK_TIMELINE, &controls) {
-                tracing::error!("Failed to emit timeline controls: {}", e);
+/// Emit `server_ping` every interval and wait for the acknowledgement.
+///
+/// Clients that never answered are kept, older clients don't know the event and
+/// the engine level ping already closes dead transports. A client that did answer
+/// and then misses `max_missed` pings in a row is frozen and gets disconnected.
+async fn heartbeat(hub: EventHub, socket: SocketRef) {
+    let config = hub.config().heartbeat.clone();
+    let interval = Duration::from_secs(config.interval_seconds.max(1));
+    let mut ticker = tokio::time::interval_at((Instant::now() + interval).into(), interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let id = socket.id.to_string();
+    let mut answered = false;
+    let mut missed = 0;
+    loop {
+        ticker.tick().await;
+
+        let ping = ServerPing {
+            timestamp: utils::unix_millis(),
+        };
+        let sent = Instant::now();
+        let ack = match socket
+            .timeout(interval)
+            .emit_with_ack::<_, serde_json::Value>(SERVER_PING, &ping)
+        {
+            Ok(ack) => ack,
+            Err(e) => {
+                tracing::debug!("Failed to ping socket {}: {}", id, e);
+                break;
+            }
+        };
+
+        match ack.await {
+            Ok(_) => {
+                answered = true;
+                missed = 0;
+                hub.clients().record_latency(&id, sent.elapsed());
+            }
+            Err(AckError::Timeout) => {
+                missed += 1;
+                if answered && missed >= config.max_missed {
+                    tracing::warn!("Socket {} missed {} pings, disconnecting", id, missed);
+                    socket.disconnect().ok();
+                    break;
+                }
             }
-            return Ok(());
+            Err(e) => tracing::debug!("Invalid ping ack from socket {}: {}", id, e),
         }
     }
-    anyhow::bail!("Failed to get timeline controls");
 }
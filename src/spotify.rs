@@ -0,0 +1,465 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use axum::{
+    Router,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::get,
+};
+use reqwest::Method;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::SpotifyConfig;
+use crate::events::EventHub;
+use crate::queue::{Queue, QueueItem};
+use crate::utils;
+
+const AUTHORIZE_URL: &str = "https://accounts.spotify.com/authorize";
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+const API_URL: &str = "https://api.spotify.com/v1";
+const TOKEN_FILE: &str = "spotify-token.json";
+const SCOPES: &str = "user-read-playback-state user-modify-playback-state \
+    user-read-currently-playing user-library-modify playlist-modify-public playlist-modify-private";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A Spotify Connect device that playback can be transferred to
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct SpotifyDevice {
+    pub id: String,
+    pub name: String,
+    /// e.g. `Computer`, `Smartphone`, `Speaker`
+    pub kind: String,
+    pub active: bool,
+    pub volume: Option<u8>,
+}
+
+/// Tokens persisted between runs so the user only logs in once
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StoredToken {
+    access_token: String,
+    refresh_token: String,
+    /// Unix timestamp in seconds
+    expires_at: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    /// Only present when Spotify rotates the refresh token
+    refresh_token: Option<String>,
+    expires_in: u64,
+}
+
+/// Spotify Web API client authorized for a user account through OAuth
+pub struct SpotifyClient {
+    http: reqwest::blocking::Client,
+    config: SpotifyConfig,
+    token: Mutex<Option<StoredToken>>,
+    /// CSRF state of the login in progress
+    pending_state: Mutex<Option<String>>,
+}
+
+impl SpotifyClient {
+    pub fn new(config: SpotifyConfig) -> Result<Self> {
+        let http = reqwest::blocking::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        // A refresh token from the config seeds the store on first run
+        let token = read_token().or_else(|| {
+            config
+                .refresh_token
+                .clone()
+                .map(|refresh_token| StoredToken {
+                    access_token: String::new(),
+                    refresh_token,
+                    expires_at: 0,
+                })
+        });
+
+        Ok(Self {
+            http,
+            config,
+            token: Mutex::new(token),
+            pending_state: Mutex::new(None),
+        })
+    }
+
+    /// URL of the Spotify consent page, starting a new login
+    pub fn authorize_url(&self) -> Result<String> {
+        let state = utils::content_hash(
+            format!("{:?}{}", SystemTime::now(), std::process::id()).as_bytes(),
+        );
+        *self.lock_pending_state()? = Some(state.clone());
+
+        let url = reqwest::Url::parse_with_params(
+            AUTHORIZE_URL,
+            &[
+                ("client_id", self.config.client_id.as_str()),
+                ("response_type", "code"),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("scope", SCOPES),
+                ("state", state.as_str()),
+            ],
+        )?;
+        Ok(url.to_string())
+    }
+
+    /// Finish a login by exchanging the authorization code for tokens
+    pub fn exchange_code(&self, code: &str, state: &str) -> Result<()> {
+        if self.lock_pending_state()?.take().as_deref() != Some(state) {
+            bail!("Spotify login state mismatch, start the login again");
+        }
+
+        let response = self.token_request(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+        ])?;
+        let refresh_token = response
+            .refresh_token
+            .clone()
+            .context("Spotify did not return a refresh token")?;
+        self.store_token(response, refresh_token)?;
+        Ok(())
+    }
+
+    fn access_token(&self) -> Result<String> {
+        let refresh_token = {
+            let token = self.lock_token()?;
+            let Some(token) = token.as_ref() else {
+                bail!("Spotify is not authorized, log in at /spotify/login");
+            };
+            // Refresh a minute early so requests in flight don't fail
+            if unix_now() + 60 < token.expires_at {
+                return Ok(token.access_token.clone());
+            }
+            token.refresh_token.clone()
+        };
+
+        let response = self.token_request(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+        ])?;
+        let refresh_token = response.refresh_token.clone().unwrap_or(refresh_token);
+        self.store_token(response, refresh_token)
+    }
+
+    fn token_request(&self, form: &[(&str, &str)]) -> Result<TokenResponse> {
+        Ok(self
+            .http
+            .post(TOKEN_URL)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .form(form)
+            .send()?
+            .error_for_status()?
+            .json()?)
+    }
+
+    fn store_token(&self, response: TokenResponse, refresh_token: String) -> Result<String> {
+        let token = StoredToken {
+            access_token: response.access_token,
+            refresh_token,
+            expires_at: unix_now() + response.expires_in,
+        };
+        write_token(&token);
+        let access_token = token.access_token.clone();
+        *self.lock_token()? = Some(token);
+        Ok(access_token)
+    }
+
+    fn lock_token(&self) -> Result<std::sync::MutexGuard<'_, Option<StoredToken>>> {
+        self.token
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Spotify token lock poisoned: {}", e))
+    }
+
+    fn lock_pending_state(&self) -> Result<std::sync::MutexGuard<'_, Option<String>>> {
+        self.pending_state
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Spotify login state lock poisoned: {}", e))
+    }
+
+    fn request(&self, method: Method, path: &str) -> Result<reqwest::blocking::RequestBuilder> {
+        Ok(self
+            .http
+            .request(method, format!("{}{}", API_URL, path))
+            .bearer_auth(self.access_token()?))
+    }
+
+    /// Spotify URI of the track currently playing, e.g. `spotify:track:...`
+    fn current_track_uri(&self) -> Result<String> {
+        let response = self
+            .request(Method::GET, "/me/player/currently-playing")?
+            .send()?;
+        if response.status() == reqwest::StatusCode::NO_CONTENT {
+            bail!("Nothing is playing on Spotify");
+        }
+        let body: serde_json::Value = response.error_for_status()?.json()?;
+        body.pointer("/item/uri")
+            .and_then(|uri| uri.as_str())
+            .map(String::from)
+            .context("Spotify did not report the current track")
+    }
+
+    /// Save the current track to the user's library
+    pub fn save_current_track(&self) -> Result<()> {
+        let uri = self.current_track_uri()?;
+        let id = uri.rsplit(':').next().unwrap_or(&uri);
+        self.request(Method::PUT, "/me/tracks")?
+            .query(&[("ids", id)])
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Add the current track to a playlist
+    ///
+    /// # Arguments
+    /// * `playlist_id` - Base62 id of the playlist
+    pub fn add_current_to_playlist(&self, playlist_id: &str) -> Result<()> {
+        let uri = self.current_track_uri()?;
+        self.request(Method::POST, &format!("/playlists/{}/tracks", playlist_id))?
+            .json(&serde_json::json!({ "uris": [uri] }))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Current track and upcoming queue
+    pub fn queue(&self) -> Result<Queue> {
+        let response: serde_json::Value = self
+            .request(Method::GET, "/me/player/queue")?
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        let upcoming = response
+            .get("queue")
+            .and_then(|queue| queue.as_array())
+            .map(|queue| queue.iter().filter_map(queue_item).collect())
+            .unwrap_or_default();
+
+        Ok(Queue {
+            source: String::from("spotify"),
+            current: response.get("currently_playing").and_then(queue_item),
+            upcoming,
+        })
+    }
+
+    /// Append a track or episode to the queue
+    ///
+    /// # Arguments
+    /// * `uri` - Spotify URI, e.g. `spotify:track:...`
+    pub fn add_to_queue(&self, uri: &str) -> Result<()> {
+        self.request(Method::POST, "/me/player/queue")?
+            .query(&[("uri", uri)])
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    pub fn devices(&self) -> Result<Vec<SpotifyDevice>> {
+        let response: serde_json::Value = self
+            .request(Method::GET, "/me/player/devices")?
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        let devices = response
+            .get("devices")
+            .and_then(|devices| devices.as_array())
+            .map(|devices| {
+                devices
+                    .iter()
+                    .filter_map(|device| {
+                        Some(SpotifyDevice {
+                            id: device.get("id")?.as_str()?.to_string(),
+                            name: device.get("name")?.as_str()?.to_string(),
+                            kind: device.get("type")?.as_str()?.to_string(),
+                            active: device.get("is_active")?.as_bool()?,
+                            volume: device
+                                .get("volume_percent")
+                                .and_then(|volume| volume.as_u64())
+                                .map(|volume| volume as u8),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(devices)
+    }
+
+    /// Play the best match of a search, the artist when the query names one and
+    /// the top track otherwise
+    ///
+    /// # Arguments
+    /// * `query` - What was asked for, e.g. an artist or a song title
+    ///
+    /// # Returns
+    /// Name of what plays now
+    pub fn play_search(&self, query: &str) -> Result<String> {
+        let response: serde_json::Value = self
+            .request(Method::GET, "/search")?
+            .query(&[("q", query), ("type", "artist,track"), ("limit", "1")])
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        let artist = response.pointer("/artists/items/0").filter(|artist| {
+            artist["name"]
+                .as_str()
+                .is_some_and(|name| name.to_lowercase() == query.trim().to_lowercase())
+        });
+        let (name, body) = match artist {
+            Some(artist) => (
+                &artist["name"],
+                serde_json::json!({ "context_uri": artist["uri"] }),
+            ),
+            None => {
+                let track = response
+                    .pointer("/tracks/items/0")
+                    .with_context(|| format!("Spotify found nothing for {}", query))?;
+                (
+                    &track["name"],
+                    serde_json::json!({ "uris": [track["uri"]] }),
+                )
+            }
+        };
+        self.request(Method::PUT, "/me/player/play")?
+            .json(&body)
+            .send()?
+            .error_for_status()?;
+        Ok(name.as_str().unwrap_or(query).to_string())
+    }
+
+    /// Move playback to another Spotify Connect device
+    ///
+    /// # Arguments
+    /// * `device_id` - Id from `devices`
+    /// * `play` - Start playing on the new device instead of keeping the current state
+    pub fn transfer_playback(&self, device_id: &str, play: bool) -> Result<()> {
+        self.request(Method::PUT, "/me/player")?
+            .json(&serde_json::json!({ "device_ids": [device_id], "play": play }))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Whether a session belongs to the Spotify desktop app
+pub fn is_spotify_app(source_app_id: &str) -> bool {
+    source_app_id.to_lowercase().contains("spotify")
+}
+
+/// Convert a Spotify track or episode object
+fn queue_item(item: &serde_json::Value) -> Option<QueueItem> {
+    let str_at = |pointer: &str| {
+        item.pointer(pointer)
+            .and_then(|value| value.as_str())
+            .map(String::from)
+    };
+
+    Some(QueueItem {
+        title: str_at("/name")?,
+        // Episodes have a show instead of artists and an album
+        artist: str_at("/artists/0/name")
+            .or_else(|| str_at("/show/publisher"))
+            .unwrap_or_default(),
+        album: str_at("/album/name").or_else(|| str_at("/show/name")),
+        duration: item
+            .get("duration_ms")
+            .and_then(|duration| duration.as_u64())
+            .unwrap_or_default(),
+        artwork: str_at("/album/images/0/url").or_else(|| str_at("/images/0/url")),
+    })
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn token_path() -> PathBuf {
+    // Next to the cache rather than in it, clearing caches shouldn't log the user out
+    utils::data_dir().join(TOKEN_FILE)
+}
+
+fn read_token() -> Option<StoredToken> {
+    let contents = std::fs::read(token_path()).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+fn write_token(token: &StoredToken) {
+    let path = token_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).ok();
+    }
+    let result = serde_json::to_vec(token)
+        .map_err(std::io::Error::other)
+        .and_then(|json| std::fs::write(&path, json));
+    if let Err(e) = result {
+        tracing::warn!("Failed to store Spotify token at {}: {}", path.display(), e);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// OAuth login routes, only useful when `[spotify]` is configured
+pub fn router(hub: EventHub) -> Router {
+    Router::new()
+        .route("/spotify/login", get(login_handler))
+        .route("/spotify/callback", get(callback_handler))
+        .with_state(hub)
+}
+
+async fn login_handler(State(hub): State<EventHub>) -> Response {
+    let Some(spotify) = hub.spotify() else {
+        return (StatusCode::NOT_FOUND, "Spotify is not configured").into_response();
+    };
+    match spotify.authorize_url() {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn callback_handler(
+    State(hub): State<EventHub>,
+    Query(params): Query<CallbackParams>,
+) -> Response {
+    let Some(spotify) = hub.spotify() else {
+        return (StatusCode::NOT_FOUND, "Spotify is not configured").into_response();
+    };
+    if let Some(error) = params.error {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Spotify login failed: {}", error),
+        )
+            .into_response();
+    }
+    let (Some(code), Some(state)) = (params.code, params.state) else {
+        return (StatusCode::BAD_REQUEST, "Missing code or state").into_response();
+    };
+
+    let result = tokio::task::spawn_blocking(move || spotify.exchange_code(&code, &state)).await;
+    match result {
+        Ok(Ok(())) => Html("Spotify connected, you can close this page.").into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Spotify login failed: {:#}", e);
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use crate::error::MediaError;
+use crate::events::EventHub;
+
+/// Interval between checks of the session manager
+const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+/// WinRT failures in a row before the session manager is recreated
+const MAX_FAILURES: u32 = 3;
+
+/// Recreate the media session manager once it keeps failing with WinRT errors.
+///
+/// The session manager can break for good, e.g. after fast user switching, which
+/// otherwise only a restart of the whole service fixes. Transports stay up while
+/// it is replaced.
+pub async fn run(hub: EventHub) {
+    let mut interval = tokio::time::interval(PROBE_INTERVAL);
+    let mut failures = 0;
+    loop {
+        interval.tick().await;
+
+        match hub.check_media_manager().await {
+            Err(e @ MediaError::WinRt { .. }) => {
+                failures += 1;
+                tracing::warn!(
+                    "Session manager check failed ({}/{}): {}",
+                    failures,
+                    MAX_FAILURES,
+                    e
+                );
+            }
+            _ => failures = 0,
+        }
+        if failures < MAX_FAILURES {
+            continue;
+        }
+
+        let restart_hub = hub.clone();
+        match tokio::task::spawn_blocking(move || restart_hub.restart_media_manager()).await {
+            Ok(Ok(())) => {
+                tracing::info!("Session manager recreated");
+                failures = 0;
+            }
+            // Tried again on the next failed check
+            Ok(Err(e)) => tracing::error!("Failed to recreate the session manager: {}", e),
+            Err(e) => tracing::error!("Session manager restart failed to run: {}", e),
+        }
+    }
+}
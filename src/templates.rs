@@ -0,0 +1,55 @@
+use anyhow::{Context, Result};
+use minijinja::{AutoEscape, Environment};
+use serde::Serialize;
+
+const NAME: &str = "template";
+
+/// A user template from the config, compiled once and rendered for every event
+///
+/// Templates use the Jinja syntax of minijinja, e.g. `{{ artist }} — {{ title }}`.
+/// Fields missing from the context render as nothing, so optional ones like the album
+/// don't need a check.
+pub struct Template {
+    environment: Environment<'static>,
+}
+
+impl Template {
+    /// Compile a template, so typos show up when the server starts
+    ///
+    /// # Arguments
+    /// * `source` - The template as written in the config
+    pub fn new(source: &str) -> Result<Self> {
+        let mut environment = Environment::new();
+        // Outputs are text, JSON or XML, escaping is up to the caller
+        environment.set_auto_escape_callback(|_| AutoEscape::None);
+        environment.add_filter("duration", duration);
+        environment
+            .add_template_owned(NAME, source.to_string())
+            .with_context(|| format!("Invalid template {:?}", source))?;
+        Ok(Self { environment })
+    }
+
+    /// Compile an optional template of the config
+    pub fn optional(source: Option<&str>) -> Result<Option<Self>> {
+        source.map(Self::new).transpose()
+    }
+
+    /// Render the template with the fields of a payload
+    ///
+    /// # Arguments
+    /// * `context` - Usually the payload of an event, its fields are the template's variables
+    pub fn render(&self, context: impl Serialize) -> Result<String> {
+        Ok(self.environment.get_template(NAME)?.render(context)?)
+    }
+}
+
+/// `{{ duration | duration }}`, milliseconds as `m:ss` or `h:mm:ss`
+pub fn duration(milliseconds: u64) -> String {
+    let seconds = milliseconds / 1000;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
@@ -0,0 +1,189 @@
+use std::io::Cursor;
+
+use anyhow::Result;
+use axum::{
+    Router,
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use color_quant::NeuQuant;
+use image::{ImageOutputFormat, imageops::FilterType};
+use serde::Deserialize;
+
+use crate::error::MediaError;
+use crate::events::EventHub;
+use crate::utils;
+
+/// Upper bound for requested sizes, artwork is never upscaled past this
+const MAX_SIZE: u32 = 2048;
+/// Upper bound for raw bitmaps, larger than any display a microcontroller drives
+pub const MAX_BITMAP_SIZE: u32 = 480;
+const JPEG_QUALITY: u8 = 85;
+/// Speed of the palette quantizer, 1 is the slowest and best
+const QUANTIZER_SAMPLING: i32 = 10;
+const PALETTE_COLORS: usize = 256;
+
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ThumbnailFormat {
+    #[default]
+    Jpeg,
+    Png,
+    Webp,
+    Rgb565,
+    Indexed,
+}
+
+impl ThumbnailFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "image/jpeg",
+            ThumbnailFormat::Png => "image/png",
+            ThumbnailFormat::Webp => "image/webp",
+            ThumbnailFormat::Rgb565 | ThumbnailFormat::Indexed => "application/octet-stream",
+        }
+    }
+}
+
+/// Uncompressed artwork microcontrollers can blit without decoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitmapFormat {
+    /// Little-endian RGB565 pixels, row by row
+    Rgb565,
+    /// 256 little-endian RGB565 palette entries followed by one palette index per pixel
+    Indexed,
+}
+
+#[derive(Debug, Deserialize)]
+struct ThumbnailParams {
+    w: Option<u32>,
+    h: Option<u32>,
+    /// JPEG when resizing, the artwork as the app sent it otherwise
+    format: Option<ThumbnailFormat>,
+}
+
+impl ThumbnailParams {
+    /// Nothing asked for that needs decoding, so animated artwork keeps moving
+    fn is_original(&self) -> bool {
+        self.w.is_none() && self.h.is_none() && self.format.is_none()
+    }
+}
+
+/// Route serving the current track's artwork, resized and re-encoded on request
+pub fn router(hub: EventHub) -> Router {
+    Router::new()
+        .route("/thumbnail", get(thumbnail_handler))
+        .with_state(hub)
+}
+
+async fn thumbnail_handler(
+    State(hub): State<EventHub>,
+    Query(params): Query<ThumbnailParams>,
+) -> Response {
+    let result = tokio::task::spawn_blocking(move || {
+        // The placeholder is versioned like artwork, so it is served by the same route
+        let artwork = hub.artwork().or_else(|e| hub.placeholder().ok_or(e))?;
+        if params.is_original() {
+            return Ok((utils::image_content_type(&artwork), artwork));
+        }
+        let format = params.format.unwrap_or_default();
+        let bytes = render(&artwork, &params).map_err(MediaError::internal)?;
+        Ok((format.content_type(), bytes))
+    })
+    .await;
+
+    match result {
+        Ok(Ok((content_type, bytes))) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, content_type),
+                (header::CACHE_CONTROL, "public, max-age=86400"),
+            ],
+            bytes,
+        )
+            .into_response(),
+        Ok(Err(MediaError::NoSession)) => StatusCode::NOT_FOUND.into_response(),
+        Ok(Err(e)) => {
+            tracing::warn!("Failed to render thumbnail: {}", e);
+            StatusCode::NOT_FOUND.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Resize the artwork to fit the requested box, keeping its aspect ratio, and encode it
+fn render(artwork: &[u8], params: &ThumbnailParams) -> Result<Vec<u8>> {
+    let bitmap = |format| {
+        let width = params.w.or(params.h).unwrap_or(MAX_BITMAP_SIZE);
+        let height = params.h.unwrap_or(width);
+        render_bitmap(artwork, format, width, height)
+    };
+    let format = params.format.unwrap_or_default();
+    let output_format = match format {
+        ThumbnailFormat::Jpeg => ImageOutputFormat::Jpeg(JPEG_QUALITY),
+        ThumbnailFormat::Png => ImageOutputFormat::Png,
+        ThumbnailFormat::Webp => ImageOutputFormat::WebP,
+        ThumbnailFormat::Rgb565 => return bitmap(BitmapFormat::Rgb565),
+        ThumbnailFormat::Indexed => return bitmap(BitmapFormat::Indexed),
+    };
+
+    let mut image = image::load_from_memory(artwork)?;
+
+    if params.w.is_some() || params.h.is_some() {
+        let width = params.w.unwrap_or(MAX_SIZE).clamp(1, MAX_SIZE);
+        let height = params.h.unwrap_or(MAX_SIZE).clamp(1, MAX_SIZE);
+        if width < image.width() || height < image.height() {
+            image = image.resize(width, height, FilterType::Lanczos3);
+        }
+    }
+    // JPEG has no alpha channel
+    if matches!(format, ThumbnailFormat::Jpeg) {
+        image = image::DynamicImage::ImageRgb8(image.to_rgb8());
+    }
+
+    let mut bytes = Cursor::new(Vec::new());
+    image.write_to(&mut bytes, output_format)?;
+    Ok(bytes.into_inner())
+}
+
+/// Crop and resize the artwork to exactly fill the box and convert it to a raw bitmap
+///
+/// # Arguments
+/// * `artwork` - Encoded artwork
+/// * `format` - Pixel format of the bitmap
+/// * `width` - Width in pixels, clamped to 480
+/// * `height` - Height in pixels, clamped to 480
+pub fn render_bitmap(
+    artwork: &[u8],
+    format: BitmapFormat,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>> {
+    let width = width.clamp(1, MAX_BITMAP_SIZE);
+    let height = height.clamp(1, MAX_BITMAP_SIZE);
+    let image = image::load_from_memory(artwork)?
+        .resize_to_fill(width, height, FilterType::Triangle)
+        .to_rgba8();
+
+    let bitmap = match format {
+        BitmapFormat::Rgb565 => image
+            .pixels()
+            .flat_map(|pixel| utils::rgb565(pixel[0], pixel[1], pixel[2]).to_le_bytes())
+            .collect(),
+        BitmapFormat::Indexed => {
+            let quantizer = NeuQuant::new(QUANTIZER_SAMPLING, PALETTE_COLORS, image.as_raw());
+            let palette = quantizer
+                .color_map_rgb()
+                .chunks_exact(3)
+                .flat_map(|color| utils::rgb565(color[0], color[1], color[2]).to_le_bytes())
+                .collect::<Vec<u8>>();
+            let indices = image
+                .pixels()
+                .map(|pixel| quantizer.index_of(&pixel.0) as u8);
+            palette.into_iter().chain(indices).collect()
+        }
+    };
+    Ok(bitmap)
+}
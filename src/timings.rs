@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Span, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use utoipa::ToSchema;
+
+use crate::utils;
+
+/// Span around a command of a client, from receiving it to answering it
+const COMMAND_SPAN: &str = "command";
+/// Span the core opens around a call into the media thread, including the wait for its queue
+const MEDIA_CALL_SPAN: &str = "media_call";
+/// Finished operations the slowest are picked from
+const RECENT_CAPACITY: usize = 500;
+const SLOWEST_COUNT: usize = 10;
+/// Operations taking longer are logged, WinRT stalls of a few seconds show up here
+const SLOW_THRESHOLD: Duration = Duration::from_secs(1);
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+static RECENT: Mutex<VecDeque<Operation>> = Mutex::new(VecDeque::new());
+
+/// A finished command or media call, as listed by `/api/status`
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct Operation {
+    /// `command` or `media_call`
+    pub kind: &'static str,
+    /// Code of the command, or the media call, e.g. `Pause` or the function that made it
+    pub name: String,
+    /// Shared by a command and the media calls made for it
+    pub request_id: Option<u64>,
+    /// Socket id the command came from, or `api`, `hook` or `mqtt`
+    pub source: Option<String>,
+    pub elapsed_ms: u64,
+    /// Unix timestamp in milliseconds
+    pub finished_at: u64,
+}
+
+/// Open the span of a command, which gets a new request id
+///
+/// # Arguments
+/// * `source` - Socket id, or `api`, `hook` or `mqtt`
+/// * `command` - Code of the command
+///
+/// # Returns
+/// * `Span` - Enter it on the thread executing the command, so its media calls nest in it
+pub fn command_span(source: &str, command: &str) -> Span {
+    let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+    tracing::info_span!("command", request_id, source, command)
+}
+
+/// The slowest of the recently finished operations, slowest first
+pub fn slowest() -> Vec<Operation> {
+    let mut operations: Vec<Operation> = RECENT
+        .lock()
+        .map(|recent| recent.iter().cloned().collect())
+        .unwrap_or_default();
+    operations.sort_by(|a, b| b.elapsed_ms.cmp(&a.elapsed_ms));
+    operations.truncate(SLOWEST_COUNT);
+    operations
+}
+
+/// Times the command and media call spans, for [`slowest`]
+///
+/// Spans are only created when the log filter lets `info` through.
+pub struct TimingLayer;
+
+struct Timing {
+    started: Instant,
+    fields: Fields,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Fields {
+    name: Option<String>,
+    request_id: Option<u64>,
+    source: Option<String>,
+}
+
+impl Visit for Fields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == "request_id" {
+            self.request_id = Some(value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "command" | "call" => self.name = Some(value.to_string()),
+            "source" => self.source = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "call" {
+            self.name = Some(format!("{:?}", value));
+        }
+    }
+}
+
+impl<S> Layer<S> for TimingLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let name = attributes.metadata().name();
+        if name != COMMAND_SPAN && name != MEDIA_CALL_SPAN {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        // Media calls belong to the command they were made for
+        let mut fields = span
+            .scope()
+            .skip(1)
+            .find_map(|parent| {
+                let extensions = parent.extensions();
+                extensions
+                    .get::<Timing>()
+                    .map(|timing| timing.fields.clone())
+            })
+            .unwrap_or_default();
+        fields.name = None;
+        attributes.record(&mut fields);
+        span.extensions_mut().insert(Timing {
+            started: Instant::now(),
+            fields,
+        });
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(timing) = span.extensions_mut().remove::<Timing>() else {
+            return;
+        };
+        let elapsed = timing.started.elapsed();
+        let operation = Operation {
+            kind: span.name(),
+            name: timing.fields.name.unwrap_or_default(),
+            request_id: timing.fields.request_id,
+            source: timing.fields.source,
+            elapsed_ms: elapsed.as_millis() as u64,
+            finished_at: utils::unix_millis(),
+        };
+        if elapsed >= SLOW_THRESHOLD {
+            tracing::warn!(
+                "Slow {} {} took {:?} (request {:?})",
+                operation.kind,
+                operation.name,
+                elapsed,
+                operation.request_id
+            );
+        }
+
+        if let Ok(mut recent) = RECENT.lock() {
+            if recent.len() == RECENT_CAPACITY {
+                recent.pop_front();
+            }
+            recent.push_back(operation);
+        }
+    }
+}
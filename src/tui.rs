@@ -0,0 +1,397 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style, Stylize},
+    text::Line,
+    widgets::{Block, Gauge, Paragraph, Row, Table},
+};
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+
+use crate::ctl::{self, format_position};
+use crate::utils::DEFAULT_SOCKETIO_PORT;
+
+/// Redraw interval, so the progress bar moves between timeline events
+const TICK: Duration = Duration::from_millis(250);
+const CLIENTS_POLL: Duration = Duration::from_secs(2);
+const SEEK_STEP_MS: i64 = 10_000;
+const VOLUME_STEP: f64 = 0.05;
+
+/// Show and control a running server in the terminal
+#[derive(Args, Debug)]
+pub struct TuiArgs {
+    /// URL of the Socket.IO server, including the base path if one is set
+    #[arg(long, default_value_t = format!("http://127.0.0.1:{}", DEFAULT_SOCKETIO_PORT))]
+    server: String,
+}
+
+#[derive(Debug)]
+enum Update {
+    Event { name: String, data: Value },
+    Clients(Vec<Value>),
+    Key(KeyCode),
+    Error(String),
+}
+
+/// What the server reported last
+#[derive(Default)]
+struct State {
+    track: Option<Value>,
+    controls: Option<Value>,
+    /// Progress in milliseconds when the last timeline event arrived
+    progress: u64,
+    /// Length of the track in the last timeline event, `None` from older servers
+    duration: Option<u64>,
+    progress_at: Option<Instant>,
+    volume: Option<f64>,
+    clients: Vec<Value>,
+    message: String,
+}
+
+impl State {
+    fn apply(&mut self, name: &str, data: Value) {
+        match name {
+            "state_snapshot" => {
+                self.volume = data["volume"].as_f64();
+                for name in ["track_info", "track_controls", "track_timeline"] {
+                    self.apply(name, data[name].clone());
+                }
+            }
+            "track_info" => {
+                // Until the timeline of the track arrives
+                self.duration = None;
+                self.track = Some(data).filter(|track| !track.is_null());
+            }
+            "track_controls" => self.controls = Some(data).filter(|controls| !controls.is_null()),
+            "track_timeline" => {
+                // Older servers only send the split fields
+                let timeline = &data["timeline"];
+                let track_id = self.track.as_ref().map(|track| &track["track_id"]);
+                if timeline.is_object() && track_id.is_some_and(|id| id != &timeline["track_id"]) {
+                    return;
+                }
+                self.progress = timeline["position"]
+                    .as_u64()
+                    .or_else(|| data["progress"].as_u64())
+                    .unwrap_or_default();
+                self.duration = timeline["duration"].as_u64();
+                self.progress_at = Some(Instant::now());
+            }
+            "session_state" if data["state"] == "none" => {
+                self.track = None;
+                self.controls = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn playing(&self) -> bool {
+        self.controls
+            .as_ref()
+            .and_then(|controls| controls["playing"].as_bool())
+            .unwrap_or_default()
+    }
+
+    fn duration(&self) -> u64 {
+        self.duration
+            .or_else(|| {
+                self.track
+                    .as_ref()
+                    .and_then(|track| track["duration"].as_u64())
+            })
+            .unwrap_or_default()
+    }
+
+    /// Progress extrapolated from the last timeline event while playing
+    fn position(&self) -> u64 {
+        let elapsed = match self.progress_at {
+            Some(at) if self.playing() => at.elapsed().as_millis() as u64,
+            _ => 0,
+        };
+        (self.progress + elapsed).min(self.duration().max(self.progress))
+    }
+}
+
+pub async fn run(args: TuiArgs) -> Result<()> {
+    let (sender, mut receiver) = mpsc::channel(64);
+
+    let snapshot = ctl::send(&args.server, "get_state_snapshot", None).await?;
+    let mut state = State::default();
+    if let Some(event) = snapshot.event {
+        state.apply("state_snapshot", event["data"].clone());
+    }
+
+    tokio::spawn(follow_events(args.server.clone(), sender.clone()));
+    tokio::spawn(poll_clients(args.server.clone(), sender.clone()));
+
+    let mut terminal = ratatui::init();
+    // Terminal input blocks, so it gets a thread of its own
+    std::thread::spawn(move || read_keys(sender));
+    let result = event_loop(&mut terminal, &args.server, &mut state, &mut receiver).await;
+    ratatui::restore();
+    result
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    server: &str,
+    state: &mut State,
+    receiver: &mut mpsc::Receiver<Update>,
+) -> Result<()> {
+    let mut tick = tokio::time::interval(TICK);
+    loop {
+        terminal.draw(|frame| draw(frame, server, state))?;
+
+        tokio::select! {
+            _ = tick.tick() => {}
+            update = receiver.recv() => match update {
+                Some(Update::Event { name, data }) => state.apply(&name, data),
+                Some(Update::Clients(clients)) => state.clients = clients,
+                Some(Update::Error(message)) => state.message = message,
+                Some(Update::Key(KeyCode::Char('q') | KeyCode::Esc)) | None => return Ok(()),
+                Some(Update::Key(key)) => {
+                    if let Err(e) = handle_key(server, state, key).await {
+                        state.message = e.to_string();
+                    }
+                }
+            },
+        }
+    }
+}
+
+async fn handle_key(server: &str, state: &mut State, key: KeyCode) -> Result<()> {
+    let (event, data) = match key {
+        KeyCode::Char(' ') => ("toggle_play_pause", None),
+        KeyCode::Char('n') => ("next_track", None),
+        KeyCode::Char('p') => ("previous_track", None),
+        KeyCode::Char('s') => ("toggle_shuffle", None),
+        KeyCode::Left | KeyCode::Right => {
+            let step = if key == KeyCode::Left {
+                -SEEK_STEP_MS
+            } else {
+                SEEK_STEP_MS
+            };
+            let position = (state.position() as i64 + step).max(0) as u64;
+            ("seek", Some(json!({ "position": position })))
+        }
+        KeyCode::Char('+' | '=' | '-') => {
+            let step = if key == KeyCode::Char('-') {
+                -VOLUME_STEP
+            } else {
+                VOLUME_STEP
+            };
+            let level = (state.volume.unwrap_or_default() + step).clamp(0.0, 1.0);
+            ctl::send(server, "set_volume", Some(json!({ "level": level }))).await?;
+            // Volume changes aren't broadcast, so keep track of it here
+            state.volume = Some(level);
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+
+    ctl::send(server, event, data).await?;
+    state.message.clear();
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, server: &str, state: &State) {
+    let [
+        track_area,
+        progress_area,
+        controls_area,
+        clients_area,
+        help_area,
+    ] = Layout::vertical([
+        Constraint::Length(5),
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let track = match &state.track {
+        Some(track) => vec![
+            Line::from(track["title"].as_str().unwrap_or_default().to_string()).bold(),
+            Line::from(track["artist"].as_str().unwrap_or_default().to_string()),
+            Line::from(track["album"].as_str().unwrap_or_default().to_string()).italic(),
+        ],
+        None => vec![Line::from("Nothing playing").italic()],
+    };
+    let app = state
+        .track
+        .as_ref()
+        .and_then(|track| track["app_name"].as_str())
+        .unwrap_or(server);
+    frame.render_widget(
+        Paragraph::new(track).block(Block::bordered().title(format!(" {} ", app))),
+        track_area,
+    );
+
+    let (position, duration) = (state.position(), state.duration());
+    let ratio = if duration > 0 {
+        (position as f64 / duration as f64).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    frame.render_widget(
+        Gauge::default()
+            .block(Block::bordered())
+            .ratio(ratio)
+            .label(format!(
+                "{} / {}",
+                format_position(position),
+                format_position(duration)
+            )),
+        progress_area,
+    );
+
+    let controls = state.controls.as_ref();
+    let flag = |key: &str| {
+        controls
+            .and_then(|controls| controls[key].as_bool())
+            .map(|value| if value { "on" } else { "off" })
+            .unwrap_or("-")
+    };
+    let repeat = controls
+        .and_then(|controls| controls["auto_repeat_mode"].as_str())
+        .unwrap_or("-");
+    let volume = state
+        .volume
+        .map(|volume| format!("{:.0}%", volume * 100.0))
+        .unwrap_or_else(|| "-".to_string());
+    let status = format!(
+        "{}   Shuffle: {}   Repeat: {}   Volume: {}",
+        if state.playing() { "Playing" } else { "Paused" },
+        flag("shuffle"),
+        repeat,
+        volume
+    );
+    frame.render_widget(
+        Paragraph::new(status).block(Block::bordered()),
+        controls_area,
+    );
+
+    let rows = state.clients.iter().map(|client| {
+        Row::new([
+            client["id"].as_str().unwrap_or_default().to_string(),
+            client["transport"].as_str().unwrap_or_default().to_string(),
+            client["address"].as_str().unwrap_or("-").to_string(),
+            client["latency"]
+                .as_u64()
+                .map(|latency| format!("{} ms", latency))
+                .unwrap_or_else(|| "-".to_string()),
+            client["last_command"]["name"]
+                .as_str()
+                .unwrap_or("-")
+                .to_string(),
+        ])
+    });
+    let clients = Table::new(
+        rows,
+        [
+            Constraint::Percentage(30),
+            Constraint::Length(10),
+            Constraint::Percentage(25),
+            Constraint::Length(8),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(
+        Row::new(["Client", "Transport", "Address", "Latency", "Last command"])
+            .style(Style::new().add_modifier(Modifier::BOLD)),
+    )
+    .block(Block::bordered().title(" Clients "));
+    frame.render_widget(clients, clients_area);
+
+    let help = if state.message.is_empty() {
+        "space play/pause  n/p next/previous  ←/→ seek  +/- volume  s shuffle  q quit".to_string()
+    } else {
+        state.message.clone()
+    };
+    frame.render_widget(Paragraph::new(help).dim(), help_area);
+}
+
+/// Forward the server-sent events stream, reconnecting when it drops
+async fn follow_events(server: String, sender: mpsc::Sender<Update>) {
+    let url = format!("{}/api/events", server.trim_end_matches('/'));
+    loop {
+        if let Err(e) = stream_events(&url, &sender).await {
+            let message = format!("Event stream: {:#}", e);
+            if sender.send(Update::Error(message)).await.is_err() {
+                return;
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+}
+
+async fn stream_events(url: &str, sender: &mpsc::Sender<Update>) -> Result<()> {
+    let response = reqwest::get(url).await?.error_for_status()?;
+    let mut stream = std::pin::pin!(response.bytes_stream());
+
+    let mut buffer = String::new();
+    while let Some(chunk) = stream.next().await {
+        buffer.push_str(&String::from_utf8_lossy(&chunk?));
+
+        // Events are separated by a blank line
+        while let Some(end) = buffer.find("\n\n") {
+            let block: String = buffer.drain(..end + 2).collect();
+            let mut name = None;
+            let mut data = String::new();
+            for line in block.lines() {
+                if let Some(value) = line.strip_prefix("event:") {
+                    name = Some(value.trim().to_string());
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data.push_str(value.trim_start());
+                }
+            }
+
+            let Some(name) = name else { continue };
+            let data = serde_json::from_str(&data).unwrap_or_default();
+            sender.send(Update::Event { name, data }).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn poll_clients(server: String, sender: mpsc::Sender<Update>) {
+    let url = format!("{}/api/status", server.trim_end_matches('/'));
+    let mut interval = tokio::time::interval(CLIENTS_POLL);
+    loop {
+        interval.tick().await;
+        let update = match fetch_clients(&url).await {
+            Ok(clients) => Update::Clients(clients),
+            Err(e) => Update::Error(format!("Status: {:#}", e)),
+        };
+        if sender.send(update).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn fetch_clients(url: &str) -> Result<Vec<Value>> {
+    let mut status: Value = reqwest::get(url).await?.error_for_status()?.json().await?;
+    let clients = status["clients"].take();
+    serde_json::from_value(clients).context("Invalid client list")
+}
+
+fn read_keys(sender: mpsc::Sender<Update>) {
+    loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                if sender.blocking_send(Update::Key(key.code)).is_err() {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(_) => return,
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/frstycodes/media-controller/releases/latest";
+/// Release asset listing the SHA-256 of every other asset, as `sha256sum` prints them
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Replace this executable with the latest release
+///
+/// The download is checked against the release's `SHA256SUMS`, which only guards
+/// against a corrupted download. Both come from the same release, so nothing is
+/// verified about who published it.
+#[derive(Args, Debug)]
+pub struct UpdateArgs {
+    /// Only tell whether a newer release is out
+    #[arg(long, default_value_t = false)]
+    check: bool,
+
+    /// Install the latest release even when it isn't newer than this one, ignored
+    /// with `--check`
+    #[arg(long, default_value_t = false)]
+    force: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .with_context(|| format!("Release {} has no {}", self.tag_name, name))
+    }
+}
+
+pub fn run(args: UpdateArgs) -> Result<()> {
+    let client = client()?;
+    let release = latest_release(&client)?;
+    let current = env!("CARGO_PKG_VERSION");
+    let newer = is_newer(release.version(), current);
+    if !newer && (args.check || !args.force) {
+        println!("media-controller {} is the latest release", current);
+        return Ok(());
+    }
+    if args.check {
+        println!(
+            "media-controller {} is out, this is {}: {}",
+            release.version(),
+            current,
+            release.html_url
+        );
+        return Ok(());
+    }
+
+    let name = asset_name();
+    let asset = release.asset(&name)?;
+    let checksums = download(
+        &client,
+        &release.asset(CHECKSUMS_ASSET)?.browser_download_url,
+    )?;
+    let expected = checksum_of(&String::from_utf8_lossy(&checksums), &name)
+        .with_context(|| format!("{} has no checksum of {}", CHECKSUMS_ASSET, name))?;
+
+    println!("Downloading {} {}", name, release.version());
+    let executable = download(&client, &asset.browser_download_url)?;
+    let actual = format!("{:x}", Sha256::digest(&executable));
+    if !actual.eq_ignore_ascii_case(&expected) {
+        bail!(
+            "Checksum of {} doesn't match, expected {} but got {}",
+            name,
+            expected,
+            actual
+        );
+    }
+
+    replace_executable(&executable)?;
+    println!(
+        "Updated to {}, restart the server to run it",
+        release.version()
+    );
+    Ok(())
+}
+
+/// Log a notice when a newer release is out, for `--check-updates`
+pub fn notify_if_outdated() {
+    let release = match client().and_then(|client| latest_release(&client)) {
+        Ok(release) => release,
+        Err(e) => {
+            tracing::debug!("Failed to check for updates: {:#}", e);
+            return;
+        }
+    };
+    let current = env!("CARGO_PKG_VERSION");
+    if is_newer(release.version(), current) {
+        tracing::info!(
+            "media-controller {} is out, this is {}. Run `media-controller update` or see {}",
+            release.version(),
+            current,
+            release.html_url
+        );
+    }
+}
+
+/// Remove the executable an update replaced, Windows doesn't let it be deleted while
+/// it is running
+pub fn remove_replaced() {
+    let Ok(executable) = std::env::current_exe() else {
+        return;
+    };
+    let replaced = replaced_path(&executable);
+    if replaced.exists() {
+        match std::fs::remove_file(&replaced) {
+            Ok(()) => tracing::debug!("Removed {}", replaced.display()),
+            Err(e) => tracing::debug!("Failed to remove {}: {}", replaced.display(), e),
+        }
+    }
+}
+
+fn client() -> Result<reqwest::blocking::Client> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        // The GitHub API refuses requests without one
+        .user_agent(concat!(
+            "media-controller/",
+            env!("CARGO_PKG_VERSION"),
+            " (https://github.com/frstycodes/media-controller)"
+        ))
+        .build()?;
+    Ok(client)
+}
+
+fn latest_release(client: &reqwest::blocking::Client) -> Result<Release> {
+    client
+        .get(LATEST_RELEASE_URL)
+        .header("Accept", "application/vnd.github+json")
+        .send()?
+        .error_for_status()?
+        .json()
+        .context("Failed to read the latest release")
+}
+
+fn download(client: &reqwest::blocking::Client, url: &str) -> Result<Vec<u8>> {
+    let bytes = client
+        .get(url)
+        .send()?
+        .error_for_status()?
+        .bytes()
+        .with_context(|| format!("Failed to download {}", url))?;
+    Ok(bytes.to_vec())
+}
+
+/// Name of the release asset built for this platform, e.g.
+/// `media-controller-x86_64-windows.exe`
+fn asset_name() -> String {
+    format!(
+        "media-controller-{}-{}{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+/// Find the checksum of a file in `sha256sum` output, names may carry a `*` for binary mode
+fn checksum_of(checksums: &str, name: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let (checksum, file) = line.split_once(char::is_whitespace)?;
+        (file.trim().trim_start_matches('*') == name).then(|| checksum.to_string())
+    })
+}
+
+/// Whether a version is newer than another, both as `major.minor.patch`
+fn is_newer(version: &str, than: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .split(['.', '-', '+'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    parse(version) > parse(than)
+}
+
+fn replaced_path(executable: &Path) -> PathBuf {
+    let mut path = OsString::from(executable.as_os_str());
+    path.push(".old");
+    PathBuf::from(path)
+}
+
+/// Swap the running executable for the new one. Windows keeps a running executable
+/// locked but lets it be renamed, so it is moved aside and removed on the next start
+fn replace_executable(executable: &[u8]) -> Result<()> {
+    let current = std::env::current_exe().context("Failed to find this executable")?;
+    let mut staged = OsString::from(current.as_os_str());
+    staged.push(".new");
+    let staged = PathBuf::from(staged);
+    let replaced = replaced_path(&current);
+
+    std::fs::write(&staged, executable)
+        .with_context(|| format!("Failed to write {}", staged.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    // Left behind by an update whose server never started again
+    std::fs::remove_file(&replaced).ok();
+    std::fs::rename(&current, &replaced)
+        .with_context(|| format!("Failed to move {} aside", current.display()))?;
+    if let Err(e) = std::fs::rename(&staged, &current) {
+        // Put the running executable back rather than leave none
+        std::fs::rename(&replaced, &current).ok();
+        return Err(e).with_context(|| format!("Failed to replace {}", current.display()));
+    }
+    Ok(())
+}
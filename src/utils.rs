@@ -1,28 +1,36 @@
 use anyhow::Result;
-use base64::Engine;
-use base64::engine::general_purpose;
-use image;
-use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
 
+pub use media_controller_core::utils::{content_hash, image_content_type, session_id_from_app_id};
+
 pub const DEFAULT_FRONTEND_PORT: u16 = 5173;
 pub const DEFAULT_SOCKETIO_PORT: u16 = 5174;
-pub const ADDR: [u8; 4] = [0, 0, 0, 0];
+pub const DEFAULT_DISPLAY_PORT: u16 = 5175;
 
 #[derive(Clone)]
 pub struct ServerConfig {
     pub port: Arc<Mutex<u16>>,
     pub host: Arc<Mutex<String>>,
+    /// Address both servers listen on
+    pub bind: IpAddr,
+    /// Prefix every route is served under, empty or like `/media`
+    pub base_path: String,
 }
 
 impl ServerConfig {
-    pub fn new(port: u16) -> Self {
+    pub fn new(bind: IpAddr, port: u16, base_path: &str) -> Self {
         Self {
             port: Arc::new(Mutex::new(port)),
             host: Arc::new(Mutex::new(String::from("localhost"))),
+            bind,
+            base_path: normalize_base_path(base_path),
         }
     }
 
@@ -36,108 +44,139 @@ impl ServerConfig {
     pub async fn get_url(&self) -> String {
         let host = self.host.lock().await.clone();
         let port = *self.port.lock().await;
-        format!("http://{}:{}", host, port)
+        format!("http://{}:{}", url_host(&host), port)
+    }
+
+    /// Origin clients should connect to, as seen through a reverse proxy when there is one
+    ///
+    /// # Arguments
+    /// * `headers` - Headers of the request asking, `X-Forwarded-Proto` and `X-Forwarded-Host` are honored
+    pub async fn public_url(&self, headers: &HeaderMap) -> String {
+        let Some(host) = forwarded_header(headers, "x-forwarded-host") else {
+            return self.get_url().await;
+        };
+        let proto = forwarded_header(headers, "x-forwarded-proto").unwrap_or("http");
+        format!("{}://{}", proto, host)
+    }
+
+    /// Path the Socket.IO server listens on
+    pub fn socketio_path(&self) -> String {
+        format!("{}/socket.io", self.base_path)
+    }
+}
+
+/// First value of a forwarded header, proxies chained together append theirs
+fn forwarded_header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    let value = headers.get(name)?.to_str().ok()?;
+    value
+        .split(',')
+        .next()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// Normalize a base path to `/segment` form, or empty for the root
+///
+/// # Arguments
+/// * `path` - e.g. `media`, `/media/` or `/`
+pub fn normalize_base_path(path: &str) -> String {
+    let path = path.trim().trim_matches('/');
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", path)
     }
 }
 
 // Server info for the frontend
 #[derive(Serialize, Deserialize)]
 pub struct ServerInfo {
+    /// Origin of the Socket.IO server, without a path
     pub socketio_url: String,
+    pub socketio_path: String,
+    pub base_path: String,
 }
 
-// Image processing utilities
-pub fn encode_image_to_base64(bytes: &[u8]) -> String {
-    let encoder = general_purpose::STANDARD;
-    format!("data:image/jpeg;base64,{}", encoder.encode(bytes))
+/// Directory for state that should outlive the caches, like tokens and schedules
+///
+/// # Returns
+/// * `PathBuf` - `%LOCALAPPDATA%/media-controller` or a temp dir fallback
+pub fn data_dir() -> PathBuf {
+    std::env::var_os("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("media-controller")
 }
 
-/// Extracts a dominant hue value (0-360)
+/// Directory for on-disk caches, under the user's local app data when available
+///
+/// # Returns
+/// * `PathBuf` - `%LOCALAPPDATA%/media-controller/cache` or a temp dir fallback
+pub fn cache_dir() -> PathBuf {
+    data_dir().join("cache")
+}
+
+/// Read a JSON value from a file in an on-disk cache
 ///
 /// # Arguments
-/// * `image_bytes` - Raw bytes of the image
+/// * `dir` - Cache subdirectory, e.g. `lyrics`
+/// * `key` - File name without extension
 ///
 /// # Returns
-/// * `Result<u16>` - Hue value between 0-360
-pub fn extract_accent_color_hue(image_bytes: &[u8]) -> Result<u16> {
-    let img = image::load_from_memory(image_bytes)?;
-    let small = img.resize(32, 32, image::imageops::FilterType::Gaussian);
-
-    // Convert to RGB for easier color analysis
-    let rgb_img = small.to_rgb8();
-
-    let mut r_sum: u64 = 0;
-    let mut g_sum: u64 = 0;
-    let mut b_sum: u64 = 0;
-    let mut pixel_count: u64 = 0;
-
-    for pixel in rgb_img.pixels() {
-        // Skip very dark/black pixels as they don't contribute to accent color
-        if pixel[0] < 30 && pixel[1] < 30 && pixel[2] < 30 {
-            continue;
-        }
-
-        r_sum += pixel[0] as u64;
-        g_sum += pixel[1] as u64;
-        b_sum += pixel[2] as u64;
-        pixel_count += 1;
-    }
+/// * `Option<T>` - `None` when the entry is missing or unreadable
+pub fn read_json_cache<T: DeserializeOwned>(dir: &str, key: &str) -> Option<T> {
+    let path = cache_dir().join(dir).join(format!("{}.json", key));
+    let contents = std::fs::read(path).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
 
-    // If we found no valid pixels, use a default hue
-    if pixel_count == 0 {
-        return Ok(148); // Defaults to emerald green
+/// Write a JSON value to an on-disk cache, logging failures as caches are best effort
+///
+/// # Arguments
+/// * `dir` - Cache subdirectory, e.g. `lyrics`
+/// * `key` - File name without extension
+/// * `value` - The value to store
+pub fn write_json_cache<T: Serialize>(dir: &str, key: &str, value: &T) {
+    let dir = cache_dir().join(dir);
+    let path = dir.join(format!("{}.json", key));
+    let result = std::fs::create_dir_all(&dir).and_then(|_| {
+        let json = serde_json::to_vec(value).map_err(std::io::Error::other)?;
+        std::fs::write(&path, json)
+    });
+    if let Err(e) = result {
+        tracing::warn!("Failed to write cache entry {}: {}", path.display(), e);
     }
+}
 
-    // Calculate average RGB
-    let r = (r_sum / pixel_count) as f32;
-    let g = (g_sum / pixel_count) as f32;
-    let b = (b_sum / pixel_count) as f32;
-
-    let (h, ..) = rgb_to_hsv(r, g, b);
-
-    // Return the hue directly in 0-360 range
-    Ok(h.round() as u16)
+/// Current time as a Unix timestamp in milliseconds
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
 }
 
-/// Convert RGB color values to HSV (Hue, Saturation, Value)
+// Session utilities
+
+/// Cache key identifying a track by artist and title, ignoring case
 ///
 /// # Arguments
-///
-/// * `r`, `g`, `b` - RGB values in range 0-255 as f32
+/// * `artist` - Track artist
+/// * `title` - Track title
 ///
 /// # Returns
-///
-/// * `(h, s, v)` - Hue (0-360), Saturation (0-1), Value (0-1)
-fn rgb_to_hsv(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
-    let r = r / 255.0;
-    let g = g / 255.0;
-    let b = b / 255.0;
-
-    let max = r.max(g).max(b);
-    let min = r.min(g).min(b);
-    let delta = max - min;
-
-    // Calculate hue
-    let h = if delta == 0.0 {
-        0.0 // No hue for grayscale colors
-    } else if max == r {
-        60.0 * (((g - b) / delta) % 6.0)
-    } else if max == g {
-        60.0 * (((b - r) / delta) + 2.0)
-    } else {
-        60.0 * (((r - g) / delta) + 4.0)
-    };
-
-    // Ensure positive hue
-    let h = if h < 0.0 { h + 360.0 } else { h };
-
-    // Calculate saturation
-    let s = if max == 0.0 { 0.0 } else { delta / max };
-
-    // Value is just the max
-    let v = max;
+/// * `String` - 16 character hex hash
+pub fn track_key(artist: &str, title: &str) -> String {
+    let identity = format!("{}\u{0}{}", artist, title).to_lowercase();
+    content_hash(identity.as_bytes())
+}
 
-    (h, s, v)
+/// Pack a color into the RGB565 format of small displays
+///
+/// # Returns
+/// * `u16` - 5 bits red, 6 bits green and 5 bits blue, red in the high bits
+pub fn rgb565(r: u8, g: u8, b: u8) -> u16 {
+    (u16::from(r >> 3) << 11) | (u16::from(g >> 2) << 5) | u16::from(b >> 3)
 }
 
 // Server and network utilities
@@ -145,17 +184,18 @@ fn rgb_to_hsv(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
 /// Try to bind to specified port, fall back to random port if unavailable
 ///
 /// # Arguments
+/// * `bind` - The address to listen on
 /// * `preferred_port` - The port to try binding to first
 ///
 /// # Returns
 /// * `Result<(TcpListener, u16)>` - The listener and the actual port number used
-pub async fn try_bind(preferred_port: u16) -> Result<(TcpListener, u16)> {
-    let preferred_addr = SocketAddr::from((ADDR, preferred_port));
+pub async fn try_bind(bind: IpAddr, preferred_port: u16) -> Result<(TcpListener, u16)> {
+    let preferred_addr = SocketAddr::from((bind, preferred_port));
     match TcpListener::bind(preferred_addr).await {
         Ok(listener) => Ok((listener, preferred_port)),
         Err(_) => {
             // If preferred port is unavailable, bind to port 0 (OS will assign random available port)
-            let random_addr = SocketAddr::from((ADDR, 0));
+            let random_addr = SocketAddr::from((bind, 0));
             let listener = TcpListener::bind(random_addr).await?;
             let actual_port = listener.local_addr()?.port();
             Ok((listener, actual_port))
@@ -163,29 +203,49 @@ pub async fn try_bind(preferred_port: u16) -> Result<(TcpListener, u16)> {
     }
 }
 
-/// Get local network IP addresses
+/// Get the network IP addresses clients can reach the server on
+///
+/// # Arguments
+/// * `bind` - The address the server listens on
 ///
 /// # Returns
-/// * `Vec<String>` - List of network IP addresses
-pub fn get_local_ips() -> Vec<String> {
-    let mut ips = Vec::new();
-
-    // Primary method: Connect to a public address and see what interface is used
-    if let Ok(socket) = std::net::UdpSocket::bind("0.0.0.0:0") {
-        // This doesn't actually send any data, just gives us the interface that would be used
-        if socket.connect("8.8.8.8:80").is_ok() {
-            if let Ok(addr) = socket.local_addr() {
-                if let SocketAddr::V4(addr) = addr {
-                    let ip = addr.ip();
-                    if !ip.is_loopback() {
-                        ips.push(ip.to_string());
-                    }
-                }
-            }
-        }
+/// * `Vec<String>` - List of network IP addresses, empty when only bound to loopback
+pub fn get_local_ips(bind: IpAddr) -> Vec<String> {
+    if bind.is_loopback() {
+        return Vec::new();
+    }
+    if !bind.is_unspecified() {
+        return vec![bind.to_string()];
     }
 
-    ips
+    // Connect to a public address and see what interface is used
+    let (local, public) = match bind {
+        IpAddr::V4(_) => ("0.0.0.0:0", "8.8.8.8:80"),
+        IpAddr::V6(_) => ("[::]:0", "[2001:4860:4860::8888]:80"),
+    };
+    // This doesn't actually send any data, just gives us the interface that would be used
+    std::net::UdpSocket::bind(local)
+        .and_then(|socket| {
+            socket.connect(public)?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip())
+        .into_iter()
+        .filter(|ip| !ip.is_loopback())
+        .map(|ip| ip.to_string())
+        .collect()
+}
+
+/// Host part of a URL, IPv6 addresses need brackets
+///
+/// # Arguments
+/// * `host` - A hostname or IP address
+pub fn url_host(host: &str) -> String {
+    if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    }
 }
 
 /// Format text with green color for terminal
@@ -207,31 +267,35 @@ macro_rules! green_print {
 ///
 /// # Arguments
 /// * `service_name` - Name of the service (e.g., "Frontend", "SocketIO")
+/// * `bind` - The address the service listens on
 /// * `port` - Port number the service is running on
-pub fn print_urls(service_name: &str, port: u16) {
+/// * `base_path` - Prefix the service is served under, empty for the root
+pub fn print_urls(service_name: &str, bind: IpAddr, port: u16, base_path: &str) {
     let mut output = String::new();
 
     output.push_str(&format!("\n  {service_name} server running at:\n\n"));
 
-    // Local URL (clickable in most terminals)
-    output.push_str(&format!(
-        "  > Local:    {}\n",
-        green_print!("http://localhost:{}/", port)
-    ));
+    // Local URL (clickable in most terminals), unreachable when bound to a single interface
+    if bind.is_unspecified() || bind.is_loopback() {
+        output.push_str(&format!(
+            "  > Local:    {}\n",
+            green_print!("http://localhost:{}{}/", port, base_path)
+        ));
+    }
 
     // Network URLs
-    let network_ips = get_local_ips();
+    let network_ips = get_local_ips(bind);
     if !network_ips.is_empty() {
         for (i, ip) in network_ips.iter().enumerate() {
             if i == 0 {
                 output.push_str(&format!(
                     "  > Network:  {}\n",
-                    green_print!("http://{}:{}/", ip, port)
+                    green_print!("http://{}:{}{}/", url_host(ip), port, base_path)
                 ));
             } else {
                 output.push_str(&format!(
                     "              {}\n",
-                    green_print!("http://{}:{}/", ip, port)
+                    green_print!("http://{}:{}{}/", url_host(ip), port, base_path)
                 ));
             }
         }
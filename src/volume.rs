@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+#[cfg(windows)]
+use windows::Win32::{
+    Media::Audio::{
+        Endpoints::IAudioEndpointVolume, IAudioSessionControl2, IAudioSessionManager2, IMMDevice,
+        IMMDeviceEnumerator, ISimpleAudioVolume, MMDeviceEnumerator, eConsole, eRender,
+    },
+    System::Com::{CLSCTX_ALL, COINIT_MULTITHREADED, CoCreateInstance, CoInitializeEx},
+};
+#[cfg(windows)]
+use windows::core::Interface;
+
+#[cfg(windows)]
+use crate::app_icon;
+use crate::error::{MediaError, MediaResult};
+
+/// Interval between volume steps while fading
+const FADE_STEP: Duration = Duration::from_millis(50);
+
+/// Which volume a fade ramps
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VolumeTarget {
+    /// Master volume of the default output device
+    #[default]
+    System,
+    /// Mixer volume of the app owning the current session
+    App,
+}
+
+/// A volume control, either the system master volume or every audio session of an app
+///
+/// Only Windows has volume controls, elsewhere none can be created.
+pub enum Volume {
+    #[cfg(windows)]
+    System(IAudioEndpointVolume),
+    #[cfg(windows)]
+    App(Vec<ISimpleAudioVolume>),
+}
+
+#[cfg(windows)]
+impl Volume {
+    pub fn system() -> MediaResult<Self> {
+        let endpoint =
+            unsafe { default_device()?.Activate::<IAudioEndpointVolume>(CLSCTX_ALL, None)? };
+        Ok(Volume::System(endpoint))
+    }
+
+    /// Audio sessions of an app on the default output device
+    ///
+    /// # Arguments
+    /// * `app_id` - App User Model ID of a desktop app, e.g. `Spotify.exe`
+    pub fn app(app_id: &str) -> MediaResult<Self> {
+        // Browsers play from a child process that shares the executable name
+        let process_ids = app_icon::find_process_ids(app_id).map_err(MediaError::internal)?;
+
+        let mut sessions = Vec::new();
+        unsafe {
+            let manager: IAudioSessionManager2 = default_device()?.Activate(CLSCTX_ALL, None)?;
+            let enumerator = manager.GetSessionEnumerator()?;
+            for index in 0..enumerator.GetCount()? {
+                let control = enumerator
+                    .GetSession(index)?
+                    .cast::<IAudioSessionControl2>()?;
+                if process_ids.contains(&control.GetProcessId()?) {
+                    sessions.push(control.cast::<ISimpleAudioVolume>()?);
+                }
+            }
+        }
+
+        if sessions.is_empty() {
+            return Err(MediaError::unsupported("app volume"));
+        }
+        Ok(Volume::App(sessions))
+    }
+
+    /// Current level as a scalar between 0 and 1, the loudest session for apps
+    pub fn level(&self) -> MediaResult<f32> {
+        unsafe {
+            match self {
+                Volume::System(endpoint) => Ok(endpoint.GetMasterVolumeLevelScalar()?),
+                Volume::App(sessions) => sessions.iter().try_fold(0.0f32, |level, session| {
+                    Ok(level.max(session.GetMasterVolume()?))
+                }),
+            }
+        }
+    }
+
+    /// Set the level
+    ///
+    /// # Arguments
+    /// * `level` - Scalar between 0 and 1
+    pub fn set_level(&self, level: f32) -> MediaResult<()> {
+        if !(0.0..=1.0).contains(&level) {
+            return Err(MediaError::invalid_input(format!(
+                "Volume must be between 0 and 1, got {}",
+                level
+            )));
+        }
+        unsafe {
+            match self {
+                Volume::System(endpoint) => {
+                    endpoint.SetMasterVolumeLevelScalar(level, std::ptr::null())?
+                }
+                Volume::App(sessions) => {
+                    for session in sessions {
+                        session.SetMasterVolume(level, std::ptr::null())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(windows))]
+impl Volume {
+    pub fn system() -> MediaResult<Self> {
+        Err(MediaError::unsupported("system volume"))
+    }
+
+    pub fn app(_app_id: &str) -> MediaResult<Self> {
+        Err(MediaError::unsupported("app volume"))
+    }
+
+    pub fn level(&self) -> MediaResult<f32> {
+        match *self {}
+    }
+
+    pub fn set_level(&self, _level: f32) -> MediaResult<()> {
+        match *self {}
+    }
+}
+
+impl Volume {
+    pub fn for_target(target: VolumeTarget, app_id: &str) -> MediaResult<Self> {
+        match target {
+            VolumeTarget::System => Self::system(),
+            VolumeTarget::App => Self::app(app_id),
+        }
+    }
+
+    /// Linearly ramp the level, blocking until the fade is done
+    ///
+    /// # Arguments
+    /// * `to` - Target scalar between 0 and 1
+    /// * `duration` - Length of the fade
+    pub fn fade(&self, to: f32, duration: Duration) -> MediaResult<()> {
+        let from = self.level()?;
+        let to = to.clamp(0.0, 1.0);
+
+        let steps = (duration.as_millis() / FADE_STEP.as_millis()).max(1) as u32;
+        for step in 1..=steps {
+            self.set_level(from + (to - from) * (step as f32 / steps as f32))?;
+            std::thread::sleep(FADE_STEP);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+fn default_device() -> MediaResult<IMMDevice> {
+    unsafe {
+        // Fails harmlessly when the thread is already initialized
+        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        Ok(enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?)
+    }
+}
+
+/// System volume as a scalar between 0 and 1
+pub fn system_volume() -> MediaResult<f32> {
+    Volume::system()?.level()
+}
+
+/// Set the system volume
+///
+/// # Arguments
+/// * `level` - Scalar between 0 and 1
+pub fn set_system_volume(level: f32) -> MediaResult<()> {
+    Volume::system()?.set_level(level)
+}
+
+/// Linearly ramp the system volume, blocking until the fade is done
+///
+/// # Arguments
+/// * `to` - Target scalar between 0 and 1
+/// * `duration` - Length of the fade
+pub fn fade_system_volume(to: f32, duration: Duration) -> MediaResult<()> {
+    Volume::system()?.fade(to, duration)
+}
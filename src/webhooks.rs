@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::WebhookConfig;
+use crate::events::{EventHub, MediaEvent};
+use crate::templates::Template;
+use crate::utils;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Delay before the first retry, doubled for every further one
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const SIGNATURE_HEADER: &str = "X-Media-Controller-Signature";
+const EVENT_HEADER: &str = "X-Media-Controller-Event";
+
+/// Body POSTed to every webhook
+#[derive(Debug, Serialize)]
+struct WebhookPayload {
+    /// `track_changed`, `playback_changed`, `session_changed` or `position_alarm`
+    event: &'static str,
+    /// Unix timestamp in milliseconds
+    timestamp: u64,
+    data: serde_json::Value,
+}
+
+/// POST track, playback and session changes and position alarms to the configured webhooks
+pub async fn run(webhooks: Vec<WebhookConfig>, hub: EventHub) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(concat!("media-controller/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let webhooks = webhooks
+        .into_iter()
+        .map(|webhook| {
+            Template::optional(webhook.template.as_deref()).map(|template| (template, webhook))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    tracing::info!("Publishing to {} webhook(s)", webhooks.len());
+
+    let mut receiver = hub.subscribe();
+    let mut playing = None;
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("Webhook publisher lagged behind by {} events", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+
+        let name = match &event {
+            MediaEvent::TrackInfo(_) => "track_changed",
+            MediaEvent::SessionState(_) => "session_changed",
+            MediaEvent::PositionAlarm(_) => "position_alarm",
+            // Controls also change with shuffle or repeat, only play/pause counts
+            MediaEvent::TrackControls(controls) => {
+                if playing.replace(controls.playing()) == Some(controls.playing()) {
+                    continue;
+                }
+                "playback_changed"
+            }
+            _ => continue,
+        };
+
+        let mut data = event.payload();
+        if let Some(track) = data.as_object_mut() {
+            // The base64 artwork would bloat every request
+            track.remove("thumbnail");
+        }
+        let payload = WebhookPayload {
+            event: name,
+            timestamp: utils::unix_millis(),
+            data,
+        };
+        let json = match serde_json::to_string(&payload) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::error!("Failed to serialize webhook payload: {}", e);
+                continue;
+            }
+        };
+
+        for (template, webhook) in &webhooks {
+            if !webhook.events.is_empty() && !webhook.events.iter().any(|event| event == name) {
+                continue;
+            }
+            let body = match template {
+                Some(template) => match template.render(&payload) {
+                    Ok(body) => body,
+                    Err(e) => {
+                        tracing::error!("Failed to render webhook {}: {:#}", webhook.url, e);
+                        continue;
+                    }
+                },
+                None => json.clone(),
+            };
+            // Each delivery retries on its own, so one slow endpoint doesn't hold up the rest
+            tokio::spawn(deliver(client.clone(), webhook.clone(), name, body));
+        }
+    }
+}
+
+async fn deliver(
+    client: reqwest::Client,
+    webhook: WebhookConfig,
+    event: &'static str,
+    body: String,
+) {
+    let mut delay = RETRY_DELAY;
+    for attempt in 1..=webhook.max_attempts.max(1) {
+        let mut request = client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, &webhook.content_type)
+            .header(EVENT_HEADER, event)
+            .body(body.clone());
+        if let Some(secret) = &webhook.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+        }
+
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => response.status().to_string(),
+            Err(e) => e.to_string(),
+        };
+        if attempt == webhook.max_attempts.max(1) {
+            tracing::error!(
+                "Webhook {} failed for {} after {} attempt(s): {}",
+                webhook.url,
+                event,
+                attempt,
+                error
+            );
+            return;
+        }
+
+        tracing::debug!(
+            "Webhook {} failed ({}), retrying in {:?}",
+            webhook.url,
+            error,
+            delay
+        );
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+/// `sha256=<hex>` HMAC of the body, so receivers can verify the sender
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    let signature: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256={}", signature)
+}
@@ -0,0 +1,229 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use axum::{
+    Router,
+    extract::{
+        ConnectInfo, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::Uri,
+    response::Response,
+    routing::get,
+};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::MissedTickBehavior;
+
+use crate::events::{CommandError, EventHub, MediaCommand, MediaEvent};
+use crate::protocol::{Encoding, EventFilter, msgpack_envelope};
+use crate::rate_limit::TokenBucket;
+use crate::timings;
+use crate::utils;
+
+/// Source of connection ids, listed next to Socket.IO ids in `/api/status`
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Plain WebSocket endpoint for clients that can't speak Socket.IO.
+///
+/// Messages in both directions are JSON objects of the form
+/// `{"event": "<code>", "data": <payload>}` using the same codes as Socket.IO.
+/// Clients connecting with `?encoding=msgpack`, or asking for it in `hello`, get
+/// the same envelope as binary MessagePack messages and may send theirs that way.
+/// The server heartbeat uses protocol level pings carrying the server timestamp,
+/// which browsers answer on their own.
+pub fn router(hub: EventHub) -> Router {
+    Router::new().route("/ws", get(ws_handler)).with_state(hub)
+}
+
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(hub): State<EventHub>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    uri: Uri,
+) -> Response {
+    let encoding = Encoding::from_query(uri.query());
+    ws.on_upgrade(move |socket| handle_socket(socket, hub, address, encoding))
+}
+
+async fn handle_socket(socket: WebSocket, hub: EventHub, address: SocketAddr, encoding: Encoding) {
+    let id = format!("ws-{}", NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    tracing::info!("websocket connected: {}", id);
+
+    let kick = hub
+        .clients()
+        .register(id.clone(), "websocket", Some(address));
+    tokio::select! {
+        _ = serve_socket(socket, &hub, &id, encoding) => {}
+        _ = kick.notified() => {}
+    }
+    hub.clients().unregister(&id);
+
+    tracing::info!("websocket disconnected: {}", id);
+}
+
+async fn serve_socket(mut socket: WebSocket, hub: &EventHub, id: &str, mut encoding: Encoding) {
+    let mut receiver = hub.subscribe();
+    let mut bucket = TokenBucket::default();
+    let mut filter = EventFilter::default();
+
+    let config = hub.config().heartbeat.clone();
+    let interval = Duration::from_secs(config.interval_seconds.max(1));
+    let mut heartbeat = tokio::time::interval_at((Instant::now() + interval).into(), interval);
+    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // Payload and send time of the unanswered ping
+    let mut pending: Option<(Vec<u8>, Instant)> = None;
+    let mut missed = 0;
+
+    let hub_for_state = hub.clone();
+    let initial = tokio::task::spawn_blocking(move || hub_for_state.initial_events())
+        .await
+        .unwrap_or_default();
+
+    for event in initial {
+        if send_event(&mut socket, &event, encoding).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(message @ (Message::Text(_) | Message::Binary(_)))) => {
+                    let Some(command) = parse_command(message) else {
+                        continue;
+                    };
+                    if !bucket.try_acquire() {
+                        tracing::warn!("Rate limited websocket command: {}", command.name());
+                        continue;
+                    }
+                    for event in handle_command(hub, id, &mut filter, &mut encoding, command).await {
+                        if send_event(&mut socket, &event, encoding).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Some(Ok(Message::Pong(payload))) => {
+                    if let Some((_, sent)) = pending.take_if(|(ping, _)| ping[..] == payload[..]) {
+                        missed = 0;
+                        hub.clients().record_latency(id, sent.elapsed());
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            event = receiver.recv() => match event {
+                Ok(event) if !filter.allows(&event) => {}
+                Ok(event) => {
+                    if send_event(&mut socket, &event, encoding).await.is_err() {
+                        break;
+                    }
+                    if matches!(event, MediaEvent::ServerShutdown) {
+                        socket.send(Message::Close(None)).await.ok();
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("websocket lagged behind by {} events", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = heartbeat.tick() => {
+                if pending.is_some() {
+                    missed += 1;
+                    if missed >= config.max_missed {
+                        tracing::warn!("websocket {} missed {} pings, disconnecting", id, missed);
+                        break;
+                    }
+                }
+
+                let payload = utils::unix_millis().to_be_bytes().to_vec();
+                if socket.send(Message::Ping(payload.clone().into())).await.is_err() {
+                    break;
+                }
+                pending = Some((payload, Instant::now()));
+            }
+        }
+    }
+}
+
+/// Parse a JSON text or MessagePack binary command message
+fn parse_command(message: Message) -> Option<MediaCommand> {
+    let command = match &message {
+        Message::Text(text) => serde_json::from_str(text.as_str()).map_err(|e| e.to_string()),
+        Message::Binary(data) => rmpv::decode::read_value(&mut &data[..])
+            .map_err(|e| e.to_string())
+            .and_then(|value| rmpv::ext::from_value(value).map_err(|e| e.to_string())),
+        _ => return None,
+    };
+    match command {
+        Ok(command) => Some(command),
+        Err(e) => {
+            tracing::error!("Invalid websocket command {:?}: {}", message, e);
+            None
+        }
+    }
+}
+
+/// Execute a command, returning the events to send back
+async fn handle_command(
+    hub: &EventHub,
+    id: &str,
+    filter: &mut EventFilter,
+    encoding: &mut Encoding,
+    command: MediaCommand,
+) -> Vec<MediaEvent> {
+    let name = command.name();
+    hub.clients().record_command(id, name);
+
+    let command = match command {
+        MediaCommand::Hello(hello) => {
+            return match hub.hello(id, &hello) {
+                Ok((negotiated, reply)) => {
+                    *filter = negotiated;
+                    *encoding = reply.encoding;
+                    vec![MediaEvent::Hello(reply)]
+                }
+                Err(e) => vec![MediaEvent::Error(CommandError::new(name, e))],
+            };
+        }
+        command => command,
+    };
+
+    let hub = hub.clone();
+    let span = timings::command_span(id, name);
+    let result = tokio::task::spawn_blocking(move || {
+        let _span = span.entered();
+        match command {
+            MediaCommand::FullState => Ok(hub.current_state()),
+            command => hub
+                .execute(command)
+                .map(|event| event.into_iter().collect()),
+        }
+    })
+    .await;
+
+    match result {
+        Ok(Ok(events)) => events,
+        Ok(Err(e)) => {
+            tracing::error!("Failed to execute {}: {}", name, e);
+            vec![MediaEvent::Error(CommandError::new(name, e))]
+        }
+        Err(e) => {
+            tracing::error!("Command task for {} failed: {}", name, e);
+            Vec::new()
+        }
+    }
+}
+
+async fn send_event(
+    socket: &mut WebSocket,
+    event: &MediaEvent,
+    encoding: Encoding,
+) -> Result<(), axum::Error> {
+    let message = match encoding {
+        Encoding::Json => Message::Text(serde_json::to_string(event).unwrap_or_default().into()),
+        Encoding::Msgpack => Message::Binary(msgpack_envelope(event).into()),
+    };
+    socket.send(message).await
+}
@@ -0,0 +1,325 @@
+//! End-to-end tests of the Socket.IO protocol, against the server running the mock backend.
+
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::FutureExt;
+use rust_socketio::asynchronous::{Client, ClientBuilder};
+use rust_socketio::{Event, Payload};
+use serde_json::{Value, json};
+use tokio::sync::{mpsc, oneshot};
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+const EVENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The first track of the mock library, where playback starts
+const FIRST_TITLE: &str = "Morning Static";
+const SECOND_TITLE: &str = "Loopback";
+
+/// The server binary on a free port, killed when dropped
+struct Server {
+    process: Child,
+    port: u16,
+    data_dir: PathBuf,
+}
+
+impl Server {
+    fn start() -> Self {
+        let port = free_port();
+        // Persisted state and schedules of other runs must not leak into the test
+        let data_dir = std::env::temp_dir().join(format!("media-controller-test-{}", port));
+        std::fs::create_dir_all(&data_dir).expect("failed to create the data directory");
+        let config = data_dir.join("config.toml");
+        std::fs::write(&config, "").expect("failed to write the config file");
+
+        let process = Command::new(env!("CARGO_BIN_EXE_media-controller"))
+            .args(["--backend", "mock", "--bind", "127.0.0.1"])
+            .args(["--socketio-port", &port.to_string()])
+            .arg("--config")
+            .arg(&config)
+            .env("LOCALAPPDATA", &data_dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start the server");
+        let server = Self {
+            process,
+            port,
+            data_dir,
+        };
+
+        let started = Instant::now();
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(
+                started.elapsed() < STARTUP_TIMEOUT,
+                "server didn't listen on port {} in time",
+                port
+            );
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        server
+    }
+
+    fn url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.process.kill().ok();
+        self.process.wait().ok();
+        std::fs::remove_dir_all(&self.data_dir).ok();
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|address| address.port())
+        .expect("no free port")
+}
+
+/// A connected client collecting every event it receives
+struct TestClient {
+    client: Client,
+    events: mpsc::UnboundedReceiver<(String, Value)>,
+}
+
+impl TestClient {
+    async fn connect(server: &Server) -> Self {
+        let (sender, events) = mpsc::unbounded_channel();
+        let client = ClientBuilder::new(server.url())
+            .on_any(move |event: Event, payload: Payload, _: Client| {
+                if let Payload::Text(mut values) = payload {
+                    let data = values.drain(..).next().unwrap_or_default();
+                    sender.send((String::from(event), data)).ok();
+                }
+                async {}.boxed()
+            })
+            .connect()
+            .await
+            .expect("failed to connect");
+        Self { client, events }
+    }
+
+    /// Wait for an event, skipping every other one
+    ///
+    /// # Arguments
+    /// * `name` - Event code, e.g. `track_info`
+    /// * `matches` - Whether the payload is the one waited for
+    async fn expect(&mut self, name: &str, matches: impl Fn(&Value) -> bool) -> Value {
+        let deadline = tokio::time::Instant::now() + EVENT_TIMEOUT;
+        loop {
+            let received = tokio::time::timeout_at(deadline, self.events.recv()).await;
+            match received {
+                Ok(Some((event, data))) if event == name && matches(&data) => return data,
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("connection closed while waiting for {}", name),
+                Err(_) => panic!("no matching {} event within {:?}", name, EVENT_TIMEOUT),
+            }
+        }
+    }
+
+    /// Call a function and wait for its acknowledgement
+    async fn call(&self, event: &str, data: Value) -> Value {
+        let (sender, receiver) = oneshot::channel();
+        let sender = Arc::new(Mutex::new(Some(sender)));
+        let on_ack = move |payload: Payload, _: Client| {
+            if let Some(sender) = sender.lock().unwrap().take() {
+                let ack = match payload {
+                    Payload::Text(mut values) => values.drain(..).next().unwrap_or_default(),
+                    _ => Value::Null,
+                };
+                sender.send(ack).ok();
+            }
+            async {}.boxed()
+        };
+
+        self.client
+            .emit_with_ack(event, data, EVENT_TIMEOUT, on_ack)
+            .await
+            .expect("failed to emit");
+        tokio::time::timeout(EVENT_TIMEOUT, receiver)
+            .await
+            .unwrap_or_else(|_| panic!("{} wasn't acknowledged", event))
+            .expect("ack callback dropped")
+    }
+}
+
+fn is_ok(ack: &Value) -> bool {
+    ack == &json!({ "ok": true, "error": null })
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connect_sends_state_snapshot() {
+    let server = Server::start();
+    let mut client = TestClient::connect(&server).await;
+
+    let snapshot = client.expect("state_snapshot", |_| true).await;
+    assert_eq!(snapshot["session"]["state"], "active");
+    assert_eq!(snapshot["sessions"].as_array().map(Vec::len), Some(1));
+    assert_eq!(snapshot["track_info"]["title"], FIRST_TITLE);
+    assert_eq!(snapshot["track_info"]["app_name"], "Mock Player");
+    assert!(snapshot["track_info"]["thumbnail"].is_string());
+    assert_eq!(snapshot["track_controls"]["playing"], false);
+    assert_eq!(snapshot["track_timeline"]["progress"], 0);
+
+    // The separate state events follow for clients that don't read the snapshot
+    client.expect("session_state", |_| true).await;
+    let track = client.expect("track_info", |_| true).await;
+    assert_eq!(track["title"], FIRST_TITLE);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn every_command_is_acknowledged() {
+    let server = Server::start();
+    let client = TestClient::connect(&server).await;
+
+    let commands = [
+        ("play", json!({})),
+        ("pause", json!({})),
+        ("toggle_play_pause", json!({})),
+        ("stop", json!({})),
+        ("fast_forward", json!({})),
+        ("rewind", json!({})),
+        ("next_track", json!({})),
+        ("previous_track", json!({})),
+        ("seek", json!({ "position": 30_000 })),
+        ("set_repeat_mode", json!("list")),
+        ("toggle_shuffle", json!({})),
+        ("get_media_details", json!({})),
+        ("get_state_snapshot", json!({})),
+        ("full_state", json!({})),
+    ];
+    for (command, data) in commands {
+        let ack = client.call(command, data).await;
+        assert!(is_ok(&ack), "{} answered {}", command, ack);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn play_and_pause_broadcast_controls() {
+    let server = Server::start();
+    let mut client = TestClient::connect(&server).await;
+    client.expect("state_snapshot", |_| true).await;
+
+    assert!(is_ok(&client.call("play", json!({})).await));
+    client
+        .expect("track_controls", |controls| controls["playing"] == true)
+        .await;
+
+    assert!(is_ok(&client.call("pause", json!({})).await));
+    client
+        .expect("track_controls", |controls| controls["playing"] == false)
+        .await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn next_and_previous_track_broadcast_track_info() {
+    let server = Server::start();
+    let mut client = TestClient::connect(&server).await;
+    client.expect("state_snapshot", |_| true).await;
+
+    assert!(is_ok(&client.call("next_track", json!({})).await));
+    let track = client
+        .expect("track_info", |track| track["title"] == SECOND_TITLE)
+        .await;
+    assert_eq!(track["track_number"], 2);
+    assert!(track["thumbnails"]["small"].is_string());
+
+    assert!(is_ok(&client.call("previous_track", json!({})).await));
+    client
+        .expect("track_info", |track| track["title"] == FIRST_TITLE)
+        .await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn seek_broadcasts_timeline() {
+    let server = Server::start();
+    let mut client = TestClient::connect(&server).await;
+    client.expect("state_snapshot", |_| true).await;
+
+    let ack = client.call("seek", json!({ "position": 60_000 })).await;
+    assert!(is_ok(&ack), "seek answered {}", ack);
+    client
+        .expect("track_timeline", |timeline| timeline["progress"] == 60_000)
+        .await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn timeline_belongs_to_the_current_track() {
+    let server = Server::start();
+    let mut client = TestClient::connect(&server).await;
+    let snapshot = client.expect("state_snapshot", |_| true).await;
+    let track_id = &snapshot["track_info"]["track_id"];
+    assert!(track_id.is_string());
+    let timeline = &snapshot["track_timeline"]["timeline"];
+    assert_eq!(&timeline["track_id"], track_id);
+    assert_eq!(timeline["duration"], snapshot["track_info"]["duration"]);
+
+    assert!(is_ok(&client.call("next_track", json!({})).await));
+    let track = client
+        .expect("track_info", |track| track["title"] == SECOND_TITLE)
+        .await;
+    assert_ne!(&track["track_id"], track_id);
+    assert_eq!(&track["previous_track_id"], track_id);
+    client
+        .expect("track_timeline", |timeline| {
+            timeline["timeline"]["track_id"] == track["track_id"]
+        })
+        .await;
+    client
+        .expect("track_controls", |controls| {
+            controls["track_id"] == track["track_id"]
+        })
+        .await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn repeat_and_shuffle_broadcast_controls() {
+    let server = Server::start();
+    let mut client = TestClient::connect(&server).await;
+    client.expect("state_snapshot", |_| true).await;
+
+    assert!(is_ok(&client.call("set_repeat_mode", json!("track")).await));
+    client
+        .expect("track_controls", |controls| {
+            controls["auto_repeat_mode"] == "track"
+        })
+        .await;
+
+    assert!(is_ok(&client.call("toggle_shuffle", json!({})).await));
+    client
+        .expect("track_controls", |controls| controls["shuffle"] == true)
+        .await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_media_details_answers_the_caller() {
+    let server = Server::start();
+    let mut client = TestClient::connect(&server).await;
+    // Sent on connect, the answer is a second one
+    client.expect("track_info", |_| true).await;
+
+    assert!(is_ok(&client.call("get_media_details", json!({})).await));
+    client
+        .expect("track_info", |track| track["title"] == FIRST_TITLE)
+        .await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invalid_repeat_mode_is_rejected() {
+    let server = Server::start();
+    let mut client = TestClient::connect(&server).await;
+
+    let ack = client.call("set_repeat_mode", json!("sometimes")).await;
+    assert_eq!(ack["ok"], false);
+    assert!(ack["error"].is_string());
+
+    let error = client.expect("error", |_| true).await;
+    assert_eq!(error["command"], "set_repeat_mode");
+}